@@ -0,0 +1,136 @@
+use crate::histogram;
+use crate::json;
+use crate::PingData;
+use chrono::Duration as chrono_Duration;
+use chrono::{DateTime, DurationRound, Utc};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+// At most how many buckets a series can have, so a tiny bucket over a long stretch of time can't
+// build a giant response.
+pub const MAX_BUCKETS: i64 = 100_000;
+
+// How a bucket's samples are summed up.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Function {
+    // The average round trip of the successful samples, in ms.
+    Avg,
+    // The slowest round trip, in ms.
+    Max,
+    // The 95th percentile round trip, in ms.
+    P95,
+    // The fraction of samples that failed, from 0 to 1.
+    Loss,
+}
+impl Function {
+    pub fn parse(text: &str) -> Option<Function> {
+        return match text {
+            "avg" => Some(Function::Avg),
+            "max" => Some(Function::Max),
+            "p95" => Some(Function::P95),
+            "loss" => Some(Function::Loss),
+            _ => None,
+        };
+    }
+}
+
+// What's been counted in a bucket so far.
+#[derive(Default)]
+struct Bucket {
+    samples: u64,
+    failures: u64,
+    total_ms: f64,
+    successes: u64,
+    max_ms: Option<f64>,
+    // Only kept for `Function::P95`.
+    round_trips_ms: Vec<f64>,
+}
+
+// A bucket of a series, as `/api/hosts/{host}/aggregate` returns it.
+#[derive(Serialize, JsonSchema)]
+#[schemars(rename = "AggregateBucket")]
+pub struct SeriesBucket {
+    #[serde(serialize_with = "json::seconds")]
+    pub start: DateTime<Utc>,
+    #[schemars(description = "How many samples the bucket has, including failures.")]
+    pub samples: u64,
+    #[schemars(description = "Null when there's nothing to sum up, e.g. every sample failed.")]
+    pub value: Option<f64>,
+}
+
+// A target's samples from `from` until `to` summed up by `function` in buckets `width` wide (lined up
+// with the epoch in UTC): each bucket that has samples, oldest first, e.g.
+// `{"start":"2024-01-01T00:05:00Z","samples":300,"value":12.5}`. `value` is null when there's nothing
+// to sum up, e.g. no round trips when every sample failed.
+//
+// Where samples have aged out, their minute and hour summaries are counted in the bucket they start
+// in. Summaries only keep an average, so for the 95th percentile each counts as one round trip.
+pub fn series(
+    ping_data: &PingData,
+    name: &str,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    width: chrono_Duration,
+    function: Function,
+) -> Vec<SeriesBucket> {
+    let mut buckets: BTreeMap<DateTime<Utc>, Bucket> = BTreeMap::new();
+    let start = |time: DateTime<Utc>| time.duration_trunc(width).unwrap();
+    for (time, sample) in ping_data.data[name].range(from..to) {
+        let bucket = buckets.entry(start(*time)).or_default();
+        bucket.samples += 1;
+        match sample.result.round_trip() {
+            Some(round_trip) => {
+                let round_trip_ms = round_trip.as_secs_f64() * 1000.0;
+                bucket.total_ms += round_trip_ms;
+                bucket.successes += 1;
+                bucket.max_ms = Some(
+                    bucket
+                        .max_ms
+                        .map_or(round_trip_ms, |max| max.max(round_trip_ms)),
+                );
+                if function == Function::P95 {
+                    bucket.round_trips_ms.push(round_trip_ms);
+                }
+            }
+            None => bucket.failures += 1,
+        }
+    }
+    for (summary_start, aggregate, _) in crate::summaries_in_scope(ping_data, name, from, to) {
+        let bucket = buckets.entry(start(*summary_start)).or_default();
+        bucket.samples += aggregate.samples as u64;
+        bucket.failures += aggregate.failures as u64;
+        bucket.total_ms += aggregate.total_round_trip.as_secs_f64() * 1000.0;
+        bucket.successes += (aggregate.samples - aggregate.failures) as u64;
+        if let Some(slowest) = aggregate.slowest {
+            let slowest_ms = slowest.as_secs_f64() * 1000.0;
+            bucket.max_ms = Some(bucket.max_ms.map_or(slowest_ms, |max| max.max(slowest_ms)));
+        }
+        if let (Function::P95, Some(mean)) = (function, aggregate.mean_round_trip()) {
+            bucket.round_trips_ms.push(mean.as_secs_f64() * 1000.0);
+        }
+    }
+
+    return buckets
+        .into_iter()
+        .map(|(start, mut bucket)| {
+            let value = match function {
+                Function::Avg if bucket.successes > 0 => {
+                    Some(bucket.total_ms / bucket.successes as f64)
+                }
+                Function::Avg => None,
+                Function::Max => bucket.max_ms,
+                Function::P95 => {
+                    bucket.round_trips_ms.sort_by(f64::total_cmp);
+                    histogram::percentile(&bucket.round_trips_ms, 95.0)
+                }
+                Function::Loss => Some(bucket.failures as f64 / bucket.samples.max(1) as f64),
+            };
+            return SeriesBucket {
+                start,
+                samples: bucket.samples,
+                value,
+            };
+        })
+        .collect();
+}
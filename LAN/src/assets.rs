@@ -0,0 +1,54 @@
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse};
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+// The stylesheet and scripts shared by the web UI's pages. They're compiled into the binary and
+// served from `/static`, so browsers can keep them rather than download them with every page.
+struct Asset {
+    name: &'static str,
+    content_type: &'static str,
+    body: &'static str,
+}
+const ASSETS: &[Asset] = &[
+    Asset {
+        name: "style.css",
+        content_type: "text/css; charset=utf-8",
+        body: include_str!("../static/style.css"),
+    },
+    Asset {
+        name: "live_update.js",
+        content_type: "text/javascript; charset=utf-8",
+        body: include_str!("../static/live_update.js"),
+    },
+    Asset {
+        name: "timezone.js",
+        content_type: "text/javascript; charset=utf-8",
+        body: include_str!("../static/timezone.js"),
+    },
+];
+
+// Serves an asset. Browsers check it hasn't changed before using the one they kept (which costs a
+// round trip but not the download), so they never use an old one after an upgrade.
+pub async fn serve(req: HttpRequest, name: web::Path<String>) -> HttpResponse {
+    let Some(asset) = ASSETS.iter().find(|asset| asset.name == name.as_str()) else {
+        return HttpResponse::NotFound().finish();
+    };
+    let mut hasher = DefaultHasher::new();
+    asset.body.hash(&mut hasher);
+    let etag = format!("\"{:x}\"", hasher.finish());
+    let is_unchanged = req
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|tags| tags.to_str().ok())
+        .is_some_and(|tags| tags.split(',').any(|tag| tag.trim() == etag));
+    if is_unchanged {
+        return HttpResponse::NotModified()
+            .insert_header((header::ETAG, etag))
+            .finish();
+    }
+    return HttpResponse::Ok()
+        .content_type(asset.content_type)
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .insert_header((header::ETAG, etag))
+        .body(asset.body);
+}
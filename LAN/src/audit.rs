@@ -0,0 +1,90 @@
+use crate::json;
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::{Mutex, OnceLock};
+
+// How many changes are kept, the oldest are dropped first.
+const MAX_ENTRIES: usize = 1_000;
+// Everything that's recorded.
+const ACTIONS: &[&str] = &[
+    "add target",
+    "edit target",
+    "move target",
+    "pause target",
+    "resume target",
+    "remove target",
+    "import snapshot",
+    "restore backup",
+    "reload config",
+];
+
+// A change someone made to the monitor, e.g. adding a target with the admin API.
+#[derive(Clone, Serialize, JsonSchema)]
+#[schemars(rename = "AuditEntry")]
+pub struct Entry {
+    #[serde(serialize_with = "json::seconds")]
+    pub time: DateTime<Utc>,
+    // Who made it, see `auth::actor`.
+    #[schemars(description = "Who made the change: who they logged in as, or their address.")]
+    pub actor: String,
+    // What they did, one of `ACTIONS`.
+    #[schemars(extend("enum" = ACTIONS))]
+    pub action: &'static str,
+    // The target it was to, if it was to one.
+    pub target: Option<String>,
+    // What changed, when there's something to show for it, e.g. a target's spec before and after
+    // editing it.
+    #[schemars(description = "What it was before.")]
+    pub old: Option<String>,
+    #[schemars(description = "What it is now.")]
+    pub new: Option<String>,
+}
+
+// Changes since the monitor started, oldest first.
+static ENTRIES: OnceLock<Mutex<VecDeque<Entry>>> = OnceLock::new();
+
+fn entries() -> &'static Mutex<VecDeque<Entry>> {
+    return ENTRIES.get_or_init(|| Mutex::new(VecDeque::new()));
+}
+
+// Records that `actor` did `action`, and prints it, so it's kept in the system log too.
+pub fn record(
+    actor: &str,
+    action: &'static str,
+    target: Option<&str>,
+    old: Option<String>,
+    new: Option<String>,
+) {
+    debug_assert!(ACTIONS.contains(&action), "{} isn't in ACTIONS", action);
+    let entry = Entry {
+        time: Utc::now(),
+        actor: actor.to_string(),
+        action,
+        target: target.map(str::to_string),
+        old,
+        new,
+    };
+    let mut line = format!("{} did \"{}\"", entry.actor, entry.action);
+    if let Some(target) = &entry.target {
+        line += &format!(" to {}", target);
+    }
+    match (&entry.old, &entry.new) {
+        (Some(old), Some(new)) => line += &format!(": {} -> {}", old, new),
+        (Some(old), None) => line += &format!(": was {}", old),
+        (None, Some(new)) => line += &format!(": {}", new),
+        (None, None) => {}
+    }
+    println!("{}", line);
+    let mut entries = entries().lock().unwrap();
+    if entries.len() == MAX_ENTRIES {
+        entries.pop_front();
+    }
+    entries.push_back(entry);
+}
+
+// The changes, newest first, for `/api/audit`.
+pub fn newest_first() -> Vec<Entry> {
+    return entries().lock().unwrap().iter().rev().cloned().collect();
+}
@@ -0,0 +1,204 @@
+use crate::config;
+use crate::oidc;
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::{header, Method};
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpMessage, HttpRequest, HttpResponse};
+use base64::engine::general_purpose::{STANDARD as BASE64, URL_SAFE_NO_PAD as BASE64_URL};
+use base64::Engine;
+use std::sync::OnceLock;
+
+// Which realm browsers say they're logging in to when they ask for the username and password.
+const REALM: &str = "network-monitor";
+
+// Credentials are compared by their HMACs under a key made up when the monitor starts, which takes
+// as long however much of them matches, so the time a guess takes doesn't give away how close it was.
+static KEY: OnceLock<ring::hmac::Key> = OnceLock::new();
+
+fn key() -> &'static ring::hmac::Key {
+    return KEY.get_or_init(|| {
+        ring::hmac::Key::generate(ring::hmac::HMAC_SHA256, &ring::rand::SystemRandom::new())
+            .unwrap()
+    });
+}
+
+fn matches(given: &[u8], expected: &str) -> bool {
+    let expected = ring::hmac::sign(key(), expected.as_bytes());
+    return ring::hmac::verify(key(), given, expected.as_ref()).is_ok();
+}
+
+// `value` and its HMAC, to hand to a browser (e.g. as a cookie) and trust when it comes back. Both
+// are base64 so they're safe to put in a cookie. Since the key is made up at startup, what's signed
+// stops being trusted when the monitor restarts.
+pub fn sign(value: &str) -> String {
+    let tag = ring::hmac::sign(key(), value.as_bytes());
+    return format!(
+        "{}.{}",
+        BASE64_URL.encode(value),
+        BASE64_URL.encode(tag.as_ref())
+    );
+}
+
+// The value `signed` was made from by `sign`, if it hasn't been tampered with.
+pub fn verify(signed: &str) -> Option<String> {
+    let (value, tag) = signed.split_once('.')?;
+    let value = BASE64_URL.decode(value).ok()?;
+    let tag = BASE64_URL.decode(tag).ok()?;
+    ring::hmac::verify(key(), &value, &tag).ok()?;
+    return String::from_utf8(value).ok();
+}
+
+// What someone who's logged in, or a script with a token, can do.
+#[derive(Clone, Copy)]
+pub enum Role {
+    // See pages and get API responses.
+    Viewer,
+    // Change things too, e.g. add and pause targets, and trace paths.
+    Admin,
+}
+
+// Whether `req` changes anything, or makes the monitor do something, so needs an admin, or is for
+// the audit log of who changed what, or a backup (which has the config file, whose settings could
+// say more than the pages do). Saving the viewer's preferences only sets cookies for their browser.
+fn needs_admin(req: &ServiceRequest) -> bool {
+    let path = req.path();
+    if req.method() == Method::GET || req.method() == Method::HEAD {
+        return path.starts_with("/trace/")
+            || path == "/settings"
+            || path == "/api/audit"
+            || path == "/api/backup";
+    }
+    return path != "/preferences";
+}
+
+// Who made a request, as they logged in, e.g. `alex` or `token …c0de`.
+struct Name(String);
+
+// Who `req`'s credentials are, and what they can do: logging in with `username` and `password` or as
+// one of `admins`, or sending an admin token, makes it an admin, and logging in as one of `viewers` or
+// sending a read token makes it a viewer. Without any, it's whoever logged in with OpenID Connect, if
+// anyone did. None if it has no credentials, or the wrong ones.
+fn role(req: &ServiceRequest, config: &config::Config) -> Option<(Role, String)> {
+    let Some(authorization) = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|authorization| authorization.to_str().ok())
+    else {
+        return oidc::session_role(req.request());
+    };
+    let is_token = authorization.starts_with("Bearer ");
+    let (given, admins, viewers): (Vec<u8>, Vec<String>, &[String]) =
+        match authorization.strip_prefix("Bearer ") {
+            Some(token) => (
+                token.trim().as_bytes().to_vec(),
+                config.admin_tokens.clone(),
+                &config.read_tokens,
+            ),
+            None => (
+                BASE64
+                    .decode(authorization.strip_prefix("Basic ")?.trim())
+                    .ok()?,
+                config
+                    .auth_username
+                    .iter()
+                    .zip(&config.auth_password)
+                    .map(|(username, password)| format!("{}:{}", username, password))
+                    .chain(config.admins.iter().cloned())
+                    .collect(),
+                &config.viewers,
+            ),
+        };
+    // Every credential is checked, so how long it takes doesn't say which one it was.
+    let any_matches = |credentials: &[String]| {
+        return credentials
+            .iter()
+            .fold(false, |found, expected| matches(&given, expected) | found);
+    };
+    let role = match (any_matches(&admins), any_matches(viewers)) {
+        (true, _) => Role::Admin,
+        (false, true) => Role::Viewer,
+        (false, false) => return None,
+    };
+    let given = String::from_utf8_lossy(&given);
+    // Tokens go by their end, which is enough to tell them apart without giving them away.
+    let name = if is_token {
+        let end: String = given.chars().rev().take(4).collect();
+        format!("token …{}", end.chars().rev().collect::<String>())
+    } else {
+        given.split(':').next().unwrap_or_default().to_string()
+    };
+    return Some((role, name));
+}
+
+// Who sent `req`, for the audit log: the name they logged in with, or their address when nobody has to
+// log in.
+pub fn actor(req: &HttpRequest) -> String {
+    if let Some(Name(name)) = req.extensions().get::<Name>() {
+        return name.clone();
+    }
+    return req
+        .peer_addr()
+        .map_or("unknown".to_string(), |address| address.ip().to_string());
+}
+
+// Whether `req` is a browser going to a page, rather than a script or a page's own requests, so can
+// be sent to log in.
+fn wants_page(req: &ServiceRequest) -> bool {
+    return req.method() == Method::GET
+        && req
+            .headers()
+            .get(header::ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .is_some_and(|accept| accept.contains("text/html"));
+}
+
+// Whether whoever sent `req` can change things, so should be shown buttons to. Everyone can when
+// nobody has to log in.
+pub fn is_admin(req: &HttpRequest) -> bool {
+    return !matches!(req.extensions().get::<Role>(), Some(Role::Viewer));
+}
+
+// Middleware that asks for credentials from the config's `[auth]` before answering any request, when
+// there are any: a username and password with HTTP Basic authentication, or a token. With `[oidc]`,
+// browsers are sent to log in with the provider instead. Only admins can make requests that change
+// things (see `needs_admin`).
+pub async fn check(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let config = config::get();
+    if !config.asks_for_credentials()
+        || (config.oidc_issuer.is_some() && oidc::ROUTES.contains(&req.path()))
+    {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    }
+    let response = match role(&req, &config) {
+        None if config.oidc_issuer.is_some() && wants_page(&req) => {
+            oidc::login_redirect(req.request())
+        }
+        None => {
+            let mut response = HttpResponse::Unauthorized();
+            // Only browsers ask for a username and password, scripts send a token.
+            if config.auth_username.is_some()
+                || !config.admins.is_empty()
+                || !config.viewers.is_empty()
+            {
+                response.insert_header((
+                    header::WWW_AUTHENTICATE,
+                    format!("Basic realm=\"{}\", charset=\"UTF-8\"", REALM),
+                ));
+            }
+            response.body("Log in, or send a token, to see the network monitor.")
+        }
+        Some((Role::Viewer, _)) if needs_admin(&req) => {
+            HttpResponse::Forbidden().body("Only admins can do that.")
+        }
+        Some((role, name)) => {
+            req.extensions_mut().insert(role);
+            req.extensions_mut().insert(Name(name));
+            return Ok(next.call(req).await?.map_into_boxed_body());
+        }
+    };
+    return Ok(req.into_response(response));
+}
@@ -0,0 +1,190 @@
+use chrono::Utc;
+
+// A backup is a tar archive of everything needed to set the monitor up again elsewhere, e.g. on a new
+// Raspberry Pi: its history, its config file and the changes made to its targets with the admin API.
+// Each is a file in the archive, so it can be taken apart with `tar` too.
+const SNAPSHOT_FILE: &str = "snapshot.json";
+const CONFIG_FILE: &str = "network-monitor.toml";
+const ADDED_TARGETS_FILE: &str = "added-targets.txt";
+const REMOVED_TARGETS_FILE: &str = "removed-targets.txt";
+
+#[derive(Default)]
+pub struct Backup {
+    // A snapshot, see `snapshot::export`.
+    pub snapshot: Option<String>,
+    // The config file, if the monitor has one.
+    pub config: Option<String>,
+    // Targets added with the admin API by spec, and removed by name.
+    pub added_specs: Vec<String>,
+    pub removed_names: Vec<String>,
+}
+impl Backup {
+    pub fn to_tar(&self) -> Vec<u8> {
+        let mut tar = Vec::new();
+        if let Some(snapshot) = &self.snapshot {
+            write_file(&mut tar, SNAPSHOT_FILE, snapshot.as_bytes());
+        }
+        if let Some(config) = &self.config {
+            write_file(&mut tar, CONFIG_FILE, config.as_bytes());
+        }
+        for (name, lines) in [
+            (ADDED_TARGETS_FILE, &self.added_specs),
+            (REMOVED_TARGETS_FILE, &self.removed_names),
+        ] {
+            let mut text = String::new();
+            for line in lines {
+                text += &format!("{}\n", line);
+            }
+            write_file(&mut tar, name, text.as_bytes());
+        }
+        // Two empty blocks end the archive.
+        tar.resize(tar.len() + 2 * BLOCK_SIZE, 0);
+        return tar;
+    }
+
+    // Reads a backup written by `to_tar`. Files it doesn't know are ignored.
+    pub fn from_tar(tar: &[u8]) -> Result<Backup, String> {
+        let mut backup = Backup::default();
+        let mut pos = 0;
+        while let Some(header) = tar.get(pos..pos + BLOCK_SIZE) {
+            if header.iter().all(|b| *b == 0) {
+                break;
+            }
+            let name = text_field(&header[0..100])?;
+            let size = usize::from_str_radix(text_field(&header[124..136])?.trim(), 8)
+                .map_err(|_err| format!("Invalid size for {} in the backup", name))?;
+            pos += BLOCK_SIZE;
+            let contents = tar
+                .get(pos..pos + size)
+                .ok_or(format!("{} is cut short in the backup", name))?;
+            let contents = String::from_utf8(contents.to_vec())
+                .map_err(|_err| format!("{} in the backup isn't UTF-8", name))?;
+            let lines = || {
+                return contents
+                    .lines()
+                    .filter(|line| !line.is_empty())
+                    .map(str::to_string)
+                    .collect();
+            };
+            match name {
+                SNAPSHOT_FILE => backup.snapshot = Some(contents.clone()),
+                CONFIG_FILE => backup.config = Some(contents.clone()),
+                ADDED_TARGETS_FILE => backup.added_specs = lines(),
+                REMOVED_TARGETS_FILE => backup.removed_names = lines(),
+                _ => {}
+            }
+            pos += size.div_ceil(BLOCK_SIZE) * BLOCK_SIZE;
+        }
+        if backup.snapshot.is_none() && backup.config.is_none() {
+            return Err("That isn't a backup from network-monitor".to_string());
+        }
+        return Ok(backup);
+    }
+}
+
+// Tar archives are made of 512 byte blocks: a header block for each file, followed by its contents
+// padded out to a whole block.
+// https://pubs.opengroup.org/onlinepubs/9699919799/utilities/pax.html#tag_20_92_13_06
+const BLOCK_SIZE: usize = 512;
+
+fn write_file(tar: &mut Vec<u8>, name: &str, contents: &[u8]) {
+    let mut header = [0u8; BLOCK_SIZE];
+    let mut set = |offset: usize, value: &[u8]| {
+        header[offset..offset + value.len()].copy_from_slice(value);
+    };
+    set(0, name.as_bytes());
+    // Numbers are octal, NUL-terminated.
+    set(100, b"0000644\0"); // mode
+    set(108, b"0000000\0"); // uid
+    set(116, b"0000000\0"); // gid
+    set(124, format!("{:011o}\0", contents.len()).as_bytes()); // size
+    set(136, format!("{:011o}\0", Utc::now().timestamp()).as_bytes()); // mtime
+    set(156, b"0"); // typeflag: a regular file
+
+    // The magic and version.
+    set(257, b"ustar\x0000");
+    // The checksum is of the header with the checksum itself as spaces.
+    set(148, b"        ");
+    let checksum: u32 = header.iter().map(|b| *b as u32).sum();
+    header[148..156].copy_from_slice(format!("{:06o}\0 ", checksum).as_bytes());
+
+    tar.extend_from_slice(&header);
+    tar.extend_from_slice(contents);
+    tar.resize(tar.len().div_ceil(BLOCK_SIZE) * BLOCK_SIZE, 0);
+}
+
+// A NUL-padded text field of a header.
+fn text_field(field: &[u8]) -> Result<&str, String> {
+    let len = field.iter().position(|b| *b == 0).unwrap_or(field.len());
+    return std::str::from_utf8(&field[..len])
+        .map_err(|_err| "Invalid file header in the backup".to_string());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backup() -> Backup {
+        return Backup {
+            snapshot: Some("{\"targets\":[]}".to_string()),
+            config: Some("targets = [\"1.1.1.1\"]\n".to_string()),
+            added_specs: vec!["8.8.8.8#burst=5".to_string(), "example.com:443".to_string()],
+            removed_names: vec!["192.168.1.1".to_string()],
+        };
+    }
+
+    #[test]
+    fn writes_ustar_headers() {
+        let tar = backup().to_tar();
+        // 4 files of a header and a block of contents each, then the 2 blocks that end it.
+        assert_eq!(tar.len(), (4 * 2 + 2) * BLOCK_SIZE);
+        let header = &tar[..BLOCK_SIZE];
+        assert_eq!(text_field(&header[0..100]).unwrap(), SNAPSHOT_FILE);
+        assert_eq!(&header[124..136], b"00000000016\0");
+        assert_eq!(header[156], b'0');
+        assert_eq!(&header[257..265], b"ustar\x0000");
+        // The checksum is of the header with the checksum as spaces.
+        let stored = u32::from_str_radix(text_field(&header[148..155]).unwrap(), 8).unwrap();
+        let sum: u32 = header
+            .iter()
+            .enumerate()
+            .map(|(i, b)| if (148..156).contains(&i) { b' ' } else { *b } as u32)
+            .sum();
+        assert_eq!(stored, sum);
+        assert!(tar[tar.len() - 2 * BLOCK_SIZE..].iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn reads_back_what_it_writes() {
+        let read = Backup::from_tar(&backup().to_tar()).unwrap();
+        let expected = backup();
+        assert_eq!(read.snapshot, expected.snapshot);
+        assert_eq!(read.config, expected.config);
+        assert_eq!(read.added_specs, expected.added_specs);
+        assert_eq!(read.removed_names, expected.removed_names);
+    }
+
+    #[test]
+    fn ignores_files_it_does_not_know() {
+        let mut tar = Vec::new();
+        write_file(&mut tar, "README", &[b'x'; BLOCK_SIZE + 1]);
+        write_file(&mut tar, CONFIG_FILE, b"web_ui_port = 8080\n");
+        let read = Backup::from_tar(&tar).unwrap();
+        assert_eq!(read.config.as_deref(), Some("web_ui_port = 8080\n"));
+        assert_eq!(read.snapshot, None);
+    }
+
+    #[test]
+    fn rejects_archives_that_are_not_backups() {
+        let err = |tar: &[u8]| Backup::from_tar(tar).err().unwrap();
+        assert_eq!(err(&[]), "That isn't a backup from network-monitor");
+        let tar = backup().to_tar();
+        assert_eq!(
+            err(&tar[..BLOCK_SIZE + 4]),
+            "snapshot.json is cut short in the backup"
+        );
+        let mut tar = tar;
+        tar[124..136].copy_from_slice(b"0000000001x\0");
+        assert_eq!(err(&tar), "Invalid size for snapshot.json in the backup");
+    }
+}
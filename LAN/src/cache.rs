@@ -0,0 +1,162 @@
+use crate::auth;
+use crate::config;
+use actix_web::body::{BodySize, BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::http::{Method, StatusCode};
+use actix_web::middleware::Next;
+use actix_web::web::{Bytes, BytesMut};
+use actix_web::{Error, HttpResponse};
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::{Mutex, OnceLock};
+use std::task::{ready, Context, Poll};
+use std::time::{Duration, Instant};
+
+// Routes that scan every sample in view to render, so are worth reusing for a few seconds. Streams of
+// samples as they're taken and the admin API never are.
+const CACHED_ROUTES: &[&str] = &[
+    "/",
+    "/summary",
+    "/embed",
+    "/histogram",
+    "/loss",
+    "/heatmap",
+    "/host/{name:.+}",
+    "/api/loss",
+    "/api/hosts/{host:.+}/histogram",
+    "/api/hosts/{host:.+}/aggregate",
+];
+// Bigger responses aren't kept, e.g. the timeline over weeks of samples.
+const MAX_CACHED_BYTES: usize = 16 << 20;
+
+// A response as it was sent.
+struct Cached {
+    sent_at: Instant,
+    content_type: Option<HeaderValue>,
+    body: Bytes,
+}
+
+// Responses by the path and query they were for.
+static CACHE: OnceLock<Mutex<HashMap<String, Cached>>> = OnceLock::new();
+
+fn cache() -> &'static Mutex<HashMap<String, Cached>> {
+    return CACHE.get_or_init(|| Mutex::new(HashMap::new()));
+}
+
+// Middleware that answers requests for `CACHED_ROUTES` with the response to the same request, if it
+// was sent less than `response_cache_msec` ago. Requests that change anything, e.g. pausing a target,
+// drop every response so pages show the change right away.
+pub async fn respond(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    if req.method() != Method::GET && req.method() != Method::HEAD {
+        cache().lock().unwrap().clear();
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    }
+    let ttl = Duration::from_millis(config::get().response_cache_msec);
+    let is_cached_route = req
+        .match_pattern()
+        .is_some_and(|pattern| CACHED_ROUTES.contains(&pattern.as_str()));
+    if ttl.is_zero() || !is_cached_route {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    }
+    // Pages follow the viewer's preferences in their cookies, and only show admins buttons to change
+    // things, so those are part of the key.
+    let cookies = req
+        .headers()
+        .get(header::COOKIE)
+        .and_then(|cookie| cookie.to_str().ok())
+        .unwrap_or_default();
+    let key = format!(
+        "{} {} {}",
+        req.uri(),
+        cookies,
+        auth::is_admin(req.request())
+    );
+    let hit = cache()
+        .lock()
+        .unwrap()
+        .get(&key)
+        .filter(|cached| cached.sent_at.elapsed() < ttl)
+        .map(|cached| {
+            let mut response = HttpResponse::Ok();
+            if let Some(content_type) = &cached.content_type {
+                response.insert_header((header::CONTENT_TYPE, content_type.clone()));
+            }
+            response.insert_header((header::AGE, cached.sent_at.elapsed().as_secs()));
+            return response.body(cached.body.clone());
+        });
+    if let Some(response) = hit {
+        return Ok(req.into_response(response));
+    }
+    let response = next.call(req).await?.map_into_boxed_body();
+    if response.status() != StatusCode::OK {
+        return Ok(response);
+    }
+    let content_type = response.headers().get(header::CONTENT_TYPE).cloned();
+    return Ok(response.map_body(|_, body| {
+        BoxBody::new(Recording {
+            body,
+            key,
+            content_type,
+            ttl,
+            recorded: Some(BytesMut::new()),
+        })
+    }));
+}
+
+// A response body passed through as it's sent, then cached once it's all been sent.
+struct Recording {
+    body: BoxBody,
+    key: String,
+    content_type: Option<HeaderValue>,
+    ttl: Duration,
+    // None once it's too big to keep, or failed.
+    recorded: Option<BytesMut>,
+}
+impl MessageBody for Recording {
+    type Error = Box<dyn std::error::Error>;
+
+    fn size(&self) -> BodySize {
+        return self.body.size();
+    }
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+    ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        let this = self.get_mut();
+        let next = ready!(Pin::new(&mut this.body).poll_next(cx));
+        match &next {
+            Some(Ok(chunk)) => {
+                this.recorded = this
+                    .recorded
+                    .take()
+                    .filter(|recorded| recorded.len() + chunk.len() <= MAX_CACHED_BYTES)
+                    .map(|mut recorded| {
+                        recorded.extend_from_slice(chunk);
+                        return recorded;
+                    });
+            }
+            Some(Err(_)) => this.recorded = None,
+            None => {
+                if let Some(recorded) = this.recorded.take() {
+                    let mut cache = cache().lock().unwrap();
+                    // Drop responses nobody asked for again while they were fresh.
+                    cache.retain(|_, cached| cached.sent_at.elapsed() < this.ttl);
+                    cache.insert(
+                        std::mem::take(&mut this.key),
+                        Cached {
+                            sent_at: Instant::now(),
+                            content_type: this.content_type.take(),
+                            body: recorded.freeze(),
+                        },
+                    );
+                }
+            }
+        }
+        return Poll::Ready(next);
+    }
+}
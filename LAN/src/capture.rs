@@ -0,0 +1,124 @@
+use chrono::Duration as chrono_Duration;
+use chrono::{DateTime, Utc};
+use socket2::Socket;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::mem::MaybeUninit;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::path::Path;
+use std::time::Duration;
+
+// Packets are captured starting from their IPv4 or IPv6 header.
+// https://www.tcpdump.org/linktypes.html
+const LINKTYPE_RAW: u32 = 101;
+// The most of each packet to keep, the largest an IP packet can be.
+const SNAPSHOT_LENGTH: usize = 65_535;
+
+// Captures ICMP and ICMPv6 traffic on `interface` (every interface if None) for `duration`, and writes
+// it to a pcap file at `path` that can be opened with Wireshark or tcpdump.
+// Returns how many packets were captured.
+pub fn capture_icmp(
+    interface: Option<&str>,
+    duration: Duration,
+    path: &Path,
+) -> std::io::Result<usize> {
+    // A link-layer socket that sees every packet, sent or received.
+    // Being a datagram socket, the kernel strips Ethernet headers for us, so packets from every kind
+    // of interface (including tunnels, which don't have them) look the same.
+    let fd = unsafe {
+        libc::socket(
+            libc::AF_PACKET,
+            libc::SOCK_DGRAM,
+            i32::from((libc::ETH_P_ALL as u16).to_be()),
+        )
+    };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let socket = unsafe { Socket::from_raw_fd(fd) };
+    if let Some(interface) = interface {
+        bind_to_interface(&socket, interface)?;
+    }
+    // Wake up regularly, so the capture ends on time even if the network is quiet.
+    socket.set_read_timeout(Some(Duration::from_millis(100)))?;
+
+    let mut pcap = BufWriter::new(File::create(path)?);
+    // The pcap file header.
+    // https://www.ietf.org/archive/id/draft-ietf-opsawg-pcap-04.html#section-4
+    pcap.write_all(&0xA1B2C3D4_u32.to_le_bytes())?; // Magic number, timestamps in microseconds
+    pcap.write_all(&2_u16.to_le_bytes())?; // Major version
+    pcap.write_all(&4_u16.to_le_bytes())?; // Minor version
+    pcap.write_all(&[0; 8])?; // Reserved
+    pcap.write_all(&(SNAPSHOT_LENGTH as u32).to_le_bytes())?;
+    pcap.write_all(&LINKTYPE_RAW.to_le_bytes())?;
+
+    let deadline = Utc::now() + chrono_Duration::from_std(duration).unwrap();
+    let mut recv_buf = vec![MaybeUninit::new(0); SNAPSHOT_LENGTH];
+    let mut num_packets = 0;
+    while Utc::now() < deadline {
+        let size = match socket.recv(&mut recv_buf) {
+            Ok(size) => size,
+            Err(_err) => continue, // Timed out, nothing was sent or received.
+        };
+        let captured_at = Utc::now();
+        // Every element of `recv_buf` was initialized when it was allocated.
+        let packet = &unsafe { recv_buf.assume_init_ref() }[..size];
+        if !is_icmp(packet) {
+            continue;
+        }
+        write_packet(&mut pcap, captured_at, packet)?;
+        num_packets += 1;
+    }
+    pcap.flush()?;
+    return Ok(num_packets);
+}
+
+// Only capture packets on the interface named `interface`.
+fn bind_to_interface(socket: &Socket, interface: &str) -> std::io::Result<()> {
+    let c_name = std::ffi::CString::new(interface).unwrap();
+    let index = unsafe { libc::if_nametoindex(c_name.as_ptr()) };
+    if index == 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let mut bind_addr: libc::sockaddr_ll = unsafe { std::mem::zeroed() };
+    bind_addr.sll_family = libc::AF_PACKET as u16;
+    bind_addr.sll_protocol = (libc::ETH_P_ALL as u16).to_be();
+    bind_addr.sll_ifindex = index as i32;
+    let res = unsafe {
+        libc::bind(
+            socket.as_raw_fd(),
+            &bind_addr as *const libc::sockaddr_ll as *const libc::sockaddr,
+            std::mem::size_of::<libc::sockaddr_ll>().try_into().unwrap(),
+        )
+    };
+    if res != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    return Ok(());
+}
+
+// Whether `packet` (starting with its IP header) is an ICMP or ICMPv6 message.
+fn is_icmp(packet: &[u8]) -> bool {
+    return match packet.first().map(|byte| byte >> 4) {
+        // The Protocol field of the IPv4 header.
+        Some(4) => packet.get(9) == Some(&(libc::IPPROTO_ICMP as u8)),
+        // The Next Header field of the IPv6 header. Our probes don't use extension headers.
+        Some(6) => packet.get(6) == Some(&(libc::IPPROTO_ICMPV6 as u8)),
+        _ => false,
+    };
+}
+
+// Appends a packet record to a pcap file.
+fn write_packet(
+    pcap: &mut impl Write,
+    captured_at: DateTime<Utc>,
+    packet: &[u8],
+) -> std::io::Result<()> {
+    pcap.write_all(&(captured_at.timestamp() as u32).to_le_bytes())?;
+    pcap.write_all(&captured_at.timestamp_subsec_micros().to_le_bytes())?;
+    // How much of the packet was captured, then how big it was. We capture packets whole.
+    pcap.write_all(&(packet.len() as u32).to_le_bytes())?;
+    pcap.write_all(&(packet.len() as u32).to_le_bytes())?;
+    pcap.write_all(packet)?;
+    return Ok(());
+}
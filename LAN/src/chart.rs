@@ -0,0 +1,486 @@
+use crate::i18n;
+use crate::timezone::Zone;
+use chrono::Duration as chrono_Duration;
+use chrono::{DateTime, Offset, Utc};
+use std::fmt::Write;
+
+// The chart's size, it's scaled to fit the host's column.
+const WIDTH: f64 = 600.0;
+const HEIGHT: f64 = 200.0;
+// Room around the plot for the axes' labels.
+const LEFT: f64 = 60.0;
+const RIGHT: f64 = 10.0;
+const TOP: f64 = 10.0;
+const BOTTOM: f64 = 20.0;
+const PLOT_WIDTH: f64 = WIDTH - LEFT - RIGHT;
+const PLOT_HEIGHT: f64 = HEIGHT - TOP - BOTTOM;
+// A sparkline's height, it's as wide as it has columns and stretched to fit.
+const SPARKLINE_HEIGHT: f64 = 30.0;
+// Points are grouped into this many columns across the chart, so a day of samples taken every second
+// doesn't draw tens of thousands of points.
+const NUM_COLUMNS: usize = 300;
+// About how many labels each axis gets.
+const NUM_Y_TICKS: f64 = 4.0;
+const MAX_X_TICKS: i64 = 8;
+// Time between the labels on the time axis, from which the fewest that fit are picked.
+const X_TICK_STEPS_SEC: [i64; 16] = [
+    10, 15, 30, 60, 300, 600, 900, 1800, 3600, 7200, 10800, 21600, 43200, 86400, 172800, 604800,
+];
+
+// A sample, or a summary of several, to chart.
+pub struct Point {
+    pub time: DateTime<Utc>,
+    // How long it stands for: the target's interval for a sample, or a summary's minute or hour.
+    pub period: chrono_Duration,
+    // The average of the successful samples, None if they all failed.
+    pub value: Option<f64>,
+    // The lowest and highest values, e.g. a burst's fastest and slowest round trip.
+    pub spread: Option<(f64, f64)>,
+    pub samples: u32,
+    pub failures: u32,
+}
+
+// What's charted in a column: the points within its slice of time.
+#[derive(Default)]
+struct Column {
+    samples: u32,
+    failures: u32,
+    // When the points in it were taken, and the longest period any of them stands for.
+    first: Option<DateTime<Utc>>,
+    last: Option<DateTime<Utc>>,
+    period: chrono_Duration,
+    // Of the points with a value.
+    total: f64,
+    num_values: u32,
+    low: f64,
+    high: f64,
+}
+impl Column {
+    fn mean(&self) -> Option<f64> {
+        if self.num_values == 0 {
+            return None;
+        }
+        return Some(self.total / self.num_values as f64);
+    }
+}
+
+// Points from an earlier stretch of time to overlay on a chart, moved forward to line up with it, and
+// what to call them, e.g. `a week earlier`.
+pub struct Earlier<'a> {
+    pub points: &'a [Point],
+    pub label: &'a str,
+}
+
+// Charts `points` from `start` to `end` as an SVG line chart, with `unit` after the values on the
+// vertical axis. The line is the average of each column, shaded out to the lowest and highest values
+// like smokeping, and failures shade the background red, darker the more of the column's samples
+// failed. The line breaks at failures, and at gaps longer than the points around them stand for.
+// `earlier` points are overlaid as a dashed grey line, to compare against. Times are labelled in
+// `zone`.
+pub fn svg(
+    points: &[Point],
+    earlier: Option<Earlier>,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    zone: Zone,
+    unit: &str,
+) -> String {
+    let column_width = PLOT_WIDTH / NUM_COLUMNS as f64;
+    let columns = into_columns(points, start, end);
+    let earlier_columns = earlier
+        .as_ref()
+        .map(|earlier| into_columns(earlier.points, start, end));
+
+    let highest = columns
+        .iter()
+        .chain(earlier_columns.iter().flatten())
+        .filter(|column| column.num_values > 0)
+        .map(|column| column.high)
+        .fold(0.0, f64::max);
+    let y_max = top_of_scale(highest);
+    let x = |column: usize| LEFT + (column as f64 + 0.5) * column_width;
+    let y = |value: f64| y_position(value, y_max);
+
+    let mut svg = open_svg();
+    // Failures go behind everything else.
+    for (i, column) in columns.iter().enumerate() {
+        if column.failures > 0 {
+            write!(
+                svg,
+                "<rect x=\"{:.1}\" y=\"{}\" width=\"{:.1}\" height=\"{}\" fill=\"red\" fill-opacity=\"{:.2}\"/>",
+                LEFT + i as f64 * column_width,
+                TOP,
+                column_width,
+                PLOT_HEIGHT,
+                0.15 + 0.35 * column.failures as f64 / column.samples.max(1) as f64
+            )
+            .unwrap();
+        }
+    }
+    write_vertical_axis(&mut svg, y_max, unit);
+    write_horizontal_labels(&mut svg, &time_labels(start, end, zone));
+
+    // The earlier line goes under the current one, without a band so the two don't muddy each other.
+    if let (Some(earlier), Some(earlier_columns)) = (&earlier, &earlier_columns) {
+        for run in runs(earlier_columns) {
+            let line: Vec<String> = run
+                .iter()
+                .map(|&i| format!("{:.1},{:.1}", x(i), y(earlier_columns[i].mean().unwrap())))
+                .collect();
+            write!(
+                svg,
+                "<polyline points=\"{}\" fill=\"none\" stroke=\"grey\" stroke-width=\"1.5\" stroke-dasharray=\"4 3\"/>",
+                line.join(" ")
+            )
+            .unwrap();
+        }
+        write!(
+            svg,
+            "<text x=\"{}\" y=\"{}\" fill=\"grey\">dashed: {}</text>",
+            LEFT + 4.0,
+            TOP + 12.0,
+            earlier.label
+        )
+        .unwrap();
+    }
+
+    // Each run of columns with values is its own band and line.
+    for run in &runs(&columns) {
+        let mut band = String::new();
+        for &i in run {
+            write!(band, "{:.1},{:.1} ", x(i), y(columns[i].high)).unwrap();
+        }
+        for &i in run.iter().rev() {
+            write!(band, "{:.1},{:.1} ", x(i), y(columns[i].low)).unwrap();
+        }
+        write!(
+            svg,
+            "<polygon points=\"{}\" fill=\"steelblue\" fill-opacity=\"0.25\" stroke=\"none\"/>",
+            band.trim_end()
+        )
+        .unwrap();
+        let line: Vec<String> = run
+            .iter()
+            .map(|&i| format!("{:.1},{:.1}", x(i), y(columns[i].mean().unwrap())))
+            .collect();
+        write!(
+            svg,
+            "<polyline points=\"{}\" fill=\"none\" stroke=\"steelblue\" stroke-width=\"1.5\"/>",
+            line.join(" ")
+        )
+        .unwrap();
+        // A lone column would be an invisible line, mark it with a dot.
+        if run.len() == 1 {
+            write!(
+                svg,
+                "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"1.5\" fill=\"steelblue\"/>",
+                x(run[0]),
+                y(columns[run[0]].mean().unwrap())
+            )
+            .unwrap();
+        }
+    }
+    if points.is_empty() {
+        write_message(&mut svg, "no samples in view");
+    }
+    close_svg(&mut svg);
+    return svg;
+}
+
+// Charts `points` from `start` to `end` as a small SVG line without axes or labels, to show at a
+// glance how a target is doing. Failures shade the background red, like `svg`.
+pub fn sparkline(points: &[Point], start: DateTime<Utc>, end: DateTime<Utc>) -> String {
+    let columns = into_columns(points, start, end);
+    let highest = columns
+        .iter()
+        .filter(|column| column.num_values > 0)
+        .map(|column| column.mean().unwrap())
+        .fold(0.0, f64::max);
+    // Columns are a unit wide, and the highest value sits a little below the top.
+    let y = |value: f64| SPARKLINE_HEIGHT * (1.0 - value / (highest * 1.1).max(f64::MIN_POSITIVE));
+    let mut svg = format!(
+        "<svg viewBox=\"0 0 {} {}\" width=\"100%\" height=\"{}\" preserveAspectRatio=\"none\">",
+        NUM_COLUMNS, SPARKLINE_HEIGHT, SPARKLINE_HEIGHT
+    );
+    for (i, column) in columns.iter().enumerate() {
+        if column.failures > 0 {
+            write!(
+                svg,
+                "<rect x=\"{}\" y=\"0\" width=\"1\" height=\"{}\" fill=\"red\" fill-opacity=\"{:.2}\"/>",
+                i,
+                SPARKLINE_HEIGHT,
+                0.15 + 0.35 * column.failures as f64 / column.samples.max(1) as f64
+            )
+            .unwrap();
+        }
+    }
+    for run in runs(&columns) {
+        let line: Vec<String> = run
+            .iter()
+            .map(|&i| format!("{}.5,{:.1}", i, y(columns[i].mean().unwrap())))
+            .collect();
+        write!(
+            svg,
+            "<polyline points=\"{}\" fill=\"none\" stroke=\"steelblue\" stroke-width=\"1.5\" vector-effect=\"non-scaling-stroke\"/>",
+            line.join(" ")
+        )
+        .unwrap();
+        // A lone column would be an invisible line, mark it with a dot (a square, the sparkline is stretched).
+        if run.len() == 1 {
+            write!(
+                svg,
+                "<rect x=\"{}\" y=\"{:.1}\" width=\"2\" height=\"2\" fill=\"steelblue\"/>",
+                run[0].saturating_sub(1),
+                y(columns[run[0]].mean().unwrap()) - 1.0
+            )
+            .unwrap();
+        }
+    }
+    svg += "</svg>";
+    return svg;
+}
+
+// Groups `points` from `start` to `end` into the chart's columns.
+fn into_columns(points: &[Point], start: DateTime<Utc>, end: DateTime<Utc>) -> Vec<Column> {
+    let span = (end - start).num_milliseconds().max(1) as f64;
+    let mut columns: Vec<Column> = (0..NUM_COLUMNS).map(|_| Column::default()).collect();
+    for point in points {
+        let offset = (point.time - start).num_milliseconds() as f64 / span;
+        if !(0.0..=1.0).contains(&offset) {
+            continue;
+        }
+        let column = &mut columns[((offset * NUM_COLUMNS as f64) as usize).min(NUM_COLUMNS - 1)];
+        column.samples += point.samples;
+        column.failures += point.failures;
+        column.first = Some(
+            column
+                .first
+                .map_or(point.time, |first| first.min(point.time)),
+        );
+        column.last = Some(column.last.map_or(point.time, |last| last.max(point.time)));
+        column.period = column.period.max(point.period);
+        if let Some(value) = point.value {
+            let (low, high) = point.spread.unwrap_or((value, value));
+            if column.num_values == 0 {
+                column.low = low;
+                column.high = high;
+            }
+            column.total += value;
+            column.num_values += 1;
+            column.low = column.low.min(low);
+            column.high = column.high.max(high);
+        }
+    }
+    return columns;
+}
+
+// The runs of `columns` with values to draw as one line, broken at failures and at gaps longer than
+// the points around them stand for.
+fn runs(columns: &[Column]) -> Vec<Vec<usize>> {
+    let mut runs: Vec<Vec<usize>> = Vec::new();
+    let mut previous: Option<usize> = None;
+    for (i, column) in columns.iter().enumerate() {
+        if column.samples == 0 {
+            continue;
+        }
+        if column.num_values == 0 {
+            previous = None;
+            continue;
+        }
+        let continues = previous.is_some_and(|previous| {
+            let previous = &columns[previous];
+            column.first.unwrap() - previous.last.unwrap() <= previous.period.max(column.period) * 2
+        });
+        match runs.last_mut() {
+            Some(run) if continues => run.push(i),
+            _ => runs.push(vec![i]),
+        }
+        previous = Some(i);
+    }
+    return runs;
+}
+
+// A bar to chart, from `start` to `end` of the way across (0 to 1), described by `title` when hovered
+// over.
+pub struct Bar {
+    pub start: f64,
+    pub end: f64,
+    pub value: f64,
+    pub color: &'static str,
+    pub title: String,
+}
+
+// Charts `bars` as an SVG bar chart, labelled along the bottom with `labels` (each at a
+// fraction of the way across) and with `unit` after the values on the vertical axis.
+pub fn bars(bars: &[Bar], labels: &[(f64, String)], unit: &str) -> String {
+    let y_max = top_of_scale(bars.iter().map(|bar| bar.value).fold(0.0, f64::max));
+    let mut svg = open_svg();
+    write_vertical_axis(&mut svg, y_max, unit);
+    write_horizontal_labels(&mut svg, labels);
+    for bar in bars.iter().filter(|bar| bar.value > 0.0) {
+        let top = y_position(bar.value, y_max);
+        write!(
+            svg,
+            "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"{}\"><title>{}</title></rect>",
+            LEFT + bar.start * PLOT_WIDTH,
+            top,
+            ((bar.end - bar.start) * PLOT_WIDTH).max(0.5),
+            TOP + PLOT_HEIGHT - top,
+            bar.color,
+            bar.title
+        )
+        .unwrap();
+    }
+    if bars.is_empty() {
+        write_message(&mut svg, "no samples in view");
+    }
+    close_svg(&mut svg);
+    return svg;
+}
+
+// Labels for a time axis from `start` to `end`, at round times in `zone`, each with how far across (0
+// to 1) it goes.
+pub fn time_labels(start: DateTime<Utc>, end: DateTime<Utc>, zone: Zone) -> Vec<(f64, String)> {
+    let span = (end - start).num_milliseconds().max(1) as f64;
+    let span_sec = (end - start).num_seconds().max(1);
+    let step = X_TICK_STEPS_SEC
+        .into_iter()
+        .find(|step| span_sec / step <= MAX_X_TICKS)
+        .unwrap_or(X_TICK_STEPS_SEC[X_TICK_STEPS_SEC.len() - 1]);
+    let utc_offset = zone.at(start).offset().fix().local_minus_utc() as i64;
+    let mut tick = (start.timestamp() + utc_offset).div_euclid(step) * step - utc_offset;
+    if tick < start.timestamp() {
+        tick += step;
+    }
+    let locale = i18n::locale();
+    let twelve_hour = locale.twelve_hour_clock();
+    let mut labels = Vec::new();
+    while tick <= end.timestamp() {
+        let Some(time) = DateTime::<Utc>::from_timestamp(tick, 0) else {
+            break;
+        };
+        // Midnight is labelled with the date, to show where days start.
+        let label = if zone.at(time).format("%H%M%S").to_string() == "000000" {
+            zone.format(time, locale.month_day_format(), "%m-%d")
+        } else if step < 60 {
+            let format = if twelve_hour {
+                "%-I:%M:%S %p"
+            } else {
+                "%H:%M:%S"
+            };
+            zone.format(time, format, "%H:%M:%S")
+        } else {
+            let format = if twelve_hour { "%-I:%M %p" } else { "%H:%M" };
+            zone.format(time, format, "%H:%M")
+        };
+        labels.push(((time - start).num_milliseconds() as f64 / span, label));
+        tick += step;
+    }
+    return labels;
+}
+
+// The top of the vertical axis for values up to `highest`, rounded up to a round number so the labels
+// are too.
+fn top_of_scale(highest: f64) -> f64 {
+    let step = round_step(highest / NUM_Y_TICKS);
+    return ((highest / step).ceil() * step).max(step);
+}
+
+fn y_position(value: f64, y_max: f64) -> f64 {
+    return TOP + PLOT_HEIGHT * (1.0 - value / y_max);
+}
+
+fn open_svg() -> String {
+    return format!(
+        "<svg viewBox=\"0 0 {} {}\" width=\"100%\" font-size=\"11\" font-family=\"sans-serif\">",
+        WIDTH, HEIGHT
+    );
+}
+
+// Frames the plot, and ends the chart.
+fn close_svg(svg: &mut String) {
+    write!(
+        svg,
+        "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"none\" stroke=\"grey\"/></svg>",
+        LEFT, TOP, PLOT_WIDTH, PLOT_HEIGHT
+    )
+    .unwrap();
+}
+
+// Labels the vertical axis from 0 to `y_max`, with gridlines across the plot.
+fn write_vertical_axis(svg: &mut String, y_max: f64, unit: &str) {
+    let step = round_step(y_max / NUM_Y_TICKS);
+    let mut value = 0.0;
+    while value <= y_max + step / 2.0 {
+        write!(
+            svg,
+            "<line x1=\"{}\" y1=\"{:.1}\" x2=\"{}\" y2=\"{:.1}\" stroke=\"lightgrey\"/><text x=\"{}\" y=\"{:.1}\" text-anchor=\"end\" dominant-baseline=\"middle\" fill=\"grey\">{}{}{}</text>",
+            LEFT,
+            y_position(value, y_max),
+            WIDTH - RIGHT,
+            y_position(value, y_max),
+            LEFT - 4.0,
+            y_position(value, y_max),
+            format_number(value),
+            // e.g. `20 ms`, but `20%` or just `20`.
+            if unit.is_empty() || unit == "%" { "" } else { " " },
+            unit
+        )
+        .unwrap();
+        value += step;
+    }
+}
+
+// Labels the horizontal axis, each label at a fraction of the way across, with gridlines up the plot.
+fn write_horizontal_labels(svg: &mut String, labels: &[(f64, String)]) {
+    for (position, label) in labels {
+        let x = LEFT + PLOT_WIDTH * position;
+        write!(
+            svg,
+            "<line x1=\"{:.1}\" y1=\"{}\" x2=\"{:.1}\" y2=\"{}\" stroke=\"lightgrey\"/><text x=\"{:.1}\" y=\"{}\" text-anchor=\"middle\" fill=\"grey\">{}</text>",
+            x,
+            TOP,
+            x,
+            HEIGHT - BOTTOM,
+            x,
+            HEIGHT - 5.0,
+            label
+        )
+        .unwrap();
+    }
+}
+
+// Writes `message` across the middle of the plot, e.g. when there's nothing to chart.
+fn write_message(svg: &mut String, message: &str) {
+    write!(
+        svg,
+        "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\" fill=\"grey\">{}</text>",
+        LEFT + PLOT_WIDTH / 2.0,
+        TOP + PLOT_HEIGHT / 2.0,
+        message
+    )
+    .unwrap();
+}
+
+// The smallest of 1, 2 or 5 times a power of ten that's at least `step`.
+pub fn round_step(step: f64) -> f64 {
+    if step <= 0.0 || !step.is_finite() {
+        return 1.0;
+    }
+    let magnitude = 10_f64.powf(step.log10().floor());
+    for multiple in [1.0, 2.0, 5.0, 10.0] {
+        if multiple * magnitude >= step {
+            return multiple * magnitude;
+        }
+    }
+    return 10.0 * magnitude;
+}
+
+// Formats an axis label without trailing zeros, e.g. `0.5` or `20` (`0,5` with a decimal comma).
+pub fn format_number(value: f64) -> String {
+    let formatted = i18n::locale().number(value, 3);
+    return formatted
+        .trim_end_matches('0')
+        .trim_end_matches(['.', ','])
+        .to_string();
+}
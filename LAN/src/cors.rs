@@ -0,0 +1,146 @@
+use crate::config;
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderMap, HeaderValue};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpResponse};
+
+// Cross-origin resource sharing, so pages on the sites in `cors.origins` (e.g. a dashboard of
+// someone's own) can use the JSON API from the browser.
+// https://fetch.spec.whatwg.org/#http-cors-protocol
+const API_PREFIX: &str = "/api/";
+// Headers other sites' pages can send: a token, and the type of what's posted.
+const ALLOWED_HEADERS: &str = "Authorization, Content-Type";
+// How long browsers can remember the answer to a preflight request.
+const PREFLIGHT_MAX_AGE_SEC: u32 = 10 * 60;
+
+// Middleware that lets the config's `cors.origins` use the API with `cors.methods`. Preflight
+// requests are answered here, before asking for credentials, since browsers don't send any with them.
+pub async fn respond(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let config = config::get();
+    if is_cross_site(&req, &config.cors_origins) {
+        let response = HttpResponse::Forbidden().body("Other sites' pages can't change anything.");
+        return Ok(req.into_response(response));
+    }
+    if config.cors_origins.is_empty() || !req.path().starts_with(API_PREFIX) {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    }
+    let origin = req
+        .headers()
+        .get(header::ORIGIN)
+        .and_then(|origin| origin.to_str().ok())
+        .filter(|origin| {
+            config
+                .cors_origins
+                .iter()
+                .any(|allowed| allowed == "*" || allowed == origin)
+        })
+        .map(str::to_string);
+    let is_preflight = req.method() == Method::OPTIONS
+        && req
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_REQUEST_METHOD);
+    if is_preflight {
+        let mut response = HttpResponse::NoContent();
+        response.insert_header((header::VARY, "Origin"));
+        if let Some(origin) = &origin {
+            response
+                .insert_header((
+                    header::ACCESS_CONTROL_ALLOW_METHODS,
+                    config.cors_methods.join(", "),
+                ))
+                .insert_header((header::ACCESS_CONTROL_ALLOW_HEADERS, ALLOWED_HEADERS))
+                .insert_header((header::ACCESS_CONTROL_MAX_AGE, PREFLIGHT_MAX_AGE_SEC));
+            let mut preflight = response.finish();
+            allow(preflight.headers_mut(), origin, &config.cors_origins);
+            return Ok(req.into_response(preflight));
+        }
+        return Ok(req.into_response(response.finish()));
+    }
+    let allowed_method = config
+        .cors_methods
+        .iter()
+        .any(|method| method == req.method().as_str());
+    let mut response = next.call(req).await?.map_into_boxed_body();
+    let headers = response.headers_mut();
+    headers.append(header::VARY, HeaderValue::from_static("Origin"));
+    if let Some(origin) = origin.filter(|_origin| allowed_method) {
+        allow(headers, &origin, &config.cors_origins);
+    }
+    return Ok(response);
+}
+
+// Whether `req` would change something, and came from a page on another site than this one or those
+// in `allowed_origins`. Browsers send the credentials they have for us (e.g. a Basic password) with
+// other sites' form posts too, so those are turned away by their `Origin`. Being allowed with `*`
+// isn't enough, since that's only meant to let any site read. Scripts don't send an `Origin`.
+fn is_cross_site(req: &ServiceRequest, allowed_origins: &[String]) -> bool {
+    if [Method::GET, Method::HEAD, Method::OPTIONS].contains(req.method()) {
+        return false;
+    }
+    let Some(origin) = req.headers().get(header::ORIGIN) else {
+        return false;
+    };
+    let Ok(origin) = origin.to_str() else {
+        return true;
+    };
+    // Only the host is compared, since behind a reverse proxy we may not know the scheme.
+    let host = origin.split_once("://").map(|(_scheme, host)| host);
+    if host.is_some_and(|host| host.eq_ignore_ascii_case(req.connection_info().host())) {
+        return false;
+    }
+    return !allowed_origins.iter().any(|allowed| allowed == origin);
+}
+
+// Lets `origin` read the response. Sites listed by name are trusted to send the viewer's credentials
+// too (e.g. their session cookie), but not every site when any is allowed.
+fn allow(headers: &mut HeaderMap, origin: &str, allowed_origins: &[String]) {
+    if allowed_origins.iter().any(|allowed| allowed == origin) {
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_ORIGIN,
+            HeaderValue::from_str(origin).unwrap(),
+        );
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+            HeaderValue::from_static("true"),
+        );
+    } else {
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_ORIGIN,
+            HeaderValue::from_static("*"),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn request(method: Method, origin: Option<&str>) -> ServiceRequest {
+        let mut request = TestRequest::default()
+            .method(method)
+            .uri("/api/targets/remove")
+            .insert_header((header::HOST, "monitor.lan:8180"));
+        if let Some(origin) = origin {
+            request = request.insert_header((header::ORIGIN, origin));
+        }
+        return request.to_srv_request();
+    }
+
+    #[test]
+    fn turns_away_changes_from_other_sites() {
+        let allowed = ["https://dash.example".to_string(), "*".to_string()];
+        let cross_site = |method, origin| is_cross_site(&request(method, origin), &allowed);
+        assert!(cross_site(Method::POST, Some("https://evil.example")));
+        assert!(cross_site(Method::DELETE, Some("null")));
+        assert!(!cross_site(Method::POST, Some("http://monitor.lan:8180")));
+        assert!(!cross_site(Method::POST, Some("https://dash.example")));
+        assert!(!cross_site(Method::POST, None));
+        assert!(!cross_site(Method::GET, Some("https://evil.example")));
+    }
+}
@@ -0,0 +1,452 @@
+use crate::config;
+use chrono::Duration as chrono_Duration;
+use chrono::{DateTime, Utc};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::collections::HashMap;
+use std::io::Read;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::sync::{Mutex, Once};
+use std::thread;
+use std::time::Duration;
+
+// https://www.rfc-editor.org/rfc/rfc6762
+const MDNS_ADDR: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+const MDNS_PORT: u16 = 5353;
+// https://datatracker.ietf.org/doc/html/draft-cai-ssdp-v1-03
+const SSDP_ADDR: Ipv4Addr = Ipv4Addr::new(239, 255, 255, 250);
+const SSDP_PORT: u16 = 1900;
+
+// DNS record types.
+// https://www.rfc-editor.org/rfc/rfc1035#section-3.2.2
+pub const DNS_TYPE_A: u16 = 1;
+const DNS_TYPE_PTR: u16 = 12;
+const DNS_TYPE_AAAA: u16 = 28;
+const DNS_TYPE_SRV: u16 = 33;
+const DNS_CLASS_IN: u16 = 1;
+
+// Service types commonly advertised by home devices, asking for these gets most devices to
+// announce themselves.
+const MDNS_SERVICE_TYPES: [&str; 10] = [
+    "_device-info._tcp.local",
+    "_workstation._tcp.local",
+    "_http._tcp.local",
+    "_ipp._tcp.local",
+    "_printer._tcp.local",
+    "_googlecast._tcp.local",
+    "_airplay._tcp.local",
+    "_raop._tcp.local",
+    "_hap._tcp.local",
+    "_smb._tcp.local",
+];
+
+const SSDP_SEARCH: &str = "M-SEARCH * HTTP/1.1\r\n\
+    HOST: 239.255.255.250:1900\r\n\
+    MAN: \"ssdp:discover\"\r\n\
+    MX: 1\r\n\
+    ST: ssdp:all\r\n\
+    \r\n";
+
+// A device found on the local network.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Device {
+    // The device's friendly name (e.g. "Living Room TV") or mDNS hostname (e.g. "printer.local").
+    pub name: String,
+    pub ip: IpAddr,
+    // Which protocol the device was found with.
+    pub found_via: &'static str,
+    // What the device says about itself, e.g. the service it advertises.
+    pub details: String,
+}
+
+// Every device found so far, whether by listening or asking.
+static DEVICES: Mutex<Vec<Device>> = Mutex::new(Vec::new());
+static START_LISTENING: Once = Once::new();
+
+fn add_device(device: Device) {
+    let mut devices = DEVICES.lock().unwrap();
+    if !devices
+        .iter()
+        .any(|known| known.name == device.name && known.ip == device.ip)
+    {
+        println!(
+            "Discovered {} (IP: {}) via {}",
+            device.name, device.ip, device.found_via
+        );
+        devices.push(device);
+    }
+}
+
+// Asks devices on the local network to identify themselves, waits for them to answer, and returns
+// every device found so far.
+pub fn discover() -> Vec<Device> {
+    start_listening();
+    let timeout = Duration::from_millis(config::get().discovery_timeout_msec);
+    let mdns_questions: Vec<(&str, u16)> = MDNS_SERVICE_TYPES
+        .iter()
+        .map(|service_type| (*service_type, DNS_TYPE_PTR))
+        .collect();
+    let mdns_thread = thread::spawn(move || query_mdns(&mdns_questions, timeout));
+    let ssdp_thread = thread::spawn(move || search_ssdp(timeout));
+    for device in mdns_thread.join().unwrap() {
+        add_device(device);
+    }
+    for device in ssdp_thread.join().unwrap() {
+        add_device(device);
+    }
+    let mut devices = DEVICES.lock().unwrap().clone();
+    devices.sort_by_key(|device| device.name.to_lowercase());
+    return devices;
+}
+
+// Finds the address of a device by its friendly name or mDNS hostname, for hostnames DNS can't
+// resolve.
+pub fn lookup(name: &str) -> Option<IpAddr> {
+    let find = |devices: &[Device]| {
+        return devices
+            .iter()
+            .find(|device| device.name.eq_ignore_ascii_case(name))
+            .map(|device| device.ip);
+    };
+    start_listening();
+    if let Some(ip) = find(&DEVICES.lock().unwrap()) {
+        return Some(ip);
+    }
+    // Ask for `.local` hostnames directly, most devices answer for their own hostname.
+    if name.to_lowercase().ends_with(".local") {
+        let timeout = Duration::from_millis(config::get().discovery_timeout_msec);
+        for device in query_mdns(&[(name, DNS_TYPE_A)], timeout) {
+            add_device(device);
+        }
+        if let Some(ip) = find(&DEVICES.lock().unwrap()) {
+            return Some(ip);
+        }
+    }
+    return find(&discover());
+}
+
+// Reads the IPv4 default gateway (usually the router) from the kernel's routing table, preferring
+// the route with the lowest metric when there's more than one.
+pub fn default_gateway() -> Option<Ipv4Addr> {
+    let routes = std::fs::read_to_string("/proc/net/route").ok()?;
+    return routes
+        .lines()
+        .skip(1) // Skip the column headings.
+        .filter_map(|route| {
+            let fields: Vec<&str> = route.split_whitespace().collect();
+            // Iface, Destination, Gateway, Flags, RefCnt, Use, Metric, ...
+            let destination = u32::from_str_radix(fields.get(1)?, 16).ok()?;
+            let gateway = u32::from_str_radix(fields.get(2)?, 16).ok()?;
+            let flags = u16::from_str_radix(fields.get(3)?, 16).ok()?;
+            let metric: u32 = fields.get(6)?.parse().ok()?;
+            if destination != 0 || flags & libc::RTF_GATEWAY == 0 {
+                return None;
+            }
+            // Addresses are in network byte order, printed as a number in host byte order.
+            return Some((metric, Ipv4Addr::from(u32::from_be(gateway))));
+        })
+        .min_by_key(|(metric, _)| *metric)
+        .map(|(_, gateway)| gateway);
+}
+
+// Opens a UDP socket that recvs everything sent to a multicast group's port, alongside any other
+// programs (e.g. Avahi) listening to the same group.
+fn multicast_listener(group: Ipv4Addr, port: u16) -> std::io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    socket.set_reuse_port(true)?;
+    socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, port)).into())?;
+    socket.join_multicast_v4(&group, &Ipv4Addr::UNSPECIFIED)?;
+    return Ok(socket.into());
+}
+
+// Listens for devices announcing themselves, in the background. Only starts the first time
+// discovery is used, so the monitor doesn't join multicast groups unless it's asked to.
+fn start_listening() {
+    START_LISTENING.call_once(|| {
+        for (group, port) in [(MDNS_ADDR, MDNS_PORT), (SSDP_ADDR, SSDP_PORT)] {
+            let socket = match multicast_listener(group, port) {
+                Ok(socket) => socket,
+                Err(err) => {
+                    // Discovery still works by asking, this just means we won't hear announcements.
+                    eprintln!(
+                        "Failed to listen for announcements on {}:{} - {}",
+                        group, port, err
+                    );
+                    continue;
+                }
+            };
+            thread::spawn(move || loop {
+                let mut buf = [0; 9000];
+                let Ok((size, origin_addr)) = socket.recv_from(&mut buf) else {
+                    continue;
+                };
+                let devices = if port == MDNS_PORT {
+                    parse_mdns_response(&buf[..size])
+                } else {
+                    parse_ssdp_message(&buf[..size], origin_addr.ip())
+                        .into_iter()
+                        .collect()
+                };
+                for device in devices {
+                    add_device(device);
+                }
+            });
+        }
+    });
+}
+
+// Sends an mDNS query with `questions` (name, record type) and collects devices from the answers.
+// Queries are sent from an ephemeral port, which tells responders to answer us directly.
+// https://www.rfc-editor.org/rfc/rfc6762#section-6.7
+fn query_mdns(questions: &[(&str, u16)], timeout: Duration) -> Vec<Device> {
+    let mut devices = Vec::new();
+    let socket = match UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)) {
+        Ok(socket) => socket,
+        Err(err) => {
+            eprintln!("Failed to open an mDNS socket - {}", err);
+            return devices;
+        }
+    };
+    let query = build_dns_query(questions);
+    if let Err(err) = socket.send_to(&query, SocketAddrV4::new(MDNS_ADDR, MDNS_PORT)) {
+        eprintln!("Error while sending an mDNS query - {}", err);
+        return devices;
+    }
+    let deadline = Utc::now() + chrono_Duration::from_std(timeout).unwrap();
+    while let Some(remaining) = time_until(deadline) {
+        socket.set_read_timeout(Some(remaining)).unwrap();
+        let mut buf = [0; 9000];
+        let Ok(size) = socket.recv(&mut buf) else {
+            break; // Timed out.
+        };
+        devices.extend(parse_mdns_response(&buf[..size]));
+    }
+    return devices;
+}
+
+// How long until `deadline`, None once it's passed.
+fn time_until(deadline: DateTime<Utc>) -> Option<Duration> {
+    return (deadline - Utc::now())
+        .to_std()
+        .ok()
+        .filter(|remaining| !remaining.is_zero());
+}
+
+// Builds a DNS query message asking `questions`.
+// https://www.rfc-editor.org/rfc/rfc1035#section-4.1
+pub fn build_dns_query(questions: &[(&str, u16)]) -> Vec<u8> {
+    // mDNS queries use an ID and flags of 0.
+    let mut query = vec![0, 0, 0, 0];
+    query.extend_from_slice(&(questions.len() as u16).to_be_bytes());
+    query.extend_from_slice(&[0; 6]); // No answer, authority or additional records.
+    for (name, record_type) in questions {
+        for label in name.split('.').filter(|label| !label.is_empty()) {
+            query.push(label.len() as u8);
+            query.extend_from_slice(label.as_bytes());
+        }
+        query.push(0);
+        query.extend_from_slice(&record_type.to_be_bytes());
+        query.extend_from_slice(&DNS_CLASS_IN.to_be_bytes());
+    }
+    return query;
+}
+
+// Reads the (possibly compressed) name at `offset` in a DNS message.
+// Returns its labels, and the offset just past the name.
+// https://www.rfc-editor.org/rfc/rfc1035#section-4.1.4
+fn read_dns_name(message: &[u8], offset: usize) -> Option<(Vec<String>, usize)> {
+    let mut labels = Vec::new();
+    let mut pos = offset;
+    let mut end_of_name = None;
+    // Bound how many pointers we follow, so a malicious message can't loop forever.
+    for _ in 0..128 {
+        let len = *message.get(pos)?;
+        match len {
+            0 => return Some((labels, end_of_name.unwrap_or(pos + 1))),
+            len if len & 0xC0 == 0xC0 => {
+                let pointer = usize::from(u16::from_be_bytes([len & 0x3F, *message.get(pos + 1)?]));
+                end_of_name.get_or_insert(pos + 2);
+                pos = pointer;
+            }
+            len => {
+                let label = message.get(pos + 1..pos + 1 + usize::from(len))?;
+                labels.push(String::from_utf8_lossy(label).to_string());
+                pos += 1 + usize::from(len);
+            }
+        }
+    }
+    return None;
+}
+
+// Collects devices from the records in an mDNS response.
+// Service instances (e.g. "Living Room TV._googlecast._tcp.local") point at a hostname with an SRV
+// record, and hostnames point at addresses with A/AAAA records.
+fn parse_mdns_response(message: &[u8]) -> Vec<Device> {
+    let mut devices = Vec::new();
+    // Only look at responses.
+    if message.len() < 12 || message[2] & 0x80 == 0 {
+        return devices;
+    }
+    let count =
+        |offset: usize| usize::from(u16::from_be_bytes([message[offset], message[offset + 1]]));
+    let num_questions = count(4);
+    let num_records = count(6) + count(8) + count(10);
+    let mut pos = 12;
+    for _ in 0..num_questions {
+        let Some((_, end_of_name)) = read_dns_name(message, pos) else {
+            return devices;
+        };
+        pos = end_of_name + 4; // Skip the type and class.
+    }
+    // Service instance name -> (service type, hostname)
+    let mut instances: HashMap<String, (String, String)> = HashMap::new();
+    // Hostname -> addresses
+    let mut addresses: HashMap<String, Vec<IpAddr>> = HashMap::new();
+    for _ in 0..num_records {
+        let Some((labels, end_of_name)) = read_dns_name(message, pos) else {
+            break;
+        };
+        let Some(header) = message.get(end_of_name..end_of_name + 10) else {
+            break;
+        };
+        let record_type = u16::from_be_bytes([header[0], header[1]]);
+        let data_len = usize::from(u16::from_be_bytes([header[8], header[9]]));
+        let data_start = end_of_name + 10;
+        let Some(data) = message.get(data_start..data_start + data_len) else {
+            break;
+        };
+        let name = labels.join(".").to_lowercase();
+        match record_type {
+            DNS_TYPE_A if data.len() == 4 => {
+                let ip = IpAddr::from(<[u8; 4]>::try_from(data).unwrap());
+                addresses.entry(name).or_default().push(ip);
+            }
+            DNS_TYPE_AAAA if data.len() == 16 => {
+                let ip = IpAddr::from(<[u8; 16]>::try_from(data).unwrap());
+                addresses.entry(name).or_default().push(ip);
+            }
+            // The SRV record's target comes after the 2B priority, 2B weight and 2B port.
+            DNS_TYPE_SRV if data.len() > 6 => {
+                if let Some((target_labels, _)) = read_dns_name(message, data_start + 6) {
+                    let service_type = labels.get(1..).unwrap_or_default().join(".");
+                    let instance = labels.first().cloned().unwrap_or_default();
+                    instances.insert(
+                        instance,
+                        (service_type, target_labels.join(".").to_lowercase()),
+                    );
+                }
+            }
+            _ => {}
+        }
+        pos = data_start + data_len;
+    }
+    for (hostname, ips) in &addresses {
+        for ip in ips {
+            devices.push(Device {
+                name: hostname.clone(),
+                ip: *ip,
+                found_via: "mDNS",
+                details: "".to_string(),
+            });
+        }
+    }
+    for (instance, (service_type, hostname)) in instances {
+        for ip in addresses.get(&hostname).into_iter().flatten() {
+            devices.push(Device {
+                name: instance.clone(),
+                ip: *ip,
+                found_via: "mDNS",
+                details: format!("{} on {}", service_type, hostname),
+            });
+        }
+    }
+    return devices;
+}
+
+// Sends an SSDP search and collects devices from the responses.
+fn search_ssdp(timeout: Duration) -> Vec<Device> {
+    let mut devices = Vec::new();
+    let socket = match UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)) {
+        Ok(socket) => socket,
+        Err(err) => {
+            eprintln!("Failed to open an SSDP socket - {}", err);
+            return devices;
+        }
+    };
+    if let Err(err) = socket.send_to(
+        SSDP_SEARCH.as_bytes(),
+        SocketAddrV4::new(SSDP_ADDR, SSDP_PORT),
+    ) {
+        eprintln!("Error while sending an SSDP search - {}", err);
+        return devices;
+    }
+    let deadline = Utc::now() + chrono_Duration::from_std(timeout).unwrap();
+    while let Some(remaining) = time_until(deadline) {
+        socket.set_read_timeout(Some(remaining)).unwrap();
+        let mut buf = [0; 9000];
+        let Ok((size, origin_addr)) = socket.recv_from(&mut buf) else {
+            break; // Timed out.
+        };
+        devices.extend(parse_ssdp_message(&buf[..size], origin_addr.ip()));
+    }
+    return devices;
+}
+
+// Parses an SSDP search response or NOTIFY announcement, which are HTTP-like messages.
+// Devices describe themselves (including a friendly name) in an XML document at LOCATION.
+fn parse_ssdp_message(message: &[u8], origin_ip: IpAddr) -> Option<Device> {
+    let message = String::from_utf8_lossy(message);
+    let headers: HashMap<String, String> = message
+        .lines()
+        .skip(1)
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_uppercase(), value.trim().to_string()))
+        .collect();
+    // Devices announce they're leaving with `NTS: ssdp:byebye`.
+    if headers.get("NTS").is_some_and(|nts| nts == "ssdp:byebye") {
+        return None;
+    }
+    let location = headers.get("LOCATION")?;
+    let name = friendly_name(location).unwrap_or(origin_ip.to_string());
+    return Some(Device {
+        name,
+        ip: origin_ip,
+        found_via: "SSDP",
+        details: headers.get("SERVER").cloned().unwrap_or_default(),
+    });
+}
+
+// Fetches a device's UPnP description and reads its friendly name.
+// Devices repeat their announcements, so remember what each description said.
+fn friendly_name(location: &str) -> Option<String> {
+    static FRIENDLY_NAMES: Mutex<Option<HashMap<String, Option<String>>>> = Mutex::new(None);
+    if let Some(name) = FRIENDLY_NAMES
+        .lock()
+        .unwrap()
+        .get_or_insert_with(HashMap::new)
+        .get(location)
+    {
+        return name.clone();
+    }
+    let name = ureq::get(location)
+        .timeout(Duration::from_millis(config::get().discovery_timeout_msec))
+        .call()
+        .ok()
+        .and_then(|response| {
+            let mut description = String::new();
+            response
+                .into_reader()
+                .take(64 * 1024)
+                .read_to_string(&mut description)
+                .ok()?;
+            let start = description.find("<friendlyName>")? + "<friendlyName>".len();
+            let len = description[start..].find("</friendlyName>")?;
+            return Some(description[start..start + len].trim().to_string());
+        });
+    FRIENDLY_NAMES
+        .lock()
+        .unwrap()
+        .get_or_insert_with(HashMap::new)
+        .insert(location.to_string(), name.clone());
+    return name;
+}
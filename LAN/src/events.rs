@@ -0,0 +1,243 @@
+use crate::probes;
+use crate::storage::{TargetSample, TimedSample};
+use actix_http::ws::{CloseCode, OpCode, Parser};
+use actix_web::body::{BodySize, MessageBody};
+use actix_web::web::{self, Bytes, BytesMut};
+use chrono::{DateTime, Utc};
+use futures_core::Stream;
+use std::convert::Infallible;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+// How many samples a slow subscriber can fall behind by before it misses some.
+const CHANNEL_CAPACITY: usize = 1024;
+// How often to send something while no samples are taken, so proxies don't close idle connections.
+const KEEPALIVE_SEC: u64 = 15;
+// The most a WebSocket client can send in a frame. They've nothing to send but pings and closes.
+const MAX_CLIENT_FRAME_BYTES: usize = 64 * 1024;
+
+// A sample, as it's taken.
+pub struct Event {
+    pub target: String,
+    // The sample as in `/api/samples`, with the target it's for as `target`.
+    pub json: String,
+}
+
+static CHANNEL: OnceLock<broadcast::Sender<Arc<Event>>> = OnceLock::new();
+
+fn channel() -> &'static broadcast::Sender<Arc<Event>> {
+    return CHANNEL.get_or_init(|| broadcast::channel(CHANNEL_CAPACITY).0);
+}
+
+// Passes a sample that was just taken to every subscriber.
+pub fn publish(target: &str, when: DateTime<Utc>, sample: &probes::Sample) {
+    let channel = channel();
+    // Nobody's listening most of the time, don't bother formatting it.
+    if channel.receiver_count() == 0 {
+        return;
+    }
+    let sample = TargetSample {
+        target,
+        sample: TimedSample::new(when, sample),
+    };
+    let _ = channel.send(Arc::new(Event {
+        target: target.to_string(),
+        json: serde_json::to_string(&sample).unwrap(),
+    }));
+}
+
+type Next = (
+    broadcast::Receiver<Arc<Event>>,
+    Option<Result<Arc<Event>, RecvError>>,
+);
+
+// Samples as they're taken, from when it's created. With a target, only that target's samples.
+pub struct Subscription {
+    target: Option<String>,
+    // Waits for the next sample, or for the keepalive interval to pass (None).
+    next: Pin<Box<dyn Future<Output = Next>>>,
+}
+impl Subscription {
+    pub fn new(target: Option<String>) -> Subscription {
+        return Subscription {
+            target,
+            next: Self::wait(channel().subscribe()),
+        };
+    }
+
+    fn wait(mut receiver: broadcast::Receiver<Arc<Event>>) -> Pin<Box<dyn Future<Output = Next>>> {
+        return Box::pin(async move {
+            let next = tokio::time::timeout(Duration::from_secs(KEEPALIVE_SEC), receiver.recv())
+                .await
+                .ok();
+            return (receiver, next);
+        });
+    }
+
+    // The next sample for the subscriber, Some(None) when it's time for a keepalive, or None if
+    // there won't be any more.
+    pub fn poll_event(&mut self, cx: &mut Context) -> Poll<Option<Option<Arc<Event>>>> {
+        loop {
+            let Poll::Ready((receiver, next)) = self.next.as_mut().poll(cx) else {
+                return Poll::Pending;
+            };
+            self.next = Self::wait(receiver);
+            match next {
+                None => return Poll::Ready(Some(None)),
+                Some(Ok(event)) => {
+                    if self
+                        .target
+                        .as_ref()
+                        .is_none_or(|target| *target == event.target)
+                    {
+                        return Poll::Ready(Some(Some(event)));
+                    }
+                }
+                // Samples missed by falling behind are skipped.
+                Some(Err(RecvError::Lagged(_))) => {}
+                Some(Err(RecvError::Closed)) => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+// Samples as Server-Sent Events, each a `sample` event with the sample as JSON as its data.
+// https://html.spec.whatwg.org/multipage/server-sent-events.html
+pub struct EventStream {
+    subscription: Subscription,
+}
+impl EventStream {
+    pub fn new(target: Option<String>) -> EventStream {
+        return EventStream {
+            subscription: Subscription::new(target),
+        };
+    }
+}
+impl MessageBody for EventStream {
+    type Error = Infallible;
+
+    fn size(&self) -> BodySize {
+        return BodySize::Stream;
+    }
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+    ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        return self.get_mut().subscription.poll_event(cx).map(|event| {
+            let message = match event? {
+                Some(event) => format!("event: sample\ndata: {}\n\n", event.json),
+                // Lines starting with a colon are comments, which clients ignore.
+                None => ":\n\n".to_string(),
+            };
+            return Some(Ok(Bytes::from(message)));
+        });
+    }
+}
+
+// Samples over a WebSocket, each a text message with the sample as JSON. The client can't send
+// anything but pings, which are answered, and a close. Idle connections are kept alive with pings.
+// https://www.rfc-editor.org/rfc/rfc6455
+pub struct WebSocketStream {
+    subscription: Subscription,
+    // What the client sends, and what's been received of its frames so far.
+    payload: web::Payload,
+    received: BytesMut,
+    closed: bool,
+}
+impl WebSocketStream {
+    pub fn new(target: Option<String>, payload: web::Payload) -> WebSocketStream {
+        return WebSocketStream {
+            subscription: Subscription::new(target),
+            payload,
+            received: BytesMut::new(),
+            closed: false,
+        };
+    }
+
+    // Frames answering what the client has sent, if anything.
+    fn poll_replies(&mut self, cx: &mut Context) -> Option<BytesMut> {
+        let mut replies = BytesMut::new();
+        loop {
+            match Pin::new(&mut self.payload).poll_next(cx) {
+                Poll::Ready(Some(Ok(bytes))) => self.received.extend_from_slice(&bytes),
+                // The client's gone.
+                Poll::Ready(Some(Err(_))) | Poll::Ready(None) => {
+                    self.closed = true;
+                    return None;
+                }
+                Poll::Pending => break,
+            }
+        }
+        while !self.closed {
+            match Parser::parse(&mut self.received, true, MAX_CLIENT_FRAME_BYTES) {
+                Ok(None) => break,
+                Ok(Some((_, OpCode::Ping, payload))) => Parser::write_message(
+                    &mut replies,
+                    payload.unwrap_or_default(),
+                    OpCode::Pong,
+                    true,
+                    false,
+                ),
+                // Closing is a handshake, the client's close is echoed back before hanging up.
+                Ok(Some((_, OpCode::Close, payload))) => {
+                    let reason = payload.and_then(|payload| {
+                        Parser::try_parse_close_payload(&payload).ok().flatten()
+                    });
+                    Parser::write_close(&mut replies, reason, false);
+                    self.closed = true;
+                }
+                // Anything else from the client is ignored.
+                Ok(Some(_)) => {}
+                Err(err) => {
+                    eprintln!("Closing a WebSocket that sent an invalid frame - {}", err);
+                    Parser::write_close(&mut replies, Some(CloseCode::Protocol.into()), false);
+                    self.closed = true;
+                }
+            }
+        }
+        return Some(replies).filter(|replies| !replies.is_empty());
+    }
+}
+impl MessageBody for WebSocketStream {
+    type Error = Infallible;
+
+    fn size(&self) -> BodySize {
+        return BodySize::Stream;
+    }
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+    ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        let this = self.get_mut();
+        if this.closed {
+            return Poll::Ready(None);
+        }
+        if let Some(replies) = this.poll_replies(cx) {
+            return Poll::Ready(Some(Ok(replies.freeze())));
+        }
+        if this.closed {
+            return Poll::Ready(None);
+        }
+        return this.subscription.poll_event(cx).map(|event| {
+            let mut frame = BytesMut::new();
+            match event {
+                Some(Some(event)) => {
+                    Parser::write_message(&mut frame, &event.json, OpCode::Text, true, false)
+                }
+                Some(None) => Parser::write_message(&mut frame, b"", OpCode::Ping, true, false),
+                // The monitor's shutting down.
+                None => {
+                    Parser::write_close(&mut frame, Some(CloseCode::Away.into()), false);
+                    this.closed = true;
+                }
+            }
+            return Some(Ok(frame.freeze()));
+        });
+    }
+}
@@ -0,0 +1,215 @@
+use crate::chart;
+use crate::json;
+use crate::probes;
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+// About how many buckets the round trips are split into. They're sized to fit the fastest 99%, the
+// slowest 1% get a bucket of their own so a few outliers don't squash the rest into one bucket.
+const NUM_BUCKETS: f64 = 40.0;
+
+// How a target's round trips were spread out over a window, to spot what an average hides, e.g. Wi-Fi
+// retries making latency bimodal.
+pub struct Histogram {
+    // The name of the target whose round trips they are.
+    pub target: String,
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    // Each successful sample's round trip in ms, sorted. A burst counts once, by its median.
+    round_trips_ms: Vec<f64>,
+    pub failures: usize,
+    // How wide each bucket is.
+    pub bucket_ms: f64,
+    // How many round trips fell in each bucket, the first starting at 0 ms.
+    pub counts: Vec<usize>,
+    // How many round trips were slower than the last bucket.
+    pub slower: usize,
+}
+
+impl Histogram {
+    pub fn new<'a>(
+        target: &str,
+        samples: impl Iterator<Item = &'a probes::Sample>,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Histogram {
+        let mut round_trips_ms = Vec::new();
+        let mut failures = 0;
+        for sample in samples {
+            match sample.result.round_trip() {
+                Some(round_trip) => round_trips_ms.push(round_trip.as_secs_f64() * 1000.0),
+                None => failures += 1,
+            }
+        }
+        round_trips_ms.sort_by(f64::total_cmp);
+        let mut histogram = Histogram {
+            target: target.to_string(),
+            from,
+            to,
+            round_trips_ms,
+            failures,
+            bucket_ms: 1.0,
+            counts: Vec::new(),
+            slower: 0,
+        };
+        let Some(p99) = histogram.percentile(99.0) else {
+            return histogram;
+        };
+        histogram.bucket_ms = chart::round_step(p99 / NUM_BUCKETS);
+        histogram.counts = vec![0; (p99 / histogram.bucket_ms) as usize + 1];
+        for round_trip in &histogram.round_trips_ms {
+            match histogram
+                .counts
+                .get_mut((round_trip / histogram.bucket_ms) as usize)
+            {
+                Some(count) => *count += 1,
+                None => histogram.slower += 1,
+            }
+        }
+        return histogram;
+    }
+
+    // How many samples were taken, successful or not.
+    pub fn samples(&self) -> usize {
+        return self.round_trips_ms.len() + self.failures;
+    }
+
+    // The round trip `percent`% of successful samples were at least as fast as, if any succeeded.
+    pub fn percentile(&self, percent: f64) -> Option<f64> {
+        return percentile(&self.round_trips_ms, percent);
+    }
+
+    // Charts the buckets as bars (see `chart::bars`), with the slower round trips in a red bar at the
+    // end.
+    pub fn svg(&self) -> String {
+        let num_bars = self.counts.len() + (self.slower > 0) as usize;
+        let width = 1.0 / num_bars.max(1) as f64;
+        let mut bars: Vec<chart::Bar> = self
+            .counts
+            .iter()
+            .enumerate()
+            .map(|(i, count)| chart::Bar {
+                start: i as f64 * width,
+                end: (i + 1) as f64 * width,
+                value: *count as f64,
+                color: "steelblue",
+                title: format!(
+                    "{} to {} ms: {}",
+                    chart::format_number(self.bucket_start(i)),
+                    chart::format_number(self.bucket_start(i + 1)),
+                    count
+                ),
+            })
+            .collect();
+        if self.slower > 0 {
+            bars.push(chart::Bar {
+                start: 1.0 - width,
+                end: 1.0,
+                value: self.slower as f64,
+                color: "red",
+                title: format!(
+                    "over {} ms: {}",
+                    chart::format_number(self.top_ms()),
+                    self.slower
+                ),
+            });
+        }
+        // Label the start of about every 8th bucket, on a round number of buckets.
+        let label_every = chart::round_step(self.counts.len() as f64 / 8.0).max(1.0) as usize;
+        let labels: Vec<(f64, String)> = (0..=self.counts.len())
+            .step_by(label_every)
+            .map(|i| {
+                (
+                    i as f64 * width,
+                    format!("{} ms", chart::format_number(self.bucket_start(i))),
+                )
+            })
+            .collect();
+        return chart::bars(&bars, &labels, "");
+    }
+
+    // Where the `i`th bucket starts, rounded so e.g. 9 * 0.002 is 0.018 rather than
+    // 0.018000000000000002.
+    fn bucket_start(&self, i: usize) -> f64 {
+        return (i as f64 * self.bucket_ms * 1e6).round() / 1e6;
+    }
+
+    // Where the last bucket ends.
+    fn top_ms(&self) -> f64 {
+        return self.bucket_start(self.counts.len());
+    }
+
+    // The histogram as `/api/hosts/{host}/histogram` returns it.
+    pub fn response(&self) -> Response {
+        let mut buckets: Vec<Bucket> = self
+            .counts
+            .iter()
+            .enumerate()
+            .map(|(i, count)| Bucket {
+                min_ms: self.bucket_start(i),
+                max_ms: Some(self.bucket_start(i + 1)),
+                count: *count,
+            })
+            .collect();
+        if !self.counts.is_empty() {
+            buckets.push(Bucket {
+                min_ms: self.top_ms(),
+                max_ms: None,
+                count: self.slower,
+            });
+        }
+        return Response {
+            target: self.target.clone(),
+            from: self.from,
+            to: self.to,
+            samples: self.samples(),
+            failures: self.failures,
+            bucket_ms: self.bucket_ms,
+            buckets,
+        };
+    }
+}
+
+// A histogram as the API returns it, e.g. `{"target": "1.1.1.1", "from": "...", "to": "...",
+// "samples": 3600, "failures": 2, "bucket_ms": 0.5, "buckets": [{"min_ms": 0, "max_ms": 0.5,
+// "count": 0}, ..., {"min_ms": 20, "max_ms": null, "count": 31}]}`, where the last bucket is
+// everything slower.
+#[derive(Serialize, JsonSchema)]
+#[schemars(rename = "Histogram")]
+pub struct Response {
+    target: String,
+    #[serde(serialize_with = "json::seconds")]
+    from: DateTime<Utc>,
+    #[serde(serialize_with = "json::seconds")]
+    to: DateTime<Utc>,
+    #[schemars(description = "How many samples were taken, including failures.")]
+    samples: usize,
+    failures: usize,
+    #[schemars(description = "How wide each bucket is.")]
+    bucket_ms: f64,
+    #[schemars(
+        description = "Successful samples by round trip, a burst counts once by its \
+                              median. Empty if none succeeded."
+    )]
+    buckets: Vec<Bucket>,
+}
+
+// How many round trips were from `min_ms` up to `max_ms`.
+#[derive(Serialize, JsonSchema)]
+#[schemars(rename = "HistogramBucket")]
+struct Bucket {
+    min_ms: f64,
+    #[schemars(description = "Null for the last bucket, the slowest 1% or so.")]
+    max_ms: Option<f64>,
+    count: usize,
+}
+
+// The value `percent`% of `sorted` values are at most, None if there aren't any.
+pub fn percentile(sorted: &[f64], percent: f64) -> Option<f64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (percent / 100.0 * sorted.len() as f64).ceil() as usize;
+    return Some(sorted[rank.clamp(1, sorted.len()) - 1]);
+}
@@ -0,0 +1,60 @@
+use crate::config::Config;
+use crate::postgres;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::ServerConfig;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::sync::Arc;
+
+// Serving the web UI over HTTPS, with the certificate and key from `tls.cert_path` and `tls.key_path`.
+// None if they aren't set, to serve it over plain HTTP.
+pub fn server_config(config: &Config) -> Result<Option<ServerConfig>, String> {
+    let (Some(cert_path), Some(key_path)) = (&config.tls_cert_path, &config.tls_key_path) else {
+        return Ok(None);
+    };
+    if config.tls_self_signed && !cert_path.exists() && !key_path.exists() {
+        let names = vec![postgres::default_instance(), "localhost".to_string()];
+        generate_self_signed(&names, cert_path, key_path)?;
+        println!(
+            "Generated a self-signed certificate for {} at {}",
+            names.join(", "),
+            cert_path.display()
+        );
+    }
+    let certs = CertificateDer::pem_file_iter(cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|err| format!("Failed to read {} - {}", cert_path.display(), err))?;
+    if certs.is_empty() {
+        return Err(format!("{} has no certificates", cert_path.display()));
+    }
+    let key = PrivateKeyDer::from_pem_file(key_path)
+        .map_err(|err| format!("Failed to read {} - {}", key_path.display(), err))?;
+    return ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map(Some)
+        .map_err(|err| format!("Invalid certificate or key - {}", err));
+}
+
+// Makes up a certificate for `names` signed by its own key, and saves them to `cert_path` and
+// `key_path`. Browsers warn about it until it's trusted, but the traffic is encrypted all the same,
+// and it's kept so the warning can be dismissed for good.
+fn generate_self_signed(names: &[String], cert_path: &Path, key_path: &Path) -> Result<(), String> {
+    let generated = rcgen::generate_simple_self_signed(names)
+        .map_err(|err| format!("Failed to generate a certificate - {}", err))?;
+    std::fs::write(cert_path, generated.cert.pem())
+        .map_err(|err| format!("Failed to write {} - {}", cert_path.display(), err))?;
+    // Only the monitor's user can read the key.
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(key_path)
+        .and_then(|mut file| file.write_all(generated.key_pair.serialize_pem().as_bytes()))
+        .map_err(|err| format!("Failed to write {} - {}", key_path.display(), err))?;
+    return Ok(());
+}
@@ -0,0 +1,813 @@
+use crate::config;
+use chrono::Weekday;
+use std::str::FromStr;
+
+// The language the web UI is shown in, `locale` in the config, and how it writes dates and numbers.
+// Text is looked up by its English (see `text`), so anything without a translation yet is shown in
+// English, and adding a language is adding a column to `TRANSLATIONS`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Locale {
+    English,
+    German,
+    Spanish,
+    French,
+}
+impl FromStr for Locale {
+    type Err = String;
+
+    // A language tag like `de` or `de-AT`, only the language matters.
+    fn from_str(tag: &str) -> Result<Locale, String> {
+        let language = tag.split(['-', '_']).next().unwrap_or_default();
+        return match language.to_lowercase().as_str() {
+            "en" => Ok(Locale::English),
+            "de" => Ok(Locale::German),
+            "es" => Ok(Locale::Spanish),
+            "fr" => Ok(Locale::French),
+            _ => Err(format!(
+                "Unsupported locale \"{}\", it can be en, de, es or fr",
+                tag
+            )),
+        };
+    }
+}
+
+// Text in the web UI by its English, in German, Spanish and French. `{}` is filled in by `fill`.
+const TRANSLATIONS: &[(&str, [&str; 3])] = &[
+    // The timeline's links and tables.
+    (
+        "compare with",
+        ["vergleichen mit", "comparar con", "comparer avec"],
+    ),
+    (
+        "the day before",
+        ["dem Vortag", "el día anterior", "la veille"],
+    ),
+    (
+        "the week before",
+        [
+            "der Vorwoche",
+            "la semana anterior",
+            "la semaine précédente",
+        ],
+    ),
+    ("nothing", ["nichts", "nada", "rien"]),
+    ("summary", ["Übersicht", "resumen", "résumé"]),
+    (
+        "latency distribution",
+        [
+            "Latenzverteilung",
+            "distribución de latencia",
+            "distribution de la latence",
+        ],
+    ),
+    (
+        "loss timeline",
+        [
+            "Verlust im Zeitverlauf",
+            "pérdida en el tiempo",
+            "pertes au fil du temps",
+        ],
+    ),
+    ("heatmap", ["Heatmap", "mapa de calor", "carte de chaleur"]),
+    (
+        "outages feed",
+        ["Ausfall-Feed", "feed de cortes", "flux des pannes"],
+    ),
+    (
+        "discover devices",
+        [
+            "Geräte suchen",
+            "buscar dispositivos",
+            "découvrir les appareils",
+        ],
+    ),
+    (
+        "preferences",
+        ["Voreinstellungen", "preferencias", "préférences"],
+    ),
+    ("settings", ["Einstellungen", "ajustes", "paramètres"]),
+    (
+        "dual-stack host",
+        ["Dual-Stack-Host", "host de doble pila", "hôte double pile"],
+    ),
+    (
+        "IPv4 average",
+        ["IPv4-Durchschnitt", "promedio IPv4", "moyenne IPv4"],
+    ),
+    ("IPv4 loss", ["IPv4-Verlust", "pérdida IPv4", "pertes IPv4"]),
+    (
+        "IPv6 average",
+        ["IPv6-Durchschnitt", "promedio IPv6", "moyenne IPv6"],
+    ),
+    ("IPv6 loss", ["IPv6-Verlust", "pérdida IPv6", "pertes IPv6"]),
+    ("DNS query", ["DNS-Abfrage", "consulta DNS", "requête DNS"]),
+    ("resolver", ["Resolver", "resolutor", "résolveur"]),
+    ("average", ["Durchschnitt", "promedio", "moyenne"]),
+    ("failures", ["Fehlschläge", "fallos", "échecs"]),
+    ("outage", ["Ausfall", "corte", "panne"]),
+    ("started", ["Beginn", "inicio", "début"]),
+    ("ended", ["Ende", "fin", "fin"]),
+    (
+        "likely cause",
+        [
+            "wahrscheinliche Ursache",
+            "causa probable",
+            "cause probable",
+        ],
+    ),
+    (
+        "local connectivity loss (all {} targets)",
+        [
+            "lokaler Verbindungsverlust (alle {} Ziele)",
+            "pérdida de conectividad local (los {} objetivos)",
+            "perte de connectivité locale (les {} cibles)",
+        ],
+    ),
+    (
+        "still down",
+        ["noch ausgefallen", "sigue caído", "toujours en panne"],
+    ),
+    (
+        "local network",
+        ["lokales Netz", "red local", "réseau local"],
+    ),
+    ("ISP", ["Provider", "proveedor", "fournisseur d'accès"]),
+    ("remote", ["Gegenstelle", "remoto", "distant"]),
+    ("unknown", ["unbekannt", "desconocida", "inconnue"]),
+    // Each group's and target's headings.
+    (
+        "{} of {} up",
+        [
+            "{} von {} erreichbar",
+            "{} de {} activos",
+            "{} sur {} en ligne",
+        ],
+    ),
+    (
+        "{} average",
+        ["{} im Durchschnitt", "{} de promedio", "{} en moyenne"],
+    ),
+    ("{} loss", ["{} Verlust", "{} de pérdida", "{} de pertes"]),
+    ("trace", ["Route", "ruta", "route"]),
+    ("pause", ["pausieren", "pausar", "mettre en pause"]),
+    ("resume", ["fortsetzen", "reanudar", "reprendre"]),
+    ("paused", ["pausiert", "en pausa", "en pause"]),
+    (
+        "connected but captive",
+        [
+            "verbunden, aber Captive Portal",
+            "conectado pero cautivo",
+            "connecté mais captif",
+        ],
+    ),
+    ("1 outage", ["1 Ausfall", "1 corte", "1 panne"]),
+    ("{} outages", ["{} Ausfälle", "{} cortes", "{} pannes"]),
+    (
+        "(down now)",
+        ["(jetzt ausgefallen)", "(caído ahora)", "(en panne)"],
+    ),
+    (
+        "clock offset",
+        ["Uhrabweichung", "desfase del reloj", "décalage d'horloge"],
+    ),
+    (
+        "{} late, {} duplicate replies",
+        [
+            "{} verspätete, {} doppelte Antworten",
+            "{} respuestas tardías, {} duplicadas",
+            "{} réponses en retard, {} en double",
+        ],
+    ),
+    (
+        "likely rate-limited",
+        [
+            "wahrscheinlich gedrosselt",
+            "probablemente limitado",
+            "probablement limité",
+        ],
+    ),
+    ("timestamp", ["Zeitpunkt", "hora", "horodatage"]),
+    ("duration", ["Dauer", "duración", "durée"]),
+    ("throughput", ["Durchsatz", "rendimiento", "débit"]),
+    // Picking the time in view.
+    (
+        "newer data",
+        [
+            "neuere Daten",
+            "datos más recientes",
+            "données plus récentes",
+        ],
+    ),
+    (
+        "older data",
+        [
+            "ältere Daten",
+            "datos más antiguos",
+            "données plus anciennes",
+        ],
+    ),
+    ("the last", ["Zeitraum:", "periodo:", "période :"]),
+    ("hour", ["Stunde", "hora", "heure"]),
+    ("6 hours", ["6 Stunden", "6 horas", "6 heures"]),
+    ("day", ["Tag", "día", "jour"]),
+    ("week", ["Woche", "semana", "semaine"]),
+    (
+        "or from {} to {}",
+        [
+            "oder von {} bis {}",
+            "o desde {} hasta {}",
+            "ou du {} au {}",
+        ],
+    ),
+    ("show", ["anzeigen", "mostrar", "afficher"]),
+    (
+        "times in {}, show them in",
+        [
+            "Zeiten in {}, anzeigen in",
+            "horas en {}, mostrarlas en",
+            "heures en {}, les afficher en",
+        ],
+    ),
+    (
+        "the monitor's time zone",
+        [
+            "der Zeitzone des Monitors",
+            "la zona horaria del monitor",
+            "le fuseau horaire du moniteur",
+        ],
+    ),
+    (
+        "your browser's",
+        [
+            "der deines Browsers",
+            "la de tu navegador",
+            "celui de votre navigateur",
+        ],
+    ),
+    // The summary page and statuses.
+    ("timeline", ["Zeitverlauf", "cronología", "chronologie"]),
+    ("latest", ["zuletzt", "último", "dernier"]),
+    (
+        "24h average",
+        ["24-h-Durchschnitt", "promedio 24 h", "moyenne 24 h"],
+    ),
+    ("24h loss", ["24-h-Verlust", "pérdida 24 h", "pertes 24 h"]),
+    (
+        "24h uptime",
+        [
+            "24-h-Verfügbarkeit",
+            "disponibilidad 24 h",
+            "disponibilité 24 h",
+        ],
+    ),
+    (
+        "down since {}",
+        [
+            "ausgefallen seit {}",
+            "caído desde {}",
+            "en panne depuis {}",
+        ],
+    ),
+    (
+        "latest probe {}",
+        ["letzte Prüfung {}", "última sonda {}", "dernière sonde {}"],
+    ),
+    ("up", ["erreichbar", "activo", "en ligne"]),
+    (
+        "not probed yet",
+        ["noch nicht geprüft", "aún sin sondear", "pas encore sondé"],
+    ),
+    // Every page but the timeline.
+    ("back", ["zurück", "volver", "retour"]),
+    // A target's page.
+    ("target", ["Ziel", "objetivo", "cible"]),
+    ("group", ["Gruppe", "grupo", "groupe"]),
+    ("status", ["Status", "estado", "état"]),
+    ("addresses", ["Adressen", "direcciones", "adresses"]),
+    ("{} (pinned)", ["{} (fest)", "{} (fija)", "{} (fixe)"]),
+    ("probed", ["geprüft", "sondeado", "sondé"]),
+    (
+        "every {}, {} timeout",
+        [
+            "alle {}, {} Timeout",
+            "cada {}, {} de espera",
+            "toutes les {}, {} d'attente",
+        ],
+    ),
+    (
+        "every {}, {} timeout, {} probes at a time",
+        [
+            "alle {}, {} Timeout, {} Prüfungen auf einmal",
+            "cada {}, {} de espera, {} sondas a la vez",
+            "toutes les {}, {} d'attente, {} sondes à la fois",
+        ],
+    ),
+    ("outages", ["Ausfälle", "cortes", "pannes"]),
+    (
+        "round trips",
+        ["Umlaufzeiten", "tiempos de ida y vuelta", "allers-retours"],
+    ),
+    (
+        "distribution",
+        ["Verteilung", "distribución", "distribution"],
+    ),
+    (
+        "by hour of the day",
+        [
+            "nach Tageszeit",
+            "por hora del día",
+            "par heure de la journée",
+        ],
+    ),
+    (
+        "replies from",
+        ["Antworten von", "respuestas de", "réponses de"],
+    ),
+    (
+        "last hour",
+        ["letzte Stunde", "última hora", "dernière heure"],
+    ),
+    ("last day", ["letzter Tag", "último día", "dernier jour"]),
+    (
+        "last 30 days",
+        ["letzte 30 Tage", "últimos 30 días", "30 derniers jours"],
+    ),
+    (
+        "last year",
+        ["letztes Jahr", "último año", "dernière année"],
+    ),
+    (
+        "time since the monitor started",
+        [
+            "seit dem Start des Monitors",
+            "desde que se inició el monitor",
+            "depuis le démarrage du moniteur",
+        ],
+    ),
+    ("Loss", ["Verlust", "Pérdida", "Pertes"]),
+    ("over the", ["Zeitraum", "periodo", "période"]),
+    (
+        "probes sent",
+        ["gesendete Prüfungen", "sondas enviadas", "sondes envoyées"],
+    ),
+    ("lost", ["verloren", "perdidas", "perdues"]),
+    ("loss", ["Verlust", "pérdida", "pertes"]),
+    (
+        "Latest {} failures",
+        [
+            "Letzte {} Fehlschläge",
+            "Últimos {} fallos",
+            "{} derniers échecs",
+        ],
+    ),
+    ("when", ["wann", "cuándo", "quand"]),
+    ("result", ["Ergebnis", "resultado", "résultat"]),
+    ("details", ["Details", "detalles", "détails"]),
+    (
+        "Reply TTLs",
+        [
+            "TTLs der Antworten",
+            "TTL de las respuestas",
+            "TTL des réponses",
+        ],
+    ),
+    ("replies", ["Antworten", "respuestas", "réponses"]),
+    (
+        "first seen",
+        [
+            "zuerst gesehen",
+            "visto por primera vez",
+            "vu la première fois",
+        ],
+    ),
+    (
+        "last seen",
+        [
+            "zuletzt gesehen",
+            "visto por última vez",
+            "vu la dernière fois",
+        ],
+    ),
+    (
+        "Trace the path to {} now",
+        [
+            "Route zu {} jetzt verfolgen",
+            "Trazar la ruta a {} ahora",
+            "Tracer la route vers {} maintenant",
+        ],
+    ),
+    (
+        "path as it went down at {}",
+        [
+            "Route beim Ausfall um {}",
+            "ruta al caerse a las {}",
+            "route lors de la panne à {}",
+        ],
+    ),
+    // Outages and traces.
+    ("feed", ["Feed", "feed", "flux"]),
+    (
+        "{} was down from {}",
+        [
+            "{} war ab {} ausgefallen",
+            "{} estuvo caído desde {}",
+            "{} était en panne depuis {}",
+        ],
+    ),
+    (
+        "{} was down from {} until {}",
+        [
+            "{} war von {} bis {} ausgefallen",
+            "{} estuvo caído desde {} hasta {}",
+            "{} était en panne du {} au {}",
+        ],
+    ),
+    (
+        "and still is",
+        ["und ist es noch", "y lo sigue estando", "et l'est toujours"],
+    ),
+    (
+        ", along with every other target (local connectivity loss)",
+        [
+            ", zusammen mit allen anderen Zielen (lokaler Verbindungsverlust)",
+            ", junto con todos los demás objetivos (pérdida de conectividad local)",
+            ", comme toutes les autres cibles (perte de connectivité locale)",
+        ],
+    ),
+    (
+        ", likely cause: {}",
+        [
+            ", wahrscheinliche Ursache: {}",
+            ", causa probable: {}",
+            ", cause probable : {}",
+        ],
+    ),
+    (
+        "Failed to trace the path: {}",
+        [
+            "Route konnte nicht verfolgt werden: {}",
+            "No se pudo trazar la ruta: {}",
+            "Impossible de tracer la route : {}",
+        ],
+    ),
+    (
+        "Tracing the path...",
+        [
+            "Route wird verfolgt...",
+            "Trazando la ruta...",
+            "Traçage de la route...",
+        ],
+    ),
+    (
+        "Captured {} ICMP packets to {}",
+        [
+            "{} ICMP-Pakete nach {} mitgeschnitten",
+            "{} paquetes ICMP capturados en {}",
+            "{} paquets ICMP capturés dans {}",
+        ],
+    ),
+    (
+        "Capturing packets...",
+        [
+            "Pakete werden mitgeschnitten...",
+            "Capturando paquetes...",
+            "Capture des paquets...",
+        ],
+    ),
+    (
+        "{} hasn't had any outages.",
+        [
+            "{} hatte keine Ausfälle.",
+            "{} no ha tenido cortes.",
+            "{} n'a eu aucune panne.",
+        ],
+    ),
+    ("path to {}", ["Route zu {}", "ruta a {}", "route vers {}"]),
+    (
+        "Failed to trace the path to {}: {}",
+        [
+            "Route zu {} konnte nicht verfolgt werden: {}",
+            "No se pudo trazar la ruta a {}: {}",
+            "Impossible de tracer la route vers {} : {}",
+        ],
+    ),
+    ("path", ["Route", "ruta", "route"]),
+    (
+        "destination not reached",
+        [
+            "Ziel nicht erreicht",
+            "destino no alcanzado",
+            "destination non atteinte",
+        ],
+    ),
+    ("hop", ["Hop", "salto", "saut"]),
+    ("address", ["Adresse", "dirección", "adresse"]),
+    ("hostname", ["Hostname", "nombre de host", "nom d'hôte"]),
+    ("round trip", ["Umlaufzeit", "ida y vuelta", "aller-retour"]),
+    // The loss timeline, histograms and heatmaps.
+    ("by the minute", ["pro Minute", "por minuto", "par minute"]),
+    (
+        "by 5 minutes",
+        ["pro 5 Minuten", "por 5 minutos", "par 5 minutes"],
+    ),
+    ("by the hour", ["pro Stunde", "por hora", "par heure"]),
+    (
+        "lost {} of {} probes ({})",
+        [
+            "{} von {} Prüfungen verloren ({})",
+            "{} de {} sondas perdidas ({})",
+            "{} sondes sur {} perdues ({})",
+        ],
+    ),
+    (
+        "{} samples, median {}, 95th percentile {}, {} failed",
+        [
+            "{} Messungen, Median {}, 95. Perzentil {}, {} fehlgeschlagen",
+            "{} muestras, mediana {}, percentil 95 {}, {} fallidas",
+            "{} mesures, médiane {}, 95e centile {}, {} en échec",
+        ],
+    ),
+    (
+        "95th percentile round trip",
+        [
+            "95. Perzentil der Umlaufzeit",
+            "percentil 95 de ida y vuelta",
+            "95e centile de l'aller-retour",
+        ],
+    ),
+    ("2 weeks", ["2 Wochen", "2 semanas", "2 semaines"]),
+    ("30 days", ["30 Tage", "30 días", "30 jours"]),
+    ("90 days", ["90 Tage", "90 días", "90 jours"]),
+    (
+        "up to {} lost",
+        ["bis zu {} verloren", "hasta {} perdido", "jusqu'à {} perdu"],
+    ),
+    (
+        "no round trips",
+        [
+            "keine Umlaufzeiten",
+            "sin tiempos de ida y vuelta",
+            "aucun aller-retour",
+        ],
+    ),
+    (
+        "{} ms to {} ms",
+        ["{} ms bis {} ms", "de {} ms a {} ms", "de {} ms à {} ms"],
+    ),
+    (
+        "darker is worse and grey is no data",
+        [
+            "dunkler ist schlechter, grau heißt keine Daten",
+            "más oscuro es peor y gris es sin datos",
+            "plus foncé est pire, gris signifie aucune donnée",
+        ],
+    ),
+    // Preferences.
+    (
+        "Preferences, kept by this browser",
+        [
+            "Voreinstellungen, in diesem Browser gespeichert",
+            "Preferencias, guardadas en este navegador",
+            "Préférences, conservées par ce navigateur",
+        ],
+    ),
+    (
+        "the page's default",
+        [
+            "Standard der Seite",
+            "el predeterminado de la página",
+            "la valeur par défaut de la page",
+        ],
+    ),
+    (
+        "the last hour",
+        ["die letzte Stunde", "la última hora", "la dernière heure"],
+    ),
+    (
+        "the last 6 hours",
+        [
+            "die letzten 6 Stunden",
+            "las últimas 6 horas",
+            "les 6 dernières heures",
+        ],
+    ),
+    (
+        "the last day",
+        ["den letzten Tag", "el último día", "le dernier jour"],
+    ),
+    (
+        "the last week",
+        [
+            "die letzte Woche",
+            "la última semana",
+            "la dernière semaine",
+        ],
+    ),
+    (
+        "hosts, all if none are picked",
+        [
+            "Hosts, alle wenn keiner gewählt ist",
+            "hosts, todos si no se elige ninguno",
+            "hôtes, tous si aucun n'est choisi",
+        ],
+    ),
+    (
+        "throughput in",
+        ["Durchsatz in", "rendimiento en", "débit en"],
+    ),
+    (
+        "update the latest samples",
+        [
+            "neueste Messungen aktualisieren",
+            "actualizar las últimas muestras",
+            "actualiser les dernières mesures",
+        ],
+    ),
+    (
+        "every second",
+        ["jede Sekunde", "cada segundo", "toutes les secondes"],
+    ),
+    (
+        "every 5 seconds",
+        [
+            "alle 5 Sekunden",
+            "cada 5 segundos",
+            "toutes les 5 secondes",
+        ],
+    ),
+    (
+        "every 30 seconds",
+        [
+            "alle 30 Sekunden",
+            "cada 30 segundos",
+            "toutes les 30 secondes",
+        ],
+    ),
+    (
+        "every minute",
+        ["jede Minute", "cada minuto", "toutes les minutes"],
+    ),
+    ("never", ["nie", "nunca", "jamais"]),
+    ("time zone", ["Zeitzone", "zona horaria", "fuseau horaire"]),
+    (
+        "the monitor's default",
+        [
+            "Standard des Monitors",
+            "el predeterminado del monitor",
+            "la valeur par défaut du moniteur",
+        ],
+    ),
+    ("save", ["speichern", "guardar", "enregistrer"]),
+    // Settings and discovery.
+    (
+        "Targets, changes take effect right away and last until a restart",
+        [
+            "Ziele, Änderungen gelten sofort und bis zum Neustart",
+            "Objetivos, los cambios se aplican de inmediato y duran hasta reiniciar",
+            "Cibles, les modifications s'appliquent tout de suite et durent jusqu'au redémarrage",
+        ],
+    ),
+    ("order", ["Reihenfolge", "orden", "ordre"]),
+    ("label", ["Bezeichnung", "etiqueta", "libellé"]),
+    ("interval", ["Intervall", "intervalo", "intervalle"]),
+    ("delete", ["löschen", "eliminar", "supprimer"]),
+    ("e.g. {}", ["z. B. {}", "p. ej. {}", "p. ex. {}"]),
+    ("add", ["hinzufügen", "añadir", "ajouter"]),
+    (
+        "No targets are being monitored.",
+        [
+            "Es werden keine Ziele überwacht.",
+            "No se está monitorizando ningún objetivo.",
+            "Aucune cible n'est surveillée.",
+        ],
+    ),
+    (
+        "Devices on the local network, monitor one by passing its name (in quotes) as a target",
+        [
+            "Geräte im lokalen Netz, eines wird überwacht, indem sein Name (in Anführungszeichen) als Ziel angegeben wird",
+            "Dispositivos en la red local, para monitorizar uno pasa su nombre (entre comillas) como objetivo",
+            "Appareils du réseau local, pour en surveiller un passez son nom (entre guillemets) comme cible",
+        ],
+    ),
+    ("name", ["Name", "nombre", "nom"]),
+    (
+        "found via",
+        ["gefunden über", "encontrado por", "trouvé via"],
+    ),
+    (
+        "No devices responded.",
+        [
+            "Keine Geräte haben geantwortet.",
+            "Ningún dispositivo respondió.",
+            "Aucun appareil n'a répondu.",
+        ],
+    ),
+];
+
+// The locale the web UI is shown in.
+pub fn locale() -> Locale {
+    return config::get().locale;
+}
+
+// `english` in the web UI's language, or in English if it hasn't been translated.
+pub fn text(english: &str) -> &str {
+    return locale().text(english);
+}
+
+// `english` in the web UI's language, with each `{}` in it filled in with the next of `values`.
+pub fn fill(english: &str, values: &[String]) -> String {
+    let mut filled = String::new();
+    for (i, piece) in text(english).split("{}").enumerate() {
+        if i > 0 {
+            filled += values.get(i - 1).map_or("", String::as_str);
+        }
+        filled += piece;
+    }
+    return filled;
+}
+
+impl Locale {
+    pub fn text(self, english: &str) -> &str {
+        let column = match self {
+            Locale::English => return english,
+            Locale::German => 0,
+            Locale::Spanish => 1,
+            Locale::French => 2,
+        };
+        return TRANSLATIONS
+            .iter()
+            .find(|(key, _)| *key == english)
+            .map_or(english, |(_, translations)| translations[column]);
+    }
+
+    // `value` with `places` decimal places, with a decimal comma where that's usual.
+    pub fn number(self, value: f64, places: usize) -> String {
+        let number = format!("{:.*}", places, value);
+        return match self {
+            Locale::English => number,
+            _ => number.replace('.', ","),
+        };
+    }
+
+    // `percent` with `places` decimal places and a percent sign, spaced from it where that's usual.
+    pub fn percent(self, percent: f64, places: usize) -> String {
+        return match self {
+            Locale::English => format!("{}%", self.number(percent, places)),
+            _ => format!("{}\u{a0}%", self.number(percent, places)),
+        };
+    }
+
+    // How dates are written, for `chrono`'s `format`.
+    pub fn date_format(self) -> &'static str {
+        return match self {
+            Locale::English => "%Y-%m-%d",
+            Locale::German => "%d.%m.%Y",
+            Locale::Spanish | Locale::French => "%d/%m/%Y",
+        };
+    }
+
+    // How dates are written without the year.
+    pub fn month_day_format(self) -> &'static str {
+        return match self {
+            Locale::English => "%m-%d",
+            Locale::German => "%d.%m.",
+            Locale::Spanish | Locale::French => "%d/%m",
+        };
+    }
+
+    // Whether times of day are written with AM and PM, rather than from 00:00 to 23:59.
+    pub fn twelve_hour_clock(self) -> bool {
+        return self == Locale::English;
+    }
+
+    // The short name of `weekday`, e.g. "Mon".
+    pub fn weekday(self, weekday: Weekday) -> &'static str {
+        let names = match self {
+            Locale::English => ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"],
+            Locale::German => ["Mo", "Di", "Mi", "Do", "Fr", "Sa", "So"],
+            Locale::Spanish => ["lun", "mar", "mié", "jue", "vie", "sáb", "dom"],
+            Locale::French => ["lun", "mar", "mer", "jeu", "ven", "sam", "dim"],
+        };
+        return names[weekday.num_days_from_monday() as usize];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn translations_fill_in_as_much_as_their_english() {
+        for (english, translations) in TRANSLATIONS {
+            for translation in translations {
+                assert_eq!(
+                    translation.matches("{}").count(),
+                    english.matches("{}").count(),
+                    "{} - {}",
+                    english,
+                    translation
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,331 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Serializer;
+use std::fmt;
+
+// A JSON value, for the sample log. Only what we need is supported: numbers are all f64, which
+// holds integers exactly up to 2^53.
+// https://www.rfc-editor.org/rfc/rfc8259
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    // Keys in the order they were added.
+    Object(Vec<(String, Json)>),
+}
+impl Json {
+    // The value of `key`, if this is an object that has it.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        return match self {
+            Json::Object(entries) => entries
+                .iter()
+                .find(|(entry_key, _)| entry_key == key)
+                .map(|(_, value)| value),
+            _ => None,
+        };
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        return match self {
+            Json::String(string) => Some(string),
+            _ => None,
+        };
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        return match self {
+            Json::Number(number) => Some(*number),
+            _ => None,
+        };
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        return match self {
+            Json::Array(values) => Some(values),
+            _ => None,
+        };
+    }
+
+    // The value as a whole number, if it is one and fits in a u64.
+    pub fn as_u64(&self) -> Option<u64> {
+        return self
+            .as_f64()
+            .filter(|number| number.fract() == 0.0 && *number >= 0.0 && *number <= u64::MAX as f64)
+            .map(|number| number as u64);
+    }
+}
+impl fmt::Display for Json {
+    // Writes the value compactly, on one line.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(boolean) => write!(f, "{}", boolean),
+            // JSON has no infinity or NaN.
+            Json::Number(number) if !number.is_finite() => write!(f, "null"),
+            Json::Number(number) => write!(f, "{}", number),
+            Json::String(string) => write_string(f, string),
+            Json::Array(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(entries) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+// A value serde wrote, e.g. a model from the API kept in the sample log, or a schema generated from
+// one.
+impl From<serde_json::Value> for Json {
+    fn from(value: serde_json::Value) -> Json {
+        return match value {
+            serde_json::Value::Null => Json::Null,
+            serde_json::Value::Bool(boolean) => Json::Bool(boolean),
+            serde_json::Value::Number(number) => number.as_f64().map_or(Json::Null, Json::Number),
+            serde_json::Value::String(string) => Json::String(string),
+            serde_json::Value::Array(values) => {
+                Json::Array(values.into_iter().map(Json::from).collect())
+            }
+            serde_json::Value::Object(entries) => Json::Object(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key, Json::from(value)))
+                    .collect(),
+            ),
+        };
+    }
+}
+
+// For `#[serde(serialize_with = "json::seconds")]`: a time to the second, e.g. 2024-01-01T08:00:00Z,
+// like the API writes times other than when samples were taken.
+pub fn seconds<S: Serializer>(time: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error> {
+    return serializer.serialize_str(&time.to_rfc3339_opts(SecondsFormat::Secs, true));
+}
+
+// `seconds` for a time that may not be known, which is null.
+pub fn maybe_seconds<S: Serializer>(
+    time: &Option<DateTime<Utc>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    return match time {
+        Some(time) => seconds(time, serializer),
+        None => serializer.serialize_none(),
+    };
+}
+
+fn write_string(f: &mut fmt::Formatter, string: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in string.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if c < ' ' => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    return write!(f, "\"");
+}
+
+// Parses a JSON document.
+pub fn parse(text: &str) -> Result<Json, String> {
+    let mut parser = JsonParser {
+        chars: text.chars().collect(),
+        pos: 0,
+    };
+    parser.skip_whitespace();
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.pos < parser.chars.len() {
+        return Err(parser.error("unexpected text after the value"));
+    }
+    return Ok(value);
+}
+
+struct JsonParser {
+    chars: Vec<char>,
+    pos: usize,
+}
+impl JsonParser {
+    fn peek(&self) -> Option<char> {
+        return self.chars.get(self.pos).copied();
+    }
+
+    fn error(&self, message: &str) -> String {
+        return format!("At character {}: {}", self.pos, message);
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        if self.peek() != Some(expected) {
+            return Err(self.error(&format!("expected '{}'", expected)));
+        }
+        self.pos += 1;
+        return Ok(());
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self
+            .peek()
+            .is_some_and(|next| matches!(next, ' ' | '\t' | '\n' | '\r'))
+        {
+            self.pos += 1;
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        return match self.peek() {
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some('"') => Ok(Json::String(self.string()?)),
+            Some(next) if next == '-' || next.is_ascii_digit() => self.number(),
+            Some(next) if next.is_ascii_alphabetic() => {
+                let start = self.pos;
+                while self.peek().is_some_and(|next| next.is_ascii_alphabetic()) {
+                    self.pos += 1;
+                }
+                match self.chars[start..self.pos]
+                    .iter()
+                    .collect::<String>()
+                    .as_str()
+                {
+                    "null" => Ok(Json::Null),
+                    "true" => Ok(Json::Bool(true)),
+                    "false" => Ok(Json::Bool(false)),
+                    word => Err(self.error(&format!("unexpected \"{}\"", word))),
+                }
+            }
+            _ => Err(self.error("expected a value")),
+        };
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        self.expect('{')?;
+        let mut entries = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.pos += 1;
+            return Ok(Json::Object(entries));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            self.skip_whitespace();
+            entries.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some('}') => {
+                    self.pos += 1;
+                    return Ok(Json::Object(entries));
+                }
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.pos += 1;
+            return Ok(Json::Array(values));
+        }
+        loop {
+            self.skip_whitespace();
+            values.push(self.value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(']') => {
+                    self.pos += 1;
+                    return Ok(Json::Array(values));
+                }
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut string = String::new();
+        loop {
+            let next = self.peek().ok_or(self.error("unterminated string"))?;
+            self.pos += 1;
+            match next {
+                '"' => return Ok(string),
+                '\\' => {
+                    let escaped = self.peek().ok_or(self.error("unterminated string"))?;
+                    self.pos += 1;
+                    string.push(match escaped {
+                        '"' => '"',
+                        '\\' => '\\',
+                        '/' => '/',
+                        'b' => '\u{8}',
+                        'f' => '\u{c}',
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        'u' => self.unicode_escape()?,
+                        _ => return Err(self.error(&format!("invalid escape \\{}", escaped))),
+                    });
+                }
+                _ => string.push(next),
+            }
+        }
+    }
+
+    // The rest of a `\uXXXX` escape, which may be the first half of a UTF-16 surrogate pair.
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let first = self.hex4()?;
+        if !(0xD800..0xDC00).contains(&first) {
+            return char::from_u32(first).ok_or(self.error("invalid \\u escape"));
+        }
+        self.expect('\\')?;
+        self.expect('u')?;
+        let second = self.hex4()?;
+        let code_point = 0x10000 + ((first - 0xD800) << 10) + (second.wrapping_sub(0xDC00) & 0x3FF);
+        return char::from_u32(code_point).ok_or(self.error("invalid \\u escape"));
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits: String = self.chars.iter().skip(self.pos).take(4).collect();
+        self.pos += 4;
+        return u32::from_str_radix(&digits, 16).map_err(|_err| self.error("invalid \\u escape"));
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.pos;
+        while self.peek().is_some_and(|next| {
+            next.is_ascii_digit() || matches!(next, '-' | '+' | '.' | 'e' | 'E')
+        }) {
+            self.pos += 1;
+        }
+        let number: String = self.chars[start..self.pos].iter().collect();
+        return number
+            .parse()
+            .map(Json::Number)
+            .map_err(|_err| self.error(&format!("invalid number \"{}\"", number)));
+    }
+}
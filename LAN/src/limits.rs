@@ -0,0 +1,107 @@
+use crate::config;
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header;
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpResponse};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+
+// Routes that read every sample (or lots of them) or make the monitor do work, so count for
+// `EXPENSIVE_COST` requests.
+const EXPENSIVE_ROUTES: &[&str] = &[
+    "/trace/{host}",
+    "/discover",
+    "/api/samples",
+    "/api/hosts/{host:.+}/pings",
+    "/api/backup",
+    "/api/restore",
+    "/api/snapshot",
+];
+pub const EXPENSIVE_COST: u64 = 10;
+// Bodies posted to anything but snapshots and restores, which are only ever a target spec or a
+// few preferences.
+pub const MAX_BODY_BYTES: usize = 64 << 10;
+// Longer query strings than any page makes are turned away before they're parsed.
+const MAX_QUERY_BYTES: usize = 4 << 10;
+// Clients forgotten about once there are this many, if they haven't made requests lately.
+const MAX_CLIENTS: usize = 10_000;
+
+// How many requests a client can make right away, refilling at `requests_per_sec` up to
+// `request_burst`.
+// https://en.wikipedia.org/wiki/Token_bucket
+struct Bucket {
+    requests: f64,
+    updated: Instant,
+}
+
+static BUCKETS: OnceLock<Mutex<HashMap<IpAddr, Bucket>>> = OnceLock::new();
+
+fn buckets() -> &'static Mutex<HashMap<IpAddr, Bucket>> {
+    return BUCKETS.get_or_init(|| Mutex::new(HashMap::new()));
+}
+
+// Takes `cost` requests from `client`'s bucket, or returns how many seconds until it can.
+fn take(client: IpAddr, cost: u64, per_sec: f64, burst: f64) -> Result<(), f64> {
+    let now = Instant::now();
+    let mut buckets = buckets().lock().unwrap();
+    if buckets.len() >= MAX_CLIENTS {
+        buckets.retain(|_client, bucket| {
+            return bucket.requests + now.duration_since(bucket.updated).as_secs_f64() * per_sec
+                < burst;
+        });
+    }
+    let bucket = buckets.entry(client).or_insert(Bucket {
+        requests: burst,
+        updated: now,
+    });
+    bucket.requests =
+        (bucket.requests + now.duration_since(bucket.updated).as_secs_f64() * per_sec).min(burst);
+    bucket.updated = now;
+    let cost = cost as f64;
+    if bucket.requests < cost {
+        return Err((cost - bucket.requests) / per_sec);
+    }
+    bucket.requests -= cost;
+    return Ok(());
+}
+
+// Middleware that turns away requests with absurdly long query strings, and clients making more than
+// `limits.requests_per_sec` (see `take`). Clients are told by their address, so everyone behind a
+// reverse proxy shares one limit.
+pub async fn check(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    if req.query_string().len() > MAX_QUERY_BYTES {
+        let response = HttpResponse::UriTooLong().body(format!(
+            "Query strings can be at most {} bytes long.",
+            MAX_QUERY_BYTES
+        ));
+        return Ok(req.into_response(response));
+    }
+    let config = config::get();
+    let client = req.peer_addr().map(|address| address.ip());
+    let (Some(client), 1..) = (client, config.requests_per_sec) else {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    };
+    let cost = match req.match_pattern() {
+        Some(pattern) if EXPENSIVE_ROUTES.contains(&pattern.as_str()) => EXPENSIVE_COST,
+        _ => 1,
+    };
+    let taken = take(
+        client,
+        cost,
+        config.requests_per_sec as f64,
+        config.request_burst as f64,
+    );
+    if let Err(wait_sec) = taken {
+        let response = HttpResponse::TooManyRequests()
+            .insert_header((header::RETRY_AFTER, wait_sec.ceil() as u64))
+            .body("Too many requests, slow down.");
+        return Ok(req.into_response(response));
+    }
+    return Ok(next.call(req).await?.map_into_boxed_body());
+}
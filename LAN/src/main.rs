@@ -1,444 +1,1100 @@
-#![feature(cursor_remaining)]
-#![feature(maybe_uninit_slice)]
+// Explicit `return`s are preferred in this codebase.
+#![allow(clippy::needless_return)]
 
+use actix_http::ws;
 use actix_web::{
-    http::header::ContentType, web, web::Query, App, HttpRequest, HttpResponse, HttpServer,
+    body::{BodySize, MessageBody},
+    error::{InternalError, QueryPayloadError, UrlencodedError},
+    http::header::{self, ContentType},
+    http::StatusCode,
+    middleware, web,
+    web::Bytes,
+    App, HttpRequest, HttpResponse, HttpServer,
 };
-use byteorder::{BigEndian, ReadBytesExt};
+use askama::Template;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL;
+use base64::Engine;
 use chrono::Duration as chrono_Duration;
-use chrono::{DateTime, Datelike, Local, Timelike, Utc};
-use dns_lookup::lookup_host;
+use chrono::{
+    DateTime, Datelike, Local, NaiveDate, NaiveDateTime, NaiveTime, SecondsFormat, Timelike, Utc,
+};
 use parse_duration::parse;
-use rand::Rng;
-use socket2::{Domain, Protocol, Socket, Type};
+use schemars::JsonSchema;
+use serde::{de, Deserialize, Deserializer, Serialize};
 use std::cmp;
 use std::collections::BTreeMap;
 use std::collections::HashMap;
-use std::io::Cursor;
-use std::mem::MaybeUninit;
-use std::net::{IpAddr, Ipv4Addr, SocketAddr};
-use std::os::unix::io::AsRawFd;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::io::Write;
+use std::net::IpAddr;
+use std::ops::Bound;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
 use std::thread;
 use std::time::Duration;
 
+mod aggregate;
+mod assets;
+mod audit;
+mod auth;
+mod backup;
+mod cache;
+mod capture;
+mod chart;
+mod cli;
 mod config;
-
-const IP_HEADER_SIZE: usize = 20;
+mod cors;
+mod discovery;
+mod events;
+mod histogram;
+mod https;
+mod i18n;
+mod json;
+mod limits;
+mod metrics;
+mod notify;
+mod oidc;
+mod openapi;
+mod pages;
+mod parquet;
+mod postgres;
+mod probes;
+mod public_ip;
+mod rollups;
+mod signals;
+mod snapshot;
+mod stats;
+mod storage;
+mod timezone;
+mod traceroute;
+mod workers;
 
 struct PingData {
+    // What's being monitored, which changes when the config is reloaded.
+    targets: Vec<probes::Target>,
     hostnames_in_order: Vec<String>,
-    data: BTreeMap<String, BTreeMap<DateTime<Utc>, Duration>>,
+    data: BTreeMap<String, BTreeMap<DateTime<Utc>, probes::Sample>>,
+    // Summaries of samples older than `data` keeps.
+    rollups: BTreeMap<String, rollups::Rollups>,
+    // How many probes were sent and lost, for every sample rather than just older ones.
+    loss_counters: BTreeMap<String, rollups::LossCounters>,
+    // Stretches of consecutive failed probes, by when they started.
+    outages: BTreeMap<String, BTreeMap<DateTime<Utc>, Outage>>,
+    // Times every target went down at once, by when the first one did.
+    connectivity_losses: BTreeMap<DateTime<Utc>, ConnectivityLoss>,
+    // When targets weren't probed because of their quiet hours or being paused, by when each stretch
+    // started.
+    quiet_periods: BTreeMap<String, BTreeMap<DateTime<Utc>, QuietPeriod>>,
+    // Targets that are paused, and not being probed until they're resumed.
+    paused: HashSet<String>,
+    // Our public IP address, by when it was first seen. Only changes are recorded.
+    public_ips: BTreeMap<DateTime<Utc>, IpAddr>,
 }
 impl PingData {
+    // Keeps any history the target was renamed with.
     fn add_hostname(&mut self, hostname: &str) {
-        self.data.insert(hostname.to_string(), BTreeMap::new());
-    }
-    fn add_entry(&mut self, hostname: &String, when: DateTime<Utc>, how_long: Duration) {
-        let ping_results = self.data.get_mut(hostname).unwrap();
-        if ping_results.len() >= config::MAX_ENTRIES_SAVED {
-            ping_results.pop_first(); // Drop the oldest entry
-        }
-        ping_results.insert(when, how_long);
-    }
-}
-
-#[derive(Debug)]
-struct IcmpEchoMessage {
-    msg_type: u8,
-    code: u8,
-    checksum: u16,
-    identifier: u16,
-    sequence_number: u16,
-    data: [u8; 56], // 56 bytes, to bring the message up to the standard 64B.
-}
-impl IcmpEchoMessage {
-    fn new(identifier: u16, sequence_number: u16) -> IcmpEchoMessage {
-        // Allocate an ICMP message for an ECHO, use boring default values.
-        let mut message = IcmpEchoMessage {
-            // https://www.iana.org/assignments/icmp-parameters/icmp-parameters.xhtml
-            // ECHO = 8, ECHO_REPLY = 0
-            msg_type: 8,
-            code: 0,
-            checksum: 0,
-            identifier: identifier,
-            sequence_number: sequence_number,
-            data: [0; 56],
-        };
-        // Set some values in the data, just for fun.
-        // A nice plus: this exercises the checksum's carry-out.
-        for i in 0..56 {
-            message.data[i] = 0xFF - i as u8;
-        }
-        // Set the checksum.
-        message.populate_checksum();
-        return message;
-    }
-
-    // Takes the sum of this message as 16-bit words, adds back in any carry out,
-    // takes the 1's complement. Then sets the resulting value in the checksum field.
-    // http://www.faqs.org/rfcs/rfc1071.html is very helpful to understand the checksum's computation.
-    fn populate_checksum(&mut self) {
-        // Accumulate using a 32-bit variable so overflow is graceful.
-        let mut sum: u32 = 0;
-        // Take the sum of the message 16 bits at a time.
-        let mut serialized = Cursor::new(self.serialize());
-        while !serialized.is_empty() {
-            sum += u32::from(serialized.read_u16::<BigEndian>().unwrap());
-        }
-        // So long as there is overflow, add it back into the lower 16 bits.
-        while (sum >> 16) > 0 {
-            sum = (sum & 0xFFFF) + (sum >> 16);
-        }
-        // Take the 1's complement of the sum.
-        sum = !sum;
-        // Truncate to 16 bits.
-        self.checksum = sum as u16;
-    }
-
-    // Marshall into a buffer using network byte order (big endian).
-    fn serialize(&self) -> [u8; std::mem::size_of::<IcmpEchoMessage>()] {
-        let mut buf_be: [u8; std::mem::size_of::<IcmpEchoMessage>()] =
-            [0; std::mem::size_of::<IcmpEchoMessage>()];
-        buf_be[0] = self.msg_type;
-        buf_be[1] = self.code;
-        buf_be[2] = self.checksum.to_be_bytes()[0];
-        buf_be[3] = self.checksum.to_be_bytes()[1];
-        buf_be[4] = self.identifier.to_be_bytes()[0];
-        buf_be[5] = self.identifier.to_be_bytes()[1];
-        buf_be[6] = self.sequence_number.to_be_bytes()[0];
-        buf_be[7] = self.sequence_number.to_be_bytes()[1];
-        let buf_data_start = 8;
-        for data_idx in 0..self.data.len() {
-            buf_be[buf_data_start + data_idx] = self.data[data_idx];
-        }
-        return buf_be;
-    }
-
-    // Marshall out of a network byte order (big endian) buffer.
-    fn from(buf_be: &[u8]) -> IcmpEchoMessage {
-        let mut buf_be_iter = Cursor::new(buf_be);
-        let mut message = IcmpEchoMessage {
-            msg_type: buf_be_iter.read_u8().unwrap(),
-            code: buf_be_iter.read_u8().unwrap(),
-            checksum: buf_be_iter.read_u16::<BigEndian>().unwrap(),
-            identifier: buf_be_iter.read_u16::<BigEndian>().unwrap(),
-            sequence_number: buf_be_iter.read_u16::<BigEndian>().unwrap(),
-            data: [0; 56],
-        };
-        for data_offset in 0..message.data.len() {
-            message.data[data_offset] = buf_be_iter.read_u8().unwrap();
-        }
-        return message;
-    }
-}
-
-// Configures `socket` to only listen for ICMP Echo Reply messages.
-// Also applies a filter so `socket` will only listen for 64B ICMP Echo Reply messages from
-// `src_ip_v4` that are annotated with ICMP ID == `echo_id` and ICMP Code == 0.
-fn filter_icmp_replies(socket: &Socket, src_ip_v4: Ipv4Addr, icmp_msg_size: usize, echo_id: u16) {
-    // Filter so the socket will only recv Echo Reply ICMP messages.
-    // Echo Reply is type 0.
-    let icmp_types_to_listen_for_bitmask: libc::c_int = !(1 << 0/* ICMP Echo Reply */);
-    unsafe {
-        libc::setsockopt(
-            socket.as_raw_fd(),
-            libc::SOL_RAW,
-            1, /* ICMP_FILTER */
-            &icmp_types_to_listen_for_bitmask as *const libc::c_int as *const libc::c_void,
-            4, /* Size of the bitmask, it's 32 bits */
-        );
+        self.data.entry(hostname.to_string()).or_default();
+        self.rollups.entry(hostname.to_string()).or_default();
+        self.loss_counters.entry(hostname.to_string()).or_default();
+        self.outages.entry(hostname.to_string()).or_default();
+        self.quiet_periods.entry(hostname.to_string()).or_default();
     }
-    // Use libc::BPF to filter yet further. Only recv 84B ICMP Echo Reply packets
-    // (20B IP header + 64B ICMP message) that are from `src_ip_v4` and annotated with `echo_id`.
-    //
-    // About BPF and Packet memory layout:
-    // https://www.kernel.org/doc/Documentation/networking/filter.txt
-    // https://en.wikipedia.org/wiki/IPv4#/media/File:IPv4_Packet-en.svg
-    // Additional reading that can be helpful but doesn't apply to the messages in this program:
-    // https://en.wikipedia.org/wiki/Ethernet_frame
-    //
-    // The bytecode we use below was generated and tweaked starting with output from `tcpdump`:
-    // `sudo tcpdump icmp and src 192.168.1.1 and ip[3] == 84 and icmp[icmptype] == 0 and icmp[icmpcode] == 0 and icmp[4:2] == 0x00FF -dd`
-    // I used tcpdump's `-dd` output. You can use regex-replace to make that output into valid Rust:
-    // find: `\{ (.*), (.*), (.*), (.*) \},` -> replace: `libc::sock_filter { code: $1, jt: $2, jf: $3, k: $4 },`
-    //
-    // Notes on using tcpdump's generated bytecode:
-    // * tcpdump generates BPF bytecode targeting RAW AF_PACKET (the low-level packet interface) sockets.
-    //   This program uses Socket2's Domain::IPV4, which maps to AF_INET (a slightly hihger-level socket
-    //   type for IPv4 messaging). This socket type's higher level of abstraction means the kernel handles
-    //   a bit more and hides a bit more.
-    //   Practically speaking, this means BPF registered in this application will see fewer headers than TCP Dump's
-    //   BFP bytecode assumes are available. As a consequence, the BPF bytecode we get from TCP dump needs to be
-    //   modified before it can be used in this application. We need to remove any byte code interacting with the
-    //   Ethernet header (the first 14B) and all subsequent offsets need to be reduced by 14B.
-    // * We can simplify out some of the checks in the BPF the command above generates, too. For example, if you
-    //   look in ping.c, the BPF used doesn't check the contents of the flags and fragment offset field of the
-    //   IP header (the 2B at offset 6). We can do the same and save a couple instructions as a consequence.
-    // * We patch in variables like `dest_ip_v4` where appropriate.
-    let mut bpf_bytecode = [
-        // Load 1B at offset 9 in the IP header (Protocol)
-        libc::sock_filter {
-            code: 0x30, /*ldb*/
-            jt: 0,
-            jf: 0,
-            k: 0x00000009,
-        },
-        // Continue if the protocol is ICMP, otherwise exit.
-        libc::sock_filter {
-            code: 0x15, /*jeq*/
-            jt: 0,
-            jf: 11,
-            k: 0x00000001, /*IPPROTO_ICMP*/
-        },
-        // Load 4B at offset 12 in the IP header (Source Address).
-        libc::sock_filter {
-            code: 0x20, /*ld*/
-            jt: 0,
-            jf: 0,
-            k: 0x0000000c,
-        },
-        // Continue if it's equal to the IP we are listening for, otherwise exit.
-        libc::sock_filter {
-            code: 0x15, /*jeq*/
-            jt: 0,
-            jf: 9,
-            k: u32::from_be_bytes(src_ip_v4.octets()),
-        },
-        // Load 2B at offset 2 in the IP header (Total Length).
-        libc::sock_filter {
-            code: 0x28, /*ldh*/
-            jt: 0,
-            jf: 0,
-            k: 0x00000002,
-        },
-        // Continue if the IP-layer message is 84B, otherwise exit.
-        libc::sock_filter {
-            code: 0x15, /*jeq*/
-            jt: 0,
-            jf: 7,
-            k: (IP_HEADER_SIZE + icmp_msg_size).try_into().unwrap(),
-        },
-        // Load byte at offset 0 in the ICMP header (20B IP header + 0), the ICMP Type.
-        libc::sock_filter {
-            code: 0x30, /*ldb*/
-            jt: 0,
-            jf: 0,
-            k: 0x00000014,
-        },
-        // Continue if the ICMP Type is 0 (Echo Reply), otherwise exit.
-        libc::sock_filter {
-            code: 0x15, /*jeq*/
-            jt: 0,
-            jf: 5,
-            k: 0x00000000, /*ICMP_ECHOREPLY*/
-        },
-        // Load byte at offset 1 in the ICMP header (20+1), the ICMP code.
-        libc::sock_filter {
-            code: 0x30, /*ldb*/
-            jt: 0,
-            jf: 0,
-            k: 0x00000016,
-        },
-        // Continue if the ICMP Code is 0, otherwise exit.
-        libc::sock_filter {
-            code: 0x15, /*jeq*/
-            jt: 0,
-            jf: 3 * 0,
-            k: 0x00000000,
-        },
-        // Load 2B at offset 4 in the ICMP header, the ICMP ID.
-        libc::sock_filter {
-            code: 0x28, /*ldh*/
-            jt: 0,
-            jf: 0,
-            k: 0x00000018,
-        },
-        // Continue if the loaded message ID matches the required ID, otherwise exit.
-        libc::sock_filter {
-            code: 0x15, /*jeq*/
-            jt: 0,
-            jf: 1,
-            k: echo_id.into(),
-        },
-        // Indicate success, the criteria were fulfilled.
-        // The message's length will be truncated to the returned value, we return the full length
-        // to keep the message intact.
-        libc::sock_filter {
-            code: 0x6, /*ret*/
-            jt: 0,
-            jf: 0,
-            k: (IP_HEADER_SIZE + icmp_msg_size).try_into().unwrap(),
-        },
-        // Indicate we didn't fulfill the criteria.
-        libc::sock_filter {
-            code: 0x6, /*ret*/
-            jt: 0,
-            jf: 0,
-            k: 0x00000000,
-        },
-    ];
-    let filter_program = libc::sock_fprog {
-        len: bpf_bytecode.len().try_into().unwrap(),
-        filter: bpf_bytecode.as_mut_ptr() as *mut libc::sock_filter,
-    };
-    let res: i32;
-    unsafe {
-        res = libc::setsockopt(
-            socket.as_raw_fd(),
-            libc::SOL_SOCKET,
-            libc::SO_ATTACH_FILTER,
-            &filter_program as *const libc::sock_fprog as *const libc::c_void,
-            std::mem::size_of::<libc::sock_fprog>().try_into().unwrap(),
+    // Targets removed when the config is reloaded may still be in the middle of a probe, anything
+    // they add afterwards is ignored.
+    fn remove_hostname(&mut self, hostname: &str) {
+        self.data.remove(hostname);
+        self.rollups.remove(hostname);
+        self.loss_counters.remove(hostname);
+        self.outages.remove(hostname);
+        self.quiet_periods.remove(hostname);
+        self.paused.remove(hostname);
+    }
+    // Moves a target's history to a new name, when it's edited.
+    fn rename_hostname(&mut self, hostname: &str, new_hostname: &str) {
+        if let Some(data) = self.data.remove(hostname) {
+            self.data.insert(new_hostname.to_string(), data);
+        }
+        if let Some(rollups) = self.rollups.remove(hostname) {
+            self.rollups.insert(new_hostname.to_string(), rollups);
+        }
+        if let Some(loss_counters) = self.loss_counters.remove(hostname) {
+            self.loss_counters
+                .insert(new_hostname.to_string(), loss_counters);
+        }
+        if let Some(outages) = self.outages.remove(hostname) {
+            self.outages.insert(new_hostname.to_string(), outages);
+        }
+        if let Some(quiet_periods) = self.quiet_periods.remove(hostname) {
+            self.quiet_periods
+                .insert(new_hostname.to_string(), quiet_periods);
+        }
+        if self.paused.remove(hostname) {
+            self.paused.insert(new_hostname.to_string());
+        }
+    }
+    // What to call the target named `name` when showing it, its label if it has one.
+    fn label<'a>(&'a self, name: &'a str) -> &'a str {
+        return self
+            .targets
+            .iter()
+            .find(|target| target.name == name)
+            .map_or(name, probes::Target::label);
+    }
+    // The group the target named `name` is shown in, if it's in one.
+    fn group(&self, name: &str) -> Option<&str> {
+        return self
+            .targets
+            .iter()
+            .find(|target| target.name == name)
+            .and_then(|target| target.options.group.as_deref());
+    }
+    // How much of the target named `name`'s history to keep, its own `keep` option if it has one.
+    fn retention(&self, name: &str) -> config::Retention {
+        return self
+            .targets
+            .iter()
+            .find(|target| target.name == name)
+            .and_then(|target| target.options.retention)
+            .unwrap_or_else(|| config::get().retention());
+    }
+    fn add_entry(&mut self, hostname: &String, when: DateTime<Utc>, sample: probes::Sample) {
+        let retention = self.retention(hostname);
+        let (Some(ping_results), Some(rollups)) =
+            (self.data.get_mut(hostname), self.rollups.get_mut(hostname))
+        else {
+            return; // The target was removed
+        };
+        // Drop the oldest entries, more than one if retention was lowered by a config reload, and
+        // summarize them for long-term history.
+        let is_full = |ping_results: &BTreeMap<DateTime<Utc>, probes::Sample>| {
+            return match retention {
+                config::Retention::Entries(max_entries) => ping_results.len() >= max_entries,
+                config::Retention::Age(max_age) => {
+                    ping_results.first_key_value().is_some_and(|(oldest, _)| {
+                        *oldest < when - chrono_Duration::from_std(max_age).unwrap()
+                    })
+                }
+            };
+        };
+        while is_full(ping_results) {
+            let (expired_at, expired) = ping_results.pop_first().unwrap();
+            rollups.add(expired_at, &expired);
+        }
+        if let Some(loss_counters) = self.loss_counters.get_mut(hostname) {
+            loss_counters.add(when, &sample);
+        }
+        ping_results.insert(when, sample);
+    }
+    // Summarizes samples older than the target's retention as of `now`, and drops summaries and
+    // counts older than they're kept. Adding a sample does this too, this catches targets that
+    // aren't getting new samples, e.g. while they're paused.
+    fn expire(&mut self, hostname: &str, now: DateTime<Utc>) {
+        let retention = self.retention(hostname);
+        let (Some(ping_results), Some(rollups)) =
+            (self.data.get_mut(hostname), self.rollups.get_mut(hostname))
+        else {
+            return; // The target was removed
+        };
+        if let config::Retention::Age(max_age) = retention {
+            let oldest = now - chrono_Duration::from_std(max_age).unwrap();
+            while ping_results
+                .first_key_value()
+                .is_some_and(|(when, _)| *when < oldest)
+            {
+                let (expired_at, expired) = ping_results.pop_first().unwrap();
+                rollups.add(expired_at, &expired);
+            }
+        }
+        rollups.age_out(now);
+        if let Some(loss_counters) = self.loss_counters.get_mut(hostname) {
+            loss_counters.age_out(now);
+        }
+    }
+    // Returns when local connectivity was lost, if this outage means every target is now down.
+    fn add_outage(&mut self, hostname: &String, started: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let outages = self.outages.get_mut(hostname)?;
+        if outages.len() >= config::get().max_outages_saved {
+            outages.pop_first(); // Drop the oldest outage
+        }
+        outages.insert(
+            started,
+            Outage {
+                ended: None,
+                trace: None,
+                capture: None,
+                alerted: false,
+            },
         );
+        return self.add_connectivity_loss();
+    }
+    // Every target going down at about the same time is one loss of local connectivity, rather than
+    // a lot of unrelated outages. Records one if that's what just happened, and returns when it
+    // started.
+    fn add_connectivity_loss(&mut self) -> Option<DateTime<Utc>> {
+        // A single target going down doesn't say anything more.
+        if self.outages.len() < 2 {
+            return None;
+        }
+        let ongoing_outage_starts = self
+            .outages
+            .values()
+            .map(|outages| {
+                outages
+                    .iter()
+                    .next_back()
+                    .filter(|(_, outage)| outage.ended.is_none())
+                    .map(|(started, _)| *started)
+            })
+            .collect::<Option<Vec<DateTime<Utc>>>>()?;
+        let first_started = *ongoing_outage_starts.iter().min().unwrap();
+        let last_started = *ongoing_outage_starts.iter().max().unwrap();
+        if last_started - first_started
+            > chrono_Duration::seconds(config::CONNECTIVITY_LOSS_WINDOW_SEC)
+            || self.connectivity_losses.contains_key(&first_started)
+        {
+            return None;
+        }
+        if self.connectivity_losses.len() >= config::get().max_outages_saved {
+            self.connectivity_losses.pop_first(); // Drop the oldest loss
+        }
+        self.connectivity_losses
+            .insert(first_started, ConnectivityLoss { ended: None });
+        return Some(first_started);
+    }
+    // The loss of local connectivity an outage that started at `started` was part of, if any.
+    fn connectivity_loss_at(
+        &self,
+        started: DateTime<Utc>,
+    ) -> Option<(&DateTime<Utc>, &ConnectivityLoss)> {
+        return self
+            .connectivity_losses
+            .range(..=started)
+            .next_back()
+            .filter(|(loss_started, _)| {
+                started - **loss_started
+                    <= chrono_Duration::seconds(config::CONNECTIVITY_LOSS_WINDOW_SEC)
+            });
     }
-    if res != 0 {
-        eprintln!(
-            "\nFailed to apply BPF filter for IP {} and ID {} - ret {} errno {}\n",
-            src_ip_v4,
-            echo_id,
-            res,
-            std::io::Error::last_os_error().raw_os_error().unwrap()
+    // Records a target going quiet (or coming back) at `when`.
+    // `reason` is why it went quiet, e.g. "paused", or None if it came back.
+    fn set_quiet(&mut self, hostname: &String, when: DateTime<Utc>, reason: Option<&'static str>) {
+        let Some(quiet_periods) = self.quiet_periods.get_mut(hostname) else {
+            return; // The target was removed
+        };
+        // End the current stretch, if there is one.
+        if let Some((_, quiet_period)) = quiet_periods.iter_mut().next_back() {
+            quiet_period.ended.get_or_insert(when);
+        }
+        let Some(reason) = reason else {
+            return;
+        };
+        // Forget stretches from before the oldest sample kept, they're out of view.
+        if let Some(oldest_sample) = self.data[hostname].keys().next() {
+            quiet_periods.retain(|_, quiet_period| {
+                quiet_period
+                    .ended
+                    .is_none_or(|ended| ended >= *oldest_sample)
+            });
+        }
+        quiet_periods.insert(
+            when,
+            QuietPeriod {
+                ended: None,
+                reason,
+            },
         );
-        // We can't just panic, it'll just crash the thread. Exit the whole process.
-        std::process::exit(0x1);
     }
+    fn is_paused(&self, hostname: &str) -> bool {
+        return self.paused.contains(hostname);
+    }
+    fn add_public_ip(&mut self, when: DateTime<Utc>, public_ip: IpAddr) {
+        if self.public_ips.values().next_back() == Some(&public_ip) {
+            return;
+        }
+        if self.public_ips.len() >= config::get().max_public_ip_changes_saved {
+            self.public_ips.pop_first(); // Drop the oldest change
+        }
+        self.public_ips.insert(when, public_ip);
+    }
+    // Returns when local connectivity was lost, if this outage ending means it's back.
+    fn end_outage(
+        &mut self,
+        hostname: &String,
+        started: DateTime<Utc>,
+        ended: DateTime<Utc>,
+    ) -> Option<DateTime<Utc>> {
+        if let Some(outage) = self
+            .outages
+            .get_mut(hostname)
+            .and_then(|outages| outages.get_mut(&started))
+        {
+            outage.ended = Some(ended);
+        }
+        // Connectivity is back as soon as any target is reachable again.
+        let loss_started = *self
+            .connectivity_loss_at(started)
+            .filter(|(_, loss)| loss.ended.is_none())?
+            .0;
+        self.connectivity_losses
+            .get_mut(&loss_started)
+            .unwrap()
+            .ended = Some(ended);
+        return Some(loss_started);
+    }
+    // Whether to alert about the outage that started at `started`, after holding its alert in case
+    // every target was going down at once: not if a loss of local connectivity covers it, that has
+    // an alert of its own. Returns the outage if so, and records that it was alerted about.
+    fn alert_outage(&mut self, hostname: &String, started: DateTime<Utc>) -> Option<&Outage> {
+        if self.connectivity_loss_at(started).is_some() {
+            return None;
+        }
+        let outage = self
+            .outages
+            .get_mut(hostname)
+            .and_then(|outages| outages.get_mut(&started))?;
+        outage.alerted = true;
+        return Some(outage);
+    }
+    fn add_outage_trace(
+        &mut self,
+        hostname: &String,
+        started: DateTime<Utc>,
+        trace: Result<traceroute::Trace, String>,
+    ) {
+        if let Some(outage) = self
+            .outages
+            .get_mut(hostname)
+            .and_then(|outages| outages.get_mut(&started))
+        {
+            outage.trace = Some(trace);
+        }
+    }
+    fn add_outage_capture(
+        &mut self,
+        hostname: &String,
+        started: DateTime<Utc>,
+        capture: Result<(PathBuf, usize), String>,
+    ) {
+        if let Some(outage) = self
+            .outages
+            .get_mut(hostname)
+            .and_then(|outages| outages.get_mut(&started))
+        {
+            outage.capture = Some(capture);
+        }
+    }
+}
+
+// What's being probed and how it was configured, so targets can be changed at runtime.
+struct Monitor {
+    args: cli::MonitorArgs,
+    ping_data: Arc<Mutex<PingData>>,
+    // Tells the thread probing each target to stop, by target name.
+    stop_probing: HashMap<String, Arc<AtomicBool>>,
+    // Targets added with the admin API by spec, and removed by name. They stay added and removed
+    // when the config is reloaded.
+    added_specs: Vec<String>,
+    removed_names: HashSet<String>,
+    // The order targets were put in with the admin API, by name. Targets it doesn't mention go last.
+    order: Option<Vec<String>>,
+}
+impl Monitor {
+    // Monitors the targets in `config`, with the admin API's changes on top.
+    // Returns the names of the targets that were added and removed, or every problem with them (in
+    // which case nothing changes).
+    fn reconfigure(
+        &mut self,
+        config: &config::Config,
+    ) -> Result<(Vec<String>, Vec<String>), Vec<String>> {
+        let targets = self.targets_for(config, &self.added_specs, &self.removed_names)?;
+        return Ok(update_targets(
+            targets,
+            &self.ping_data,
+            &mut self.stop_probing,
+        ));
+    }
+
+    // The targets in `config` with the targets `added_specs` added and those in `removed_names`
+    // removed, in the order they were put in. Or every problem with them.
+    fn targets_for(
+        &self,
+        config: &config::Config,
+        added_specs: &[String],
+        removed_names: &HashSet<String>,
+    ) -> Result<Vec<probes::Target>, Vec<String>> {
+        // Unless the target has since been added to the config too.
+        let added_specs: Vec<String> = added_specs
+            .iter()
+            .filter(|spec| !config.targets.contains(spec) && !self.args.targets.contains(spec))
+            .cloned()
+            .collect();
+        let mut targets = configured_targets(config, &self.args, &added_specs)?;
+        targets.retain(|target| !removed_names.contains(&target.name));
+        if let Some(order) = &self.order {
+            targets.sort_by_key(|target| {
+                order
+                    .iter()
+                    .position(|name| *name == target.name)
+                    .unwrap_or(order.len())
+            });
+        }
+        return Ok(targets);
+    }
+
+    // Starts monitoring the target `spec`, alongside the current targets.
+    // Returns how many targets it was expanded into, or every problem with it.
+    fn add(&mut self, spec: &str) -> Result<usize, Vec<String>> {
+        let default_options = self
+            .args
+            .default_target_options()
+            .map_err(|err| vec![err])?;
+        let new_targets = probes::Target::parse(spec, &default_options)
+            .and_then(probes::Target::expand)
+            .map_err(|err| vec![err])?;
+        let mut targets = self.ping_data.lock().unwrap().targets.clone();
+        let mut problems = Vec::new();
+        for new_target in &new_targets {
+            if targets.iter().any(|target| target.name == new_target.name) {
+                problems.push(format!("{} is already monitored", new_target.name));
+            }
+            problems.extend(new_target.problems());
+        }
+        if !problems.is_empty() {
+            return Err(problems);
+        }
+        for new_target in &new_targets {
+            self.removed_names.remove(&new_target.name);
+        }
+        self.added_specs.push(spec.to_string());
+        targets.extend(new_targets);
+        let (added, _) = update_targets(targets, &self.ping_data, &mut self.stop_probing);
+        return Ok(added.len());
+    }
+
+    // Replaces the target named `name` with the target `spec`, which keeps its history.
+    fn edit(&mut self, name: &str, spec: &str) -> Result<(), Vec<String>> {
+        let mut targets = self.ping_data.lock().unwrap().targets.clone();
+        let Some(position) = targets.iter().position(|target| target.name == name) else {
+            return Err(vec![format!("{} isn't a monitored target", name)]);
+        };
+        if spec == name {
+            return Ok(());
+        }
+        let default_options = self
+            .args
+            .default_target_options()
+            .map_err(|err| vec![err])?;
+        let new_target = probes::Target::parse(spec, &default_options)
+            .and_then(probes::Target::expand)
+            .map_err(|err| vec![err])?;
+        // Edits can't split a target in two, there'd be no telling which should keep its history.
+        let [new_target] = &new_target[..] else {
+            return Err(vec![format!(
+                "{} would be split into several targets, add it as a new target instead",
+                spec
+            )]);
+        };
+        let mut problems = new_target.problems();
+        if targets.iter().any(|target| target.name == new_target.name) {
+            problems.push(format!("{} is already monitored", new_target.name));
+        }
+        if !problems.is_empty() {
+            return Err(problems);
+        }
+        match self.added_specs.iter().position(|added| added == name) {
+            Some(added) => self.added_specs[added] = spec.to_string(),
+            None => {
+                self.removed_names.insert(name.to_string());
+                self.added_specs.push(spec.to_string());
+            }
+        }
+        if let Some(order) = &mut self.order {
+            for ordered in order.iter_mut().filter(|ordered| *ordered == name) {
+                *ordered = spec.to_string();
+            }
+        }
+        self.ping_data
+            .lock()
+            .unwrap()
+            .rename_hostname(name, &new_target.name);
+        targets[position] = new_target.clone();
+        update_targets(targets, &self.ping_data, &mut self.stop_probing);
+        return Ok(());
+    }
+
+    // Moves the target named `name` to `position` in the list, counting from 0.
+    // Returns where it was and where it ended up, None if it wasn't being monitored.
+    fn move_to(&mut self, name: &str, position: usize) -> Option<(usize, usize)> {
+        let mut targets = self.ping_data.lock().unwrap().targets.clone();
+        let current = targets.iter().position(|target| target.name == name)?;
+        let target = targets.remove(current);
+        let position = cmp::min(position, targets.len());
+        targets.insert(position, target);
+        self.order = Some(targets.iter().map(|target| target.name.clone()).collect());
+        update_targets(targets, &self.ping_data, &mut self.stop_probing);
+        return Some((current, position));
+    }
+
+    // Stops monitoring the target named `name`, and drops its data.
+    // Returns whether it was being monitored.
+    fn remove(&mut self, name: &str) -> bool {
+        let mut targets = self.ping_data.lock().unwrap().targets.clone();
+        let Some(position) = targets.iter().position(|target| target.name == name) else {
+            return false;
+        };
+        targets.remove(position);
+        // Targets from the config are remembered, so reloading it doesn't bring them back.
+        match self.added_specs.iter().position(|spec| spec == name) {
+            Some(added) => {
+                self.added_specs.remove(added);
+            }
+            None => {
+                self.removed_names.insert(name.to_string());
+            }
+        }
+        update_targets(targets, &self.ping_data, &mut self.stop_probing);
+        return true;
+    }
+}
+
+// A time every target was down at once.
+struct ConnectivityLoss {
+    // When the first target came back, None while it's ongoing.
+    ended: Option<DateTime<Utc>>,
+}
+
+// A stretch of time a target wasn't probed, e.g. during quiet hours.
+struct QuietPeriod {
+    // When the target started being probed again, None while it's ongoing.
+    ended: Option<DateTime<Utc>>,
+    // Why it wasn't probed, e.g. "quiet hours".
+    reason: &'static str,
+}
+
+// A stretch of consecutive failed probes.
+struct Outage {
+    // When the first successful probe after the outage was sent, None while it's ongoing.
+    ended: Option<DateTime<Utc>>,
+    // The path to the host as the outage was detected, None until the traceroute finishes.
+    trace: Option<Result<traceroute::Trace, String>>,
+    // Where ICMP traffic was captured to as the outage was detected, and how many packets were
+    // captured. None until the capture finishes, or if the target doesn't capture traffic.
+    capture: Option<Result<(PathBuf, usize), String>>,
+    // Whether an alert went out for it, so its resolution goes out too. Alerts are held for a while
+    // first, and dropped if every target turned out to be going down at once.
+    alerted: bool,
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    // Skip the program name, all other command line args are hosts to ping.
-    let hostnames_to_ping: Vec<String> = std::env::args().skip(1).collect();
+    let args = match cli::parse() {
+        cli::Command::Monitor(args) => *args,
+        cli::Command::Discover => {
+            let devices = discovery::discover();
+            if devices.is_empty() {
+                println!("No devices answered");
+            }
+            for device in devices {
+                println!(
+                    "{} (IP: {}) via {} {}",
+                    device.name, device.ip, device.found_via, device.details
+                );
+            }
+            return Ok(());
+        }
+        cli::Command::Trace { host } => {
+            let trace = probes::resolve(&host).and_then(traceroute::trace);
+            match trace {
+                Ok(trace) => {
+                    for hop in trace.hops {
+                        println!(
+                            "{:>2}  {}  {}",
+                            hop.ttl,
+                            match (hop.responder, hop.responder_hostname) {
+                                (Some(responder), Some(hostname)) =>
+                                    format!("{} ({})", hostname, responder),
+                                (Some(responder), None) => responder.to_string(),
+                                (None, _) => "*".to_string(),
+                            },
+                            match hop.rtt {
+                                Some(rtt) => format!("{:.1} ms", rtt.as_secs_f64() * 1000.0),
+                                None => "".to_string(),
+                            }
+                        );
+                    }
+                    return Ok(());
+                }
+                Err(err) => {
+                    eprintln!("Failed to trace the path to {} - {}", host, err);
+                    std::process::exit(0x1);
+                }
+            }
+        }
+        // Copy history out of (or into) a running monitor, e.g. to move it to another host.
+        cli::Command::Export {
+            url,
+            parquet_dir: Some(parquet_dir),
+        } => match snapshot::export_parquet(&url, &parquet_dir) {
+            Ok(num_files) => {
+                println!("Wrote {} files to {}", num_files, parquet_dir.display());
+                return Ok(());
+            }
+            Err(err) => {
+                eprintln!("Failed to export from {} - {}", url, err);
+                std::process::exit(0x1);
+            }
+        },
+        cli::Command::Export {
+            url,
+            parquet_dir: None,
+        } => match snapshot::download(&url, "/api/snapshot") {
+            Ok(snapshot) => {
+                println!("{}", String::from_utf8_lossy(&snapshot));
+                return Ok(());
+            }
+            Err(err) => {
+                eprintln!("Failed to export from {} - {}", url, err);
+                std::process::exit(0x1);
+            }
+        },
+        cli::Command::Import { path, url } => {
+            upload_file(&path, &url, "/api/snapshot", "application/json");
+            return Ok(());
+        }
+        cli::Command::Backup { url } => match snapshot::download(&url, "/api/backup") {
+            Ok(backup) => {
+                std::io::stdout().write_all(&backup)?;
+                return Ok(());
+            }
+            Err(err) => {
+                eprintln!("Failed to back up {} - {}", url, err);
+                std::process::exit(0x1);
+            }
+        },
+        cli::Command::Restore { path, url } => {
+            upload_file(&path, &url, "/api/restore", "application/x-tar");
+            return Ok(());
+        }
+        // Alternatively, act as the remote end for `udp://` targets.
+        cli::Command::UdpEchoResponder { port } => probes::udp::run_echo_responder(port),
+    };
+
+    // Settings can be changed with a config file, see `config::Config`, and on the command line.
+    match load_config(&args) {
+        Ok(config) => config::set(config),
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(0x2);
+        }
+    }
 
     let ping_data = Arc::new(Mutex::new(PingData {
-        hostnames_in_order: hostnames_to_ping.clone(),
+        targets: Vec::new(),
+        hostnames_in_order: Vec::new(),
         data: BTreeMap::new(),
+        rollups: BTreeMap::new(),
+        loss_counters: BTreeMap::new(),
+        outages: BTreeMap::new(),
+        connectivity_losses: BTreeMap::new(),
+        quiet_periods: BTreeMap::new(),
+        paused: HashSet::new(),
+        public_ips: BTreeMap::new(),
     }));
 
-    if hostnames_to_ping.is_empty() {
-        panic!("\nPlease provide hostnames to ping as command line args.\n");
+    if let Some(public_ip_source) = args.public_ip_source.clone() {
+        let ping_data_threadlocal = ping_data.clone();
+        thread::spawn(move || {
+            public_ip::repeatedly_discover(public_ip_source, ping_data_threadlocal)
+        });
+    }
+
+    let mut monitor = Monitor {
+        args,
+        ping_data: ping_data.clone(),
+        stop_probing: HashMap::new(),
+        added_specs: Vec::new(),
+        removed_names: HashSet::new(),
+        order: None,
+    };
+    // Report every problem with the targets at once, so they can all be fixed before trying again.
+    match monitor.reconfigure(&config::get()) {
+        // Targets can be added later with the admin API, if it's on.
+        Ok((added, _)) if added.is_empty() && !config::get().admin_api => {
+            eprintln!(
+                "Please provide targets to monitor, the default gateway couldn't be found.\n\n{}",
+                cli::usage()
+            );
+            std::process::exit(0x2);
+        }
+        Ok(_) => {}
+        Err(problems) => {
+            eprintln!("{}", describe_problems(&problems));
+            std::process::exit(0x2);
+        }
     }
+    let monitor = Arc::new(Mutex::new(monitor));
 
-    for hostname in hostnames_to_ping {
-        ping_data.lock().unwrap().add_hostname(&hostname);
-        let hostname_threadlocal = hostname.to_string();
+    {
         let ping_data_threadlocal = ping_data.clone();
-        thread::spawn(move || repeatedly_ping(hostname_threadlocal, ping_data_threadlocal));
+        thread::spawn(move || repeatedly_expire(ping_data_threadlocal));
+    }
+
+    // Load history from before a restart, and keep storing it.
+    match storage::open(&config::get()) {
+        Ok(Some(mut backend)) => {
+            let flushed_until = storage::replay(backend.as_mut(), &ping_data);
+            let ping_data_threadlocal = ping_data.clone();
+            thread::spawn(move || {
+                storage::repeatedly_flush(backend, ping_data_threadlocal, flushed_until)
+            });
+        }
+        Ok(None) => {}
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(0x2);
+        }
+    }
+
+    // Reload the config on SIGHUP, to add and remove targets without a restart.
+    {
+        let monitor = monitor.clone();
+        let web_ui_address = config::get().web_ui_address;
+        let web_ui_port = config::get().web_ui_port;
+        signals::on_sighup(move || {
+            let mut monitor = monitor.lock().unwrap();
+            let config = match load_config(&monitor.args) {
+                Ok(config) => config,
+                Err(err) => {
+                    eprintln!("{}, keeping the current config", err);
+                    return;
+                }
+            };
+            match monitor.reconfigure(&config) {
+                Ok((added, removed)) => {
+                    let changes = describe_changes(&added, &removed);
+                    eprintln!("Reloaded the config, {}", changes);
+                    audit::record("SIGHUP", "reload config", None, None, Some(changes));
+                }
+                Err(problems) => {
+                    eprintln!(
+                        "{}, keeping the current config",
+                        describe_problems(&problems)
+                    );
+                    return;
+                }
+            }
+            if config.web_ui_address != web_ui_address || config.web_ui_port != web_ui_port {
+                eprintln!("The web UI's address and port only change after a restart");
+            }
+            config::set(config);
+        });
     }
 
     let ping_data_read_clone = web::Data::new(Arc::clone(&ping_data));
-    return HttpServer::new(move || {
+    let monitor_clone = web::Data::new(monitor);
+    let server = HttpServer::new(move || {
         App::new()
             .app_data(ping_data_read_clone.clone())
+            .app_data(monitor_clone.clone())
+            .wrap(middleware::from_fn(cache::respond))
+            // Compress responses, which matters when the dashboard is viewed over the link being
+            // monitored.
+            .wrap(middleware::Compress::default())
+            // Outside those, so nothing is answered (even from the cache) without logging in.
+            .wrap(middleware::from_fn(auth::check))
+            // Outside that, since browsers ask other sites' permission to use the API without
+            // credentials, and other sites' changes are turned away before they're let in.
+            .wrap(middleware::from_fn(cors::respond))
+            // Outermost, so even requests without credentials are limited.
+            .wrap(middleware::from_fn(limits::check))
+            .app_data(web::PayloadConfig::new(limits::MAX_BODY_BYTES))
+            .app_data(web::QueryConfig::default().error_handler(query_error))
             .route("/", web::get().to(index))
-    })
-    .bind(("0.0.0.0", config::WEB_UI_PORT))?
-    .run()
-    .await;
-}
-
-// Repeatedly pings a destination hostname.
-fn repeatedly_ping(hostname: String, ping_data: Arc<Mutex<PingData>>) {
-    // Set up this thread's ping metadata.
-    let unique_threadlocal_id: u16 = rand::thread_rng().gen::<u16>();
-    let mut sequence_number: u16 = 0;
-    // Determine destination.
-    // Only IPv4 is supported, the BPF filter and various header parsing depends on it.
-    let dest_ip_v4 = *lookup_host(&hostname)
-        .unwrap()
-        .into_iter()
-        .filter(|ip| match ip {
-            IpAddr::V4(_) => true,
-            _ => false,
-        })
-        .map(|ip| match ip {
-            IpAddr::V4(ip_v4) => ip_v4,
-            _ => unreachable!(),
-        })
-        .collect::<Vec<Ipv4Addr>>()
-        .first()
-        .unwrap();
-    let dest_addr_v1 = SocketAddr::new(IpAddr::V4(dest_ip_v4), 0);
-    let dest_addr_v2: socket2::SockAddr = dest_addr_v1.into();
-    // Set up a socket.
-    // This is a raw ICMPv4 socket, it will recv all ICMP traffic to this host.
-    // We will apply filters to make it behave more reasonably.
-    let socket = Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::ICMPV4)).unwrap();
-    // Apply filters so we only recv and process relevant packets.
-    filter_icmp_replies(
-        &socket,
-        dest_ip_v4,
-        std::mem::size_of::<IcmpEchoMessage>(),
-        unique_threadlocal_id,
-    );
-    // Set the ping timeout.
-    let ping_timeout = Duration::from_millis(config::PING_TIMEOUT_MSEC);
-    socket.set_write_timeout(Some(ping_timeout)).unwrap();
-    socket.set_read_timeout(Some(ping_timeout)).unwrap();
-    // Log important details.
-    println!(
-        "Pinging host {} (IP: {}) using ID {}",
-        hostname, dest_ip_v4, unique_threadlocal_id
-    );
-    // Ping repeatedly.
-    loop {
-        sequence_number += 1;
-        let start_time = Utc::now();
-        let deadline = start_time + chrono_Duration::from_std(ping_timeout).unwrap();
-        // Construct an ICMP Ping message.
-        let request = IcmpEchoMessage::new(unique_threadlocal_id, sequence_number);
-        // Send the ping.
-        let send_res = socket.send_to(&request.serialize(), &dest_addr_v2);
-        match send_res {
-            Ok(_size) => {}
-            Err(err) => eprintln!("Error while sending to {} - {:?}", dest_ip_v4, err),
-        }
-        // Wait for the response.
-        // We are using a raw ICMP socket. Even with filters may see ICMPv4 Echo Replies meant for other
-        // threads or processes. Thus, we recv in a loop until our remote's response is the one we recv.
-        let mut response_recvd: bool = false;
-        while Utc::now() < deadline && !response_recvd {
-            let mut recv_buf = [MaybeUninit::new(0); 1024];
-            let recv_res = socket.recv_from(&mut recv_buf);
-            response_recvd = match recv_res {
-                Ok((size, _origin_addr)) => {
-                    let response_buf = &unsafe { MaybeUninit::slice_assume_init_ref(&recv_buf) }
-                        [IP_HEADER_SIZE..size];
-                    let response = IcmpEchoMessage::from(&response_buf);
-                    let matching_response_found: bool = response.msg_type == 0
-                        && response.code == 0
-                        && response.identifier == unique_threadlocal_id
-                        && response.sequence_number == sequence_number;
-                    if !matching_response_found {
-                        eprintln!(
-                            "An unexpected message got through the BPF filter: {:?}. Expected code={} id={} seq={}.",
-                            response,
-                            0,
-                            unique_threadlocal_id,
-                            sequence_number
-                        );
+            .route("/summary", web::get().to(summary))
+            .route("/embed", web::get().to(embed))
+            .route("/trace/{host}", web::get().to(trace))
+            .route("/outages", web::get().to(outages))
+            .route("/outages.atom", web::get().to(outages_feed))
+            .route("/histogram", web::get().to(histogram))
+            .route("/loss", web::get().to(loss))
+            .route("/heatmap", web::get().to(heatmap))
+            // Target names can have slashes in them, e.g. URLs.
+            .route("/host/{name:.+}", web::get().to(host))
+            .route("/discover", web::get().to(discover))
+            .route("/settings", web::get().to(settings))
+            .route("/static/{name}", web::get().to(assets::serve))
+            .route("/timezone", web::get().to(pick_zone))
+            .route("/preferences", web::get().to(preferences))
+            .route("/preferences", web::post().to(save_preferences))
+            .route("/login", web::get().to(oidc::login))
+            .route("/oidc/callback", web::get().to(oidc::callback))
+            .route("/logout", web::get().to(oidc::logout))
+            .route("/api/targets", web::get().to(list_targets))
+            .route("/api/targets", web::post().to(add_target))
+            .route("/api/targets", web::delete().to(remove_target))
+            .route("/api/targets/remove", web::post().to(remove_target))
+            .service(
+                web::resource("/api/targets/edit")
+                    .app_data(web::FormConfig::default().error_handler(form_error))
+                    .route(web::post().to(edit_target)),
+            )
+            .route("/api/targets/move", web::post().to(move_target))
+            .route("/api/targets/pause", web::post().to(pause_target))
+            .route("/api/targets/resume", web::post().to(resume_target))
+            .route("/api/samples", web::get().to(stream_samples))
+            .route("/events", web::get().to(sample_events))
+            .route("/ws", web::get().to(sample_websocket))
+            .route("/api/loss", web::get().to(loss_series))
+            .route("/api/stats/self", web::get().to(self_stats))
+            .route("/debug", web::get().to(debug))
+            .route("/metrics", web::get().to(prometheus_metrics))
+            .route("/api/openapi.json", web::get().to(openapi_document))
+            .route("/api/hosts", web::get().to(list_hosts))
+            // Target names can have slashes in them, e.g. URLs.
+            .route("/api/hosts/{host:.+}/pings", web::get().to(host_pings))
+            .route(
+                "/api/hosts/{host:.+}/histogram",
+                web::get().to(host_histogram),
+            )
+            .route(
+                "/api/hosts/{host:.+}/aggregate",
+                web::get().to(host_aggregate),
+            )
+            .route("/api/backup", web::get().to(backup))
+            .route("/api/audit", web::get().to(audit_log))
+            .service(
+                web::resource("/api/restore")
+                    .app_data(web::PayloadConfig::new(MAX_SNAPSHOT_BYTES))
+                    .route(web::post().to(restore)),
+            )
+            .service(
+                web::resource("/api/snapshot")
+                    // Snapshots are much bigger than the other requests.
+                    .app_data(web::PayloadConfig::new(MAX_SNAPSHOT_BYTES))
+                    .route(web::get().to(export_snapshot))
+                    .route(web::post().to(import_snapshot)),
+            )
+    });
+    let address = (config::get().web_ui_address, config::get().web_ui_port);
+    let server = match https::server_config(&config::get()) {
+        Ok(Some(tls_config)) => server.bind_rustls_0_23(address, tls_config)?,
+        Ok(None) => server.bind(address)?,
+        Err(err) => {
+            eprintln!("Failed to serve the web UI over HTTPS - {}", err);
+            std::process::exit(0x1);
+        }
+    };
+    return server.run().await;
+}
+
+// Loads the config file, if there is one, with settings given as environment variables and then on
+// the command line on top.
+fn load_config(args: &cli::MonitorArgs) -> Result<config::Config, String> {
+    let text = match &args.config_path {
+        Some(config_path) => Some(
+            std::fs::read_to_string(config_path)
+                .map_err(|err| format!("Failed to load config {} - {}", config_path, err))?,
+        ),
+        None => None,
+    };
+    return config_with_overrides(args, text.as_deref());
+}
+
+// Like `load_config`, with `text` in place of the config file.
+fn config_with_overrides(
+    args: &cli::MonitorArgs,
+    text: Option<&str>,
+) -> Result<config::Config, String> {
+    let mut config = match text {
+        Some(text) => config::Config::parse(text).map_err(|err| {
+            format!(
+                "Failed to load config {} - {}",
+                args.config_path.as_deref().unwrap_or_default(),
+                err
+            )
+        })?,
+        None => config::Config::default(),
+    };
+    config
+        .apply_env(std::env::vars())
+        .map_err(|err| format!("Failed to load settings from the environment - {}", err))?;
+    args.apply(&mut config);
+    return Ok(config);
+}
+
+// Sends the file at `path` to `api_path` of the monitor at `url`, for `import` and `restore`, and
+// prints what it made of it.
+fn upload_file(path: &str, url: &str, api_path: &str, content_type: &str) {
+    let uploaded = std::fs::read(path)
+        .map_err(|err| format!("Failed to read {} - {}", path, err))
+        .and_then(|body| {
+            snapshot::upload(url, api_path, content_type, &body)
+                .map_err(|err| format!("Failed to send {} to {} - {}", path, url, err))
+        });
+    match uploaded {
+        Ok(message) => print!("{}", message),
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(0x1);
+        }
+    }
+}
+
+// The targets to monitor, from the config, the command line and `extra_specs`, or every problem
+// with them.
+fn configured_targets(
+    config: &config::Config,
+    args: &cli::MonitorArgs,
+    extra_specs: &[String],
+) -> Result<Vec<probes::Target>, Vec<String>> {
+    let mut problems = Vec::new();
+    let default_options = args.default_target_options().unwrap_or_else(|err| {
+        problems.push(err);
+        return probes::TargetOptions::default();
+    });
+    let default_options = &default_options;
+    // Targets can be given on the command line as a shorthand for listing them in the config file.
+    let mut targets: Vec<probes::Target> = Vec::new();
+    for spec in config
+        .targets
+        .iter()
+        .chain(&args.targets)
+        .chain(extra_specs)
+    {
+        match probes::Target::parse(spec, default_options).and_then(probes::Target::expand) {
+            Ok(expanded) => targets.extend(expanded),
+            Err(err) => problems.push(err),
+        }
+    }
+    // Nearly every problem starts with checking whether the router is reachable, so ping it first.
+    if !args.no_gateway {
+        match discovery::default_gateway() {
+            Some(gateway) => match probes::Target::parse(&gateway.to_string(), default_options) {
+                Ok(gateway_target) => {
+                    let mut gateway_target = probes::Target {
+                        name: format!("{} (default gateway)", gateway),
+                        ..gateway_target
+                    };
+                    gateway_target.options.tier = Some(probes::Tier::Gateway);
+                    // Unless it's already a target.
+                    if !targets.iter().any(|target| {
+                        target.kind == gateway_target.kind
+                            && target.hostname == gateway_target.hostname
+                    }) {
+                        targets.insert(0, gateway_target);
                     }
-                    matching_response_found
-                }
-                Err(err) => {
-                    eprintln!("Error while recving from {} - {:?}", dest_ip_v4, err);
-                    false
                 }
-            }
+                Err(err) => problems.push(err),
+            },
+            None => eprintln!("Couldn't find the default gateway to monitor it"),
         }
-        // Determine how long the round trip took.
-        let ping_duration = (Utc::now() - start_time).to_std().unwrap();
-        // Store the ping duration.
-        ping_data
-            .lock()
-            .unwrap()
-            .add_entry(&hostname, start_time, ping_duration);
-        // Wait for the ping interval to elapse and repeat.
-        let next_ping_time =
-            start_time + chrono_Duration::seconds(config::SEC_BETWEEN_PINGS as i64);
-        let cur_time = Utc::now();
-        if next_ping_time > cur_time {
-            thread::sleep((next_ping_time - cur_time).to_std().unwrap());
+    }
+    // Targets are told apart by name, so two with the same name would share their data.
+    let mut names = HashSet::new();
+    for target in &targets {
+        if !names.insert(&target.name) {
+            problems.push(format!("{} is listed more than once", target.name));
+        }
+        problems.extend(target.problems());
+    }
+    if !problems.is_empty() {
+        return Err(problems);
+    }
+    return Ok(targets);
+}
+
+// Lists problems with the config, one per line.
+fn describe_problems(problems: &[String]) -> String {
+    let noun = if problems.len() == 1 {
+        "problem"
+    } else {
+        "problems"
+    };
+    let mut description = format!("Found {} {} with the targets:", problems.len(), noun);
+    for problem in problems {
+        description += &format!("\n  - {}", problem);
+    }
+    return description;
+}
+
+// e.g. `2 targets added (1.1.1.1, 8.8.8.8) and 0 removed`, for the log and the audit log.
+fn describe_changes(added: &[String], removed: &[String]) -> String {
+    let names = |names: &[String]| match names {
+        [] => String::new(),
+        names => format!(" ({})", names.join(", ")),
+    };
+    return format!(
+        "{} targets added{} and {} removed{}",
+        added.len(),
+        names(added),
+        removed.len(),
+        names(removed)
+    );
+}
+
+// Starts probing targets that are new, and stops probing targets that are gone. Targets that haven't
+// changed keep being probed, and keep their data.
+// Returns the names of the targets that were added and removed.
+fn update_targets(
+    new_targets: Vec<probes::Target>,
+    ping_data: &Arc<Mutex<PingData>>,
+    stop_probing: &mut HashMap<String, Arc<AtomicBool>>,
+) -> (Vec<String>, Vec<String>) {
+    let mut locked_ping_data = ping_data.lock().unwrap();
+    let old_targets = std::mem::take(&mut locked_ping_data.targets);
+    let removed: Vec<&probes::Target> = old_targets
+        .iter()
+        .filter(|target| !new_targets.contains(target))
+        .collect();
+    for target in &removed {
+        if let Some(stop) = stop_probing.remove(&target.name) {
+            stop.store(true, Ordering::SeqCst);
+        }
+        locked_ping_data.remove_hostname(&target.name);
+    }
+    // Spread targets' first probes evenly over the interval, by where they are in the list, so
+    // targets aren't all probed at the same instant.
+    let added: Vec<(f64, &probes::Target)> = new_targets
+        .iter()
+        .enumerate()
+        .filter(|(_, target)| !old_targets.contains(target))
+        .map(|(position, target)| (position as f64 / new_targets.len() as f64, target))
+        .collect();
+    for &(phase, target) in &added {
+        locked_ping_data.add_hostname(&target.name);
+        let stop = Arc::new(AtomicBool::new(false));
+        stop_probing.insert(target.name.clone(), stop.clone());
+        let target_threadlocal = target.clone();
+        let ping_data_threadlocal = ping_data.clone();
+        thread::spawn(move || {
+            probes::repeatedly_probe(target_threadlocal, phase, ping_data_threadlocal, stop)
+        });
+    }
+    let added_names = added
+        .iter()
+        .map(|(_, target)| target.name.clone())
+        .collect();
+    let removed_names = removed.iter().map(|target| target.name.clone()).collect();
+    locked_ping_data.hostnames_in_order = new_targets
+        .iter()
+        .map(|target| target.name.clone())
+        .collect();
+    locked_ping_data.targets = new_targets;
+    return (added_names, removed_names);
+}
+
+// Enforces retention for every target every `RETENTION_CHECK_SEC`, see `PingData::expire`.
+fn repeatedly_expire(ping_data: Arc<Mutex<PingData>>) {
+    loop {
+        thread::sleep(Duration::from_secs(config::RETENTION_CHECK_SEC));
+        let now = Utc::now();
+        let mut locked_ping_data = ping_data.lock().unwrap();
+        for hostname in locked_ping_data.hostnames_in_order.clone() {
+            locked_ping_data.expire(&hostname, now);
         }
     }
 }
@@ -446,177 +1102,4002 @@ fn repeatedly_ping(hostname: String, ping_data: Arc<Mutex<PingData>>) {
 // The web UI.
 const START_OFFSET_PARAM: &str = "start_offset";
 const HOW_MUCH_DATA: &str = "how_much_data";
-async fn index(req: HttpRequest, ping_data: web::Data<Arc<Mutex<PingData>>>) -> HttpResponse {
-    let cur_time = Utc::now();
-    let offset_params = Query::<HashMap<String, String>>::from_query(req.query_string()).unwrap();
-    let start_offset = match offset_params.get(START_OFFSET_PARAM) {
-        Some(start_offset) => parse(start_offset.as_str()).unwrap(),
-        None => Duration::from_secs(0), // Default to now.
-    };
-    let newest_timestamp_in_scope = cur_time - chrono_Duration::from_std(start_offset).unwrap();
-    let how_much_data = match offset_params.get(HOW_MUCH_DATA) {
-        Some(end_offset) => parse(end_offset.as_str()).unwrap(),
-        None => Duration::from_secs(60 * 60 * 6), // Default to 6 hours of data.
-    };
-    let oldest_timestamp_in_scope =
-        newest_timestamp_in_scope - chrono_Duration::from_std(how_much_data).unwrap();
-
-    let mut html = String::new();
-
-    // Style the tables.
-    html += "
-    <meta name=\"viewport\" content=\"width=1200, initial-scale=1\">
-    <style>
-    * {
-        // Reset default margin & padding
-        margin:0;
-        padding:0;
-    }
-    html,body {
-        position:relative;
-    }
-    .root {
-        width:1200px;
-    }
-    table {
-        width: 100%;
-        table-layout: fixed;
-        overflow: hidden;
-        margin: 0 auto;
-        border-collapse: collapse;
-    }
-    table {
-        color: black;
-        background: white;
-        border: 1px solid grey;
-    }
-    table caption {
-        padding:.5em;
-    }
-    table th,
-    table td {
-        white-space: nowrap;
-        overflow: hidden;
-        text-overflow: clip;
-        padding: .5em;
-        border: 1px solid lightgrey;
-    }
-    table tr .TimedOut {
-        color: red;
-    }
-    table tr .NewDay {
-        border-top: 20px solid black;
-    }
-    table tr .NewHour {
-        border-top: 10px solid black;
-    }
-    table tr .NewMinute {
-        border-top: 2px solid black;
-    }
-    </style>";
-
-    html += format!(
-        "<a style=\"float: left\" href=\"/?start_offset={:?}&how_much_data={:?}\">❮ newer data</a>",
-        if start_offset < how_much_data {
-            Duration::from_secs(0)
-        } else {
-            start_offset - how_much_data
-        },
-        how_much_data
-    )
-    .as_str();
-    html += format!(
-        "<a style=\"float: right\" href=\"/?start_offset={:?}&how_much_data={:?}\">older data ❯</a>",
-        (start_offset + how_much_data),
-        how_much_data
-    )
-    .as_str();
-
-    // Create a table to display the data.
-    html += "<table class=\"root\"><thead><tr>";
+const TARGET_PARAM: &str = "target";
+const BY_PARAM: &str = "by";
+const METRIC_PARAM: &str = "metric";
+const DAYS_PARAM: &str = "days";
+const COMPARE_PARAM: &str = "compare";
+const BUCKET_PARAM: &str = "bucket";
+const FN_PARAM: &str = "fn";
+const LIMIT_PARAM: &str = "limit";
+const CURSOR_PARAM: &str = "cursor";
+const FROM_PARAM: &str = "from";
+const TO_PARAM: &str = "to";
+const TZ_PARAM: &str = "tz";
+const HOSTS_PARAM: &str = "hosts";
+const UNITS_PARAM: &str = "units";
+const REFRESH_PARAM: &str = "refresh";
+// The most samples a page of `/api/hosts/{host}/pings` can have.
+const MAX_PAGE_SAMPLES: usize = 100_000;
+// How many of a target's latest failures and outage traces its page shows.
+const HOST_PAGE_FAILURES: usize = 20;
+const HOST_PAGE_TRACES: usize = 5;
+// The time zone viewers' browsers are in (set by `static/timezone.js`).
+const BROWSER_TZ_COOKIE: &str = "browser_tz";
+// The choices the preferences page offers for how long the timeline covers by default, and how often
+// pages showing the latest samples update.
+const PREFERRED_SPANS: &[(&str, &str)] = &[
+    ("the page's default", ""),
+    ("the last hour", "1h"),
+    ("the last 6 hours", "6h"),
+    ("the last day", "1d"),
+    ("the last week", "1w"),
+];
+const REFRESH_CHOICES: &[(&str, &str)] = &[
+    ("every second", "1s"),
+    ("every 5 seconds", "5s"),
+    ("every 30 seconds", "30s"),
+    ("every minute", "1m"),
+    ("never", "off"),
+];
+async fn index(
+    req: HttpRequest,
+    range_query: web::Query<TimeRangeQuery>,
+    compare_query: web::Query<CompareQuery>,
+    ping_data: web::Data<Arc<Mutex<PingData>>>,
+) -> HttpResponse {
+    // Default to the last 6 hours.
+    let range = match time_range(&req, &range_query, Duration::from_secs(60 * 60 * 6)) {
+        Ok(range) => range,
+        Err(err) => return admin_response(&req, StatusCode::BAD_REQUEST, err),
+    };
+    let (units, refresh) = match units(&req).and_then(|units| Ok((units, refresh(&req)?))) {
+        Ok(preferences) => preferences,
+        Err(err) => return admin_response(&req, StatusCode::BAD_REQUEST, err),
+    };
+    let newest_timestamp_in_scope = range.newest;
+    let oldest_timestamp_in_scope = range.oldest;
+    // Overlay the same stretch of time from a day or a week before on the charts, to see whether
+    // what's happening now is new.
+    let compare = compare_query.compare.as_deref().unwrap_or_default();
+    let earlier = match compare {
+        "day" => Some((chrono_Duration::days(1), "the day before")),
+        "week" => Some((chrono_Duration::weeks(1), "the week before")),
+        _ => None,
+    };
 
+    let kept_params: Vec<(&str, &str)> = earlier
+        .map(|_| (COMPARE_PARAM, compare))
+        .into_iter()
+        .collect();
+    let compare_choices = [
+        ("the day before", "day"),
+        ("the week before", "week"),
+        ("nothing", ""),
+    ]
+    .into_iter()
+    .map(|(label, link_compare)| {
+        let mut query = other_params(&req, COMPARE_PARAM);
+        if !link_compare.is_empty() {
+            query.append_pair(COMPARE_PARAM, link_compare);
+        }
+        return pages::Choice {
+            label: i18n::text(label),
+            href: (link_compare != compare).then(|| format!("/?{}", query.finish())),
+        };
+    })
+    .collect();
+    let mut page = pages::Index {
+        time_range_picker: time_range_picker("/", &kept_params, &range),
+        compare_choices,
+        admin_api: config::get().admin_api && auth::is_admin(&req),
+        dual_stack_rows: Vec::new(),
+        resolver_rows: Vec::new(),
+        outage_rows: Vec::new(),
+    };
+
+    // Compare IPv4 and IPv6 for dual-stack targets, and resolvers for DNS targets, and summarize where
+    // outages happened.
     // Use a scope so we drop the lock as soon as possible.
     {
         let locked_ping_data = &ping_data.lock().unwrap();
+        let targets = &locked_ping_data.targets;
+        let samples_in_scope = |target: &probes::Target| {
+            return locked_ping_data.data[target.name.as_str()]
+                .range(oldest_timestamp_in_scope..=newest_timestamp_in_scope)
+                .map(|(_, sample)| sample);
+        };
+        for target_v4 in targets.iter().filter(|target| {
+            target.options.dual_stack && target.address.is_some_and(|ip| ip.is_ipv4())
+        }) {
+            let Some(target_v6) = targets.iter().find(|target| {
+                target.address.is_some_and(|ip| ip.is_ipv6())
+                    && target.hostname == target_v4.hostname
+                    && target.kind == target_v4.kind
+                    && target.options == target_v4.options
+            }) else {
+                continue;
+            };
+            let (latency_v4, loss_v4) = latency_and_loss(samples_in_scope(target_v4));
+            let (latency_v6, loss_v6) = latency_and_loss(samples_in_scope(target_v6));
+            page.dual_stack_rows.push(pages::DualStackRow {
+                host: target_v4.label().trim_end_matches(" (IPv4)").to_string(),
+                latency_v4,
+                loss_v4,
+                latency_v6,
+                loss_v6,
+            });
+        }
 
-        // Add hostname headings, each will get a column.
-        for hostname in &locked_ping_data.hostnames_in_order {
-            html += format!("<th>{}</th>", hostname).as_str();
-        }
-        html += "</tr></thead>";
-        html += "<tbody><tr>";
-        // Add the per-host data.
-        for hostname in &locked_ping_data.hostnames_in_order {
-            let initial_timestamp = DateTime::<Local>::from(newest_timestamp_in_scope);
-            let mut prev_day = initial_timestamp.day();
-            let mut prev_hour = initial_timestamp.hour();
-            let mut prev_minute = initial_timestamp.minute();
-            // Iterate the range in newest (highest datetime) to oldest order.
-            // Filter to only data in the time-frame we want.
-            let hostname_data_iter = locked_ping_data.data[hostname.as_str()]
-                .range(..newest_timestamp_in_scope)
-                .rev()
-                .filter(|data| {
-                    data.0 >= &oldest_timestamp_in_scope && data.0 <= &newest_timestamp_in_scope
+        // Compare resolvers asked the same DNS query.
+        for target in targets.iter() {
+            let probes::ProbeKind::Dns { query_name } = &target.kind else {
+                continue;
+            };
+            let num_resolvers = targets
+                .iter()
+                .filter(|other| other.kind == target.kind)
+                .count();
+            if num_resolvers < 2 {
+                continue;
+            }
+            let (latency, loss) = latency_and_loss(samples_in_scope(target));
+            page.resolver_rows.push(pages::ResolverRow {
+                query_name: query_name.clone(),
+                resolver: target.hostname.clone(),
+                latency,
+                loss,
+            });
+        }
+
+        // Work out where outages in view happened, when targets are tagged with tiers. Outages from
+        // every target going down at once are collapsed into one loss of local connectivity.
+        let in_view = |started: &DateTime<Utc>, ended: Option<DateTime<Utc>>| {
+            return *started <= newest_timestamp_in_scope
+                && ended.is_none_or(|ended| ended >= oldest_timestamp_in_scope);
+        };
+        let mut outages_in_view: Vec<_> = locked_ping_data
+            .outages
+            .iter()
+            .flat_map(|(name, outages)| {
+                outages
+                    .iter()
+                    .filter(|(started, outage)| {
+                        in_view(started, outage.ended)
+                            && locked_ping_data.connectivity_loss_at(**started).is_none()
+                    })
+                    .map(move |(started, outage)| (*started, outage.ended, Some(name)))
+            })
+            .collect();
+        let num_outages_by_target = outages_in_view.len();
+        outages_in_view.extend(
+            locked_ping_data
+                .connectivity_losses
+                .iter()
+                .filter(|(started, loss)| in_view(started, loss.ended))
+                .map(|(started, loss)| (*started, loss.ended, None)),
+        );
+        let has_tiers = targets.iter().any(|target| target.options.tier.is_some());
+        if has_tiers || outages_in_view.len() > num_outages_by_target {
+            // Newest first.
+            outages_in_view.sort_by_key(|(started, _, _)| cmp::Reverse(*started));
+            let format_local = |time: DateTime<Utc>| range.zone.date_time(time);
+            for (started, ended, name) in outages_in_view {
+                page.outage_rows.push(pages::OutageRow {
+                    target: name
+                        .map(|name| (locked_ping_data.label(name).to_string(), outages_url(name))),
+                    num_targets: targets.len(),
+                    started: format_local(started),
+                    ended: ended.map(format_local),
+                    likely_cause: match name {
+                        Some(_) => {
+                            localize_outage(locked_ping_data, started, ended).unwrap_or("unknown")
+                        }
+                        None => "local network",
+                    },
                 });
-            // Label the per-host ping data fields.
-            html += "<td><table><thead><tr><th style=\"width:40%\">timestamp</th><th style=\"width:25%\">duration</th><th style=\"width:35%\">magnitude</th></tr></thead>";
-            // Rows of per-host ping data.
-            html += "<tbody>";
-            for (timestamp, duration) in hostname_data_iter {
-                let tens_of_ms = duration.as_millis() / 10;
-                // Print a bar for every 10 ms, with a max of 10 bars.
-                let mut num_bars = cmp::min(tens_of_ms, 10);
-                let mut magnitude_bars = "".to_string();
-                while num_bars > 0 {
-                    magnitude_bars += "█";
-                    num_bars -= 1;
+            }
+        }
+    }
+
+    // The rest of the page is streamed, a piece at a time.
+    let html = match page.render() {
+        Ok(html) => html,
+        Err(err) => {
+            return admin_response(
+                &req,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Couldn't render the page - {}", err),
+            )
+        }
+    };
+    let mut pieces = VecDeque::from([IndexPiece::Html(html), IndexPiece::Groups]);
+    // While showing the latest samples, keep the page up to date as new ones are taken.
+    if range.live {
+        pieces.push_back(IndexPiece::Html(live_update_script(refresh)));
+    }
+    return HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(IndexStream {
+            ping_data: ping_data.get_ref().clone(),
+            oldest: oldest_timestamp_in_scope,
+            newest: newest_timestamp_in_scope,
+            earlier,
+            zone: range.zone,
+            units,
+            hosts: chosen_hosts(&req),
+            can_change: auth::is_admin(&req),
+            pieces,
+        });
+}
+
+// How many samples each piece of a target's column on the timeline has.
+const TIMELINE_BATCH_SIZE: usize = 1000;
+
+// The timeline after its top, streamed a piece at a time: a table per group of targets, opened with
+// their headings, then each target's column a batch of rows at a time. With a week of samples from
+// many targets the page is megabytes, this way it's never in memory all at once and the lock is only
+// held long enough to render each piece.
+struct IndexStream {
+    ping_data: Arc<Mutex<PingData>>,
+    oldest: DateTime<Utc>,
+    newest: DateTime<Utc>,
+    earlier: Option<(chrono_Duration, &'static str)>,
+    zone: timezone::Zone,
+    units: Units,
+    // The targets the viewer chose to see, None for all of them.
+    hosts: Option<String>,
+    // Whether the viewer can pause targets and trace paths to them.
+    can_change: bool,
+    // What's left to render, in order.
+    pieces: VecDeque<IndexPiece>,
+}
+
+enum IndexPiece {
+    Html(String),
+    // Which targets are shown together is worked out once the top of the page is sent.
+    Groups,
+    GroupHeadings {
+        group: Option<String>,
+        hostnames: Vec<String>,
+    },
+    Column(String),
+    // A target's samples taken before `newer`.
+    Rows {
+        hostname: String,
+        newer: DateTime<Utc>,
+        is_bandwidth_test: bool,
+        rate_limited: bool,
+    },
+    // A target's summaries from further back, closing its column.
+    Summaries {
+        hostname: String,
+        is_bandwidth_test: bool,
+    },
+}
+
+impl IndexStream {
+    // The next piece of the page, None once it's all sent. Empty pieces are skipped, an empty chunk
+    // would end the response.
+    fn next_piece(&mut self) -> Option<Result<String, askama::Error>> {
+        loop {
+            match self.render_piece()? {
+                Ok(html) if html.is_empty() => continue,
+                html => return Some(html),
+            }
+        }
+    }
+
+    fn render_piece(&mut self) -> Option<Result<String, askama::Error>> {
+        let piece = self.pieces.pop_front()?;
+        let locked_ping_data = &self.ping_data.lock().unwrap();
+        // Targets may be removed while the page is streamed, their columns are left empty.
+        let is_monitored = |hostname: &String| locked_ping_data.data.contains_key(hostname);
+        let mut next_pieces = Vec::new();
+        let html = match piece {
+            IndexPiece::Html(html) => Ok(html),
+            // Each group of targets gets a table of its own, under a summary of how the group is
+            // doing. Ungrouped targets come first, then groups in the order they first appear.
+            IndexPiece::Groups => {
+                let mut groups: Vec<(Option<&str>, Vec<String>)> = vec![(None, Vec::new())];
+                for hostname in locked_ping_data
+                    .hostnames_in_order
+                    .iter()
+                    .filter(|hostname| is_chosen(locked_ping_data, &self.hosts, hostname))
+                {
+                    let group = locked_ping_data.group(hostname);
+                    match groups.iter_mut().find(|(other, _)| *other == group) {
+                        Some((_, hostnames)) => hostnames.push(hostname.clone()),
+                        None => groups.push((group, vec![hostname.clone()])),
+                    }
                 }
-                let local_timestamp = DateTime::<Local>::from(timestamp.clone());
-                // Add some style to clearly delineate days, minutes, hours
-                let mut class = "class=\"".to_string();
-                class += if local_timestamp.day() != prev_day {
-                    prev_day = local_timestamp.day();
-                    prev_hour = local_timestamp.hour();
-                    prev_minute = local_timestamp.minute();
-                    " NewDay "
-                } else if local_timestamp.hour() != prev_hour {
-                    prev_hour = local_timestamp.hour();
-                    prev_minute = local_timestamp.minute();
-                    " NewHour "
-                } else if local_timestamp.minute() != prev_minute {
-                    prev_minute = local_timestamp.minute();
-                    " NewMinute "
-                } else {
-                    ""
+                next_pieces.extend(
+                    groups
+                        .into_iter()
+                        .filter(|(_, hostnames)| !hostnames.is_empty())
+                        .map(|(group, hostnames)| IndexPiece::GroupHeadings {
+                            group: group.map(str::to_string),
+                            hostnames,
+                        }),
+                );
+                Ok(String::new())
+            }
+            IndexPiece::GroupHeadings {
+                group,
+                mut hostnames,
+            } => {
+                hostnames.retain(is_monitored);
+                let headings = pages::GroupHeadings {
+                    summary: group.map(|group| {
+                        group_summary(
+                            locked_ping_data,
+                            &group,
+                            &hostnames,
+                            self.oldest..=self.newest,
+                        )
+                    }),
+                    hosts: hostnames
+                        .iter()
+                        .map(|hostname| {
+                            host_heading(
+                                locked_ping_data,
+                                hostname,
+                                self.oldest,
+                                self.newest,
+                                self.can_change,
+                            )
+                        })
+                        .collect(),
                 };
-                if duration >= &Duration::from_millis(config::PING_TIMEOUT_MSEC) {
-                    class += " TimedOut ";
-                }
-                class += "\"";
-                // Add a row of ping data to the table.
-                html += format!(
-                    "<tr {}><td>{:02}-{:02} {:02}:{:02}:{:02} {}</td><td>{:_>6.1} ms</td><td style=\"font-family: monospace;\">⎹{:_<10}</td></tr>",
-                    class,
-                    local_timestamp.month(),
-                    local_timestamp.day(),
-                    local_timestamp.hour12().1,
-                    local_timestamp.minute(),
-                    local_timestamp.second(),
-                    if local_timestamp.hour12().0 { "PM" } else { "AM" },
-                    duration.as_secs_f64() * 1000.0,
-                    magnitude_bars
-                )
-                .as_str();
+                next_pieces.extend(hostnames.into_iter().map(IndexPiece::Column));
+                next_pieces.push(IndexPiece::Html("</tr></tbody></table><br>".to_string()));
+                headings.render()
+            }
+            IndexPiece::Column(hostname) if !is_monitored(&hostname) => Ok("<td></td>".to_string()),
+            IndexPiece::Column(hostname) => {
+                // Bandwidth tests show throughput instead of how long they took.
+                let is_bandwidth_test = locked_ping_data
+                    .targets
+                    .iter()
+                    .find(|target| target.name == hostname)
+                    .is_some_and(|target| {
+                        matches!(target.kind, probes::ProbeKind::Bandwidth { .. })
+                    });
+                let column = pages::Column {
+                    chart: history_chart(
+                        locked_ping_data,
+                        &hostname,
+                        self.oldest,
+                        self.newest,
+                        self.earlier,
+                        self.zone,
+                        self.units,
+                    ),
+                    value_heading: if is_bandwidth_test {
+                        "throughput"
+                    } else {
+                        "duration"
+                    },
+                };
+                next_pieces.push(IndexPiece::Rows {
+                    rate_limited: is_rate_limited(
+                        locked_ping_data,
+                        &hostname,
+                        self.oldest,
+                        self.newest,
+                    ),
+                    hostname,
+                    newer: self.newest,
+                    is_bandwidth_test,
+                });
+                column.render()
+            }
+            IndexPiece::Rows { ref hostname, .. } | IndexPiece::Summaries { ref hostname, .. }
+                if !is_monitored(hostname) =>
+            {
+                Ok("</tbody></table></td>".to_string())
+            }
+            IndexPiece::Rows {
+                hostname,
+                newer,
+                is_bandwidth_test,
+                rate_limited,
+            } => {
+                let (rows, last) = sample_rows(
+                    locked_ping_data,
+                    &hostname,
+                    self.oldest,
+                    newer,
+                    self.zone,
+                    is_bandwidth_test.then_some(self.units),
+                    rate_limited,
+                );
+                next_pieces.push(match last {
+                    Some(newer) => IndexPiece::Rows {
+                        hostname,
+                        newer,
+                        is_bandwidth_test,
+                        rate_limited,
+                    },
+                    None => IndexPiece::Summaries {
+                        hostname,
+                        is_bandwidth_test,
+                    },
+                });
+                pages::TimelineRows { rows }.render()
             }
-            html += "</tbody></table></td>"
+            IndexPiece::Summaries {
+                hostname,
+                is_bandwidth_test,
+            } => pages::TimelineRows {
+                rows: summary_rows(
+                    locked_ping_data,
+                    &hostname,
+                    self.oldest,
+                    self.newest,
+                    self.zone,
+                    is_bandwidth_test.then_some(self.units),
+                ),
+            }
+            .render()
+            .map(|html| html + "</tbody></table></td>"),
+        };
+        for piece in next_pieces.into_iter().rev() {
+            self.pieces.push_front(piece);
         }
+        return Some(html);
     }
+}
+impl MessageBody for IndexStream {
+    type Error = askama::Error;
 
-    html += "</tbody>";
-    html += "</table>";
+    fn size(&self) -> BodySize {
+        return BodySize::Stream;
+    }
 
+    fn poll_next(
+        self: Pin<&mut Self>,
+        _cx: &mut Context,
+    ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        return Poll::Ready(
+            self.get_mut()
+                .next_piece()
+                .map(|html| html.map(Bytes::from)),
+        );
+    }
+}
+
+// Spots hosts that drop some of our pings because they rate limit ICMP, so their timeouts aren't
+// mistaken for outages.
+fn is_rate_limited(
+    locked_ping_data: &PingData,
+    hostname: &str,
+    oldest: DateTime<Utc>,
+    newest: DateTime<Utc>,
+) -> bool {
+    let is_pinged = locked_ping_data
+        .targets
+        .iter()
+        .find(|target| target.name == hostname)
+        .is_some_and(|target| target.kind == probes::ProbeKind::Icmp);
+    return is_pinged
+        && likely_rate_limited(
+            locked_ping_data.data[hostname]
+                .range(oldest..=newest)
+                .map(|(_, sample)| sample),
+        );
+}
+
+// A target's heading on the timeline: a link to its page, how it's probed, and warnings about it.
+fn host_heading(
+    locked_ping_data: &PingData,
+    hostname: &String,
+    oldest_timestamp_in_scope: DateTime<Utc>,
+    newest_timestamp_in_scope: DateTime<Utc>,
+    can_change: bool,
+) -> pages::HostHeading {
+    let cur_time = Utc::now();
+    let target = locked_ping_data
+        .targets
+        .iter()
+        .find(|target| &target.name == hostname);
+    let samples = &locked_ping_data.data[hostname.as_str()];
+    // Warn about TLS certificates that are about to expire, based on the latest handshake.
+    let cert_warning = match samples
+        .values()
+        .rev()
+        .find_map(|sample| sample.cert_not_after)
+    {
+        Some(not_after) if not_after < cur_time => Some("certificate expired".to_string()),
+        Some(not_after)
+            if not_after - cur_time < chrono_Duration::days(config::CERT_EXPIRY_WARNING_DAYS) =>
+        {
+            Some(format!(
+                "certificate expires in {} days",
+                (not_after - cur_time).num_days()
+            ))
+        }
+        _ => None,
+    };
+    // Show how often targets with their own interval or timeout are probed, their rows are
+    // spaced and time out differently from the rest.
+    let probe_timing = target.and_then(|target| {
+        let timing: Vec<String> = [
+            target
+                .options
+                .interval
+                .map(|interval| format!("every {:?}", interval)),
+            target
+                .options
+                .timeout
+                .map(|timeout| format!("{:?} timeout", timeout)),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        return (!timing.is_empty()).then(|| timing.join(", "));
+    });
+    // Show which version of SSH servers is running, hovering over rows shows older banners.
+    let ssh_banner = match target {
+        Some(target) if matches!(target.kind, probes::ProbeKind::Ssh { .. }) => samples
+            .values()
+            .rev()
+            .find_map(|sample| sample.status.clone()),
+        _ => None,
+    };
+    // Warn about replies that arrived late or more than once in view.
+    let odd_replies = samples
+        .range(oldest_timestamp_in_scope..=newest_timestamp_in_scope)
+        .fold((0, 0), |(late, duplicate), (_, sample)| {
+            (
+                late + sample.late_replies,
+                duplicate + sample.duplicate_replies,
+            )
+        });
+    // Link to outages, and the path to the host as each one started.
+    let outages = &locked_ping_data.outages[hostname.as_str()];
+    // Paused targets say so, and can be paused and resumed from here with the admin API.
+    let paused = locked_ping_data.is_paused(hostname);
+    return pages::HostHeading {
+        name: hostname.clone(),
+        // Labels link to the target's page, and show labelled targets' specs when hovered
+        // over.
+        label: locked_ping_data.label(hostname).to_string(),
+        href: host_url(hostname),
+        // Link to a traceroute of the path to the host.
+        trace_href: target
+            .filter(|_| can_change)
+            .map(|target| format!("/trace/{}", target.hostname)),
+        paused,
+        pause_action: (config::get().admin_api && can_change).then(|| {
+            format!(
+                "/api/targets/{}?{}",
+                if paused { "resume" } else { "pause" },
+                url::form_urlencoded::Serializer::new(String::new())
+                    .append_pair(TARGET_PARAM, hostname)
+                    .finish()
+            )
+        }),
+        probe_timing,
+        // Call out being stuck behind a captive portal, it's not the same as being
+        // offline.
+        captive: samples
+            .values()
+            .next_back()
+            .is_some_and(|latest| matches!(latest.result, probes::ProbeResult::Captive(_))),
+        outages: outages.values().next_back().map(|latest| pages::Outages {
+            href: outages_url(hostname),
+            count: outages.len(),
+            down_now: latest.ended.is_none(),
+        }),
+        cert_warning,
+        // Show how far off the clock of NTP servers is.
+        clock_offset: samples
+            .values()
+            .next_back()
+            .and_then(|sample| sample.clock_offset)
+            .map(format_offset),
+        // Show how busy interfaces polled with SNMP are.
+        interface_stats: samples
+            .values()
+            .next_back()
+            .and_then(|sample| sample.interface_stats.as_ref())
+            .map(format_interface_stats),
+        ssh_banner,
+        odd_replies: (odd_replies != (0, 0)).then_some(odd_replies),
+        rate_limited: is_rate_limited(
+            locked_ping_data,
+            hostname,
+            oldest_timestamp_in_scope,
+            newest_timestamp_in_scope,
+        ),
+    };
+}
+
+// Up to `TIMELINE_BATCH_SIZE` of a target's samples taken before `newer` and no earlier than
+// `oldest`, newest first, with public IP changes and quiet hours above the first sample after them.
+// Also returns when the last one was taken to carry on from, or None once there are no more.
+fn sample_rows(
+    locked_ping_data: &PingData,
+    hostname: &str,
+    oldest: DateTime<Utc>,
+    newer: DateTime<Utc>,
+    zone: timezone::Zone,
+    throughput_units: Option<Units>,
+    rate_limited: bool,
+) -> (Vec<pages::Row>, Option<DateTime<Utc>>) {
+    let locale = i18n::locale();
+    let mut rows = Vec::new();
+    // Rows are styled by how their time differs from the row above's.
+    let initial_timestamp = zone.at(newer);
+    let mut prev_day = initial_timestamp.day();
+    let mut prev_hour = initial_timestamp.hour();
+    let mut prev_minute = initial_timestamp.minute();
+    // Iterate the range in newest (highest datetime) to oldest order.
+    let mut hostname_data_iter = locked_ping_data.data[hostname]
+        .range(..newer)
+        .rev()
+        .take_while(|(timestamp, _)| **timestamp >= oldest);
+    let mut newer_timestamp = newer;
+    let mut num_samples = 0;
+    while let Some((timestamp, sample)) = hostname_data_iter.next() {
+        for (started, quiet_period) in locked_ping_data.quiet_periods[hostname]
+            .range((
+                Bound::Excluded(*timestamp),
+                Bound::Included(newer_timestamp),
+            ))
+            .rev()
+        {
+            rows.push(pages::Row::NotMonitored(format!(
+                "not monitored {} ({})",
+                match quiet_period.ended {
+                    Some(ended) => format!(
+                        "from {} until {}",
+                        zone.time_of_day(*started),
+                        zone.time_of_day(ended)
+                    ),
+                    None => format!("since {}", zone.time_of_day(*started)),
+                },
+                quiet_period.reason
+            )));
+        }
+        for (changed_at, public_ip) in locked_ping_data
+            .public_ips
+            .range((
+                Bound::Excluded(*timestamp),
+                Bound::Included(newer_timestamp),
+            ))
+            .rev()
+        {
+            let previous_ip = locked_ping_data
+                .public_ips
+                .range(..changed_at)
+                .next_back()
+                .map(|(_, previous_ip)| format!(" (was {})", previous_ip))
+                .unwrap_or_default();
+            rows.push(pages::Row::PublicIpChange(format!(
+                "public IP {} {}{} at {}",
+                if previous_ip.is_empty() {
+                    "is"
+                } else {
+                    "changed to"
+                },
+                public_ip,
+                previous_ip,
+                zone.time_of_day(*changed_at),
+            )));
+        }
+        newer_timestamp = *timestamp;
+        let local_timestamp = zone.at(*timestamp);
+        // Add some style to clearly delineate days, minutes, hours
+        let mut classes = Vec::new();
+        if local_timestamp.day() != prev_day {
+            prev_day = local_timestamp.day();
+            prev_hour = local_timestamp.hour();
+            prev_minute = local_timestamp.minute();
+            classes.push("NewDay");
+        } else if local_timestamp.hour() != prev_hour {
+            prev_hour = local_timestamp.hour();
+            prev_minute = local_timestamp.minute();
+            classes.push("NewHour");
+        } else if local_timestamp.minute() != prev_minute {
+            prev_minute = local_timestamp.minute();
+            classes.push("NewMinute");
+        }
+        classes.push(match &sample.result {
+            probes::ProbeResult::Success(_) => "",
+            // The host is probably just ignoring some pings.
+            probes::ProbeResult::Timeout if rate_limited => "Warning",
+            probes::ProbeResult::Timeout => "TimedOut",
+            probes::ProbeResult::Unreachable(_) => "Unreachable",
+            probes::ProbeResult::SendError(_) => "SendError",
+            probes::ProbeResult::DnsError(_) => "DnsError",
+            probes::ProbeResult::Failed(_) => "Failed",
+            probes::ProbeResult::Captive(_) => "Captive",
+        });
+        if !sample.result.is_failure() && sample.burst.as_ref().is_some_and(|burst| burst.lost > 0)
+        {
+            classes.push("Warning");
+        }
+        if sample.late_replies > 0 || sample.duplicate_replies > 0 || sample.reply_source.is_some()
+        {
+            classes.push("Warning");
+        }
+        // Highlight replies that arrived with a different TTL than the previous reply, the path they
+        // took changed.
+        let mut description = describe_sample(sample, zone);
+        if rate_limited && sample.result == probes::ProbeResult::Timeout {
+            description += " (likely rate-limited)";
+        }
+        if let Some(reply_ttl) = sample.reply_ttl {
+            let previous_reply_ttl = hostname_data_iter
+                .clone()
+                .find_map(|(_, older)| older.reply_ttl);
+            if let Some(previous_reply_ttl) =
+                previous_reply_ttl.filter(|previous| *previous != reply_ttl)
+            {
+                classes.push("Warning");
+                description += format!(" (changed from {})", previous_reply_ttl).as_str();
+            }
+        }
+        classes.retain(|class| !class.is_empty());
+        classes.dedup();
+        let value = match sample.result.round_trip() {
+            _ if sample.result.is_failure() => sample.result.label().to_string(),
+            _ if let Some(units) = throughput_units => format!(
+                "{:_>6} {}",
+                locale.number(units.scale(sample.throughput_bps.unwrap_or(0.0)), 1),
+                units.label()
+            ),
+            round_trip => format!(
+                "{:_>6} ms",
+                locale.number(round_trip.unwrap().as_secs_f64() * 1000.0, 1)
+            ),
+        };
+        rows.push(pages::Row::Sample {
+            class: classes.join(" "),
+            // Explain failures and statuses when hovering over the row.
+            title: description,
+            time: zone.timeline_time(*timestamp, true),
+            value,
+        });
+        num_samples += 1;
+        if num_samples == TIMELINE_BATCH_SIZE {
+            return (rows, Some(*timestamp));
+        }
+    }
+    return (rows, None);
+}
+
+// A target's summaries from before `oldest` and no later than `newest`, newest first.
+fn summary_rows(
+    locked_ping_data: &PingData,
+    hostname: &str,
+    oldest: DateTime<Utc>,
+    newest: DateTime<Utc>,
+    zone: timezone::Zone,
+    throughput_units: Option<Units>,
+) -> Vec<pages::Row> {
+    let locale = i18n::locale();
+    let mut rows = Vec::new();
+    for (start, aggregate, period) in summaries_in_scope(locked_ping_data, hostname, oldest, newest)
+    {
+        let value = match aggregate.mean_round_trip() {
+            _ if let Some(units) = throughput_units => format!(
+                "{:_>6} {}",
+                locale.number(units.scale(aggregate.mean_throughput_bps()), 1),
+                units.label()
+            ),
+            None => "all failed".to_string(),
+            Some(mean) => format!("{:_>6} ms", locale.number(mean.as_secs_f64() * 1000.0, 1)),
+        };
+        let class = match aggregate.failures {
+            0 => "Summary",
+            failures if failures == aggregate.samples => "Summary TimedOut",
+            _ => "Summary Warning",
+        };
+        let mut description = format!(
+            "Average of {} samples over the {}, {:.0}% lost",
+            aggregate.samples,
+            period,
+            aggregate.loss() * 100.0
+        );
+        if let (Some(fastest), Some(slowest)) = (aggregate.fastest, aggregate.slowest) {
+            description += &format!(
+                ", {:.1} ms to {:.1} ms",
+                fastest.as_secs_f64() * 1000.0,
+                slowest.as_secs_f64() * 1000.0
+            );
+        }
+        rows.push(pages::Row::Sample {
+            class: class.to_string(),
+            title: description,
+            time: format!("{} ({})", zone.timeline_time(*start, false), period),
+            value,
+        });
+    }
+    return rows;
+}
+
+// The stretch of time a page shows, either relative to now from the `start_offset` and `how_much_data`
+// params (how long ago it ends, and how long it covers), or between absolute `from` and `to` times.
+struct TimeRange {
+    oldest: DateTime<Utc>,
+    newest: DateTime<Utc>,
+    // How long ago it ends, None if it was given as absolute times.
+    start_offset: Option<Duration>,
+    // Whether it ends now, so the page can keep itself up to date.
+    live: bool,
+    // The time zone times are shown in.
+    zone: timezone::Zone,
+}
+
+// The longest stretch of time a page shows, and the furthest back it can end, about 10 years. Longer
+// ones (e.g. a typo like `how_much_data=100000y`) are cut down to it, rather than charting millennia
+// with nothing in them or running off the end of the calendar.
+const MAX_SPAN: Duration = Duration::from_secs(10 * 365 * 24 * 60 * 60);
+
+// The stretch of time the `req`uest asks for in `query`, by default the last `default_span`.
+fn time_range(
+    req: &HttpRequest,
+    query: &TimeRangeQuery,
+    default_span: Duration,
+) -> Result<TimeRange, String> {
+    let now = Utc::now();
+    let zone = zone(req)?;
+    // The stretch of time the viewer prefers takes the place of the page's default.
+    let default_span = req
+        .cookie(HOW_MUCH_DATA)
+        .and_then(|cookie| parse(cookie.value()).ok())
+        .unwrap_or(default_span);
+    if query.bounds.from.is_some() || query.bounds.to.is_some() {
+        let (from, to) = time_bounds(req, &query.bounds)?;
+        let newest = to.unwrap_or(now);
+        let longest = chrono_Duration::from_std(MAX_SPAN).unwrap();
+        let default_span = chrono_Duration::from_std(default_span.min(MAX_SPAN)).unwrap();
+        return Ok(TimeRange {
+            oldest: from.unwrap_or(newest - default_span).max(newest - longest),
+            newest,
+            start_offset: None,
+            live: false,
+            zone,
+        });
+    }
+    let start_offset = query
+        .start_offset
+        .unwrap_or(Duration::from_secs(0)) // Default to now.
+        .min(MAX_SPAN);
+    let how_much_data = query.how_much_data.unwrap_or(default_span).min(MAX_SPAN);
+    let start_offset_delta = chrono_Duration::from_std(start_offset).unwrap();
+    let how_much_data_delta = chrono_Duration::from_std(how_much_data).unwrap();
+    let newest = now - start_offset_delta;
+    return Ok(TimeRange {
+        oldest: newest - how_much_data_delta,
+        newest,
+        start_offset: Some(start_offset),
+        live: start_offset.is_zero(),
+        zone,
+    });
+}
+
+// Links to the stretches of time just newer and older than `range`, to the last hour, 6 hours, day and
+// week, and a form to show any stretch of time, for the page at `path` with `params` kept. Then links
+// to pick the time zone times are shown in.
+fn time_range_picker(
+    path: &str,
+    params: &[(&str, &str)],
+    range: &TimeRange,
+) -> pages::TimeRangePicker {
+    let link = |range_params: &[(&str, String)]| {
+        let mut query = url::form_urlencoded::Serializer::new(String::new());
+        for (param, value) in params {
+            query.append_pair(param, value);
+        }
+        for (param, value) in range_params {
+            query.append_pair(param, value);
+        }
+        return format!("{}?{}", path, query.finish());
+    };
+    let span = range.newest - range.oldest;
+    let (newer, older) = match range.start_offset {
+        Some(start_offset) => {
+            let how_much_data = format!("{:?}", span.to_std().unwrap());
+            (
+                link(&[
+                    (
+                        START_OFFSET_PARAM,
+                        format!("{:?}", start_offset.saturating_sub(span.to_std().unwrap())),
+                    ),
+                    (HOW_MUCH_DATA, how_much_data.clone()),
+                ]),
+                link(&[
+                    (
+                        START_OFFSET_PARAM,
+                        format!("{:?}", start_offset + span.to_std().unwrap()),
+                    ),
+                    (HOW_MUCH_DATA, how_much_data),
+                ]),
+            )
+        }
+        None => {
+            let bounds = |oldest: DateTime<Utc>, newest: DateTime<Utc>| {
+                return [
+                    (
+                        FROM_PARAM,
+                        oldest.to_rfc3339_opts(SecondsFormat::Secs, true),
+                    ),
+                    (TO_PARAM, newest.to_rfc3339_opts(SecondsFormat::Secs, true)),
+                ];
+            };
+            (
+                link(&bounds(range.oldest + span, range.newest + span)),
+                link(&bounds(range.oldest - span, range.newest - span)),
+            )
+        }
+    };
+    let presets = [("hour", 1), ("6 hours", 6), ("day", 24), ("week", 24 * 7)]
+        .into_iter()
+        .map(|(label, preset)| {
+            let preset = Duration::from_secs(60 * 60 * preset);
+            return pages::Choice {
+                label: i18n::text(label),
+                href: if range.live && span.to_std().unwrap() == preset {
+                    None
+                } else {
+                    Some(link(&[(HOW_MUCH_DATA, format!("{:?}", preset))]))
+                },
+            };
+        })
+        .collect();
+    // A datetime-local input gives the time without a time zone, which is taken as the one times are
+    // shown in.
+    let local_input = |time: DateTime<Utc>| {
+        return range.zone.format(time, "%Y-%m-%dT%H:%M", "%Y-%m-%dT%H:%M");
+    };
+    return pages::TimeRangePicker {
+        path: path.to_string(),
+        newer,
+        older,
+        presets,
+        from: local_input(range.oldest),
+        to: local_input(range.newest),
+        params: params
+            .iter()
+            .map(|(param, value)| (param.to_string(), value.to_string()))
+            .collect(),
+        zone: range.zone.describe(),
+    };
+}
+
+// A target's summaries by the minute, and then by the hour, from further back than its samples are
+// kept, newest first, within `oldest..newest`.
+fn summaries_in_scope<'a>(
+    ping_data: &'a PingData,
+    name: &str,
+    oldest: DateTime<Utc>,
+    newest: DateTime<Utc>,
+) -> impl Iterator<Item = (&'a DateTime<Utc>, &'a rollups::Aggregate, &'static str)> + Clone {
+    let rollups = &ping_data.rollups[name];
+    let before = |end: Option<&DateTime<Utc>>| {
+        let end = end.map_or(newest, |end| cmp::min(*end, newest));
+        return oldest..cmp::max(end, oldest);
+    };
+    let oldest_sample = ping_data.data[name].keys().next();
+    let oldest_minute = rollups.minutes.keys().next().or(oldest_sample);
+    return rollups
+        .minutes
+        .range(before(oldest_sample))
+        .rev()
+        .map(|(start, aggregate)| (start, aggregate, "minute"))
+        .chain(
+            rollups
+                .hours
+                .range(before(oldest_minute))
+                .rev()
+                .map(|(start, aggregate)| (start, aggregate, "hour")),
+        );
+}
+
+// Charts a target's samples and summaries from `oldest` to `newest` (see `chart::svg`), in `units` for
+// bandwidth tests and ms for the rest, with times in `zone`. With `earlier`, the same stretch of time
+// that long before is overlaid too, described by its label.
+fn history_chart(
+    ping_data: &PingData,
+    name: &str,
+    oldest: DateTime<Utc>,
+    newest: DateTime<Utc>,
+    earlier: Option<(chrono_Duration, &str)>,
+    zone: timezone::Zone,
+    units: Units,
+) -> String {
+    let is_bandwidth_test = ping_data
+        .targets
+        .iter()
+        .find(|target| target.name == name)
+        .is_some_and(|target| matches!(target.kind, probes::ProbeKind::Bandwidth { .. }));
+    let points = history_points(ping_data, name, oldest, newest, units);
+    let earlier_points = earlier.map(|(shift, _)| {
+        let mut points = history_points(ping_data, name, oldest - shift, newest - shift, units);
+        for point in &mut points {
+            point.time += shift;
+        }
+        return points;
+    });
+    return chart::svg(
+        &points,
+        earlier
+            .zip(earlier_points.as_deref())
+            .map(|((_, label), points)| chart::Earlier { points, label }),
+        oldest,
+        newest,
+        zone,
+        if is_bandwidth_test {
+            units.label()
+        } else {
+            "ms"
+        },
+    );
+}
+
+// A target's samples and summaries from `oldest` to `newest`, to chart, with throughput in `units`.
+fn history_points(
+    ping_data: &PingData,
+    name: &str,
+    oldest: DateTime<Utc>,
+    newest: DateTime<Utc>,
+    units: Units,
+) -> Vec<chart::Point> {
+    let target = ping_data.targets.iter().find(|target| target.name == name);
+    let is_bandwidth_test =
+        target.is_some_and(|target| matches!(target.kind, probes::ProbeKind::Bandwidth { .. }));
+    let interval = target.map_or(
+        Duration::from_secs(config::get().sec_between_pings),
+        |target| target.interval(),
+    );
+    let scale = |duration: Duration| duration.as_secs_f64() * 1000.0;
+    let samples = ping_data.data[name]
+        .range(oldest..newest)
+        .map(|(timestamp, sample)| {
+            let (value, spread) = if sample.result.is_failure() {
+                (None, None)
+            } else if is_bandwidth_test {
+                (sample.throughput_bps.map(|bps| units.scale(bps)), None)
+            } else {
+                let round_trip = sample.result.round_trip();
+                let spread = sample
+                    .burst
+                    .as_ref()
+                    .zip(round_trip)
+                    .map(|(burst, median)| {
+                        (
+                            scale(burst.fastest.unwrap_or(median)),
+                            scale(burst.slowest.unwrap_or(median)),
+                        )
+                    });
+                (round_trip.map(scale), spread)
+            };
+            return chart::Point {
+                time: *timestamp,
+                period: chrono_Duration::from_std(interval).unwrap(),
+                value,
+                spread,
+                samples: 1,
+                failures: sample.result.is_failure() as u32,
+            };
+        });
+    let summaries =
+        summaries_in_scope(ping_data, name, oldest, newest).map(|(start, aggregate, period)| {
+            let (value, spread) = match aggregate.mean_round_trip() {
+                _ if is_bandwidth_test => {
+                    (Some(units.scale(aggregate.mean_throughput_bps())), None)
+                }
+                None => (None, None),
+                Some(mean) => (
+                    Some(scale(mean)),
+                    Some((
+                        scale(aggregate.fastest.unwrap_or(mean)),
+                        scale(aggregate.slowest.unwrap_or(mean)),
+                    )),
+                ),
+            };
+            return chart::Point {
+                time: *start,
+                period: match period {
+                    "minute" => chrono_Duration::minutes(1),
+                    _ => chrono_Duration::hours(1),
+                },
+                value,
+                spread,
+                samples: aggregate.samples,
+                failures: aggregate.failures,
+            };
+        });
+    return samples.chain(summaries).collect();
+}
+
+// Every target at a glance, as a card with how it's doing now and over the last day: whether it's up,
+// its latest round trip, its average round trip, loss and uptime. Each card links to the target's
+// page, and the page keeps itself up to date like the timeline.
+async fn summary(req: HttpRequest, ping_data: web::Data<Arc<Mutex<PingData>>>) -> HttpResponse {
+    let zone = match zone(&req) {
+        Ok(zone) => zone,
+        Err(err) => return admin_response(&req, StatusCode::BAD_REQUEST, err),
+    };
+    let (units, refresh) = match units(&req).and_then(|units| Ok((units, refresh(&req)?))) {
+        Ok(preferences) => preferences,
+        Err(err) => return admin_response(&req, StatusCode::BAD_REQUEST, err),
+    };
+    let hosts = chosen_hosts(&req);
+    let now = Utc::now();
+    let day_ago = now - chrono_Duration::days(1);
+    let locale = i18n::locale();
+    let locked_ping_data = ping_data.lock().unwrap();
+
+    let mut page = pages::Summary {
+        cards: Vec::new(),
+        live_update: live_update_script(refresh),
+    };
+    for target in locked_ping_data
+        .targets
+        .iter()
+        .filter(|target| is_chosen(&locked_ping_data, &hosts, &target.name))
+    {
+        let name = &target.name;
+        let (Some(samples), Some(loss_counters)) = (
+            locked_ping_data.data.get(name),
+            locked_ping_data.loss_counters.get(name),
+        ) else {
+            continue; // The target is being renamed
+        };
+        let is_bandwidth_test = matches!(target.kind, probes::ProbeKind::Bandwidth { .. });
+        let format_value = |sample: &probes::Sample| match sample.result.round_trip() {
+            _ if sample.result.is_failure() => sample.result.label().to_string(),
+            _ if is_bandwidth_test => format!(
+                "{} {}",
+                locale.number(units.scale(sample.throughput_bps.unwrap_or(0.0)), 1),
+                units.label()
+            ),
+            round_trip => format!(
+                "{} ms",
+                locale.number(round_trip.unwrap().as_secs_f64() * 1000.0, 1)
+            ),
+        };
+        let latest = samples.values().next_back();
+        let outages = &locked_ping_data.outages[name];
+        let (class, status) = target_status(&locked_ping_data, name, zone);
+
+        // The average over the last day, of the samples kept and the summaries of older ones.
+        let mut successes = 0;
+        let mut total = 0.0;
+        for sample in samples.range(day_ago..).map(|(_, sample)| sample) {
+            if sample.result.is_failure() {
+                continue;
+            }
+            successes += 1;
+            total += if is_bandwidth_test {
+                sample.throughput_bps.unwrap_or(0.0)
+            } else {
+                sample.result.round_trip().unwrap().as_secs_f64()
+            };
+        }
+        for (_, aggregate, _) in summaries_in_scope(&locked_ping_data, name, day_ago, now) {
+            let aggregate_successes = aggregate.samples - aggregate.failures;
+            successes += aggregate_successes;
+            total += if is_bandwidth_test {
+                aggregate.total_throughput_bps
+            } else {
+                aggregate.total_round_trip.as_secs_f64()
+            };
+        }
+        let average = match successes {
+            0 => "-".to_string(),
+            _ if is_bandwidth_test => format!(
+                "{} {}",
+                locale.number(units.scale(total / successes as f64), 1),
+                units.label()
+            ),
+            _ => format!("{} ms", locale.number(total / successes as f64 * 1000.0, 1)),
+        };
+
+        let mut loss = rollups::LossCount::default();
+        for (_, counted) in loss_counters.minutes.range(day_ago..) {
+            loss.sent += counted.sent;
+            loss.lost += counted.lost;
+        }
+
+        // Uptime is the share of the last day it wasn't in an outage, from when it was first probed
+        // if that's more recent.
+        let first_probed = [
+            samples.keys().next(),
+            locked_ping_data.rollups[name].minutes.keys().next(),
+            locked_ping_data.rollups[name].hours.keys().next(),
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+        .copied();
+        let uptime = first_probed.map(|first_probed| {
+            let start = cmp::max(first_probed, day_ago);
+            let mut downtime = chrono_Duration::zero();
+            for (started, outage) in outages.range(..now).rev() {
+                let ended = outage.ended.unwrap_or(now);
+                if ended <= start {
+                    break;
+                }
+                downtime += ended - cmp::max(*started, start);
+            }
+            let monitored = (now - start).num_milliseconds().max(1) as f64;
+            return 1.0 - (downtime.num_milliseconds() as f64 / monitored).min(1.0);
+        });
+
+        page.cards.push(pages::Card {
+            class,
+            status,
+            href: host_url(name),
+            label: target.label().to_string(),
+            group: target.options.group.clone(),
+            latest: latest.map_or("-".to_string(), format_value),
+            average,
+            loss: match loss.sent {
+                0 => "-".to_string(),
+                _ => locale.percent(loss.loss() * 100.0, 1),
+            },
+            uptime: uptime.map_or("-".to_string(), |uptime| locale.percent(uptime * 100.0, 2)),
+        });
+    }
+    return render_page(&req, &page);
+}
+
+// A strip of targets' statuses and sparklines of the last hour (or the time asked for), without the
+// rest of the web UI around it, to embed in other dashboards in an iframe. `?hosts=` picks which
+// targets, by default every one. It keeps itself up to date like the timeline.
+async fn embed(
+    req: HttpRequest,
+    range_query: web::Query<TimeRangeQuery>,
+    ping_data: web::Data<Arc<Mutex<PingData>>>,
+) -> HttpResponse {
+    let range = match time_range(&req, &range_query, Duration::from_secs(60 * 60)) {
+        Ok(range) => range,
+        Err(err) => return admin_response(&req, StatusCode::BAD_REQUEST, err),
+    };
+    let (units, refresh) = match units(&req).and_then(|units| Ok((units, refresh(&req)?))) {
+        Ok(preferences) => preferences,
+        Err(err) => return admin_response(&req, StatusCode::BAD_REQUEST, err),
+    };
+    let hosts = chosen_hosts(&req);
+    let locale = i18n::locale();
+    let locked_ping_data = ping_data.lock().unwrap();
+    let mut page = pages::Embed {
+        hosts: Vec::new(),
+        live_update: if range.live {
+            live_update_script(refresh)
+        } else {
+            String::new()
+        },
+    };
+    for target in locked_ping_data
+        .targets
+        .iter()
+        .filter(|target| is_chosen(&locked_ping_data, &hosts, &target.name))
+    {
+        let name = &target.name;
+        let Some(samples) = locked_ping_data.data.get(name) else {
+            continue; // The target is being renamed
+        };
+        let (class, status) = target_status(&locked_ping_data, name, range.zone);
+        let latest = samples
+            .range(..=range.newest)
+            .next_back()
+            .map(|(_, sample)| sample);
+        page.hosts.push(pages::EmbedHost {
+            label: target.label().to_string(),
+            href: host_url(name),
+            class,
+            status,
+            latest: match latest {
+                None => "-".to_string(),
+                Some(sample) if sample.result.is_failure() => sample.result.label().to_string(),
+                Some(sample) => match sample.result.round_trip() {
+                    _ if matches!(target.kind, probes::ProbeKind::Bandwidth { .. }) => format!(
+                        "{} {}",
+                        locale.number(units.scale(sample.throughput_bps.unwrap_or(0.0)), 1),
+                        units.label()
+                    ),
+                    round_trip => format!(
+                        "{} ms",
+                        locale.number(round_trip.unwrap().as_secs_f64() * 1000.0, 1)
+                    ),
+                },
+            },
+            sparkline: chart::sparkline(
+                &history_points(&locked_ping_data, name, range.oldest, range.newest, units),
+                range.oldest,
+                range.newest,
+            ),
+        });
+    }
+    return render_page(&req, &page);
+}
+
+// A caption summarizing how a group of targets is doing: how many are up, and their average latency
+// and loss over `in_scope`.
+fn group_summary(
+    locked_ping_data: &PingData,
+    group: &str,
+    hostnames: &[String],
+    in_scope: std::ops::RangeInclusive<DateTime<Utc>>,
+) -> pages::GroupSummary {
+    let num_down = hostnames
+        .iter()
+        .filter(|hostname| {
+            locked_ping_data.outages[hostname.as_str()]
+                .values()
+                .next_back()
+                .is_some_and(|latest| latest.ended.is_none())
+        })
+        .count();
+    let (latency, loss) = latency_and_loss(hostnames.iter().flat_map(|hostname| {
+        locked_ping_data.data[hostname.as_str()]
+            .range(in_scope.clone())
+            .map(|(_, sample)| sample)
+    }));
+    return pages::GroupSummary {
+        name: group.to_string(),
+        num_up: hostnames.len() - num_down,
+        num_hosts: hostnames.len(),
+        latency,
+        loss,
+    };
+}
+
+// Works out where an outage from `started` until `ended` (None while it's ongoing) most likely
+// happened, from the nearest tier of targets that was down at the same time. For example, if the
+// gateway was down too it's the local network, but if only Internet hosts were down it's somewhere
+// past the ISP. None if no targets with a tier were down.
+fn localize_outage(
+    locked_ping_data: &PingData,
+    started: DateTime<Utc>,
+    ended: Option<DateTime<Utc>>,
+) -> Option<&'static str> {
+    let ended = ended.unwrap_or(Utc::now());
+    let nearest_tier_down = locked_ping_data
+        .targets
+        .iter()
+        .filter(|target| {
+            locked_ping_data.outages[target.name.as_str()]
+                .range(..=ended)
+                .any(|(_, other)| other.ended.is_none_or(|other_ended| other_ended >= started))
+        })
+        .filter_map(|target| target.options.tier)
+        .min()?;
+    return Some(match nearest_tier_down {
+        probes::Tier::Lan | probes::Tier::Gateway => "local network",
+        probes::Tier::Isp => "ISP",
+        probes::Tier::Internet => "remote",
+    });
+}
+
+// Where to see a target's outages.
+fn outages_url(name: &str) -> String {
+    return format!(
+        "/outages?{}",
+        url::form_urlencoded::Serializer::new(String::new())
+            .append_pair(TARGET_PARAM, name)
+            .finish()
+    );
+}
+
+// Formats the average duration of successful samples, and what percent of samples failed.
+fn latency_and_loss<'a>(samples: impl Iterator<Item = &'a probes::Sample>) -> (String, String) {
+    let mut num_probes = 0;
+    let mut num_lost = 0;
+    let mut successful_durations = Vec::new();
+    for sample in samples {
+        // Count every probe in bursts, not just the sample summarizing them.
+        match &sample.burst {
+            Some(burst) => {
+                num_probes += burst.sent;
+                num_lost += burst.lost;
+            }
+            None => {
+                num_probes += 1;
+                if sample.result.is_failure() {
+                    num_lost += 1;
+                }
+            }
+        }
+        if let Some(round_trip) = sample.result.round_trip() {
+            successful_durations.push(round_trip);
+        }
+    }
+    if num_probes == 0 {
+        return ("-".to_string(), "-".to_string());
+    }
+    let locale = i18n::locale();
+    let loss = locale.percent(num_lost as f64 * 100.0 / num_probes as f64, 1);
+    if successful_durations.is_empty() {
+        return ("-".to_string(), loss);
+    }
+    let average = successful_durations.iter().sum::<Duration>() / successful_durations.len() as u32;
+    return (
+        format!("{} ms", locale.number(average.as_secs_f64() * 1000.0, 1)),
+        loss,
+    );
+}
+
+// Whether a host's lost probes look like it's rate limiting them, rather than like outages. Either
+// short runs of timeouts that recur at a steady cadence, or bursts that keep losing some (but never
+// all) of their probes.
+fn likely_rate_limited<'a>(samples: impl Iterator<Item = &'a probes::Sample>) -> bool {
+    // Where each run of timeouts starts (counting samples), and how many samples it lasts.
+    let mut timeout_runs: Vec<(usize, usize)> = Vec::new();
+    let mut burst_losses = Vec::new();
+    let mut num_bursts = 0;
+    for (i, sample) in samples.enumerate() {
+        if let Some(burst) = &sample.burst {
+            num_bursts += 1;
+            if burst.lost > 0 && burst.lost < burst.sent {
+                burst_losses.push(burst.lost);
+            }
+        }
+        if sample.result != probes::ProbeResult::Timeout {
+            continue;
+        }
+        match timeout_runs.last_mut() {
+            Some((start, length)) if *start + *length == i => *length += 1,
+            _ => timeout_runs.push((i, 1)),
+        }
+    }
+    // Bursts outrun the host's limit every time, by about the same number of probes.
+    if num_bursts >= config::RATE_LIMIT_MIN_RECURRENCES
+        && burst_losses.len() == num_bursts
+        && burst_losses.iter().max().unwrap() - burst_losses.iter().min().unwrap() <= 1
+    {
+        return true;
+    }
+    // Outages cause long runs of timeouts, rate limiting causes short ones at a steady cadence.
+    if timeout_runs.len() < config::RATE_LIMIT_MIN_RECURRENCES
+        || timeout_runs
+            .iter()
+            .any(|(_, length)| *length > config::RATE_LIMIT_MAX_TIMEOUT_RUN)
+    {
+        return false;
+    }
+    let gaps: Vec<usize> = timeout_runs
+        .windows(2)
+        .map(|runs| runs[1].0 - runs[0].0)
+        .collect();
+    return gaps.iter().max().unwrap() - gaps.iter().min().unwrap() <= 1;
+}
+
+// Describes everything notable about a sample, beyond how long it took.
+fn describe_sample(sample: &probes::Sample, zone: timezone::Zone) -> String {
+    let mut details: Vec<String> = Vec::new();
+    if let Some(failure) = sample.result.failure() {
+        details.push(failure);
+    }
+    if let Some(status) = &sample.status {
+        details.push(format!("status {}", status));
+    }
+    if let Some(not_after) = sample.cert_not_after {
+        details.push(format!(
+            "certificate valid until {}",
+            zone.date_time_minutes(not_after)
+        ));
+    }
+    if let Some(clock_offset) = sample.clock_offset {
+        details.push(format!("clock offset {}", format_offset(clock_offset)));
+    }
+    if let Some(interface_stats) = &sample.interface_stats {
+        details.push(format_interface_stats(interface_stats));
+    }
+    if let Some(burst) = &sample.burst {
+        // The sample's round trip is the burst's median.
+        match (burst.fastest, sample.result.round_trip(), burst.slowest) {
+            (Some(fastest), Some(median), Some(slowest)) => details.push(format!(
+                "min {:.1} ms, median {:.1} ms, max {:.1} ms, {} of {} lost",
+                fastest.as_secs_f64() * 1000.0,
+                median.as_secs_f64() * 1000.0,
+                slowest.as_secs_f64() * 1000.0,
+                burst.lost,
+                burst.sent
+            )),
+            _ => details.push(format!("{} of {} lost", burst.lost, burst.sent)),
+        }
+    }
+    if let Some(reply_source) = sample.reply_source {
+        details.push(format!("reply from {}", reply_source));
+    }
+    if sample.late_replies > 0 {
+        details.push(format!("{} late replies", sample.late_replies));
+    }
+    if sample.duplicate_replies > 0 {
+        details.push(format!("{} duplicate replies", sample.duplicate_replies));
+    }
+    // Last, so a note about how it changed can be appended.
+    if let Some(reply_ttl) = sample.reply_ttl {
+        details.push(format!("reply TTL {}", reply_ttl));
+    }
+    return details.join(", ");
+}
+
+// Formats an interface's traffic and error rates.
+fn format_interface_stats(interface_stats: &probes::InterfaceStats) -> String {
+    return format!(
+        "in {:.1} Mbps, out {:.1} Mbps, {} errors",
+        interface_stats.in_bps / 1_000_000.0,
+        interface_stats.out_bps / 1_000_000.0,
+        interface_stats.errors
+    );
+}
+
+// Formats a signed duration, e.g. a clock offset, in milliseconds.
+fn format_offset(offset: chrono_Duration) -> String {
+    return format!(
+        "{:+.1} ms",
+        offset.num_microseconds().unwrap_or(i64::MAX) as f64 / 1000.0
+    );
+}
+
+// Traces the path to a monitored host.
+async fn trace(
+    req: HttpRequest,
+    path: web::Path<String>,
+    ping_data: web::Data<Arc<Mutex<PingData>>>,
+) -> HttpResponse {
+    let hostname = path.into_inner();
+    // Only trace monitored hosts, this page shouldn't be usable to probe arbitrary hosts.
+    let is_monitored = ping_data
+        .lock()
+        .unwrap()
+        .targets
+        .iter()
+        .any(|target| target.hostname == hostname);
+    if !is_monitored {
+        return admin_response(
+            &req,
+            StatusCode::NOT_FOUND,
+            format!("{} isn't a monitored host.", hostname),
+        );
+    }
+    let hostname_threadlocal = hostname.clone();
+    // Tracing takes up to a timeout per hop, do it off of the web server's worker threads.
+    let trace_res =
+        web::block(move || probes::resolve(&hostname_threadlocal).and_then(traceroute::trace))
+            .await
+            .unwrap();
+
+    let page = pages::Trace {
+        trace: trace_res.map(|trace| {
+            return trace_table(
+                pages::TraceCaption::Text(i18n::fill("path to {}", &[hostname.to_string()])),
+                &trace,
+            );
+        }),
+        hostname,
+    };
+    return render_page(&req, &page);
+}
+
+// A traceroute as a table of hops, captioned with `caption`.
+fn trace_table(caption: pages::TraceCaption, trace: &traceroute::Trace) -> pages::TraceTable {
+    return pages::TraceTable {
+        caption,
+        dest_ip: trace.dest_ip.to_string(),
+        reached_dest: trace.reached_dest,
+        hops: trace
+            .hops
+            .iter()
+            .map(|hop| pages::Hop {
+                ttl: hop.ttl,
+                address: match hop.responder {
+                    Some(responder) => responder.to_string(),
+                    None => "*".to_string(),
+                },
+                hostname: hop.responder_hostname.clone().unwrap_or_default(),
+                round_trip: match hop.rtt {
+                    Some(rtt) => format!("{:.1} ms", rtt.as_secs_f64() * 1000.0),
+                    None => "*".to_string(),
+                },
+            })
+            .collect(),
+    };
+}
+
+// Lists a target's outages, with the path to its host as each one was detected.
+async fn outages(
+    req: HttpRequest,
+    target_query: web::Query<TargetQuery>,
+    ping_data: web::Data<Arc<Mutex<PingData>>>,
+) -> HttpResponse {
+    let zone = match zone(&req) {
+        Ok(zone) => zone,
+        Err(err) => return admin_response(&req, StatusCode::BAD_REQUEST, err),
+    };
+    let locked_ping_data = ping_data.lock().unwrap();
+    let Some((name, outages)) = target_query
+        .target
+        .as_ref()
+        .and_then(|name| locked_ping_data.outages.get_key_value(name))
+    else {
+        return admin_response(
+            &req,
+            StatusCode::NOT_FOUND,
+            "That isn't a monitored target.".to_string(),
+        );
+    };
+    let captures = locked_ping_data
+        .targets
+        .iter()
+        .find(|target| &target.name == name)
+        .is_some_and(|target| target.options.capture_dir.is_some());
+
+    let mut page = pages::OutageList {
+        label: locked_ping_data.label(name).to_string(),
+        feed_href: format!(
+            "/outages.atom?{}",
+            url::form_urlencoded::Serializer::new(String::new())
+                .append_pair(TARGET_PARAM, name)
+                .finish()
+        ),
+        outages: Vec::new(),
+    };
+    // Newest first.
+    for (started, outage) in outages.iter().rev() {
+        let likely_cause = match (
+            locked_ping_data.connectivity_loss_at(*started),
+            localize_outage(&locked_ping_data, *started, outage.ended),
+        ) {
+            (Some(_loss), _) => {
+                i18n::text(", along with every other target (local connectivity loss)").to_string()
+            }
+            (None, Some(likely_cause)) => i18n::fill(
+                ", likely cause: {}",
+                &[i18n::text(likely_cause).to_string()],
+            ),
+            (None, None) => "".to_string(),
+        };
+        let caption = pages::OutageCaption {
+            label: page.label.clone(),
+            started: zone.date_time(*started),
+            ended: outage.ended.map(|ended| zone.date_time(ended)),
+            likely_cause,
+        };
+        page.outages.push(pages::OutageDetails {
+            trace: outage.trace.as_ref().map(|trace| match trace {
+                Ok(trace) => Ok(trace_table(
+                    pages::TraceCaption::Outage(caption.clone()),
+                    trace,
+                )),
+                Err(err) => Err(err.clone()),
+            }),
+            caption,
+            capture: outage.capture.as_ref().map(|capture| match capture {
+                Ok((path, num_packets)) => Ok((*num_packets, path.display().to_string())),
+                Err(err) => Err(err.clone()),
+            }),
+            capturing: captures,
+        });
+    }
+    return render_page(&req, &page);
+}
+
+// How many outages the feed lists, the newest.
+const FEED_ENTRIES: usize = 100;
+
+// An Atom feed of outages, newest first, to follow in a feed reader: each target's, or `?target=`'s,
+// and losses of local connectivity (every target going down at once) in place of the outages they
+// caused. Times in titles are in the time zone pages show them in.
+async fn outages_feed(
+    req: HttpRequest,
+    target_query: web::Query<TargetQuery>,
+    ping_data: web::Data<Arc<Mutex<PingData>>>,
+) -> HttpResponse {
+    let zone = match zone(&req) {
+        Ok(zone) => zone,
+        Err(err) => return admin_response(&req, StatusCode::BAD_REQUEST, err),
+    };
+    let target = target_query.into_inner().target;
+    let locked_ping_data = ping_data.lock().unwrap();
+    if target
+        .as_ref()
+        .is_some_and(|name| !locked_ping_data.outages.contains_key(name))
+    {
+        return HttpResponse::NotFound()
+            .content_type(ContentType::plaintext())
+            .body("That isn't a monitored target.");
+    }
+    // Feed readers need absolute links.
+    let connection = req.connection_info();
+    let base = format!("{}://{}", connection.scheme(), connection.host());
+    let mut outages: Vec<_> = locked_ping_data
+        .outages
+        .iter()
+        .filter(|(name, _)| target.as_ref().is_none_or(|target| target == *name))
+        .flat_map(|(name, outages)| {
+            outages
+                .iter()
+                .filter(|(started, _)| {
+                    target.is_some() || locked_ping_data.connectivity_loss_at(**started).is_none()
+                })
+                .map(move |(started, outage)| (*started, outage.ended, Some(name)))
+        })
+        .collect();
+    if target.is_none() {
+        outages.extend(
+            locked_ping_data
+                .connectivity_losses
+                .iter()
+                .map(|(started, loss)| (*started, loss.ended, None)),
+        );
+    }
+    outages.sort_by_key(|(started, _, _)| cmp::Reverse(*started));
+    outages.truncate(FEED_ENTRIES);
+
+    let mut entries = String::new();
+    for (started, ended, name) in &outages {
+        // e.g. `2024-01-01 02:13–02:19`, with the end's date too if it's on another day.
+        let until = match ended {
+            None => "ongoing".to_string(),
+            Some(ended) if zone.at(*ended).date_naive() == zone.at(*started).date_naive() => {
+                zone.format(*ended, "%H:%M", "%H:%MZ")
+            }
+            Some(ended) => zone.date_time_minutes(*ended),
+        };
+        let (title, link) = match name {
+            Some(name) => (
+                format!(
+                    "{} down {}–{}",
+                    locked_ping_data.label(name),
+                    zone.date_time_minutes(*started),
+                    until
+                ),
+                format!("{}{}", base, outages_url(name)),
+            ),
+            None => (
+                format!(
+                    "Lost local connectivity {}–{}",
+                    zone.date_time_minutes(*started),
+                    until
+                ),
+                format!("{}/", base),
+            ),
+        };
+        let mut summary = match name {
+            Some(_) => localize_outage(&locked_ping_data, *started, *ended)
+                .map(|likely_cause| format!("Likely cause: {}. ", likely_cause))
+                .unwrap_or_default(),
+            None => "Every target was down at once. ".to_string(),
+        };
+        summary += &match ended.map(|ended| ended - *started) {
+            Some(lasted) if lasted.num_minutes() == 0 => {
+                format!("It lasted {} seconds.", lasted.num_seconds())
+            }
+            Some(lasted) => format!("It lasted {} minutes.", lasted.num_minutes()),
+            None => "It's still going on.".to_string(),
+        };
+        entries += &format!(
+            "<entry><id>{}#{}</id><title>{}</title><link href=\"{}\"/><updated>{}</updated><summary>{}</summary></entry>",
+            escape_html(&link),
+            started.to_rfc3339_opts(SecondsFormat::Millis, true),
+            escape_html(&title),
+            escape_html(&link),
+            ended
+                .unwrap_or(*started)
+                .to_rfc3339_opts(SecondsFormat::Secs, true),
+            escape_html(&summary),
+        );
+    }
+    let updated = outages
+        .iter()
+        .map(|(started, ended, _)| ended.unwrap_or(*started))
+        .max()
+        .unwrap_or(Utc::now());
+    let feed_url = escape_html(&format!(
+        "{}{}",
+        base,
+        req.uri().path_and_query().map_or("", |path| path.as_str())
+    ));
+    let feed = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<feed xmlns=\"http://www.w3.org/2005/Atom\"><id>{}</id><title>{}</title><link rel=\"self\" href=\"{}\"/><link href=\"{}/\"/><updated>{}</updated>{}</feed>",
+        feed_url,
+        escape_html(&match &target {
+            Some(name) => format!("{} outages", locked_ping_data.label(name)),
+            None => "Outages".to_string(),
+        }),
+        feed_url,
+        escape_html(&base),
+        updated.to_rfc3339_opts(SecondsFormat::Secs, true),
+        entries
+    );
+    return HttpResponse::Ok()
+        .content_type("application/atom+xml; charset=utf-8")
+        .body(feed);
+}
+
+// How each target's round trips were spread out over the time picked (the last hour by default),
+// as a histogram with its median and 95th percentile, to spot what an average hides (e.g. Wi-Fi
+// retries making latency bimodal). Only shows `target` if it's given.
+async fn histogram(
+    req: HttpRequest,
+    target_query: web::Query<TargetQuery>,
+    range_query: web::Query<TimeRangeQuery>,
+    ping_data: web::Data<Arc<Mutex<PingData>>>,
+) -> HttpResponse {
+    // Default to the last hour.
+    let range = match time_range(&req, &range_query, Duration::from_secs(60 * 60)) {
+        Ok(range) => range,
+        Err(err) => return admin_response(&req, StatusCode::BAD_REQUEST, err),
+    };
+    let (oldest, newest) = (range.oldest, range.newest);
+    let target_param = target_query.target.as_deref();
+
+    let locked_ping_data = ping_data.lock().unwrap();
+    let targets: Vec<&probes::Target> = locked_ping_data
+        .targets
+        .iter()
+        .filter(|target| match target_param {
+            Some(name) => target.name == name,
+            // Bandwidth tests' round trips are how long the transfer took, they'd only be noise here.
+            None => !matches!(target.kind, probes::ProbeKind::Bandwidth { .. }),
+        })
+        .collect();
+    if target_param.is_some() && targets.is_empty() {
+        return admin_response(
+            &req,
+            StatusCode::NOT_FOUND,
+            "That isn't a monitored target.".to_string(),
+        );
+    }
+
+    let kept_params: Vec<(&str, &str)> = target_param
+        .map(|target| (TARGET_PARAM, target))
+        .into_iter()
+        .collect();
+    let mut page = pages::Histograms {
+        time_range_picker: time_range_picker("/histogram", &kept_params, &range),
+        charts: Vec::new(),
+    };
+    for target in targets {
+        // Samples older than the retention period have been summarized, and summaries don't keep
+        // each round trip, so the histogram only covers what's left.
+        let histogram = histogram::Histogram::new(
+            &target.name,
+            locked_ping_data.data[&target.name]
+                .range(oldest..newest)
+                .map(|(_, sample)| sample),
+            oldest,
+            newest,
+        );
+        let format_ms = |ms: Option<f64>| {
+            return ms.map_or("-".to_string(), |ms| {
+                format!("{} ms", chart::format_number(ms))
+            });
+        };
+        page.charts.push(pages::HistogramChart {
+            href: host_url(&target.name),
+            label: target.label().to_string(),
+            samples: histogram.samples(),
+            median: format_ms(histogram.percentile(50.0)),
+            p95: format_ms(histogram.percentile(95.0)),
+            failures: histogram.failures,
+            svg: histogram.svg(),
+        });
+    }
+    return render_page(&req, &page);
+}
+
+// Where to see how round trips were spread out over the last `how_much_data`, for `target` or every
+// target.
+fn histogram_url(target: Option<&str>, how_much_data: Duration) -> String {
+    let mut query = url::form_urlencoded::Serializer::new(String::new());
+    if let Some(target) = target {
+        query.append_pair(TARGET_PARAM, target);
+    }
+    query.append_pair(HOW_MUCH_DATA, &format!("{:?}", how_much_data));
+    return format!("/histogram?{}", query.finish());
+}
+
+// Each target's loss charted over time, as the share of probes lost in each minute, 5 minutes or hour
+// (`by`), from `PingData::loss_counters`. By the minute shows the last 6 hours, by 5 minutes the last
+// day and by the hour the last week, unless another stretch of time is picked. Only shows `target` if
+// it's given.
+async fn loss(
+    req: HttpRequest,
+    target_query: web::Query<TargetQuery>,
+    bucket_query: web::Query<LossBucketQuery>,
+    range_query: web::Query<TimeRangeQuery>,
+    ping_data: web::Data<Arc<Mutex<PingData>>>,
+) -> HttpResponse {
+    let by = bucket_query.by.as_deref().unwrap_or("minute");
+    let width = loss_bucket(by);
+    let range = match time_range(&req, &range_query, (width * 360).to_std().unwrap()) {
+        Ok(range) => range,
+        Err(err) => return admin_response(&req, StatusCode::BAD_REQUEST, err),
+    };
+    let (oldest, newest) = (range.oldest, range.newest);
+    let target_param = target_query.target.as_deref();
+
+    let locked_ping_data = ping_data.lock().unwrap();
+    let targets: Vec<&probes::Target> = locked_ping_data
+        .targets
+        .iter()
+        .filter(|target| target_param.is_none_or(|name| target.name == name))
+        .collect();
+    if target_param.is_some() && targets.is_empty() {
+        return admin_response(
+            &req,
+            StatusCode::NOT_FOUND,
+            "That isn't a monitored target.".to_string(),
+        );
+    }
+
+    let mut kept_params = vec![(BY_PARAM, by)];
+    if let Some(target) = target_param {
+        kept_params.push((TARGET_PARAM, target));
+    }
+    let mut page = pages::LossTimeline {
+        widths: [
+            ("by the minute", "minute"),
+            ("by 5 minutes", "5min"),
+            ("by the hour", "hour"),
+        ]
+        .into_iter()
+        .map(|(label, link_by)| pages::Choice {
+            label: i18n::text(label),
+            href: (link_by != by).then(|| loss_url(target_param, link_by)),
+        })
+        .collect(),
+        time_range_picker: time_range_picker("/loss", &kept_params, &range),
+        charts: Vec::new(),
+    };
+    let span = (newest - oldest).num_milliseconds().max(1) as f64;
+    let across = |time: DateTime<Utc>| (time - oldest).num_milliseconds() as f64 / span;
+    let labels = chart::time_labels(oldest, newest, range.zone);
+    for target in targets {
+        let mut total = rollups::LossCount::default();
+        let mut bars = Vec::new();
+        // Include the bucket the window starts partway through.
+        for (start, count) in locked_ping_data.loss_counters[&target.name]
+            .by(width)
+            .range(oldest - width..newest)
+            .filter(|(start, _)| **start + width > oldest)
+        {
+            total.sent += count.sent;
+            total.lost += count.lost;
+            bars.push(chart::Bar {
+                start: across(*start).max(0.0),
+                end: across(*start + width).min(1.0),
+                value: count.loss() * 100.0,
+                color: "red",
+                title: format!(
+                    "{}: lost {} of {} ({:.2}%)",
+                    range.zone.date_time_minutes(*start),
+                    count.lost,
+                    count.sent,
+                    count.loss() * 100.0
+                ),
+            });
+        }
+        page.charts.push(pages::LossChart {
+            href: host_url(&target.name),
+            label: target.label().to_string(),
+            lost: total.lost,
+            sent: total.sent,
+            loss: i18n::locale().percent(total.loss() * 100.0, 2),
+            chart: chart::bars(&bars, &labels, "%"),
+        });
+    }
+    return render_page(&req, &page);
+}
+
+// Where to see loss charted `by` the minute, 5 minutes or hour, for `target` or every target.
+fn loss_url(target: Option<&str>, by: &str) -> String {
+    let mut query = url::form_urlencoded::Serializer::new(String::new());
+    if let Some(target) = target {
+        query.append_pair(TARGET_PARAM, target);
+    }
+    query.append_pair(BY_PARAM, by);
+    return format!("/loss?{}", query.finish());
+}
+
+// Each target's 95th percentile round trip (or its loss, with `metric=loss`) in each hour of each of the
+// last `days` days (14 by default), as a table with a row per day and a column per hour shaded darker
+// the worse it was, to make patterns like "every evening at 8pm it gets bad" obvious. Only shows
+// `target` if it's given.
+async fn heatmap(
+    req: HttpRequest,
+    target_query: web::Query<TargetQuery>,
+    heatmap_query: web::Query<HeatmapQuery>,
+    ping_data: web::Data<Arc<Mutex<PingData>>>,
+) -> HttpResponse {
+    let metric = heatmap_query.metric.as_deref().unwrap_or("latency");
+    let by_loss = metric == "loss";
+    let days = heatmap_query.days.unwrap_or(14);
+    let zone = match zone(&req) {
+        Ok(zone) => zone,
+        Err(err) => return admin_response(&req, StatusCode::BAD_REQUEST, err),
+    };
+    let today = zone.today();
+    let first_day = today - chrono_Duration::days(days as i64 - 1);
+    let locale = i18n::locale();
+    let hour_format = if locale.twelve_hour_clock() {
+        "%-I%P"
+    } else {
+        "%H"
+    };
+    let Some(oldest) = first_day
+        .and_hms_opt(0, 0, 0)
+        .and_then(|midnight| zone.resolve(midnight))
+    else {
+        return admin_response(
+            &req,
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Couldn't find when the first day started".to_string(),
+        );
+    };
+    let now = Utc::now();
+    let target_param = target_query.target.as_deref();
+    // Which day and hour a time falls in, in the time zone times are shown in.
+    let cell = |time: DateTime<Utc>| {
+        let local_time = zone.at(time);
+        return (local_time.date_naive(), local_time.hour());
+    };
+
+    let locked_ping_data = ping_data.lock().unwrap();
+    let targets: Vec<&probes::Target> = locked_ping_data
+        .targets
+        .iter()
+        .filter(|target| match target_param {
+            Some(name) => target.name == name,
+            // Bandwidth tests' round trips are how long the transfer took, they'd only be noise here.
+            None => by_loss || !matches!(target.kind, probes::ProbeKind::Bandwidth { .. }),
+        })
+        .collect();
+    if target_param.is_some() && targets.is_empty() {
+        return admin_response(
+            &req,
+            StatusCode::NOT_FOUND,
+            "That isn't a monitored target.".to_string(),
+        );
+    }
+
+    let mut page = pages::Heatmaps {
+        choices: [
+            ("95th percentile round trip", "latency", days),
+            ("loss", "loss", days),
+            ("2 weeks", metric, 14),
+            ("30 days", metric, 30),
+            ("90 days", metric, 90),
+        ]
+        .into_iter()
+        .map(|(label, link_metric, link_days)| pages::Choice {
+            label: i18n::text(label),
+            href: (link_metric != metric || link_days != days)
+                .then(|| heatmap_url(target_param, link_metric, link_days)),
+        })
+        .collect(),
+        hours: (0..24)
+            .map(|hour| {
+                return NaiveTime::from_hms_opt(hour, 0, 0)
+                    .unwrap()
+                    .format(hour_format)
+                    .to_string();
+            })
+            .collect(),
+        heatmaps: Vec::new(),
+    };
+    for target in targets {
+        let name = target.name.as_str();
+        // Each cell's value, and a description of it for hovering over.
+        let mut cells: BTreeMap<(NaiveDate, u32), (f64, String)> = BTreeMap::new();
+        if by_loss {
+            // Loss is counted by the UTC hour, which lines up with local hours in most time zones.
+            let mut counts: BTreeMap<(NaiveDate, u32), rollups::LossCount> = BTreeMap::new();
+            for (start, count) in locked_ping_data.loss_counters[name].hours.range(oldest..) {
+                let counted = counts.entry(cell(*start)).or_default();
+                counted.sent += count.sent;
+                counted.lost += count.lost;
+            }
+            for (key, count) in counts {
+                cells.insert(
+                    key,
+                    (
+                        count.loss() * 100.0,
+                        format!(
+                            "lost {} of {} ({:.2}%)",
+                            count.lost,
+                            count.sent,
+                            count.loss() * 100.0
+                        ),
+                    ),
+                );
+            }
+        } else {
+            // Summaries only keep an average, so where samples have aged out each minute (or hour)
+            // counts as one round trip.
+            let mut round_trips: BTreeMap<(NaiveDate, u32), Vec<f64>> = BTreeMap::new();
+            let samples =
+                locked_ping_data.data[name]
+                    .range(oldest..)
+                    .filter_map(|(timestamp, sample)| {
+                        sample
+                            .result
+                            .round_trip()
+                            .map(|round_trip| (*timestamp, round_trip))
+                    });
+            let summaries = summaries_in_scope(&locked_ping_data, name, oldest, now).filter_map(
+                |(start, aggregate, _)| {
+                    aggregate
+                        .mean_round_trip()
+                        .map(|round_trip| (*start, round_trip))
+                },
+            );
+            for (time, round_trip) in samples.chain(summaries) {
+                round_trips
+                    .entry(cell(time))
+                    .or_default()
+                    .push(round_trip.as_secs_f64() * 1000.0);
+            }
+            for (key, mut values) in round_trips {
+                values.sort_by(f64::total_cmp);
+                let p95 = histogram::percentile(&values, 95.0).unwrap();
+                cells.insert(
+                    key,
+                    (
+                        p95,
+                        format!(
+                            "{} ms ({} round trips)",
+                            chart::format_number(p95),
+                            values.len()
+                        ),
+                    ),
+                );
+            }
+        }
+        // Shade from the best cell (or no loss) to the worst.
+        let lowest = if by_loss {
+            0.0
+        } else {
+            cells
+                .values()
+                .map(|(value, _)| *value)
+                .fold(f64::INFINITY, f64::min)
+        };
+        let highest = cells.values().map(|(value, _)| *value).fold(0.0, f64::max);
+        let scale = if by_loss {
+            i18n::fill("up to {} lost", &[locale.percent(highest, 2)])
+        } else if cells.is_empty() {
+            i18n::text("no round trips").to_string()
+        } else {
+            i18n::fill(
+                "{} ms to {} ms",
+                &[chart::format_number(lowest), chart::format_number(highest)],
+            )
+        };
+        let mut heatmap = pages::Heatmap {
+            href: host_url(name),
+            label: target.label().to_string(),
+            scale,
+            days: Vec::new(),
+        };
+        // Newest first.
+        let mut day = today;
+        while day >= first_day {
+            heatmap.days.push(pages::HeatmapDay {
+                label: format!(
+                    "{} {}",
+                    locale.weekday(day.weekday()),
+                    day.format(locale.month_day_format())
+                ),
+                cells: (0..24)
+                    .map(|hour| {
+                        let (value, description) = cells.get(&(day, hour))?;
+                        let shade = if highest > lowest {
+                            (value - lowest) / (highest - lowest)
+                        } else {
+                            0.0
+                        };
+                        return Some(pages::HeatmapCell {
+                            shade: format!("{:.2}", shade),
+                            title: format!(
+                                "{} {}: {}",
+                                day.format(locale.date_format()),
+                                page.hours[hour as usize],
+                                description
+                            ),
+                        });
+                    })
+                    .collect(),
+            });
+            day -= chrono_Duration::days(1);
+        }
+        page.heatmaps.push(heatmap);
+    }
+    return render_page(&req, &page);
+}
+
+// Where to see a heatmap of `metric` (latency or loss) over the last `days`, for `target` or every
+// target.
+fn heatmap_url(target: Option<&str>, metric: &str, days: u32) -> String {
+    let mut query = url::form_urlencoded::Serializer::new(String::new());
+    if let Some(target) = target {
+        query.append_pair(TARGET_PARAM, target);
+    }
+    query.append_pair(METRIC_PARAM, metric);
+    query.append_pair(DAYS_PARAM, &days.to_string());
+    return format!("/heatmap?{}", query.finish());
+}
+
+// Everything about one target: its history charted over the last hour, day, month and year, its loss
+// over each of them, its latest failures, the addresses it resolves to, the TTLs its replies arrived
+// with, and the paths traced to it as outages started.
+async fn host(
+    req: HttpRequest,
+    path: web::Path<String>,
+    ping_data: web::Data<Arc<Mutex<PingData>>>,
+) -> HttpResponse {
+    let (zone, units) = match zone(&req).and_then(|zone| Ok((zone, units(&req)?))) {
+        Ok(preferences) => preferences,
+        Err(err) => return admin_response(&req, StatusCode::BAD_REQUEST, err),
+    };
+    let name = path.into_inner();
+    let target = ping_data
+        .lock()
+        .unwrap()
+        .targets
+        .iter()
+        .find(|target| target.name == name)
+        .cloned();
+    let Some(target) = target else {
+        return admin_response(
+            &req,
+            StatusCode::NOT_FOUND,
+            "That isn't a monitored target.".to_string(),
+        );
+    };
+    // Resolving can wait on DNS, do it off of the web server's worker threads.
+    let hostname = target.hostname.clone();
+    let resolved_ips = web::block(move || probes::resolve_all(&hostname))
+        .await
+        .unwrap();
+
+    let now = Utc::now();
+    let locked_ping_data = ping_data.lock().unwrap();
+    let (Some(samples), Some(loss_counters)) = (
+        locked_ping_data.data.get(&name),
+        locked_ping_data.loss_counters.get(&name),
+    ) else {
+        return admin_response(
+            &req,
+            StatusCode::NOT_FOUND,
+            "That isn't a monitored target.".to_string(),
+        );
+    };
+    let outages = &locked_ping_data.outages[&name];
+    let format_local = |time: DateTime<Utc>| zone.date_time(time);
+
+    // What the target is, and how it's doing.
+    let status = match outages.iter().next_back() {
+        _ if locked_ping_data.is_paused(&name) => (i18n::text("paused").to_string(), true),
+        Some((started, outage)) if outage.ended.is_none() => {
+            (i18n::fill("down since {}", &[format_local(*started)]), true)
+        }
+        _ => match samples.values().next_back() {
+            Some(latest) if latest.result.is_failure() => (
+                i18n::fill("latest probe {}", &[latest.result.label().to_string()]),
+                true,
+            ),
+            Some(_) => (i18n::text("up").to_string(), false),
+            None => (i18n::text("not probed yet").to_string(), false),
+        },
+    };
+    let addresses = match (target.address, resolved_ips) {
+        (Some(address), _) => Ok(i18n::fill("{} (pinned)", &[address.to_string()])),
+        (None, Ok(resolved_ips)) => Ok(resolved_ips
+            .iter()
+            .map(IpAddr::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")),
+        (None, Err(err)) => Err(err),
+    };
+    let mut reply_sources: Vec<IpAddr> = samples
+        .values()
+        .filter_map(|sample| sample.reply_source)
+        .collect();
+    reply_sources.sort();
+    reply_sources.dedup();
+
+    // Its history, as far back as it's kept.
+    let windows = [
+        ("last hour", chrono_Duration::hours(1)),
+        ("last day", chrono_Duration::days(1)),
+        ("last 30 days", chrono_Duration::days(30)),
+        ("last year", chrono_Duration::days(365)),
+    ];
+    let history = windows
+        .chunks(2)
+        .map(|pair| {
+            return pair
+                .iter()
+                .map(|(heading, span)| {
+                    let chart = history_chart(
+                        &locked_ping_data,
+                        &name,
+                        now - *span,
+                        now,
+                        None,
+                        zone,
+                        units,
+                    );
+                    return (i18n::text(heading), chart);
+                })
+                .collect();
+        })
+        .collect();
+
+    // Its loss over the same spans, from the loss counters so timeouts don't need picking out.
+    let mut loss_rows = Vec::new();
+    for (heading, span) in windows {
+        // Minutes are kept for a month, hours for longer.
+        let counts = if span <= chrono_Duration::days(1) {
+            &loss_counters.minutes
+        } else {
+            &loss_counters.hours
+        };
+        let mut count = rollups::LossCount::default();
+        for (_, counted) in counts.range(now - span..) {
+            count.sent += counted.sent;
+            count.lost += counted.lost;
+        }
+        loss_rows.push((i18n::text(heading), count));
+    }
+    loss_rows.push((
+        i18n::text("time since the monitor started"),
+        loss_counters.total,
+    ));
+
+    // Its latest failures, newest first.
+    let failures = samples
+        .iter()
+        .rev()
+        .filter(|(_, sample)| sample.result.is_failure())
+        .take(HOST_PAGE_FAILURES)
+        .map(|(timestamp, sample)| pages::Failure {
+            when: format_local(*timestamp),
+            class: match &sample.result {
+                probes::ProbeResult::Unreachable(_) => "Unreachable",
+                probes::ProbeResult::SendError(_) => "SendError",
+                probes::ProbeResult::DnsError(_) => "DnsError",
+                probes::ProbeResult::Captive(_) => "Captive",
+                probes::ProbeResult::Timeout => "TimedOut",
+                _ => "Failed",
+            },
+            result: sample.result.label().to_string(),
+            // Most failures have nothing more to say than what they were.
+            details: match describe_sample(sample, zone) {
+                details if details == sample.result.label() => "".to_string(),
+                details => details,
+            },
+        })
+        .collect();
+
+    // The TTLs its replies arrived with, a change means they took a different path.
+    let mut reply_ttls: BTreeMap<u8, (usize, DateTime<Utc>, DateTime<Utc>)> = BTreeMap::new();
+    for (timestamp, sample) in samples {
+        if let Some(reply_ttl) = sample.reply_ttl {
+            let (count, _, last_seen) = reply_ttls
+                .entry(reply_ttl)
+                .or_insert((0, *timestamp, *timestamp));
+            *count += 1;
+            *last_seen = *timestamp;
+        }
+    }
+
+    // The paths to it as its latest outages started.
+    let traces = outages
+        .iter()
+        .rev()
+        .filter_map(|(started, outage)| match &outage.trace {
+            Some(Ok(trace)) => Some((started, trace)),
+            _ => None,
+        })
+        .take(HOST_PAGE_TRACES)
+        .map(|(started, trace)| {
+            let caption = i18n::fill("path as it went down at {}", &[format_local(*started)]);
+            return trace_table(pages::TraceCaption::Text(caption), trace);
+        })
+        .collect();
+
+    let page = pages::HostDetails {
+        label: target.label().to_string(),
+        hostname: target.hostname.clone(),
+        group: target.options.group.clone(),
+        status,
+        addresses,
+        probed: match target.options.burst {
+            Some(burst) => i18n::fill(
+                "every {}, {} timeout, {} probes at a time",
+                &[
+                    format!("{:?}", target.interval()),
+                    format!("{:?}", target.timeout()),
+                    burst.to_string(),
+                ],
+            ),
+            None => i18n::fill(
+                "every {}, {} timeout",
+                &[
+                    format!("{:?}", target.interval()),
+                    format!("{:?}", target.timeout()),
+                ],
+            ),
+        },
+        outages_href: outages_url(&name),
+        num_outages: outages.len(),
+        histogram_href: histogram_url(Some(&name), Duration::from_secs(60 * 60)),
+        latency_heatmap_href: heatmap_url(Some(&name), "latency", 14),
+        reply_sources: reply_sources
+            .iter()
+            .map(IpAddr::to_string)
+            .collect::<Vec<_>>()
+            .join(", "),
+        history,
+        loss_href: loss_url(Some(&name), "5min"),
+        loss_heatmap_href: heatmap_url(Some(&name), "loss", 14),
+        loss_rows: loss_rows
+            .into_iter()
+            .map(|(heading, count)| pages::LossRow {
+                heading,
+                sent: count.sent,
+                lost: count.lost,
+                loss: i18n::locale().percent(count.loss() * 100.0, 2),
+            })
+            .collect(),
+        failures,
+        reply_ttls: reply_ttls
+            .into_iter()
+            .map(|(ttl, (replies, first_seen, last_seen))| pages::ReplyTtl {
+                ttl,
+                replies,
+                first_seen: format_local(first_seen),
+                last_seen: format_local(last_seen),
+            })
+            .collect(),
+        traces,
+        name,
+    };
+    return render_page(&req, &page);
+}
+
+// How the target `name` is doing, as a class to style it with (`Down`, `Degraded`, `Paused`, or none
+// when it's up) and a description, with times in `zone`.
+fn target_status(ping_data: &PingData, name: &str, zone: timezone::Zone) -> (&'static str, String) {
+    let latest = ping_data
+        .data
+        .get(name)
+        .and_then(|samples| samples.values().next_back());
+    let down_since = ping_data
+        .outages
+        .get(name)
+        .and_then(|outages| outages.iter().next_back())
+        .filter(|(_, outage)| outage.ended.is_none())
+        .map(|(started, _)| *started);
+    if ping_data.is_paused(name) {
+        return ("Paused", i18n::text("paused").to_string());
+    }
+    if let Some(started) = down_since {
+        return (
+            "Down",
+            i18n::fill("down since {}", &[zone.time_of_day(started)]),
+        );
+    }
+    return match latest {
+        Some(latest) if latest.result.is_failure() => (
+            "Degraded",
+            i18n::fill("latest probe {}", &[latest.result.label().to_string()]),
+        ),
+        Some(_) => ("", i18n::text("up").to_string()),
+        None => ("", i18n::text("not probed yet").to_string()),
+    };
+}
+
+// Where to see everything about a target, see `host`.
+fn host_url(name: &str) -> String {
+    let mut url = url::Url::parse("http://localhost/host").unwrap();
+    url.path_segments_mut().unwrap().extend(name.split('/'));
+    return url.path().to_string();
+}
+
+// Lists devices found on the local network, so they can be monitored without knowing their IPs.
+async fn discover(req: HttpRequest) -> HttpResponse {
+    // Discovery waits for devices to respond, do it off of the web server's worker threads.
+    let devices = web::block(discovery::discover).await.unwrap();
+    return render_page(&req, &pages::Discover { devices });
+}
+
+// The admin API, to change what's monitored at runtime. Requests and responses are plain text, so
+// it's easy to use with curl, e.g.
+// `curl -d 'example.com:443#burst=3' http://localhost:8180/api/targets` adds a target, and
+// `curl -X DELETE 'http://localhost:8180/api/targets?target=example.com:443'` removes it.
+// The settings page uses it too, with HTML forms.
+
+// Lists the names of the targets being monitored, one per line.
+async fn list_targets(ping_data: web::Data<Arc<Mutex<PingData>>>) -> HttpResponse {
+    if !config::get().admin_api {
+        return admin_api_off();
+    }
+    let mut names = String::new();
+    for name in &ping_data.lock().unwrap().hostnames_in_order {
+        names += format!("{}\n", name).as_str();
+    }
+    return HttpResponse::Ok()
+        .content_type(ContentType::plaintext())
+        .body(names);
+}
+
+// Starts monitoring the target spec in the request body, in any form the command line accepts. Forms
+// send it as the `spec` field.
+async fn add_target(
+    req: HttpRequest,
+    body: String,
+    monitor: web::Data<Arc<Mutex<Monitor>>>,
+) -> HttpResponse {
+    if !config::get().admin_api {
+        return admin_api_off();
+    }
+    let spec = match web::Query::<TargetAdd>::from_query(&body).map(web::Query::into_inner) {
+        Ok(TargetAdd { spec: Some(spec) }) => spec.trim().to_string(),
+        _ => body.trim().to_string(),
+    };
+    if spec.is_empty() {
+        return admin_response(
+            &req,
+            StatusCode::BAD_REQUEST,
+            "The request body should be the target to add".to_string(),
+        );
+    }
+    // Checking the target may look up its hostname, do it off of the web server's worker threads.
+    let monitor = monitor.get_ref().clone();
+    let added_spec = spec.clone();
+    let added = web::block(move || monitor.lock().unwrap().add(&added_spec))
+        .await
+        .unwrap();
+    return match added {
+        Ok(num_added) => {
+            audit::record(&auth::actor(&req), "add target", None, None, Some(spec));
+            admin_response(
+                &req,
+                StatusCode::CREATED,
+                format!("Added {} targets", num_added),
+            )
+        }
+        Err(problems) => {
+            admin_response(&req, StatusCode::BAD_REQUEST, describe_problems(&problems))
+        }
+    };
+}
+
+// Stops monitoring the target named by the `target` query parameter, and drops its history.
+// HTML forms can't send DELETE, so POST to `/api/targets/remove` does the same.
+async fn remove_target(
+    req: HttpRequest,
+    target_query: web::Query<TargetQuery>,
+    monitor: web::Data<Arc<Mutex<Monitor>>>,
+) -> HttpResponse {
+    if !config::get().admin_api {
+        return admin_api_off();
+    }
+    let Some(name) = target_query.into_inner().target else {
+        return no_target_param(&req);
+    };
+    if !monitor.lock().unwrap().remove(&name) {
+        return not_monitored(&req, &name);
+    }
+    audit::record(
+        &auth::actor(&req),
+        "remove target",
+        Some(&name),
+        Some(name.clone()),
+        None,
+    );
+    return admin_response(&req, StatusCode::OK, format!("Removed {}", name));
+}
+
+// Changes the target named by the `target` query parameter, keeping its history. The form fields
+// in the request body are all optional: `spec` replaces the target, and `label` and `interval` set
+// those options (or clear them if they're empty).
+// e.g. `curl -d 'label=Router&interval=30s' 'http://localhost:8180/api/targets/edit?target=192.168.1.1'`
+async fn edit_target(
+    req: HttpRequest,
+    target_query: web::Query<TargetQuery>,
+    form: web::Form<TargetEdit>,
+    monitor: web::Data<Arc<Mutex<Monitor>>>,
+) -> HttpResponse {
+    if !config::get().admin_api {
+        return admin_api_off();
+    }
+    let Some(name) = target_query.into_inner().target else {
+        return no_target_param(&req);
+    };
+    let mut spec = match form.spec.as_deref().map(str::trim) {
+        Some(spec) if !spec.is_empty() => spec.to_string(),
+        _ => name.clone(),
+    };
+    for (option, value) in [("label", &form.label), ("interval", &form.interval)] {
+        if let Some(value) = value {
+            spec = probes::set_option(&spec, option, value.trim());
+        }
+    }
+    let monitor = monitor.get_ref().clone();
+    let (edited_name, edited_spec) = (name.clone(), spec.clone());
+    let edited = web::block(move || monitor.lock().unwrap().edit(&edited_name, &edited_spec))
+        .await
+        .unwrap();
+    return match edited {
+        Ok(()) => {
+            if spec != name {
+                audit::record(
+                    &auth::actor(&req),
+                    "edit target",
+                    Some(&name),
+                    Some(name.clone()),
+                    Some(spec),
+                );
+            }
+            admin_response(&req, StatusCode::OK, "Saved".to_string())
+        }
+        Err(problems) => {
+            admin_response(&req, StatusCode::BAD_REQUEST, describe_problems(&problems))
+        }
+    };
+}
+
+// Moves the target named by the `target` query parameter to the `position` query parameter in the
+// list, counting from 0.
+async fn move_target(
+    req: HttpRequest,
+    target_query: web::Query<TargetQuery>,
+    position_query: web::Query<PositionQuery>,
+    monitor: web::Data<Arc<Mutex<Monitor>>>,
+) -> HttpResponse {
+    if !config::get().admin_api {
+        return admin_api_off();
+    }
+    let Some(name) = target_query.into_inner().target else {
+        return no_target_param(&req);
+    };
+    let Some(position) = position_query.position else {
+        return admin_response(&req, StatusCode::BAD_REQUEST, NO_POSITION.to_string());
+    };
+    let Some((old_position, position)) = monitor.lock().unwrap().move_to(&name, position) else {
+        return not_monitored(&req, &name);
+    };
+    audit::record(
+        &auth::actor(&req),
+        "move target",
+        Some(&name),
+        Some(old_position.to_string()),
+        Some(position.to_string()),
+    );
+    return admin_response(&req, StatusCode::OK, format!("Moved {}", name));
+}
+
+// Stops probing the target named by the `target` query parameter until it's resumed, keeping its
+// history.
+async fn pause_target(
+    req: HttpRequest,
+    target_query: web::Query<TargetQuery>,
+    ping_data: web::Data<Arc<Mutex<PingData>>>,
+) -> HttpResponse {
+    return set_paused(req, target_query, ping_data, true);
+}
+
+async fn resume_target(
+    req: HttpRequest,
+    target_query: web::Query<TargetQuery>,
+    ping_data: web::Data<Arc<Mutex<PingData>>>,
+) -> HttpResponse {
+    return set_paused(req, target_query, ping_data, false);
+}
+
+fn set_paused(
+    req: HttpRequest,
+    target_query: web::Query<TargetQuery>,
+    ping_data: web::Data<Arc<Mutex<PingData>>>,
+    paused: bool,
+) -> HttpResponse {
+    if !config::get().admin_api {
+        return admin_api_off();
+    }
+    let Some(name) = target_query.into_inner().target else {
+        return no_target_param(&req);
+    };
+    {
+        let mut locked_ping_data = ping_data.lock().unwrap();
+        if !locked_ping_data.hostnames_in_order.contains(&name) {
+            return not_monitored(&req, &name);
+        }
+        // The target's thread notices before its next probe.
+        let changed = if paused {
+            locked_ping_data.paused.insert(name.clone())
+        } else {
+            locked_ping_data.paused.remove(&name)
+        };
+        if changed {
+            let (old, new) = if paused {
+                ("probing", "paused")
+            } else {
+                ("paused", "probing")
+            };
+            audit::record(
+                &auth::actor(&req),
+                if paused {
+                    "pause target"
+                } else {
+                    "resume target"
+                },
+                Some(&name),
+                Some(old.to_string()),
+                Some(new.to_string()),
+            );
+        }
+    }
+    return admin_response(
+        &req,
+        StatusCode::OK,
+        format!("{} {}", if paused { "Paused" } else { "Resumed" }, name),
+    );
+}
+
+// Query params are read into a struct per request, with `web::Query`. Those that can be wrong are
+// checked as they're read, and `query_error` answers with what's wrong.
+
+// The `target` param, the target a page or an admin API request is about.
+#[derive(Deserialize)]
+struct TargetQuery {
+    target: Option<String>,
+}
+
+// The `from` and `to` params, times in the time zone pages are shown in (see `time_bounds`).
+#[derive(Deserialize)]
+struct TimeBoundsQuery {
+    from: Option<String>,
+    to: Option<String>,
+}
+
+// The params that pick the stretch of time a page shows, see `time_range`.
+#[derive(Deserialize)]
+struct TimeRangeQuery {
+    #[serde(default, deserialize_with = "start_offset_param")]
+    start_offset: Option<Duration>,
+    #[serde(default, deserialize_with = "how_much_data_param")]
+    how_much_data: Option<Duration>,
+    #[serde(flatten)]
+    bounds: TimeBoundsQuery,
+}
+
+// The timeline's `compare` param, `day` or `week`.
+#[derive(Deserialize)]
+struct CompareQuery {
+    #[serde(default, deserialize_with = "compare_param")]
+    compare: Option<String>,
+}
+
+// The `by` param, how wide the buckets loss is counted in are (see `loss_bucket`).
+#[derive(Deserialize)]
+struct LossBucketQuery {
+    #[serde(default, deserialize_with = "by_param")]
+    by: Option<String>,
+}
+
+// The heatmap's `metric` and `days` params.
+#[derive(Deserialize)]
+struct HeatmapQuery {
+    #[serde(default, deserialize_with = "metric_param")]
+    metric: Option<String>,
+    #[serde(default, deserialize_with = "days_param")]
+    days: Option<u32>,
+}
+
+// Where to move a target to, counting from 0.
+#[derive(Deserialize)]
+struct PositionQuery {
+    #[serde(default, deserialize_with = "position_param")]
+    position: Option<usize>,
+}
+
+// How many samples to return at most, and where to pick up from (see `host_pings`).
+#[derive(Deserialize)]
+struct PageQuery {
+    #[serde(default, deserialize_with = "limit_param")]
+    limit: Option<usize>,
+    #[serde(default, deserialize_with = "cursor_param")]
+    cursor: Option<DateTime<Utc>>,
+}
+
+// How wide to make the buckets samples are summed up in, and how (see `host_aggregate`).
+#[derive(Deserialize)]
+struct AggregateQuery {
+    #[serde(default, deserialize_with = "bucket_param")]
+    bucket: Option<chrono_Duration>,
+    #[serde(rename = "fn", default, deserialize_with = "fn_param")]
+    function: Option<aggregate::Function>,
+}
+
+// The `tz` param, a time zone to show times in.
+#[derive(Deserialize)]
+struct ZoneQuery {
+    tz: Option<String>,
+}
+
+// The params that take the place of the viewer's preferences for a page, see `preference`.
+#[derive(Deserialize)]
+struct PreferenceQuery {
+    tz: Option<String>,
+    hosts: Option<String>,
+    units: Option<String>,
+    refresh: Option<String>,
+}
+
+// The form adding a target, see `add_target`.
+#[derive(Deserialize, JsonSchema)]
+struct TargetAdd {
+    #[schemars(description = "The target, in any form the command line accepts.")]
+    spec: Option<String>,
+}
+
+// The form editing a target, see `edit_target`.
+#[derive(Deserialize, JsonSchema)]
+struct TargetEdit {
+    #[schemars(description = "The target in full, replacing it.")]
+    spec: Option<String>,
+    #[schemars(description = "What to show the target as.")]
+    label: Option<String>,
+    #[schemars(description = "How long to wait between probes, e.g. 30s.")]
+    interval: Option<String>,
+}
+
+// Answers a request whose query params can't be read with what's wrong with them.
+fn query_error(err: QueryPayloadError, req: &HttpRequest) -> actix_web::Error {
+    let message = match &err {
+        QueryPayloadError::Deserialize(err) => err.to_string(),
+        err => format!("Invalid query string - {}", err),
+    };
+    let response = admin_response(req, StatusCode::BAD_REQUEST, message);
+    return InternalError::from_response(err, response).into();
+}
+
+// Answers a target edit whose body isn't form fields.
+fn form_error(err: UrlencodedError, req: &HttpRequest) -> actix_web::Error {
+    let response = admin_response(
+        req,
+        StatusCode::BAD_REQUEST,
+        "The request body should be form fields, e.g. label=Router".to_string(),
+    );
+    return InternalError::from_response(err, response).into();
+}
+
+// A param's text, checked by `check`, which says what's wrong with it otherwise.
+fn checked_param<'de, D: Deserializer<'de>, T>(
+    deserializer: D,
+    check: impl FnOnce(&str) -> Result<T, String>,
+) -> Result<Option<T>, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    return check(&text).map(Some).map_err(de::Error::custom);
+}
+
+// `text` as a length of time, for the `param` param.
+fn duration_text(param: &str, text: &str) -> Result<Duration, String> {
+    return parse(text).map_err(|_err| {
+        format!(
+            "Invalid {} \"{}\", it should be a length of time like 30m, 6h or 2d",
+            param, text
+        )
+    });
+}
+
+fn start_offset_param<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Duration>, D::Error> {
+    return checked_param(deserializer, |text| duration_text(START_OFFSET_PARAM, text));
+}
+
+fn how_much_data_param<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Duration>, D::Error> {
+    return checked_param(deserializer, |text| duration_text(HOW_MUCH_DATA, text));
+}
+
+// `text` if it's one of `choices`, for the `param` param, which can be `described`.
+fn choice_text(
+    param: &str,
+    text: &str,
+    choices: &[&str],
+    described: &str,
+) -> Result<String, String> {
+    if !choices.contains(&text) {
+        return Err(format!(
+            "Invalid {} \"{}\", it can be {}",
+            param, text, described
+        ));
+    }
+    return Ok(text.to_string());
+}
+
+// An empty `compare` compares with nothing.
+fn compare_param<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    let compare = checked_param(deserializer, |text| {
+        return choice_text(COMPARE_PARAM, text, &["", "day", "week"], "day or week");
+    })?;
+    return Ok(compare.filter(|compare| !compare.is_empty()));
+}
+
+fn by_param<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    return checked_param(deserializer, |text| {
+        let choices = ["minute", "5min", "hour"];
+        return choice_text(BY_PARAM, text, &choices, "minute, 5min or hour");
+    });
+}
+
+fn metric_param<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    return checked_param(deserializer, |text| {
+        return choice_text(METRIC_PARAM, text, &["latency", "loss"], "latency or loss");
+    });
+}
+
+fn days_param<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u32>, D::Error> {
+    return checked_param(deserializer, |text| match text.parse() {
+        Ok(days) if (1..=366).contains(&days) => Ok(days),
+        _ => Err(format!("Invalid {}, it can be from 1 to 366", DAYS_PARAM)),
+    });
+}
+
+// What's said when a target's moved without saying where to.
+const NO_POSITION: &str = "Say where to move the target with ?position=N, counting from 0";
+
+fn position_param<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<usize>, D::Error> {
+    return checked_param(deserializer, |text| {
+        return text.parse().map_err(|_err| NO_POSITION.to_string());
+    });
+}
+
+fn limit_param<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<usize>, D::Error> {
+    return checked_param(deserializer, |text| match text.parse() {
+        Ok(limit) if (1..=MAX_PAGE_SAMPLES).contains(&limit) => Ok(limit),
+        _ => Err(format!(
+            "Invalid {}, it can be from 1 to {}",
+            LIMIT_PARAM, MAX_PAGE_SAMPLES
+        )),
+    });
+}
+
+fn cursor_param<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<DateTime<Utc>>, D::Error> {
+    return checked_param(deserializer, |text| {
+        return parse_cursor(text).ok_or(format!(
+            "Invalid {}, use the one from the last page's Link",
+            CURSOR_PARAM
+        ));
+    });
+}
+
+fn bucket_param<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<chrono_Duration>, D::Error> {
+    return checked_param(deserializer, |text| {
+        return match parse(text).map(chrono_Duration::from_std) {
+            Ok(Ok(width))
+                if (chrono_Duration::seconds(1)..=chrono_Duration::days(366)).contains(&width) =>
+            {
+                Ok(width)
+            }
+            _ => Err(format!(
+                "Invalid {} \"{}\", it should be a duration from a second to a year, like 5m",
+                BUCKET_PARAM, text
+            )),
+        };
+    });
+}
+
+fn fn_param<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<aggregate::Function>, D::Error> {
+    return checked_param(deserializer, |text| {
+        return aggregate::Function::parse(text).ok_or(format!(
+            "Invalid {} \"{}\", it can be avg, max, p95 or loss",
+            FN_PARAM, text
+        ));
+    });
+}
+
+// The query params other than `param`, to link to the same page with it changed.
+fn other_params(
+    req: &HttpRequest,
+    param: &str,
+) -> url::form_urlencoded::Serializer<'static, String> {
+    let mut query = url::form_urlencoded::Serializer::new(String::new());
+    for (name, value) in url::form_urlencoded::parse(req.query_string().as_bytes()) {
+        if name != param {
+            query.append_pair(&name, &value);
+        }
+    }
+    return query;
+}
+
+fn no_target_param(req: &HttpRequest) -> HttpResponse {
+    return admin_response(
+        req,
+        StatusCode::BAD_REQUEST,
+        "Name the target with ?target=NAME".to_string(),
+    );
+}
+
+fn not_monitored(req: &HttpRequest, name: &str) -> HttpResponse {
+    return admin_response(
+        req,
+        StatusCode::NOT_FOUND,
+        format!("{} isn't a monitored target", name),
+    );
+}
+
+// Responds to an admin API request with `message`. Browsers, which send the web UI's forms, are sent
+// back to the page they came from instead, or shown what went wrong.
+fn admin_response(req: &HttpRequest, status: StatusCode, message: String) -> HttpResponse {
+    let accepts_html = req
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/html"));
+    if !accepts_html {
+        return HttpResponse::build(status)
+            .content_type(ContentType::plaintext())
+            .body(format!("{}\n", message));
+    }
+    let back = req
+        .headers()
+        .get(header::REFERER)
+        .and_then(|referer| referer.to_str().ok())
+        .unwrap_or("/");
+    if status.is_success() {
+        return HttpResponse::SeeOther()
+            .insert_header((header::LOCATION, back))
+            .finish();
+    }
+    let page = pages::Message {
+        back: back.to_string(),
+        message,
+    };
+    return match page.render() {
+        Ok(html) => HttpResponse::build(status)
+            .content_type(ContentType::html())
+            .body(html),
+        Err(_err) => HttpResponse::build(status)
+            .content_type(ContentType::plaintext())
+            .body(format!("{}\n", page.message)),
+    };
+}
+
+// Responds with `page`, or with what went wrong rendering it.
+fn render_page(req: &HttpRequest, page: &impl Template) -> HttpResponse {
+    return match page.render() {
+        Ok(html) => HttpResponse::Ok()
+            .content_type(ContentType::html())
+            .body(html),
+        Err(err) => admin_response(
+            req,
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Couldn't render the page - {}", err),
+        ),
+    };
+}
+
+fn admin_api_off() -> HttpResponse {
+    return HttpResponse::Forbidden()
+        .content_type(ContentType::plaintext())
+        .body("The admin API is off, turn it on with `admin_api = true` in the config or --admin-api\n");
+}
+
+// A year of samples every 10 seconds is around 300 MB.
+const MAX_SNAPSHOT_BYTES: usize = 1 << 30;
+
+// Everything the monitor has recorded, as JSON, to import into another monitor with
+// `POST /api/snapshot` or `network-monitor import`.
+async fn export_snapshot(ping_data: web::Data<Arc<Mutex<PingData>>>) -> HttpResponse {
+    let snapshot = snapshot::export(&ping_data.lock().unwrap());
     return HttpResponse::Ok()
-        .content_type(ContentType::html())
-        .body(html);
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            "attachment; filename=\"network-monitor-snapshot.json\"",
+        ))
+        .json(snapshot);
+}
+
+// Every sample as NDJSON, streamed so a long history doesn't have to fit in memory again, e.g.
+// `curl -s http://localhost:8180/api/samples | jq 'select(.result != "success")'`. `?target=NAME`
+// streams just that target's samples.
+async fn stream_samples(
+    req: HttpRequest,
+    target_query: web::Query<TargetQuery>,
+    ping_data: web::Data<Arc<Mutex<PingData>>>,
+) -> HttpResponse {
+    let target = target_query.into_inner().target;
+    if let Some(name) = &target {
+        if !ping_data.lock().unwrap().hostnames_in_order.contains(name) {
+            return not_monitored(&req, name);
+        }
+    }
+    return HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .body(snapshot::SampleStream::new(
+            ping_data.get_ref().clone(),
+            target,
+        ));
+}
+
+// Samples as they're taken, as Server-Sent Events (see `events::EventStream`), for the web UI to update
+// itself and for scripts, e.g. `curl -N http://localhost:8180/events`. `?target=NAME` sends just that
+// target's samples.
+async fn sample_events(
+    req: HttpRequest,
+    target_query: web::Query<TargetQuery>,
+    ping_data: web::Data<Arc<Mutex<PingData>>>,
+) -> HttpResponse {
+    let target = target_query.into_inner().target;
+    if let Some(name) = &target {
+        if !ping_data.lock().unwrap().hostnames_in_order.contains(name) {
+            return not_monitored(&req, name);
+        }
+    }
+    return HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        // Compression would hold events back until there's enough of them to compress.
+        .insert_header(header::ContentEncoding::Identity)
+        .body(events::EventStream::new(target));
+}
+
+// Samples as they're taken, over a WebSocket (see `events::WebSocketStream`), for dashboards and home
+// automation, e.g. `websocat ws://localhost:8180/ws`. `?target=NAME` sends just that target's samples.
+async fn sample_websocket(
+    req: HttpRequest,
+    target_query: web::Query<TargetQuery>,
+    payload: web::Payload,
+    ping_data: web::Data<Arc<Mutex<PingData>>>,
+) -> HttpResponse {
+    let target = target_query.into_inner().target;
+    if let Some(name) = &target {
+        if !ping_data.lock().unwrap().hostnames_in_order.contains(name) {
+            return not_monitored(&req, name);
+        }
+    }
+    return match ws::handshake(req.head()) {
+        Ok(mut response) => HttpResponse::from(
+            response
+                .message_body(events::WebSocketStream::new(target, payload))
+                .unwrap()
+                .map_into_boxed_body(),
+        ),
+        Err(err) => admin_response(
+            &req,
+            StatusCode::BAD_REQUEST,
+            format!("This needs a WebSocket connection, {}", err),
+        ),
+    };
+}
+
+// A target's loss over time, from `PingData::loss_counters`, as a JSON array of
+// `{"start":"2024-01-01T00:00:00Z","sent":6,"lost":1,"loss":0.167}` for each minute, or each 5 minutes
+// or hour with `?by=5min` or `?by=hour`.
+// e.g. `curl 'http://localhost:8180/api/loss?target=192.168.1.1&by=hour'`
+async fn loss_series(
+    req: HttpRequest,
+    target_query: web::Query<TargetQuery>,
+    bucket_query: web::Query<LossBucketQuery>,
+    ping_data: web::Data<Arc<Mutex<PingData>>>,
+) -> HttpResponse {
+    let Some(name) = target_query.into_inner().target else {
+        return no_target_param(&req);
+    };
+    let width = loss_bucket(bucket_query.by.as_deref().unwrap_or("minute"));
+    let locked_ping_data = ping_data.lock().unwrap();
+    let Some(loss_counters) = locked_ping_data.loss_counters.get(&name) else {
+        return not_monitored(&req, &name);
+    };
+    let series: Vec<rollups::LossBucket> = loss_counters
+        .by(width)
+        .iter()
+        .map(|(start, count)| rollups::LossBucket::new(*start, *count))
+        .collect();
+    return HttpResponse::Ok().json(series);
+}
+
+// How wide the buckets loss is counted in are, from the `by` param: a minute, 5 minutes or an hour.
+fn loss_bucket(by: &str) -> chrono_Duration {
+    return match by {
+        "5min" => chrono_Duration::minutes(5),
+        "hour" => chrono_Duration::hours(1),
+        _ => chrono_Duration::minutes(1),
+    };
+}
+
+// The API described for client generators, see `openapi::document`.
+async fn openapi_document() -> HttpResponse {
+    return HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .body(openapi::document().to_string());
+}
+
+// Metrics for Prometheus to scrape, see `metrics::exposition`.
+async fn prometheus_metrics(ping_data: web::Data<Arc<Mutex<PingData>>>) -> HttpResponse {
+    let metrics = metrics::exposition(&ping_data.lock().unwrap());
+    return HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4; charset=utf-8")
+        .body(metrics);
+}
+
+// A target as `/api/hosts` lists it.
+#[derive(Serialize, JsonSchema)]
+struct Host {
+    name: String,
+    #[schemars(description = "What the target is shown as.")]
+    label: String,
+    group: Option<String>,
+    interval_sec: f64,
+    paused: bool,
+    #[schemars(description = "How many samples are kept.")]
+    samples: usize,
+    last_sample: Option<storage::TimedSample>,
+    #[serde(serialize_with = "json::maybe_seconds")]
+    #[schemars(description = "When the target's ongoing outage started, null if it's up.")]
+    down_since: Option<DateTime<Utc>>,
+}
+
+// Every target, in the order they're shown, as a JSON array of `Host`s, e.g.
+// `curl http://localhost:8180/api/hosts`
+async fn list_hosts(ping_data: web::Data<Arc<Mutex<PingData>>>) -> HttpResponse {
+    let locked_ping_data = ping_data.lock().unwrap();
+    let mut hosts = Vec::new();
+    for target in &locked_ping_data.targets {
+        let name = &target.name;
+        let Some(samples) = locked_ping_data.data.get(name) else {
+            continue; // The target is being renamed
+        };
+        let down_since = locked_ping_data.outages[name]
+            .iter()
+            .next_back()
+            .filter(|(_, outage)| outage.ended.is_none())
+            .map(|(started, _)| *started);
+        let host = Host {
+            name: name.clone(),
+            label: target.label().to_string(),
+            group: target.options.group.clone(),
+            interval_sec: target.interval().as_secs_f64(),
+            paused: locked_ping_data.is_paused(name),
+            samples: samples.len(),
+            last_sample: samples
+                .iter()
+                .next_back()
+                .map(|(when, sample)| storage::TimedSample::new(*when, sample)),
+            down_since,
+        };
+        hosts.push(host);
+    }
+    return HttpResponse::Ok().json(hosts);
+}
+
+// A target's samples as a JSON array, oldest first, in the same form as `/api/samples`.
+// `?from=` and `&to=` limit them to those taken from and before an RFC 3339 time, e.g.
+// `curl 'http://localhost:8180/api/hosts/192.168.1.1/pings?from=2024-01-01T00:00:00Z'`
+// With `&limit=N`, at most N samples are returned, and if there are more a `Link` header with
+// `rel="next"` gives the URL of the next page, with a `cursor` param to pick up after the last one.
+async fn host_pings(
+    req: HttpRequest,
+    path: web::Path<String>,
+    bounds_query: web::Query<TimeBoundsQuery>,
+    page_query: web::Query<PageQuery>,
+    ping_data: web::Data<Arc<Mutex<PingData>>>,
+) -> HttpResponse {
+    let name = path.into_inner();
+    let (from, to) = match time_bounds(&req, &bounds_query) {
+        Ok(bounds) => bounds,
+        Err(err) => return admin_response(&req, StatusCode::BAD_REQUEST, err),
+    };
+    let limit = page_query.limit.unwrap_or(usize::MAX);
+    // Carry on after the last sample of the previous page.
+    let start = match page_query.cursor {
+        None => from.map_or(Bound::Unbounded, Bound::Included),
+        Some(after) => Bound::Excluded(after),
+    };
+    let locked_ping_data = ping_data.lock().unwrap();
+    let Some(samples) = locked_ping_data.data.get(&name) else {
+        return not_monitored(&req, &name);
+    };
+    let mut in_range = samples.range((start, to.map_or(Bound::Unbounded, Bound::Excluded)));
+    let page: Vec<(&DateTime<Utc>, &probes::Sample)> = in_range.by_ref().take(limit).collect();
+    let mut response = HttpResponse::Ok();
+    if let (Some((last, _)), Some(_)) = (page.last(), in_range.next()) {
+        let mut query = other_params(&req, CURSOR_PARAM);
+        query.append_pair(CURSOR_PARAM, &format_cursor(**last));
+        response.insert_header((
+            header::LINK,
+            format!("<{}?{}>; rel=\"next\"", req.path(), query.finish()),
+        ));
+    }
+    let samples: Vec<storage::TimedSample> = page
+        .into_iter()
+        .map(|(when, sample)| storage::TimedSample::new(*when, sample))
+        .collect();
+    return response.json(samples);
+}
+
+// A cursor to page through samples from after `after`. It's opaque to clients, so what's in it can
+// change.
+fn format_cursor(after: DateTime<Utc>) -> String {
+    return BASE64_URL.encode(format!(
+        "after:{}",
+        after.timestamp_nanos_opt().unwrap_or(0)
+    ));
+}
+
+// When the samples a cursor from `format_cursor` pages through start after.
+fn parse_cursor(cursor: &str) -> Option<DateTime<Utc>> {
+    let cursor = String::from_utf8(BASE64_URL.decode(cursor).ok()?).ok()?;
+    let nanos = cursor.strip_prefix("after:")?.parse().ok()?;
+    return Some(DateTime::from_timestamp_nanos(nanos));
+}
+
+// How a host's round trips were spread out from `from` until `to` (RFC 3339 times, by default the last
+// hour), as JSON, see `histogram::Response`.
+// e.g. `curl 'http://localhost:8180/api/hosts/1.1.1.1/histogram?from=2024-01-01T00:00:00Z'`
+async fn host_histogram(
+    req: HttpRequest,
+    path: web::Path<String>,
+    bounds_query: web::Query<TimeBoundsQuery>,
+    ping_data: web::Data<Arc<Mutex<PingData>>>,
+) -> HttpResponse {
+    let name = path.into_inner();
+    let (from, to) = match time_bounds(&req, &bounds_query) {
+        Ok(bounds) => bounds,
+        Err(err) => return admin_response(&req, StatusCode::BAD_REQUEST, err),
+    };
+    let to = to.unwrap_or_else(Utc::now);
+    let from = from.unwrap_or(cmp::min(to, Utc::now()) - chrono_Duration::hours(1));
+    let locked_ping_data = ping_data.lock().unwrap();
+    let Some(samples) = locked_ping_data.data.get(&name) else {
+        return not_monitored(&req, &name);
+    };
+    let histogram = histogram::Histogram::new(
+        &name,
+        samples.range(from..to).map(|(_, sample)| sample),
+        from,
+        to,
+    );
+    return HttpResponse::Ok().json(histogram.response());
+}
+
+// Which time zone to show times in for `req`: `?tz=` if it's given, then the one the viewer picked
+// (see `pick_zone`), then `timezone` from the config.
+fn zone_choice(req: &HttpRequest) -> String {
+    return preference(req, TZ_PARAM).unwrap_or_else(|| config::get().timezone.clone());
+}
+
+// The time zone to show times in for `req` (see `zone_choice`). A viewer's browser's zone is the
+// monitor's until their browser has said which it is, on the first page they load.
+fn zone(req: &HttpRequest) -> Result<timezone::Zone, String> {
+    let choice = zone_choice(req);
+    if choice == config::BROWSER_TIMEZONE {
+        return Ok(req
+            .cookie(BROWSER_TZ_COOKIE)
+            .and_then(|cookie| cookie.value().parse().ok())
+            .unwrap_or(timezone::Zone::Local));
+    }
+    return choice.parse();
+}
+
+// Picks the time zone pages show times in, `?tz=` is `local`, `browser`, `utc` or an IANA zone. Goes
+// back to the page it was picked on.
+async fn pick_zone(req: HttpRequest, zone_query: web::Query<ZoneQuery>) -> HttpResponse {
+    let Some(choice) = &zone_query.tz else {
+        return admin_response(
+            &req,
+            StatusCode::BAD_REQUEST,
+            format!("Pick a time zone with ?{}=NAME", TZ_PARAM),
+        );
+    };
+    if choice != config::BROWSER_TIMEZONE {
+        if let Err(err) = choice.parse::<timezone::Zone>() {
+            return admin_response(&req, StatusCode::BAD_REQUEST, err);
+        }
+    }
+    let back = req
+        .headers()
+        .get(header::REFERER)
+        .and_then(|referer| referer.to_str().ok())
+        .unwrap_or("/");
+    return HttpResponse::SeeOther()
+        .insert_header((header::LOCATION, back))
+        .cookie(preference_cookie(TZ_PARAM, choice))
+        .finish();
+}
+
+// The viewer's preference for `name` in `req`: the param of that name if it's given, then the one
+// saved on the preferences page, kept in a cookie of the same name (see `save_preferences`).
+fn preference(req: &HttpRequest, name: &str) -> Option<String> {
+    let query = web::Query::<PreferenceQuery>::from_query(req.query_string()).ok()?;
+    let param = match name {
+        TZ_PARAM => &query.tz,
+        HOSTS_PARAM => &query.hosts,
+        UNITS_PARAM => &query.units,
+        REFRESH_PARAM => &query.refresh,
+        _ => &None,
+    };
+    return match param {
+        Some(value) => Some(value.clone()),
+        None => req.cookie(name).map(|cookie| cookie.value().to_string()),
+    };
+}
+
+// Saves `value` as the viewer's preference for `name` for a year, or forgets it if it's empty.
+fn preference_cookie<'a>(name: &'a str, value: &str) -> actix_web::cookie::Cookie<'a> {
+    let mut cookie = actix_web::cookie::Cookie::build(name, value.to_string())
+        .path("/")
+        .max_age(actix_web::cookie::time::Duration::days(365))
+        .finish();
+    if value.is_empty() {
+        cookie.make_removal();
+    }
+    return cookie;
+}
+
+// How bandwidth tests' throughput is shown, in megabits a second (the default) or megabytes.
+#[derive(Clone, Copy, PartialEq)]
+enum Units {
+    Bits,
+    Bytes,
+}
+
+impl Units {
+    fn label(self) -> &'static str {
+        return match self {
+            Units::Bits => "Mbps",
+            Units::Bytes => "MB/s",
+        };
+    }
+
+    // `bps` bits a second in these units.
+    fn scale(self, bps: f64) -> f64 {
+        return match self {
+            Units::Bits => bps / 1_000_000.0,
+            Units::Bytes => bps / 8_000_000.0,
+        };
+    }
+}
+
+impl std::str::FromStr for Units {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        return match text {
+            "bits" => Ok(Units::Bits),
+            "bytes" => Ok(Units::Bytes),
+            _ => Err(format!(
+                "Invalid {} \"{}\", it can be bits or bytes",
+                UNITS_PARAM, text
+            )),
+        };
+    }
+}
+
+// The units the viewer wants throughput in for `req`, `?units=` or their preference.
+fn units(req: &HttpRequest) -> Result<Units, String> {
+    return preference(req, UNITS_PARAM).map_or(Ok(Units::Bits), |units| units.parse());
+}
+
+// The targets the viewer wants to see for `req`, `?hosts=` (names or labels, separated by commas) or
+// their preference. None shows them all.
+fn chosen_hosts(req: &HttpRequest) -> Option<String> {
+    let hosts = preference(req, HOSTS_PARAM)?;
+    let hosts: Vec<&str> = hosts.split(',').map(str::trim).collect();
+    return (hosts.iter().any(|host| !host.is_empty())).then(|| hosts.join(","));
+}
+
+// Whether the target `name` is one of the `hosts` the viewer chose (see `chosen_hosts`). Names are
+// looked for whole rather than split apart, as they can have commas of their own.
+fn is_chosen(ping_data: &PingData, hosts: &Option<String>, name: &str) -> bool {
+    return hosts.as_ref().is_none_or(|hosts| {
+        let hosts = format!(",{},", hosts);
+        return [name, ping_data.label(name)]
+            .iter()
+            .any(|host| hosts.contains(&format!(",{},", host)));
+    });
+}
+
+// Pages update at most this often, however often samples are taken.
+const MIN_REFRESH: Duration = Duration::from_secs(1);
+
+// How often the viewer wants pages showing the latest samples to update for `req`, `?refresh=` or
+// their preference, by default (and at most) every second. None if they'd rather they didn't.
+fn refresh(req: &HttpRequest) -> Result<Option<Duration>, String> {
+    return match preference(req, REFRESH_PARAM) {
+        None => Ok(Some(MIN_REFRESH)),
+        Some(refresh) if refresh == "off" => Ok(None),
+        Some(refresh) => match parse(&refresh) {
+            Ok(refresh) => Ok(Some(refresh.max(MIN_REFRESH))),
+            Err(_err) => Err(format!(
+                "Invalid {} \"{}\", it should be off or a length of time like 5s or 1m",
+                REFRESH_PARAM, refresh
+            )),
+        },
+    };
+}
+
+// Fetches the page again when samples are taken (see `/events`), at most every `refresh`, and swaps
+// it in without a reload. Scripts in the new page don't run, so there's only ever one event source.
+fn live_update_script(refresh: Option<Duration>) -> String {
+    return match refresh {
+        Some(refresh) => format!(
+            include_str!("../templates/live_update.html"),
+            refresh.as_millis()
+        ),
+        None => String::new(),
+    };
+}
+
+// A form for the viewer's preferences, saved in cookies so pages honor them without params.
+async fn preferences(req: HttpRequest, ping_data: web::Data<Arc<Mutex<PingData>>>) -> HttpResponse {
+    let saved = |name: &str| {
+        return req
+            .cookie(name)
+            .map(|cookie| cookie.value().to_string())
+            .unwrap_or_default();
+    };
+    let chosen_hosts = Some(saved(HOSTS_PARAM));
+    let locked_ping_data = ping_data.lock().unwrap();
+    let page = pages::Preferences {
+        spans: choices(PREFERRED_SPANS, &saved(HOW_MUCH_DATA)),
+        hosts: locked_ping_data
+            .hostnames_in_order
+            .iter()
+            .map(|name| pages::HostChoice {
+                name: name.clone(),
+                label: locked_ping_data.label(name).to_string(),
+                chosen: is_chosen(&locked_ping_data, &chosen_hosts, name),
+            })
+            .collect(),
+        units: choices(&[("Mbps", "bits"), ("MB/s", "bytes")], &saved(UNITS_PARAM)),
+        refreshes: choices(REFRESH_CHOICES, &saved(REFRESH_PARAM)),
+        zone: saved(TZ_PARAM),
+    };
+    return render_page(&req, &page);
+}
+
+// The `options` of a select, as (label, value) pairs, with `chosen` selected.
+fn choices(options: &[(&'static str, &'static str)], chosen: &str) -> Vec<pages::SelectOption> {
+    return options
+        .iter()
+        .map(|(label, value)| pages::SelectOption {
+            label: i18n::text(label),
+            value,
+            selected: *value == chosen,
+        })
+        .collect();
+}
+
+// Saves the preferences posted from the preferences page, checking them first, and goes back to the
+// timeline. Empty ones are forgotten.
+async fn save_preferences(req: HttpRequest, body: String) -> HttpResponse {
+    let mut fields: HashMap<String, String> = HashMap::new();
+    // Each host chosen is a field of its own.
+    let mut hosts = Vec::new();
+    for (name, value) in url::form_urlencoded::parse(body.as_bytes()) {
+        if name == HOSTS_PARAM {
+            hosts.push(value.trim().to_string());
+        } else {
+            fields.insert(name.to_string(), value.trim().to_string());
+        }
+    }
+    fields.insert(HOSTS_PARAM.to_string(), hosts.join(","));
+    let field = |name: &str| fields.get(name).map_or("", String::as_str);
+    let mut errors = Vec::new();
+    let duration = |name: &str| match field(name) {
+        "" => None,
+        "off" if name == REFRESH_PARAM => None,
+        value => parse(value)
+            .err()
+            .map(|err| format!("Invalid {} \"{}\" - {}", name, value, err)),
+    };
+    errors.extend(duration(HOW_MUCH_DATA));
+    errors.extend(duration(REFRESH_PARAM));
+    if !field(UNITS_PARAM).is_empty() {
+        errors.extend(field(UNITS_PARAM).parse::<Units>().err());
+    }
+    if !["", config::BROWSER_TIMEZONE].contains(&field(TZ_PARAM)) {
+        errors.extend(field(TZ_PARAM).parse::<timezone::Zone>().err());
+    }
+    if !errors.is_empty() {
+        return admin_response(&req, StatusCode::BAD_REQUEST, errors.join("\n"));
+    }
+    let mut response = HttpResponse::SeeOther();
+    response.insert_header((header::LOCATION, "/"));
+    for name in [
+        HOW_MUCH_DATA,
+        HOSTS_PARAM,
+        UNITS_PARAM,
+        REFRESH_PARAM,
+        TZ_PARAM,
+    ] {
+        response.cookie(preference_cookie(name, field(name)));
+    }
+    return response.finish();
+}
+
+// Parses an RFC 3339 time, or a time without a time zone (e.g. `2024-01-01T00:00`, as a datetime-local
+// input gives it) in `zone`.
+fn parse_time(text: &str, zone: timezone::Zone) -> Result<DateTime<Utc>, String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    let local_time = NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M")
+        .or_else(|_| NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%S"))
+        .map_err(|err| err.to_string())?;
+    return match zone.resolve(local_time) {
+        Some(time) => Ok(time),
+        None => Err("that time is skipped by a change to daylight saving time".to_string()),
+    };
+}
+
+// A target's samples from `from` until `to` (RFC 3339 times, by default the last day) summed up in
+// buckets, `bucket` wide (e.g. `5m`, the default), by `fn`: avg, max or p95 round trip, or loss. So
+// charting a week doesn't mean fetching every sample, see `aggregate::series`.
+// e.g. `curl 'http://localhost:8180/api/hosts/1.1.1.1/aggregate?bucket=1h&fn=p95'`
+async fn host_aggregate(
+    req: HttpRequest,
+    path: web::Path<String>,
+    bounds_query: web::Query<TimeBoundsQuery>,
+    aggregate_query: web::Query<AggregateQuery>,
+    ping_data: web::Data<Arc<Mutex<PingData>>>,
+) -> HttpResponse {
+    let name = path.into_inner();
+    let (from, to) = match time_bounds(&req, &bounds_query) {
+        Ok(bounds) => bounds,
+        Err(err) => return admin_response(&req, StatusCode::BAD_REQUEST, err),
+    };
+    let to = to.unwrap_or_else(Utc::now);
+    let from = from.unwrap_or(cmp::min(to, Utc::now()) - chrono_Duration::days(1));
+    let width = aggregate_query
+        .bucket
+        .unwrap_or(chrono_Duration::minutes(5));
+    if (to - from).num_milliseconds() / width.num_milliseconds() > aggregate::MAX_BUCKETS {
+        return admin_response(
+            &req,
+            StatusCode::BAD_REQUEST,
+            format!(
+                "That's more than {} buckets, use a wider {} or less time",
+                aggregate::MAX_BUCKETS,
+                BUCKET_PARAM
+            ),
+        );
+    }
+    let function = aggregate_query.function.unwrap_or(aggregate::Function::Avg);
+    let locked_ping_data = ping_data.lock().unwrap();
+    if !locked_ping_data.data.contains_key(&name) {
+        return not_monitored(&req, &name);
+    }
+    let series = aggregate::series(&locked_ping_data, &name, from, to, width, function);
+    return HttpResponse::Ok().json(series);
+}
+
+// The `from` and `to` params, either of which can be left out.
+type TimeBounds = (Option<DateTime<Utc>>, Option<DateTime<Utc>>);
+
+// The `from` and `to` params as times, in the time zone times are shown in if they don't have one.
+fn time_bounds(req: &HttpRequest, query: &TimeBoundsQuery) -> Result<TimeBounds, String> {
+    let zone = zone(req)?;
+    let mut bounds = Vec::new();
+    for (param, time) in [(FROM_PARAM, &query.from), (TO_PARAM, &query.to)] {
+        bounds.push(match time {
+            None => None,
+            Some(time) => match parse_time(time, zone) {
+                Ok(time) => Some(time),
+                Err(err) => {
+                    return Err(format!(
+                        "Invalid {} \"{}\", it should be a time like 2024-01-01T00:00:00Z - {}",
+                        param, time, err
+                    ))
+                }
+            },
+        });
+    }
+    let (from, to) = (bounds[0], bounds[1]);
+    if let (Some(from), Some(to)) = (from, to) {
+        if from > to {
+            return Err(format!("{} is after {}", FROM_PARAM, TO_PARAM));
+        }
+    }
+    return Ok((from, to));
+}
+
+// What the monitor itself is using (samples and summaries per target, estimated memory, storage file
+// sizes) and whether targets are being probed on time, as JSON, see `stats::SelfStats`.
+// e.g. `curl http://localhost:8180/api/stats/self`
+async fn self_stats(ping_data: web::Data<Arc<Mutex<PingData>>>) -> HttpResponse {
+    let stats = stats::collect(&ping_data.lock().unwrap());
+    return HttpResponse::Ok().json(stats);
+}
+
+// What each target's probing thread is doing, as JSON: when it last sent a probe and got a reply,
+// how many probes in a row have failed, the address it probes, errors from its socket and whether
+// its BPF filter is attached. For when a target quietly stops getting new samples.
+// e.g. `curl http://localhost:8180/debug`
+async fn debug(ping_data: web::Data<Arc<Mutex<PingData>>>) -> HttpResponse {
+    let report = workers::report(
+        ping_data
+            .lock()
+            .unwrap()
+            .targets
+            .iter()
+            .map(|target| target.name.as_str()),
+    );
+    return HttpResponse::Ok().json(report);
+}
+
+// Adds the history in a snapshot from `GET /api/snapshot` to what's been recorded, for the targets
+// that are monitored here.
+// e.g. `curl --data-binary @snapshot.json http://localhost:8180/api/snapshot`
+async fn import_snapshot(
+    req: HttpRequest,
+    body: String,
+    ping_data: web::Data<Arc<Mutex<PingData>>>,
+) -> HttpResponse {
+    if !config::get().admin_api {
+        return admin_api_off();
+    }
+    // Parsing a big snapshot takes a while, do it off of the web server's worker threads.
+    let ping_data = ping_data.get_ref().clone();
+    let imported = web::block(move || {
+        let snapshot = json::parse(&body).map_err(|err| format!("Invalid JSON - {}", err))?;
+        return snapshot::import(&mut ping_data.lock().unwrap(), &snapshot);
+    })
+    .await
+    .unwrap();
+    return match imported {
+        Ok(summary) => {
+            let mut message = format!(
+                "Imported {} samples and {} outages",
+                summary.num_samples, summary.num_outages
+            );
+            if !summary.skipped_targets.is_empty() {
+                message += &format!(
+                    "\nSkipped targets that aren't monitored: {}",
+                    summary.skipped_targets.join(", ")
+                );
+            }
+            audit::record(
+                &auth::actor(&req),
+                "import snapshot",
+                None,
+                None,
+                Some(message.clone()),
+            );
+            admin_response(&req, StatusCode::OK, message)
+        }
+        Err(err) => admin_response(
+            &req,
+            StatusCode::BAD_REQUEST,
+            format!("Failed to import the snapshot - {}", err),
+        ),
+    };
+}
+
+// The changes made to the monitor since it started, newest first: targets added, edited, moved,
+// paused and removed, snapshots imported, backups restored and the config reloaded, by who made them.
+async fn audit_log() -> HttpResponse {
+    return HttpResponse::Ok().json(audit::newest_first());
+}
+
+// Everything needed to move the monitor elsewhere, as a tar archive: a snapshot of its history, its
+// config file and the changes made with the admin API. Restore it with `POST /api/restore` or
+// `network-monitor restore`. It's part of the admin API, since the config can have passwords in it.
+async fn backup(
+    ping_data: web::Data<Arc<Mutex<PingData>>>,
+    monitor: web::Data<Arc<Mutex<Monitor>>>,
+) -> HttpResponse {
+    if !config::get().admin_api {
+        return admin_api_off();
+    }
+    let mut backup = backup::Backup::default();
+    {
+        let monitor = monitor.lock().unwrap();
+        if let Some(config_path) = &monitor.args.config_path {
+            match std::fs::read_to_string(config_path)
+                .map_err(|err| err.to_string())
+                .and_then(|config| config::without_secrets(&config))
+            {
+                Ok(config) => backup.config = Some(config),
+                Err(err) => {
+                    return HttpResponse::InternalServerError()
+                        .content_type(ContentType::plaintext())
+                        .body(format!("Failed to read {} - {}\n", config_path, err))
+                }
+            }
+        }
+        backup.added_specs = monitor.added_specs.clone();
+        backup.removed_names = monitor.removed_names.iter().cloned().collect();
+    }
+    let snapshot = snapshot::export(&ping_data.lock().unwrap());
+    backup.snapshot = Some(serde_json::to_string(&snapshot).unwrap());
+    return HttpResponse::Ok()
+        .content_type("application/x-tar")
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!(
+                "attachment; filename=\"network-monitor-backup-{}.tar\"",
+                Local::now().format("%Y-%m-%d")
+            ),
+        ))
+        .body(backup.to_tar());
+}
+
+// Sets the monitor up from a backup from `GET /api/backup`: its config file is replaced with the
+// backup's, the admin API's changes are applied on top of the current ones and its history is added
+// to what's been recorded.
+// e.g. `curl --data-binary @backup.tar http://localhost:8180/api/restore`
+async fn restore(
+    req: HttpRequest,
+    body: web::Bytes,
+    ping_data: web::Data<Arc<Mutex<PingData>>>,
+    monitor: web::Data<Arc<Mutex<Monitor>>>,
+) -> HttpResponse {
+    if !config::get().admin_api {
+        return admin_api_off();
+    }
+    // Checking targets may look up their hostnames, do it off of the web server's worker threads.
+    let ping_data = ping_data.get_ref().clone();
+    let monitor = monitor.get_ref().clone();
+    let restored = web::block(move || restore_backup(&body, &monitor, &ping_data))
+        .await
+        .unwrap();
+    return match restored {
+        Ok(message) => {
+            audit::record(
+                &auth::actor(&req),
+                "restore backup",
+                None,
+                None,
+                Some(message.clone()),
+            );
+            admin_response(&req, StatusCode::OK, message)
+        }
+        Err(err) => admin_response(
+            &req,
+            StatusCode::BAD_REQUEST,
+            format!("Failed to restore the backup - {}", err),
+        ),
+    };
+}
+
+// Returns what was restored. Nothing changes if the backup has problems.
+fn restore_backup(
+    tar: &[u8],
+    monitor: &Mutex<Monitor>,
+    ping_data: &Mutex<PingData>,
+) -> Result<String, String> {
+    let backup = backup::Backup::from_tar(tar)?;
+    let snapshot = backup
+        .snapshot
+        .as_deref()
+        .map(json::parse)
+        .transpose()
+        .map_err(|err| format!("Invalid snapshot - {}", err))?;
+    let mut monitor = monitor.lock().unwrap();
+    let mut restored = Vec::new();
+
+    let config_text = match (&backup.config, &monitor.args.config_path) {
+        // Backups don't have credentials, this monitor's are kept.
+        (Some(config_text), Some(config_path)) => match std::fs::read_to_string(config_path) {
+            Ok(current) => Some(config::with_secrets_from(config_text, &current)?),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Some(config_text.clone()),
+            Err(err) => return Err(format!("Failed to read {} - {}", config_path, err)),
+        },
+        (Some(_), None) => {
+            restored.push(
+                "Skipped the config file, since this monitor wasn't started with --config"
+                    .to_string(),
+            );
+            None
+        }
+        (None, _) => None,
+    };
+    let config = match &config_text {
+        Some(config_text) => config_with_overrides(&monitor.args, Some(config_text))?,
+        None => load_config(&monitor.args)?,
+    };
+    let mut added_specs = monitor.added_specs.clone();
+    for spec in backup.added_specs {
+        if !added_specs.contains(&spec) {
+            added_specs.push(spec);
+        }
+    }
+    let mut removed_names = monitor.removed_names.clone();
+    removed_names.extend(backup.removed_names);
+    let targets = monitor
+        .targets_for(&config, &added_specs, &removed_names)
+        .map_err(|problems| describe_problems(&problems))?;
+    let snapshot = snapshot
+        .as_ref()
+        .map(snapshot::read)
+        .transpose()
+        .map_err(|err| format!("Invalid snapshot - {}", err))?;
+
+    // Everything checks out, the config file is written first since it's the only part that can
+    // still fail.
+    if let (Some(config_text), Some(config_path)) = (&config_text, &monitor.args.config_path) {
+        std::fs::write(config_path, config_text)
+            .map_err(|err| format!("Failed to write {} - {}", config_path, err))?;
+    }
+    monitor.added_specs = added_specs;
+    monitor.removed_names = removed_names;
+    let monitor = &mut *monitor;
+    let (added, removed) = update_targets(targets, &monitor.ping_data, &mut monitor.stop_probing);
+    restored.push(describe_changes(&added, &removed));
+    if let (Some(_), Some(config_path)) = (&config_text, &monitor.args.config_path) {
+        restored.push(format!("Restored the config file to {}", config_path));
+    }
+    config::set(config);
+
+    if let Some(snapshot) = snapshot {
+        let summary = snapshot::apply(&mut ping_data.lock().unwrap(), snapshot);
+        restored.push(format!(
+            "Imported {} samples and {} outages",
+            summary.num_samples, summary.num_outages
+        ));
+    }
+    return Ok(restored.join("\n"));
+}
+
+// Manages targets with the admin API: adding, editing, reordering, pausing and removing them.
+async fn settings(req: HttpRequest, ping_data: web::Data<Arc<Mutex<PingData>>>) -> HttpResponse {
+    if !config::get().admin_api {
+        return admin_api_off();
+    }
+    let locked_ping_data = ping_data.lock().unwrap();
+    let targets = &locked_ping_data.targets;
+    let page = pages::Settings {
+        targets: targets
+            .iter()
+            .enumerate()
+            .map(|(position, target)| pages::SettingsTarget {
+                query: url::form_urlencoded::Serializer::new(String::new())
+                    .append_pair(TARGET_PARAM, &target.name)
+                    .finish(),
+                spec: probes::set_option(
+                    &probes::set_option(&target.name, "label", ""),
+                    "interval",
+                    "",
+                ),
+                label: target.options.label.clone().unwrap_or_default(),
+                interval: target
+                    .options
+                    .interval
+                    .map(|interval| format!("{:?}", interval))
+                    .unwrap_or_default(),
+                default_interval: format!("{:?}", target.interval()),
+                move_up: position.checked_sub(1),
+                move_down: Some(position + 1).filter(|below| *below < targets.len()),
+                paused: locked_ping_data.is_paused(&target.name),
+            })
+            .collect(),
+    };
+    return render_page(&req, &page);
+}
+
+// Escapes text so it can be safely embedded in HTML, including inside attribute values.
+fn escape_html(text: &str) -> String {
+    return text
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;");
 }
@@ -0,0 +1,145 @@
+use crate::rollups::LossCount;
+use crate::PingData;
+use chrono::Duration as chrono_Duration;
+use chrono::Utc;
+use std::fmt::Write;
+
+// How far back the rolling loss and latency metrics look.
+const LOSS_WINDOWS: [(&str, i64); 2] = [("5m", 5), ("1h", 60)];
+const LATENCY_WINDOW_MIN: i64 = 60;
+const QUANTILES: [f64; 3] = [0.5, 0.95, 0.99];
+
+// Every target's metrics in Prometheus' text exposition format, for `/metrics`.
+// https://prometheus.io/docs/instrumenting/exposition_formats/
+pub fn exposition(ping_data: &PingData) -> String {
+    let now = Utc::now();
+    let mut up = Vec::new();
+    let mut down = Vec::new();
+    let mut last_rtt = Vec::new();
+    let mut loss = Vec::new();
+    let mut rtt_quantiles = Vec::new();
+    let mut sent = Vec::new();
+    let mut lost = Vec::new();
+    for target in &ping_data.targets {
+        let name = &target.name;
+        let (Some(samples), Some(loss_counters)) =
+            (ping_data.data.get(name), ping_data.loss_counters.get(name))
+        else {
+            continue; // The target is being renamed
+        };
+        let labels = format!(
+            "target=\"{}\",label=\"{}\"",
+            escape(name),
+            escape(target.label())
+        );
+
+        if let Some((_, sample)) = samples.iter().next_back() {
+            up.push((labels.clone(), !sample.result.is_failure() as u8 as f64));
+            if let Some(round_trip) = sample.result.round_trip() {
+                last_rtt.push((labels.clone(), round_trip.as_secs_f64()));
+            }
+        }
+        let is_down = ping_data.outages[name]
+            .values()
+            .next_back()
+            .is_some_and(|outage| outage.ended.is_none());
+        down.push((labels.clone(), is_down as u8 as f64));
+
+        for (window, minutes) in LOSS_WINDOWS {
+            // Probes are counted by the minute, so this is the minutes that started within the
+            // window, including the one still being counted.
+            let start = now - chrono_Duration::minutes(minutes);
+            let mut count = LossCount::default();
+            for (_, minute) in loss_counters.minutes.range(start..) {
+                count.sent += minute.sent;
+                count.lost += minute.lost;
+            }
+            if count.sent > 0 {
+                loss.push((format!("{},window=\"{}\"", labels, window), count.loss()));
+            }
+        }
+
+        let start = now - chrono_Duration::minutes(LATENCY_WINDOW_MIN);
+        let mut round_trips: Vec<f64> = samples
+            .range(start..)
+            .filter_map(|(_, sample)| sample.result.round_trip())
+            .map(|round_trip| round_trip.as_secs_f64())
+            .collect();
+        round_trips.sort_by(f64::total_cmp);
+        if !round_trips.is_empty() {
+            for quantile in QUANTILES {
+                // The nearest-rank method: the smallest round trip at least `quantile` of them are
+                // no slower than.
+                let rank = (quantile * round_trips.len() as f64).ceil() as usize;
+                rtt_quantiles.push((
+                    format!("{},quantile=\"{}\"", labels, quantile),
+                    round_trips[rank.max(1) - 1],
+                ));
+            }
+        }
+
+        sent.push((labels.clone(), loss_counters.total.sent as f64));
+        lost.push((labels, loss_counters.total.lost as f64));
+    }
+
+    let mut text = String::new();
+    for (name, kind, help, values) in [
+        (
+            "network_monitor_target_up",
+            "gauge",
+            "Whether the target's latest probe succeeded.",
+            up,
+        ),
+        (
+            "network_monitor_target_down",
+            "gauge",
+            "Whether the target is in an outage, after several failed probes in a row.",
+            down,
+        ),
+        (
+            "network_monitor_last_rtt_seconds",
+            "gauge",
+            "The round trip of the target's latest probe, if it succeeded.",
+            last_rtt,
+        ),
+        (
+            "network_monitor_loss_ratio",
+            "gauge",
+            "The fraction of probes lost over the window, from 0 to 1.",
+            loss,
+        ),
+        (
+            "network_monitor_rtt_quantile_seconds",
+            "gauge",
+            "Round trip quantiles of the successful probes over the last hour.",
+            rtt_quantiles,
+        ),
+        (
+            "network_monitor_probes_sent_total",
+            "counter",
+            "Probes sent to the target since the monitor started, counting each probe in a burst.",
+            sent,
+        ),
+        (
+            "network_monitor_probes_lost_total",
+            "counter",
+            "Probes to the target that failed since the monitor started.",
+            lost,
+        ),
+    ] {
+        writeln!(text, "# HELP {} {}", name, help).unwrap();
+        writeln!(text, "# TYPE {} {}", name, kind).unwrap();
+        for (labels, value) in values {
+            writeln!(text, "{}{{{}}} {}", name, labels, value).unwrap();
+        }
+    }
+    return text;
+}
+
+// Escapes a label value, which is quoted.
+fn escape(value: &str) -> String {
+    return value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+}
@@ -0,0 +1,200 @@
+use crate::config;
+use crate::json::Json;
+use crate::timezone;
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::thread;
+use std::time::Duration;
+
+// How long each attempt to deliver an alert can take.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+// Deliveries that fail are retried this many times in all, waiting twice as long before each retry,
+// so a webhook that's restarting gets about half a minute to come back.
+const MAX_ATTEMPTS: u32 = 5;
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+// The kinds of alerts, each notifier's `rules` in the config picks which of them it's sent.
+pub const RULES: [&str; 2] = ["outage", "connectivity_loss"];
+
+// Something worth telling someone about, when it starts (fires) and again when it ends (resolves).
+pub struct Alert {
+    // What happened: `outage` when a target fails enough probes in a row, or `connectivity_loss`
+    // when every target goes down at once.
+    pub rule: &'static str,
+    // The target's name and label, None for connectivity loss.
+    pub host: Option<(String, String)>,
+    // For outages, how many probes in a row had failed. For connectivity loss, how many targets
+    // were down.
+    pub value: f64,
+    pub started: DateTime<Utc>,
+    // None while it's still going.
+    pub resolved: Option<DateTime<Utc>>,
+}
+impl Alert {
+    // The JSON webhooks are sent, e.g.
+    // `{"status":"firing","rule":"outage","host":"1.1.1.1","label":"Cloudflare","value":3,
+    // "started":"2024-01-01T08:00:00Z","resolved":null}`.
+    fn to_json(&self) -> Json {
+        let time =
+            |time: DateTime<Utc>| Json::String(time.to_rfc3339_opts(SecondsFormat::Secs, true));
+        let (host, label) = match &self.host {
+            Some((name, label)) => (Json::String(name.clone()), Json::String(label.clone())),
+            None => (Json::Null, Json::Null),
+        };
+        return Json::Object(vec![
+            (
+                "status".to_string(),
+                Json::String(
+                    match self.resolved {
+                        Some(_) => "resolved",
+                        None => "firing",
+                    }
+                    .to_string(),
+                ),
+            ),
+            ("rule".to_string(), Json::String(self.rule.to_string())),
+            ("host".to_string(), host),
+            ("label".to_string(), label),
+            ("value".to_string(), Json::Number(self.value)),
+            ("started".to_string(), time(self.started)),
+            (
+                "resolved".to_string(),
+                self.resolved.map_or(Json::Null, time),
+            ),
+        ]);
+    }
+
+    // The alert as a chat message, with times in the time zone pages show them in, e.g. `Cloudflare
+    // (1.1.1.1) is down since 2024-01-01 08:00, 3 probes in a row failed`.
+    fn message(&self) -> String {
+        // Each viewer's browser's zone isn't known here, so that's the monitor's.
+        let zone = config::get()
+            .timezone
+            .parse()
+            .unwrap_or(timezone::Zone::Local);
+        let target = match &self.host {
+            Some((name, label)) if label != name => format!("{} ({})", label, name),
+            Some((name, _)) => name.clone(),
+            None => String::new(),
+        };
+        let Some(resolved) = self.resolved else {
+            return match self.host {
+                Some(_) => format!(
+                    "{} is down since {}, {} probes in a row failed",
+                    target,
+                    zone.date_time_minutes(self.started),
+                    self.value
+                ),
+                None => format!(
+                    "Lost local connectivity at {}, all {} targets are down",
+                    zone.date_time_minutes(self.started),
+                    self.value
+                ),
+            };
+        };
+        let lasted = resolved - self.started;
+        let lasted = if lasted.num_minutes() == 0 {
+            format!("{} seconds", lasted.num_seconds())
+        } else {
+            format!("{} minutes", lasted.num_minutes())
+        };
+        return match self.host {
+            Some(_) => format!(
+                "{} is back up, it was down for {} since {}",
+                target,
+                lasted,
+                zone.date_time_minutes(self.started)
+            ),
+            None => format!(
+                "Local connectivity is back, it was lost for {} since {}",
+                lasted,
+                zone.date_time_minutes(self.started)
+            ),
+        };
+    }
+}
+
+// Sends `alert` to each of the config's webhooks, and to Slack, Discord and Telegram if they're set
+// up, unless its rule isn't one they're sent. Sent in the background, so a slow or unreachable one
+// doesn't hold up probing.
+pub fn send(alert: Alert) {
+    let config = config::get();
+    let wants = |rules: &Vec<String>| rules.iter().any(|rule| rule == alert.rule);
+    // Each one's URL, what to call it in the log (a Telegram URL has the bot's token in it), and the
+    // JSON to POST it.
+    let mut deliveries = Vec::new();
+    if wants(&config.webhook_rules) {
+        let body = alert.to_json();
+        for url in &config.webhook_urls {
+            deliveries.push((url.clone(), url.clone(), body.clone()));
+        }
+    }
+    let text = |key: &str| {
+        return Json::Object(vec![(key.to_string(), Json::String(alert.message()))]);
+    };
+    if let Some(url) = config
+        .slack_webhook_url
+        .as_ref()
+        .filter(|_| wants(&config.slack_rules))
+    {
+        deliveries.push((url.clone(), "Slack".to_string(), text("text")));
+    }
+    if let Some(url) = config
+        .discord_webhook_url
+        .as_ref()
+        .filter(|_| wants(&config.discord_rules))
+    {
+        deliveries.push((url.clone(), "Discord".to_string(), text("content")));
+    }
+    if let (Some(bot_token), Some(chat_id)) = (&config.telegram_bot_token, &config.telegram_chat_id)
+    {
+        if wants(&config.telegram_rules) {
+            // https://core.telegram.org/bots/api#sendmessage
+            deliveries.push((
+                format!("https://api.telegram.org/bot{}/sendMessage", bot_token),
+                "Telegram".to_string(),
+                Json::Object(vec![
+                    ("chat_id".to_string(), Json::String(chat_id.clone())),
+                    ("text".to_string(), Json::String(alert.message())),
+                ]),
+            ));
+        }
+    }
+    for (url, destination, body) in deliveries {
+        thread::spawn(move || {
+            if let Err(err) = post_with_retries(&url, "application/json", &body.to_string()) {
+                eprintln!(
+                    "Failed to send an alert to {} - {}",
+                    destination,
+                    err.replace(&url, &destination)
+                );
+            }
+        });
+    }
+}
+
+// POSTs `body` to `url`, retrying with exponential backoff when the request doesn't get through or
+// the server has trouble with it (a 5xx or 429). Other statuses mean retrying wouldn't help.
+fn post_with_retries(url: &str, content_type: &str, body: &str) -> Result<(), String> {
+    let mut delay = FIRST_RETRY_DELAY;
+    let mut attempt = 1;
+    loop {
+        let response = ureq::post(url)
+            .timeout(REQUEST_TIMEOUT)
+            .set("Content-Type", content_type)
+            .send_string(body);
+        let err = match response {
+            Ok(_) => return Ok(()),
+            Err(ureq::Error::Status(status, _)) if status != 429 && status < 500 => {
+                return Err(format!("HTTP status {}", status));
+            }
+            Err(ureq::Error::Status(status, _)) => format!("HTTP status {}", status),
+            Err(ureq::Error::Transport(err)) => err.to_string(),
+        };
+        if attempt == MAX_ATTEMPTS {
+            return Err(format!("{}, gave up after {} attempts", err, attempt));
+        }
+        thread::sleep(delay);
+        delay *= 2;
+        attempt += 1;
+    }
+}
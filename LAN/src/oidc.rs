@@ -0,0 +1,444 @@
+use crate::auth::{self, Role};
+use crate::config::{self, Config};
+use crate::json::{self, Json};
+use actix_web::cookie::{time, Cookie, SameSite};
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse};
+use base64::engine::general_purpose::{STANDARD as BASE64, URL_SAFE_NO_PAD as BASE64_URL};
+use base64::Engine;
+use chrono::Utc;
+use ring::rand::SecureRandom;
+use std::collections::HashMap;
+use std::time::Duration;
+
+// Logging in to the web UI with an OpenID Connect provider (e.g. Authelia or Keycloak), with the
+// authorization code flow: `/login` sends the browser to the provider, which sends it back to
+// `/oidc/callback` with a code the monitor swaps for an ID token saying who logged in and which groups
+// they're in. They then have a session cookie until `SESSION_LENGTH` is up or they go to `/logout`.
+// https://openid.net/specs/openid-connect-core-1_0.html
+pub const ROUTES: [&str; 3] = ["/login", "/oidc/callback", "/logout"];
+const CALLBACK_PATH: &str = "/oidc/callback";
+const NEXT_PARAM: &str = "next";
+
+const SESSION_COOKIE: &str = "session";
+const SESSION_LENGTH: time::Duration = time::Duration::hours(12);
+// Remembers the state and nonce sent to the provider, and the page to go back to, while logging in.
+const LOGIN_COOKIE: &str = "oidc_login";
+const LOGIN_LENGTH: time::Duration = time::Duration::minutes(10);
+
+// How long to wait for the provider to answer.
+const PROVIDER_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Where the provider's endpoints are, from its discovery document. It's fetched again for each login,
+// which are rare, so key rotations are picked up.
+// https://openid.net/specs/openid-connect-discovery-1_0.html
+struct Provider {
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+fn discover(issuer: &str) -> Result<Provider, String> {
+    let document = get_json(ureq::get(&format!(
+        "{}/.well-known/openid-configuration",
+        issuer.trim_end_matches('/')
+    )))?;
+    let endpoint = |name: &str| {
+        return document
+            .get(name)
+            .and_then(Json::as_str)
+            .map(str::to_string)
+            .ok_or(format!("The provider's discovery document has no {}", name));
+    };
+    return Ok(Provider {
+        authorization_endpoint: endpoint("authorization_endpoint")?,
+        token_endpoint: endpoint("token_endpoint")?,
+        jwks_uri: endpoint("jwks_uri")?,
+    });
+}
+
+fn get_json(request: ureq::Request) -> Result<Json, String> {
+    let body = request
+        .timeout(PROVIDER_TIMEOUT)
+        .call()
+        .map_err(|err| err.to_string())?
+        .into_string()
+        .map_err(|err| err.to_string())?;
+    return json::parse(&body);
+}
+
+// Sends the browser to the provider to log in, coming back to `?next=` (or the home page) after.
+pub async fn login(req: HttpRequest) -> HttpResponse {
+    let config = config::get();
+    let Some(issuer) = config.oidc_issuer.clone() else {
+        return HttpResponse::NotFound()
+            .body("OpenID Connect isn't set up, see [oidc] in the config.");
+    };
+    let provider = match web::block(move || discover(&issuer)).await.unwrap() {
+        Ok(provider) => provider,
+        Err(err) => {
+            return HttpResponse::BadGateway().body(format!(
+                "Failed to reach the OpenID Connect provider - {}",
+                err
+            ))
+        }
+    };
+    let params = web::Query::<HashMap<String, String>>::from_query(req.query_string())
+        .map(|params| params.into_inner())
+        .unwrap_or_default();
+    let next = params
+        .get(NEXT_PARAM)
+        .filter(|next| is_local(next))
+        .map_or("/", String::as_str);
+    let state = random_string();
+    let nonce = random_string();
+    let mut location = match url::Url::parse(&provider.authorization_endpoint) {
+        Ok(location) => location,
+        Err(err) => {
+            return HttpResponse::BadGateway()
+                .body(format!("Invalid authorization_endpoint - {}", err))
+        }
+    };
+    location
+        .query_pairs_mut()
+        .append_pair("response_type", "code")
+        .append_pair("client_id", config.oidc_client_id.as_deref().unwrap())
+        .append_pair("redirect_uri", &redirect_url(&req, &config))
+        .append_pair("scope", &config.oidc_scopes.join(" "))
+        .append_pair("state", &state)
+        .append_pair("nonce", &nonce);
+    return HttpResponse::SeeOther()
+        .insert_header((header::LOCATION, location.as_str()))
+        .cookie(cookie(
+            &req,
+            LOGIN_COOKIE,
+            &auth::sign(&format!("{}:{}:{}", state, nonce, next)),
+            LOGIN_LENGTH,
+        ))
+        .finish();
+}
+
+// Where the provider brings the browser back to after logging in, with a code to swap for an ID token.
+pub async fn callback(req: HttpRequest) -> HttpResponse {
+    let config = config::get();
+    if config.oidc_issuer.is_none() {
+        return HttpResponse::NotFound()
+            .body("OpenID Connect isn't set up, see [oidc] in the config.");
+    }
+    let params = web::Query::<HashMap<String, String>>::from_query(req.query_string())
+        .map(|params| params.into_inner())
+        .unwrap_or_default();
+    if let Some(error) = params.get("error") {
+        return HttpResponse::Unauthorized().body(format!(
+            "The OpenID Connect provider didn't log you in - {} {}",
+            error,
+            params.get("error_description").map_or("", String::as_str)
+        ));
+    }
+    let Some(login) = req
+        .cookie(LOGIN_COOKIE)
+        .and_then(|cookie| auth::verify(cookie.value()))
+    else {
+        return HttpResponse::BadRequest()
+            .body("Logging in took too long, or was started elsewhere.");
+    };
+    let mut login = login.splitn(3, ':');
+    let (Some(state), Some(nonce), Some(next)) = (login.next(), login.next(), login.next()) else {
+        return HttpResponse::BadRequest()
+            .body("Logging in took too long, or was started elsewhere.");
+    };
+    if params.get("state").map(String::as_str) != Some(state) {
+        return HttpResponse::BadRequest()
+            .body("Logging in took too long, or was started elsewhere.");
+    }
+    let Some(code) = params.get("code").cloned() else {
+        return HttpResponse::BadRequest().body("The OpenID Connect provider sent no code.");
+    };
+    let redirect_url = redirect_url(&req, &config);
+    let nonce = nonce.to_string();
+    let claims = web::block(move || {
+        return log_in(&config::get(), &code, &redirect_url, &nonce);
+    })
+    .await
+    .unwrap();
+    let claims = match claims {
+        Ok(claims) => claims,
+        Err(err) => {
+            return HttpResponse::Unauthorized().body(format!("Failed to log in - {}", err))
+        }
+    };
+    let username = ["preferred_username", "email", "sub"]
+        .iter()
+        .find_map(|claim| claims.get(claim).and_then(Json::as_str))
+        .unwrap_or("")
+        .to_string();
+    let groups: Vec<&str> = match claims.get(&config.oidc_groups_claim) {
+        Some(Json::Array(groups)) => groups.iter().filter_map(Json::as_str).collect(),
+        Some(Json::String(group)) => vec![group.as_str()],
+        _ => Vec::new(),
+    };
+    let Some(role) = role_for(&groups, &config) else {
+        return HttpResponse::Forbidden().body(format!(
+            "{} isn't in any of oidc.admin_groups or oidc.viewer_groups.",
+            username
+        ));
+    };
+    let expires = Utc::now().timestamp() + SESSION_LENGTH.whole_seconds();
+    let role_name = match role {
+        Role::Admin => "admin",
+        Role::Viewer => "viewer",
+    };
+    let mut login_cookie = cookie(&req, LOGIN_COOKIE, "", LOGIN_LENGTH);
+    login_cookie.make_removal();
+    return HttpResponse::SeeOther()
+        .insert_header((header::LOCATION, next))
+        .cookie(cookie(
+            &req,
+            SESSION_COOKIE,
+            &auth::sign(&format!("{}:{}:{}", role_name, expires, username)),
+            SESSION_LENGTH,
+        ))
+        .cookie(login_cookie)
+        .finish();
+}
+
+// Ends the session. Logging in again goes straight back in while the provider still remembers them.
+pub async fn logout(req: HttpRequest) -> HttpResponse {
+    let mut session_cookie = cookie(&req, SESSION_COOKIE, "", SESSION_LENGTH);
+    session_cookie.make_removal();
+    return HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .cookie(session_cookie)
+        .body("<p>Logged out. <a href=\"/login\">Log in again</a></p>");
+}
+
+// Where an unauthenticated browser going to a page is sent, to come back to it after logging in.
+pub fn login_redirect(req: &HttpRequest) -> HttpResponse {
+    let next = req
+        .uri()
+        .path_and_query()
+        .map_or("/", |path_and_query| path_and_query.as_str());
+    let query = url::form_urlencoded::Serializer::new(String::new())
+        .append_pair(NEXT_PARAM, next)
+        .finish();
+    return HttpResponse::SeeOther()
+        .insert_header((header::LOCATION, format!("/login?{}", query)))
+        .finish();
+}
+
+// The role and username of whoever logged in with `req`'s session cookie, if it has one that's still
+// good.
+pub fn session_role(req: &HttpRequest) -> Option<(Role, String)> {
+    config::get().oidc_issuer.as_ref()?;
+    let session = auth::verify(req.cookie(SESSION_COOKIE)?.value())?;
+    let mut session = session.splitn(3, ':');
+    let role = match session.next()? {
+        "admin" => Role::Admin,
+        "viewer" => Role::Viewer,
+        _ => return None,
+    };
+    let expires: i64 = session.next()?.parse().ok()?;
+    if expires < Utc::now().timestamp() {
+        return None;
+    }
+    return Some((role, session.next()?.to_string()));
+}
+
+// Swaps `code` for an ID token at the provider's token endpoint, and returns its claims once it's
+// checked that the provider signed it, for us, and for this login.
+fn log_in(config: &Config, code: &str, redirect_url: &str, nonce: &str) -> Result<Json, String> {
+    let issuer = config.oidc_issuer.as_deref().unwrap();
+    let client_id = config.oidc_client_id.as_deref().unwrap();
+    let provider = discover(issuer)?;
+    // The client ID and secret are form encoded before being sent with HTTP Basic authentication.
+    // https://www.rfc-editor.org/rfc/rfc6749#section-2.3.1
+    let client_credentials = format!(
+        "{}:{}",
+        url::form_urlencoded::byte_serialize(client_id.as_bytes()).collect::<String>(),
+        url::form_urlencoded::byte_serialize(
+            config.oidc_client_secret.as_deref().unwrap().as_bytes()
+        )
+        .collect::<String>()
+    );
+    let response = ureq::post(&provider.token_endpoint)
+        .timeout(PROVIDER_TIMEOUT)
+        .set(
+            "Authorization",
+            &format!("Basic {}", BASE64.encode(client_credentials)),
+        )
+        .send_form(&[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", redirect_url),
+        ])
+        .map_err(|err| err.to_string())?
+        .into_string()
+        .map_err(|err| err.to_string())?;
+    let id_token = json::parse(&response)?
+        .get("id_token")
+        .and_then(Json::as_str)
+        .map(str::to_string)
+        .ok_or("The provider sent no ID token")?;
+    let keys = get_json(ureq::get(&provider.jwks_uri))?;
+    let claims = verify_id_token(&id_token, &keys)?;
+    if claims
+        .get("iss")
+        .and_then(Json::as_str)
+        .map(|iss| iss.trim_end_matches('/'))
+        != Some(issuer.trim_end_matches('/'))
+    {
+        return Err("The ID token is from a different issuer".to_string());
+    }
+    let for_us = match claims.get("aud") {
+        Some(Json::String(audience)) => audience == client_id,
+        Some(Json::Array(audiences)) => audiences
+            .iter()
+            .any(|audience| audience.as_str() == Some(client_id)),
+        _ => false,
+    };
+    if !for_us {
+        return Err("The ID token is for a different client".to_string());
+    }
+    if claims
+        .get("exp")
+        .and_then(Json::as_f64)
+        .is_none_or(|exp| exp < Utc::now().timestamp() as f64)
+    {
+        return Err("The ID token has expired".to_string());
+    }
+    if claims.get("nonce").and_then(Json::as_str) != Some(nonce) {
+        return Err("The ID token is for a different login".to_string());
+    }
+    return Ok(claims);
+}
+
+// The claims in `id_token`, a JWT, if it's signed by one of `keys`, the provider's JWK set. Only
+// RS256, which every provider supports, and ES256 signatures are.
+// https://www.rfc-editor.org/rfc/rfc7515 https://www.rfc-editor.org/rfc/rfc7517
+fn verify_id_token(id_token: &str, keys: &Json) -> Result<Json, String> {
+    let parts: Vec<&str> = id_token.split('.').collect();
+    let [header, claims, signature] = parts[..] else {
+        return Err("The ID token isn't a JWT".to_string());
+    };
+    let decode = |part: &str| {
+        return BASE64_URL
+            .decode(part)
+            .map_err(|_err| "The ID token isn't a JWT".to_string());
+    };
+    let decode_json = |part: &str| {
+        let part = String::from_utf8(decode(part)?).map_err(|err| err.to_string())?;
+        return json::parse(&part);
+    };
+    let header = decode_json(header)?;
+    let algorithm = header.get("alg").and_then(Json::as_str).unwrap_or("");
+    let key_type = match algorithm {
+        "RS256" => "RSA",
+        "ES256" => "EC",
+        _ => {
+            return Err(format!(
+                "ID tokens signed with {} aren't supported",
+                algorithm
+            ))
+        }
+    };
+    let key_id = header.get("kid").and_then(Json::as_str);
+    let key = keys
+        .get("keys")
+        .and_then(Json::as_array)
+        .unwrap_or_default()
+        .iter()
+        .find(|key| {
+            return key.get("kty").and_then(Json::as_str) == Some(key_type)
+                && (key_id.is_none() || key.get("kid").and_then(Json::as_str) == key_id);
+        })
+        .ok_or("The provider has no key for the ID token")?;
+    let component = |name: &str| {
+        return key
+            .get(name)
+            .and_then(Json::as_str)
+            .ok_or(format!("The provider's key has no {}", name))
+            .and_then(decode);
+    };
+    // The header and claims are what's signed.
+    let message = &id_token[..id_token.rfind('.').unwrap()];
+    let signature = decode(signature)?;
+    let verified = if key_type == "RSA" {
+        ring::signature::RsaPublicKeyComponents {
+            n: component("n")?,
+            e: component("e")?,
+        }
+        .verify(
+            &ring::signature::RSA_PKCS1_2048_8192_SHA256,
+            message.as_bytes(),
+            &signature,
+        )
+    } else {
+        // An uncompressed P-256 point.
+        let point = [vec![4], component("x")?, component("y")?].concat();
+        ring::signature::UnparsedPublicKey::new(&ring::signature::ECDSA_P256_SHA256_FIXED, point)
+            .verify(message.as_bytes(), &signature)
+    };
+    verified.map_err(|_err| "The ID token's signature is wrong".to_string())?;
+    return decode_json(claims);
+}
+
+// The role someone in `groups` gets, if any: admin if they're in one of the admin groups, otherwise
+// viewer if they're in one of the viewer groups or there aren't any.
+fn role_for(groups: &[&str], config: &Config) -> Option<Role> {
+    let in_any = |of: &[String]| {
+        return groups
+            .iter()
+            .any(|group| of.iter().any(|other| other == group));
+    };
+    if in_any(&config.oidc_admin_groups) {
+        return Some(Role::Admin);
+    }
+    if config.oidc_viewer_groups.is_empty() || in_any(&config.oidc_viewer_groups) {
+        return Some(Role::Viewer);
+    }
+    return None;
+}
+
+// Where the provider sends the browser back to, which has to be the same when logging in and
+// swapping the code.
+fn redirect_url(req: &HttpRequest, config: &Config) -> String {
+    if let Some(redirect_url) = &config.oidc_redirect_url {
+        return redirect_url.clone();
+    }
+    let connection = req.connection_info();
+    return format!(
+        "{}://{}{}",
+        connection.scheme(),
+        connection.host(),
+        CALLBACK_PATH
+    );
+}
+
+// Whether `path` is on this site, so it's safe to send the browser to after logging in.
+fn is_local(path: &str) -> bool {
+    return path.starts_with('/') && !path.starts_with("//") && !path.starts_with("/\\");
+}
+
+// 128 random bits, for states and nonces.
+fn random_string() -> String {
+    let mut bytes = [0; 16];
+    ring::rand::SystemRandom::new().fill(&mut bytes).unwrap();
+    return BASE64_URL.encode(bytes);
+}
+
+// A cookie only the monitor reads, so scripts on the page can't.
+fn cookie<'a>(
+    req: &HttpRequest,
+    name: &'a str,
+    value: &str,
+    max_age: time::Duration,
+) -> Cookie<'a> {
+    return Cookie::build(name, value.to_string())
+        .path("/")
+        .max_age(max_age)
+        .http_only(true)
+        // Lax, so it's still sent when the provider sends the browser back.
+        .same_site(SameSite::Lax)
+        .secure(req.connection_info().scheme() == "https")
+        .finish();
+}
@@ -0,0 +1,825 @@
+use crate::json::Json;
+use crate::{aggregate, audit, histogram, rollups, snapshot, stats, storage, workers};
+use schemars::generate::{SchemaGenerator, SchemaSettings};
+use schemars::JsonSchema;
+
+// A description of the HTTP API as an OpenAPI 3.0 document, for `/api/openapi.json`, so clients in
+// other languages can be generated against it. The models in `components` are generated from the
+// types the JSON endpoints serialize and the admin API's forms are read into.
+// https://spec.openapis.org/oas/v3.0.3
+pub fn document() -> Json {
+    let mut generator = SchemaSettings::openapi3().into_generator();
+    let target_param = query_param("target", "The target's name.", true, string());
+    let optional_target_param = query_param(
+        "target",
+        "Only this target, by name, rather than every target.",
+        false,
+        string(),
+    );
+    // Admin API endpoints answer with a message in plain text, or redirect browsers back to the page
+    // their form was on.
+    let admin_responses = |success: (&'static str, &'static str)| {
+        return vec![
+            (success.0, success.1, Some(("text/plain", string()))),
+            (
+                "400",
+                "What was wrong with the request",
+                Some(("text/plain", string())),
+            ),
+            (
+                "403",
+                "The admin API is off",
+                Some(("text/plain", string())),
+            ),
+            (
+                "404",
+                "The target isn't monitored",
+                Some(("text/plain", string())),
+            ),
+        ];
+    };
+    let paths = vec![
+        (
+            "/api/hosts",
+            vec![(
+                "get",
+                operation(
+                    "List the targets with their latest sample",
+                    vec![],
+                    None,
+                    vec![(
+                        "200",
+                        "The targets, in the order they're shown",
+                        Some(("application/json", array(model::<crate::Host>(&mut generator)))),
+                    )],
+                ),
+            )],
+        ),
+        (
+            "/api/hosts/{host}/pings",
+            vec![(
+                "get",
+                operation(
+                    "A target's samples, oldest first",
+                    vec![
+                        param(
+                            "host",
+                            "path",
+                            "The target's name, percent-encoded.",
+                            true,
+                            string(),
+                        ),
+                        query_param(
+                            "from",
+                            "Only samples taken at or after this time.",
+                            false,
+                            date_time(),
+                        ),
+                        query_param(
+                            "to",
+                            "Only samples taken before this time.",
+                            false,
+                            date_time(),
+                        ),
+                        query_param(
+                            "limit",
+                            "At most how many samples to return, from 1 to 100000.",
+                            false,
+                            integer(),
+                        ),
+                        query_param(
+                            "cursor",
+                            "Where to pick up, from the previous page's `Link` header.",
+                            false,
+                            string(),
+                        ),
+                    ],
+                    None,
+                    vec![
+                        (
+                            "200",
+                            "The samples. If `limit` cut them short, a `Link` header with \
+                             `rel=\"next\"` gives the URL of the next page.",
+                            Some((
+                                "application/json",
+                                array(model::<storage::TimedSample>(&mut generator)),
+                            )),
+                        ),
+                        (
+                            "400",
+                            "Invalid times, limit or cursor",
+                            Some(("text/plain", string())),
+                        ),
+                        (
+                            "404",
+                            "The target isn't monitored",
+                            Some(("text/plain", string())),
+                        ),
+                    ],
+                ),
+            )],
+        ),
+        (
+            "/api/hosts/{host}/aggregate",
+            vec![(
+                "get",
+                operation(
+                    "A target's samples summed up in buckets, oldest first",
+                    vec![
+                        param(
+                            "host",
+                            "path",
+                            "The target's name, percent-encoded.",
+                            true,
+                            string(),
+                        ),
+                        query_param(
+                            "from",
+                            "Only samples taken at or after this time, by default a day before now.",
+                            false,
+                            date_time(),
+                        ),
+                        query_param(
+                            "to",
+                            "Only samples taken before this time, by default now.",
+                            false,
+                            date_time(),
+                        ),
+                        query_param(
+                            "bucket",
+                            "How wide each bucket is, from a second to a year, e.g. 5m (the default) or 1h.",
+                            false,
+                            string(),
+                        ),
+                        query_param(
+                            "fn",
+                            "How each bucket is summed up: the average (the default), slowest or 95th \
+                             percentile round trip in ms, or the fraction of samples that failed.",
+                            false,
+                            enumeration(&["avg", "max", "p95", "loss"]),
+                        ),
+                    ],
+                    None,
+                    vec![
+                        (
+                            "200",
+                            "Each bucket with samples",
+                            Some((
+                                "application/json",
+                                array(model::<aggregate::SeriesBucket>(&mut generator)),
+                            )),
+                        ),
+                        (
+                            "400",
+                            "Invalid times, bucket or fn, or too many buckets",
+                            Some(("text/plain", string())),
+                        ),
+                        (
+                            "404",
+                            "The target isn't monitored",
+                            Some(("text/plain", string())),
+                        ),
+                    ],
+                ),
+            )],
+        ),
+        (
+            "/api/hosts/{host}/histogram",
+            vec![(
+                "get",
+                operation(
+                    "How a target's round trips were spread out over a window",
+                    vec![
+                        param(
+                            "host",
+                            "path",
+                            "The target's name, percent-encoded.",
+                            true,
+                            string(),
+                        ),
+                        query_param(
+                            "from",
+                            "Only samples taken at or after this time, by default an hour before now.",
+                            false,
+                            date_time(),
+                        ),
+                        query_param(
+                            "to",
+                            "Only samples taken before this time, by default now.",
+                            false,
+                            date_time(),
+                        ),
+                    ],
+                    None,
+                    vec![
+                        (
+                            "200",
+                            "The histogram",
+                            Some((
+                                "application/json",
+                                model::<histogram::Response>(&mut generator),
+                            )),
+                        ),
+                        ("400", "Invalid times", Some(("text/plain", string()))),
+                        (
+                            "404",
+                            "The target isn't monitored",
+                            Some(("text/plain", string())),
+                        ),
+                    ],
+                ),
+            )],
+        ),
+        (
+            "/api/samples",
+            vec![(
+                "get",
+                operation(
+                    "Stream every sample as NDJSON, one JSON object per line",
+                    vec![optional_target_param.clone()],
+                    None,
+                    vec![
+                        (
+                            "200",
+                            "The samples, each with the target it's for",
+                            Some((
+                                "application/x-ndjson",
+                                model::<storage::TargetSample>(&mut generator),
+                            )),
+                        ),
+                        (
+                            "404",
+                            "The target isn't monitored",
+                            Some(("text/plain", string())),
+                        ),
+                    ],
+                ),
+            )],
+        ),
+        (
+            "/events",
+            vec![(
+                "get",
+                operation(
+                    "Stream samples as they're taken, as Server-Sent Events",
+                    vec![optional_target_param.clone()],
+                    None,
+                    vec![
+                        (
+                            "200",
+                            "A `sample` event for each sample, with the sample as JSON as its data",
+                            Some((
+                                "text/event-stream",
+                                model::<storage::TargetSample>(&mut generator),
+                            )),
+                        ),
+                        (
+                            "404",
+                            "The target isn't monitored",
+                            Some(("text/plain", string())),
+                        ),
+                    ],
+                ),
+            )],
+        ),
+        (
+            "/ws",
+            vec![(
+                "get",
+                operation(
+                    "Stream samples as they're taken over a WebSocket",
+                    vec![optional_target_param],
+                    None,
+                    vec![
+                        (
+                            "101",
+                            "Switched to a WebSocket, which sends a text message for each sample with \
+                             the sample as JSON (see `TargetSample`)",
+                            None,
+                        ),
+                        (
+                            "400",
+                            "The request wasn't a WebSocket handshake",
+                            Some(("text/plain", string())),
+                        ),
+                        (
+                            "404",
+                            "The target isn't monitored",
+                            Some(("text/plain", string())),
+                        ),
+                    ],
+                ),
+            )],
+        ),
+        (
+            "/api/loss",
+            vec![(
+                "get",
+                operation(
+                    "A target's probes sent and lost, by the minute, 5 minutes or hour",
+                    vec![
+                        target_param.clone(),
+                        query_param(
+                            "by",
+                            "Whether to count by the minute (the default), 5 minutes or the hour.",
+                            false,
+                            enumeration(&["minute", "5min", "hour"]),
+                        ),
+                    ],
+                    None,
+                    vec![
+                        (
+                            "200",
+                            "The counts, oldest first",
+                            Some((
+                                "application/json",
+                                array(model::<rollups::LossBucket>(&mut generator)),
+                            )),
+                        ),
+                        (
+                            "400",
+                            "No target, or an invalid `by`",
+                            Some(("text/plain", string())),
+                        ),
+                        (
+                            "404",
+                            "The target isn't monitored",
+                            Some(("text/plain", string())),
+                        ),
+                    ],
+                ),
+            )],
+        ),
+        (
+            "/api/stats/self",
+            vec![(
+                "get",
+                operation(
+                    "What the monitor itself is using, and whether targets are probed on time",
+                    vec![],
+                    None,
+                    vec![(
+                        "200",
+                        "The stats",
+                        Some(("application/json", model::<stats::SelfStats>(&mut generator))),
+                    )],
+                ),
+            )],
+        ),
+        (
+            "/debug",
+            vec![(
+                "get",
+                operation(
+                    "What each target's probing thread is doing",
+                    vec![],
+                    None,
+                    vec![(
+                        "200",
+                        "Each target's thread, in the order targets are shown",
+                        Some(("application/json", model::<workers::Report>(&mut generator))),
+                    )],
+                ),
+            )],
+        ),
+        (
+            "/api/targets",
+            vec![
+                (
+                    "get",
+                    operation(
+                        "List the names of the targets, one per line",
+                        vec![],
+                        None,
+                        vec![
+                            ("200", "The names", Some(("text/plain", string()))),
+                            (
+                                "403",
+                                "The admin API is off",
+                                Some(("text/plain", string())),
+                            ),
+                        ],
+                    ),
+                ),
+                (
+                    "post",
+                    operation(
+                        "Start monitoring a target",
+                        vec![],
+                        Some(Json::Object(vec![
+                            ("required".to_string(), Json::Bool(true)),
+                            (
+                                "content".to_string(),
+                                Json::Object(vec![
+                                    (
+                                        "text/plain".to_string(),
+                                        media_type(described(
+                                            string(),
+                                            "The target, in any form the command line accepts.",
+                                        )),
+                                    ),
+                                    (
+                                        "application/x-www-form-urlencoded".to_string(),
+                                        media_type(model::<crate::TargetAdd>(&mut generator)),
+                                    ),
+                                ]),
+                            ),
+                        ])),
+                        admin_responses(("201", "How many targets were added")),
+                    ),
+                ),
+                (
+                    "delete",
+                    operation(
+                        "Stop monitoring a target, and drop its history",
+                        vec![target_param.clone()],
+                        None,
+                        admin_responses(("200", "The target was removed")),
+                    ),
+                ),
+            ],
+        ),
+        (
+            "/api/targets/remove",
+            vec![(
+                "post",
+                operation(
+                    "Stop monitoring a target, like DELETE /api/targets",
+                    vec![target_param.clone()],
+                    None,
+                    admin_responses(("200", "The target was removed")),
+                ),
+            )],
+        ),
+        (
+            "/api/targets/edit",
+            vec![(
+                "post",
+                operation(
+                    "Change a target, keeping its history",
+                    vec![target_param.clone()],
+                    Some(Json::Object(vec![
+                        ("required".to_string(), Json::Bool(true)),
+                        (
+                            "content".to_string(),
+                            Json::Object(vec![(
+                                "application/x-www-form-urlencoded".to_string(),
+                                media_type(model::<crate::TargetEdit>(&mut generator)),
+                            )]),
+                        ),
+                    ])),
+                    admin_responses(("200", "The target was changed")),
+                ),
+            )],
+        ),
+        (
+            "/api/targets/move",
+            vec![(
+                "post",
+                operation(
+                    "Move a target to another position in the list",
+                    vec![
+                        target_param.clone(),
+                        query_param(
+                            "position",
+                            "Where to move it, counting from 0.",
+                            true,
+                            integer(),
+                        ),
+                    ],
+                    None,
+                    admin_responses(("200", "The target was moved")),
+                ),
+            )],
+        ),
+        (
+            "/api/targets/pause",
+            vec![(
+                "post",
+                operation(
+                    "Stop probing a target until it's resumed",
+                    vec![target_param.clone()],
+                    None,
+                    admin_responses(("200", "The target was paused")),
+                ),
+            )],
+        ),
+        (
+            "/api/targets/resume",
+            vec![(
+                "post",
+                operation(
+                    "Start probing a paused target again",
+                    vec![target_param],
+                    None,
+                    admin_responses(("200", "The target was resumed")),
+                ),
+            )],
+        ),
+        (
+            "/api/snapshot",
+            vec![
+                (
+                    "get",
+                    operation(
+                        "Everything the monitor has recorded, to import into another monitor",
+                        vec![],
+                        None,
+                        vec![(
+                            "200",
+                            "The snapshot",
+                            Some(("application/json", model::<snapshot::Export>(&mut generator))),
+                        )],
+                    ),
+                ),
+                (
+                    "post",
+                    operation(
+                        "Add the history in a snapshot, for the targets monitored here",
+                        vec![],
+                        Some(Json::Object(vec![
+                            ("required".to_string(), Json::Bool(true)),
+                            (
+                                "content".to_string(),
+                                Json::Object(vec![(
+                                    "application/json".to_string(),
+                                    media_type(model::<snapshot::Export>(&mut generator)),
+                                )]),
+                            ),
+                        ])),
+                        admin_responses(("200", "What was imported")),
+                    ),
+                ),
+            ],
+        ),
+        (
+            "/api/backup",
+            vec![(
+                "get",
+                operation(
+                    "History, config and target changes as a tar archive",
+                    vec![],
+                    None,
+                    vec![
+                        ("200", "The backup", Some(("application/x-tar", binary()))),
+                        (
+                            "403",
+                            "The admin API is off",
+                            Some(("text/plain", string())),
+                        ),
+                    ],
+                ),
+            )],
+        ),
+        (
+            "/api/restore",
+            vec![(
+                "post",
+                operation(
+                    "Set the monitor up from a backup",
+                    vec![],
+                    Some(Json::Object(vec![
+                        ("required".to_string(), Json::Bool(true)),
+                        (
+                            "content".to_string(),
+                            Json::Object(vec![(
+                                "application/x-tar".to_string(),
+                                media_type(binary()),
+                            )]),
+                        ),
+                    ])),
+                    admin_responses(("200", "What was restored")),
+                ),
+            )],
+        ),
+        (
+            "/api/audit",
+            vec![(
+                "get",
+                operation(
+                    "Changes made to the monitor since it started, newest first",
+                    vec![],
+                    None,
+                    vec![
+                        (
+                            "200",
+                            "The changes",
+                            Some((
+                                "application/json",
+                                array(model::<audit::Entry>(&mut generator)),
+                            )),
+                        ),
+                        (
+                            "403",
+                            "Only admins can see who changed what",
+                            Some(("text/plain", string())),
+                        ),
+                    ],
+                ),
+            )],
+        ),
+        (
+            "/metrics",
+            vec![(
+                "get",
+                operation(
+                    "Metrics in Prometheus' text exposition format",
+                    vec![],
+                    None,
+                    vec![("200", "The metrics", Some(("text/plain", string())))],
+                ),
+            )],
+        ),
+    ];
+
+    let schemas = Json::from(serde_json::Value::Object(generator.take_definitions(true)));
+
+    return Json::Object(vec![
+        ("openapi".to_string(), Json::String("3.0.3".to_string())),
+        (
+            "info".to_string(),
+            Json::Object(vec![
+                (
+                    "title".to_string(),
+                    Json::String("network-monitor".to_string()),
+                ),
+                (
+                    "version".to_string(),
+                    Json::String(env!("CARGO_PKG_VERSION").to_string()),
+                ),
+            ]),
+        ),
+        (
+            "paths".to_string(),
+            Json::Object(
+                paths
+                    .into_iter()
+                    .map(|(path, operations)| {
+                        let operations = operations
+                            .into_iter()
+                            .map(|(method, operation)| (method.to_string(), operation))
+                            .collect();
+                        return (path.to_string(), Json::Object(operations));
+                    })
+                    .collect(),
+            ),
+        ),
+        (
+            "components".to_string(),
+            Json::Object(vec![("schemas".to_string(), schemas)]),
+        ),
+    ]);
+}
+
+// A response's status, description, and content type and schema if it has a body.
+type Response<'a> = (&'a str, &'a str, Option<(&'a str, Json)>);
+
+fn operation(
+    summary: &str,
+    parameters: Vec<Json>,
+    request_body: Option<Json>,
+    responses: Vec<Response>,
+) -> Json {
+    let mut operation = vec![("summary".to_string(), Json::String(summary.to_string()))];
+    if !parameters.is_empty() {
+        operation.push(("parameters".to_string(), Json::Array(parameters)));
+    }
+    if let Some(request_body) = request_body {
+        operation.push(("requestBody".to_string(), request_body));
+    }
+    let responses = responses
+        .into_iter()
+        .map(|(status, description, content)| {
+            let mut response = vec![(
+                "description".to_string(),
+                Json::String(description.to_string()),
+            )];
+            if let Some((content_type, schema)) = content {
+                response.push((
+                    "content".to_string(),
+                    Json::Object(vec![(content_type.to_string(), media_type(schema))]),
+                ));
+            }
+            return (status.to_string(), Json::Object(response));
+        })
+        .collect();
+    operation.push(("responses".to_string(), Json::Object(responses)));
+    return Json::Object(operation);
+}
+
+fn param(name: &str, location: &str, description: &str, required: bool, schema: Json) -> Json {
+    return Json::Object(vec![
+        ("name".to_string(), Json::String(name.to_string())),
+        ("in".to_string(), Json::String(location.to_string())),
+        (
+            "description".to_string(),
+            Json::String(description.to_string()),
+        ),
+        ("required".to_string(), Json::Bool(required)),
+        ("schema".to_string(), schema),
+    ]);
+}
+
+fn query_param(name: &str, description: &str, required: bool, schema: Json) -> Json {
+    return param(name, "query", description, required, schema);
+}
+
+fn media_type(schema: Json) -> Json {
+    return Json::Object(vec![("schema".to_string(), schema)]);
+}
+
+fn array(items: Json) -> Json {
+    return Json::Object(vec![
+        ("type".to_string(), Json::String("array".to_string())),
+        ("items".to_string(), items),
+    ]);
+}
+
+// A reference to the schema of `T` in `components`, which `generator` adds it to.
+fn model<T: JsonSchema>(generator: &mut SchemaGenerator) -> Json {
+    return Json::from(generator.subschema_for::<T>().to_value());
+}
+
+fn typed(kind: &str, format: Option<&str>) -> Json {
+    let mut schema = vec![("type".to_string(), Json::String(kind.to_string()))];
+    if let Some(format) = format {
+        schema.push(("format".to_string(), Json::String(format.to_string())));
+    }
+    return Json::Object(schema);
+}
+
+fn string() -> Json {
+    return typed("string", None);
+}
+
+fn date_time() -> Json {
+    return typed("string", Some("date-time"));
+}
+
+fn binary() -> Json {
+    return typed("string", Some("binary"));
+}
+
+fn integer() -> Json {
+    return typed("integer", Some("int64"));
+}
+
+fn enumeration(values: &[&str]) -> Json {
+    return Json::Object(vec![
+        ("type".to_string(), Json::String("string".to_string())),
+        (
+            "enum".to_string(),
+            Json::Array(
+                values
+                    .iter()
+                    .map(|value| Json::String(value.to_string()))
+                    .collect(),
+            ),
+        ),
+    ]);
+}
+
+fn described(schema: Json, description: &str) -> Json {
+    let Json::Object(mut fields) = schema else {
+        return schema;
+    };
+    fields.push((
+        "description".to_string(),
+        Json::String(description.to_string()),
+    ));
+    return Json::Object(fields);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every `$ref` in `json`.
+    fn references(json: &Json, found: &mut Vec<String>) {
+        match json {
+            Json::Object(fields) => {
+                for (key, value) in fields {
+                    match (key.as_str(), value) {
+                        ("$ref", Json::String(reference)) => found.push(reference.clone()),
+                        _ => references(value, found),
+                    }
+                }
+            }
+            Json::Array(values) => values.iter().for_each(|value| references(value, found)),
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn references_are_to_schemas() {
+        let document = document();
+        let schemas = document.get("components").unwrap().get("schemas").unwrap();
+        let mut found = Vec::new();
+        references(&document, &mut found);
+        assert!(!found.is_empty());
+        for reference in found {
+            let name = reference.trim_start_matches("#/components/schemas/");
+            assert!(schemas.get(name).is_some(), "{} isn't a schema", reference);
+        }
+    }
+}
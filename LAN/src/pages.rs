@@ -0,0 +1,426 @@
+use askama::Template;
+
+// What the pages rendered from `templates/` show, worked out by their handlers in main.rs. Values are
+// escaped as they're rendered, apart from those marked `safe` in the templates (charts, and HTML
+// built by other pages' helpers).
+
+// The top of the timeline: tables comparing dual-stack hosts and DNS resolvers and listing outages.
+// The rest of it is streamed a piece at a time (see `IndexStream` in main.rs): a table per group of
+// targets, with a column per target of its chart, then its samples and summaries in view, newest
+// first.
+#[derive(Template)]
+#[template(path = "index.html")]
+pub struct Index {
+    pub time_range_picker: TimeRangePicker,
+    pub compare_choices: Vec<Choice>,
+    pub admin_api: bool,
+    pub dual_stack_rows: Vec<DualStackRow>,
+    pub resolver_rows: Vec<ResolverRow>,
+    pub outage_rows: Vec<OutageRow>,
+}
+
+// Links to the stretches of time just newer and older than the one in view, to the last hour, 6
+// hours, day and week, and a form to show any stretch of time, then links to pick the time zone
+// times are shown in (see `time_range_picker` in main.rs). Pages showing a stretch of time open with
+// it.
+#[derive(Template)]
+#[template(path = "time_range_picker.html")]
+pub struct TimeRangePicker {
+    pub path: String,
+    pub newer: String,
+    pub older: String,
+    pub presets: Vec<Choice>,
+    // The stretch of time in view, in the time zone times are shown in, for the form's inputs.
+    pub from: String,
+    pub to: String,
+    // The page's own params, kept when picking another stretch of time.
+    pub params: Vec<(String, String)>,
+    pub zone: String,
+}
+
+// One of several links to pick from, e.g. what to compare with. The one picked has no link.
+pub struct Choice {
+    pub label: &'static str,
+    pub href: Option<String>,
+}
+
+pub struct DualStackRow {
+    pub host: String,
+    pub latency_v4: String,
+    pub loss_v4: String,
+    pub latency_v6: String,
+    pub loss_v6: String,
+}
+
+pub struct ResolverRow {
+    pub query_name: String,
+    pub resolver: String,
+    pub latency: String,
+    pub loss: String,
+}
+
+pub struct OutageRow {
+    // The label of the target that went down and where to see its outages, None for a loss of local
+    // connectivity, when every target went down at once.
+    pub target: Option<(String, String)>,
+    pub num_targets: usize,
+    pub started: String,
+    // None if it's still down.
+    pub ended: Option<String>,
+    pub likely_cause: &'static str,
+}
+
+// Opens the table of targets shown together, ungrouped targets or a group's, with a heading per
+// target, leaving a row open for their columns.
+#[derive(Template)]
+#[template(path = "index_group.html")]
+pub struct GroupHeadings {
+    pub summary: Option<GroupSummary>,
+    pub hosts: Vec<HostHeading>,
+}
+
+// How a group of targets is doing over the time in view.
+pub struct GroupSummary {
+    pub name: String,
+    pub num_up: usize,
+    pub num_hosts: usize,
+    pub latency: String,
+    pub loss: String,
+}
+
+pub struct HostHeading {
+    pub name: String,
+    pub label: String,
+    pub href: String,
+    pub trace_href: Option<String>,
+    pub paused: bool,
+    // Where to pause or resume the target, with the admin API on.
+    pub pause_action: Option<String>,
+    // How often it's probed and how long it waits, if it has its own interval or timeout.
+    pub probe_timing: Option<String>,
+    pub captive: bool,
+    pub outages: Option<Outages>,
+    pub cert_warning: Option<String>,
+    pub clock_offset: Option<String>,
+    pub interface_stats: Option<String>,
+    pub ssh_banner: Option<String>,
+    // Replies in view that arrived late, and more than once.
+    pub odd_replies: Option<(u32, u32)>,
+    pub rate_limited: bool,
+}
+
+// A target's outages, and whether it's down now.
+pub struct Outages {
+    pub href: String,
+    pub count: usize,
+    pub down_now: bool,
+}
+
+// Opens a target's column, leaving its table of rows open.
+#[derive(Template)]
+#[template(path = "index_column.html")]
+pub struct Column {
+    pub chart: String,
+    pub value_heading: &'static str,
+}
+
+#[derive(Template)]
+#[template(path = "timeline_rows.html")]
+pub struct TimelineRows {
+    pub rows: Vec<Row>,
+}
+
+pub enum Row {
+    // When the target wasn't monitored, e.g. during quiet hours.
+    NotMonitored(String),
+    PublicIpChange(String),
+    // A sample or summary, with how to style it, and the details shown when hovering over it.
+    Sample {
+        class: String,
+        title: String,
+        time: String,
+        value: String,
+    },
+}
+
+// A form for the viewer's preferences, posted back to save them (see `save_preferences` in main.rs).
+#[derive(Template)]
+#[template(path = "preferences.html")]
+pub struct Preferences {
+    pub spans: Vec<SelectOption>,
+    pub hosts: Vec<HostChoice>,
+    pub units: Vec<SelectOption>,
+    pub refreshes: Vec<SelectOption>,
+    // The time zone picked, empty for the monitor's default.
+    pub zone: String,
+}
+
+pub struct SelectOption {
+    pub label: &'static str,
+    pub value: &'static str,
+    pub selected: bool,
+}
+
+pub struct HostChoice {
+    pub name: String,
+    pub label: String,
+    pub chosen: bool,
+}
+
+// A strip of targets' statuses and sparklines, to embed in other dashboards (see `embed` in main.rs).
+#[derive(Template)]
+#[template(path = "embed.html")]
+pub struct Embed {
+    pub hosts: Vec<EmbedHost>,
+    // Keeps the strip up to date, empty if it shows an earlier stretch of time.
+    pub live_update: String,
+}
+
+pub struct EmbedHost {
+    pub label: String,
+    pub href: String,
+    // `Down`, `Degraded`, `Paused` or empty, and a description of it.
+    pub class: &'static str,
+    pub status: String,
+    pub latest: String,
+    pub sparkline: String,
+}
+
+// A card per target of how it's doing now and over the last day.
+#[derive(Template)]
+#[template(path = "summary.html")]
+pub struct Summary {
+    pub cards: Vec<Card>,
+    pub live_update: String,
+}
+
+pub struct Card {
+    // `Down`, `Degraded`, `Paused` or empty, and a description of it.
+    pub class: &'static str,
+    pub status: String,
+    pub href: String,
+    pub label: String,
+    pub group: Option<String>,
+    pub latest: String,
+    pub average: String,
+    pub loss: String,
+    pub uptime: String,
+}
+
+// A table of the hops along a traced path.
+#[derive(Template)]
+#[template(path = "trace_table.html")]
+pub struct TraceTable {
+    pub caption: TraceCaption,
+    pub dest_ip: String,
+    pub reached_dest: bool,
+    pub hops: Vec<Hop>,
+}
+
+pub enum TraceCaption {
+    Text(String),
+    // The path as an outage was detected.
+    Outage(OutageCaption),
+}
+
+pub struct Hop {
+    pub ttl: u8,
+    // `*` where nobody responded.
+    pub address: String,
+    pub hostname: String,
+    pub round_trip: String,
+}
+
+// When a target was down, and what likely caused it.
+#[derive(Template, Clone)]
+#[template(path = "outage_caption.html")]
+pub struct OutageCaption {
+    pub label: String,
+    pub started: String,
+    // None if it's still down.
+    pub ended: Option<String>,
+    // Empty if it's unknown.
+    pub likely_cause: String,
+}
+
+// The path to a host, traced when the page is asked for.
+#[derive(Template)]
+#[template(path = "trace.html")]
+pub struct Trace {
+    pub hostname: String,
+    pub trace: Result<TraceTable, String>,
+}
+
+// A target's outages, newest first, with the path to its host as each one was detected.
+#[derive(Template)]
+#[template(path = "outages.html")]
+pub struct OutageList {
+    pub label: String,
+    pub feed_href: String,
+    pub outages: Vec<OutageDetails>,
+}
+
+pub struct OutageDetails {
+    pub caption: OutageCaption,
+    // None while it's being traced.
+    pub trace: Option<Result<TraceTable, String>>,
+    // How many ICMP packets were captured, and where to. None while they're being captured, or if
+    // the target doesn't capture traffic (`capturing` is false).
+    pub capture: Option<Result<(usize, String), String>>,
+    pub capturing: bool,
+}
+
+// Each target's round trips spread out over the time in view.
+#[derive(Template)]
+#[template(path = "histogram.html")]
+pub struct Histograms {
+    pub time_range_picker: TimeRangePicker,
+    pub charts: Vec<HistogramChart>,
+}
+
+pub struct HistogramChart {
+    pub href: String,
+    pub label: String,
+    pub samples: usize,
+    pub median: String,
+    pub p95: String,
+    pub failures: usize,
+    pub svg: String,
+}
+
+// Each target's loss charted over the time in view.
+#[derive(Template)]
+#[template(path = "loss.html")]
+pub struct LossTimeline {
+    // By the minute, 5 minutes or hour.
+    pub widths: Vec<Choice>,
+    pub time_range_picker: TimeRangePicker,
+    pub charts: Vec<LossChart>,
+}
+
+pub struct LossChart {
+    pub href: String,
+    pub label: String,
+    pub lost: u64,
+    pub sent: u64,
+    pub loss: String,
+    pub chart: String,
+}
+
+// Each target's latency or loss in each hour of each of the last days, a row per day.
+#[derive(Template)]
+#[template(path = "heatmap.html")]
+pub struct Heatmaps {
+    // Latency or loss, and how many days.
+    pub choices: Vec<Choice>,
+    pub hours: Vec<String>,
+    pub heatmaps: Vec<Heatmap>,
+}
+
+pub struct Heatmap {
+    pub href: String,
+    pub label: String,
+    // The range of values shaded.
+    pub scale: String,
+    // Newest first.
+    pub days: Vec<HeatmapDay>,
+}
+
+pub struct HeatmapDay {
+    pub label: String,
+    // One per hour, None if there's no data.
+    pub cells: Vec<Option<HeatmapCell>>,
+}
+
+pub struct HeatmapCell {
+    // How red the cell is, from 0 to 1.
+    pub shade: String,
+    pub title: String,
+}
+
+// Everything about one target (see `host` in main.rs).
+#[derive(Template)]
+#[template(path = "host.html")]
+pub struct HostDetails {
+    pub name: String,
+    pub label: String,
+    pub hostname: String,
+    pub group: Option<String>,
+    // How it's doing, and whether that's a problem.
+    pub status: (String, bool),
+    // What it resolves to, or why it couldn't be resolved.
+    pub addresses: Result<String, String>,
+    pub probed: String,
+    pub outages_href: String,
+    pub num_outages: usize,
+    pub histogram_href: String,
+    pub latency_heatmap_href: String,
+    // Empty if no replies have said where they came from.
+    pub reply_sources: String,
+    // Charts of its history in pairs, with headings.
+    pub history: Vec<Vec<(&'static str, String)>>,
+    pub loss_href: String,
+    pub loss_heatmap_href: String,
+    pub loss_rows: Vec<LossRow>,
+    pub failures: Vec<Failure>,
+    pub reply_ttls: Vec<ReplyTtl>,
+    // The paths to it as its latest outages started.
+    pub traces: Vec<TraceTable>,
+}
+
+pub struct LossRow {
+    pub heading: &'static str,
+    pub sent: u64,
+    pub lost: u64,
+    pub loss: String,
+}
+
+pub struct Failure {
+    pub when: String,
+    pub class: &'static str,
+    pub result: String,
+    pub details: String,
+}
+
+pub struct ReplyTtl {
+    pub ttl: u8,
+    pub replies: usize,
+    pub first_seen: String,
+    pub last_seen: String,
+}
+
+// Devices found on the local network.
+#[derive(Template)]
+#[template(path = "discover.html")]
+pub struct Discover {
+    pub devices: Vec<crate::discovery::Device>,
+}
+
+// The targets, with forms posting to the admin API to change them.
+#[derive(Template)]
+#[template(path = "settings.html")]
+pub struct Settings {
+    pub targets: Vec<SettingsTarget>,
+}
+
+pub struct SettingsTarget {
+    // `target=NAME`, for the admin API.
+    pub query: String,
+    // The spec without the label and interval, which have fields of their own.
+    pub spec: String,
+    pub label: String,
+    pub interval: String,
+    pub default_interval: String,
+    // The positions to move it up or down to, if it isn't already first or last.
+    pub move_up: Option<usize>,
+    pub move_down: Option<usize>,
+    pub paused: bool,
+}
+
+// What went wrong with a request, with a link back to the page it came from.
+#[derive(Template)]
+#[template(path = "message.html")]
+pub struct Message {
+    pub back: String,
+    pub message: String,
+}
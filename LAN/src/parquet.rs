@@ -0,0 +1,483 @@
+// Writes Parquet files, for analytics tools like DuckDB and Athena. Only what we need is supported:
+// flat schemas, one row group, one data page per column, PLAIN encoding and no compression. Readers
+// don't mind, and the files are small enough as they're split up by day.
+// https://parquet.apache.org/docs/file-format/
+
+const MAGIC: &[u8] = b"PAR1";
+
+// Physical types.
+const TYPE_INT32: i32 = 1;
+const TYPE_INT64: i32 = 2;
+const TYPE_DOUBLE: i32 = 5;
+const TYPE_BYTE_ARRAY: i32 = 6;
+// Repetition types.
+const REQUIRED: i32 = 0;
+const OPTIONAL: i32 = 1;
+// Converted types, for readers that don't know logical types.
+const CONVERTED_UTF8: i32 = 0;
+const CONVERTED_TIMESTAMP_MICROS: i32 = 10;
+const ENCODING_PLAIN: i32 = 0;
+const ENCODING_RLE: i32 = 3;
+const CODEC_UNCOMPRESSED: i32 = 0;
+const PAGE_TYPE_DATA_PAGE: i32 = 0;
+
+// Thrift compact protocol types.
+// https://github.com/apache/thrift/blob/master/doc/specs/thrift-compact-protocol.md
+const THRIFT_TRUE: u8 = 1;
+const THRIFT_FALSE: u8 = 2;
+const THRIFT_I32: u8 = 5;
+const THRIFT_I64: u8 = 6;
+const THRIFT_BINARY: u8 = 8;
+const THRIFT_LIST: u8 = 9;
+const THRIFT_STRUCT: u8 = 12;
+
+// The values of a column, None where a row has no value.
+pub enum Values {
+    Int32(Vec<Option<i32>>),
+    Double(Vec<Option<f64>>),
+    String(Vec<Option<String>>),
+    // Microseconds since the Unix epoch, in UTC.
+    Timestamp(Vec<Option<i64>>),
+}
+impl Values {
+    fn len(&self) -> usize {
+        return match self {
+            Values::Int32(values) => values.len(),
+            Values::Timestamp(values) => values.len(),
+            Values::Double(values) => values.len(),
+            Values::String(values) => values.len(),
+        };
+    }
+
+    fn physical_type(&self) -> i32 {
+        return match self {
+            Values::Int32(_) => TYPE_INT32,
+            Values::Timestamp(_) => TYPE_INT64,
+            Values::Double(_) => TYPE_DOUBLE,
+            Values::String(_) => TYPE_BYTE_ARRAY,
+        };
+    }
+
+    // Whether each row has a value.
+    fn present(&self) -> Vec<bool> {
+        return match self {
+            Values::Int32(values) => values.iter().map(Option::is_some).collect(),
+            Values::Timestamp(values) => values.iter().map(Option::is_some).collect(),
+            Values::Double(values) => values.iter().map(Option::is_some).collect(),
+            Values::String(values) => values.iter().map(Option::is_some).collect(),
+        };
+    }
+
+    // The values that are present, PLAIN encoded.
+    fn encode(&self) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        match self {
+            Values::Int32(values) => {
+                for value in values.iter().flatten() {
+                    encoded.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+            Values::Timestamp(values) => {
+                for value in values.iter().flatten() {
+                    encoded.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+            Values::Double(values) => {
+                for value in values.iter().flatten() {
+                    encoded.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+            Values::String(values) => {
+                for value in values.iter().flatten() {
+                    encoded.extend_from_slice(&(value.len() as u32).to_le_bytes());
+                    encoded.extend_from_slice(value.as_bytes());
+                }
+            }
+        }
+        return encoded;
+    }
+}
+
+pub struct Column {
+    pub name: &'static str,
+    // Whether every row has a value.
+    pub required: bool,
+    pub values: Values,
+}
+
+// A Parquet file with `columns`, which all have a value (or None) for every row.
+pub fn write(columns: &[Column]) -> Vec<u8> {
+    let num_rows = columns.first().map_or(0, |column| column.values.len());
+    let mut file = MAGIC.to_vec();
+    // Where each column's page starts, and how big it is.
+    let mut chunks = Vec::new();
+    for column in columns {
+        let offset = file.len();
+        file.extend_from_slice(&data_page(column));
+        chunks.push((offset, file.len() - offset));
+    }
+
+    let mut metadata = Thrift::new();
+    metadata.i32(1, 1); // version
+    metadata.list(2, THRIFT_STRUCT, columns.len() + 1); // schema
+    metadata.begin_list_struct();
+    metadata.binary(4, b"schema"); // name
+    metadata.i32(5, columns.len() as i32); // num_children
+    metadata.end_struct();
+    for column in columns {
+        metadata.begin_list_struct();
+        metadata.i32(1, column.values.physical_type()); // type
+        metadata.i32(3, if column.required { REQUIRED } else { OPTIONAL }); // repetition_type
+        metadata.binary(4, column.name.as_bytes()); // name
+        match column.values {
+            Values::String(_) => {
+                metadata.i32(6, CONVERTED_UTF8); // converted_type
+                metadata.begin_struct(10); // logicalType
+                metadata.begin_struct(1); // STRING
+                metadata.end_struct();
+                metadata.end_struct();
+            }
+            Values::Timestamp(_) => {
+                metadata.i32(6, CONVERTED_TIMESTAMP_MICROS); // converted_type
+                metadata.begin_struct(10); // logicalType
+                metadata.begin_struct(8); // TIMESTAMP
+                metadata.bool(1, true); // isAdjustedToUTC
+                metadata.begin_struct(2); // unit
+                metadata.begin_struct(2); // MICROS
+                metadata.end_struct();
+                metadata.end_struct();
+                metadata.end_struct();
+                metadata.end_struct();
+            }
+            _ => {}
+        }
+        metadata.end_struct();
+    }
+    metadata.i64(3, num_rows as i64); // num_rows
+    metadata.list(4, THRIFT_STRUCT, 1); // row_groups
+    metadata.begin_list_struct();
+    metadata.list(1, THRIFT_STRUCT, columns.len()); // columns
+    for (column, (offset, size)) in columns.iter().zip(&chunks) {
+        metadata.begin_list_struct();
+        metadata.i64(2, *offset as i64); // file_offset
+        metadata.begin_struct(3); // meta_data
+        metadata.i32(1, column.values.physical_type()); // type
+        metadata.list(2, THRIFT_I32, 2); // encodings
+        metadata.list_i32(ENCODING_PLAIN);
+        metadata.list_i32(ENCODING_RLE);
+        metadata.list(3, THRIFT_BINARY, 1); // path_in_schema
+        metadata.list_binary(column.name.as_bytes());
+        metadata.i32(4, CODEC_UNCOMPRESSED); // codec
+        metadata.i64(5, num_rows as i64); // num_values
+        metadata.i64(6, *size as i64); // total_uncompressed_size
+        metadata.i64(7, *size as i64); // total_compressed_size
+        metadata.i64(9, *offset as i64); // data_page_offset
+        metadata.end_struct();
+        metadata.end_struct();
+    }
+    let total_size: usize = chunks.iter().map(|(_, size)| size).sum();
+    metadata.i64(2, total_size as i64); // total_byte_size
+    metadata.i64(3, num_rows as i64); // num_rows
+    metadata.end_struct();
+    metadata.binary(6, b"network-monitor"); // created_by
+    let metadata = metadata.finish();
+
+    file.extend_from_slice(&metadata);
+    file.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+    file.extend_from_slice(MAGIC);
+    return file;
+}
+
+// A column's values as a (version 1) data page, with its header.
+fn data_page(column: &Column) -> Vec<u8> {
+    let mut page = Vec::new();
+    // Optional columns start with whether each row has a value (its definition level), as runs of the
+    // RLE/bit-packing hybrid encoding prefixed with their length. There are no repetition levels,
+    // since nothing is nested.
+    if !column.required {
+        let mut levels = Vec::new();
+        let present = column.values.present();
+        let mut i = 0;
+        while i < present.len() {
+            let run = present[i..]
+                .iter()
+                .take_while(|level| **level == present[i])
+                .count();
+            write_varint(&mut levels, (run as u64) << 1);
+            levels.push(present[i] as u8);
+            i += run;
+        }
+        page.extend_from_slice(&(levels.len() as u32).to_le_bytes());
+        page.extend_from_slice(&levels);
+    }
+    page.extend_from_slice(&column.values.encode());
+
+    let mut header = Thrift::new();
+    header.i32(1, PAGE_TYPE_DATA_PAGE); // type
+    header.i32(2, page.len() as i32); // uncompressed_page_size
+    header.i32(3, page.len() as i32); // compressed_page_size
+    header.begin_struct(5); // data_page_header
+    header.i32(1, column.values.len() as i32); // num_values
+    header.i32(2, ENCODING_PLAIN); // encoding
+    header.i32(3, ENCODING_RLE); // definition_level_encoding
+    header.i32(4, ENCODING_RLE); // repetition_level_encoding
+    header.end_struct();
+    let mut header = header.finish();
+    header.extend_from_slice(&page);
+    return header;
+}
+
+// Writes a Thrift struct in the compact protocol, which is how Parquet's metadata is encoded.
+// Fields are numbered as in parquet.thrift.
+// https://github.com/apache/parquet-format/blob/master/src/main/thrift/parquet.thrift
+struct Thrift {
+    bytes: Vec<u8>,
+    // The last field ID written in each struct being written, innermost last. Field headers hold the
+    // difference from it.
+    last_field_ids: Vec<i16>,
+}
+impl Thrift {
+    fn new() -> Thrift {
+        return Thrift {
+            bytes: Vec::new(),
+            last_field_ids: vec![0],
+        };
+    }
+
+    fn field_header(&mut self, id: i16, kind: u8) {
+        let last_id = self.last_field_ids.last_mut().unwrap();
+        let delta = id - *last_id;
+        if (1..=15).contains(&delta) {
+            self.bytes.push((delta as u8) << 4 | kind);
+        } else {
+            self.bytes.push(kind);
+            write_varint(&mut self.bytes, zigzag(id.into()));
+        }
+        *last_id = id;
+    }
+
+    fn i32(&mut self, id: i16, value: i32) {
+        self.field_header(id, THRIFT_I32);
+        write_varint(&mut self.bytes, zigzag(value.into()));
+    }
+
+    fn i64(&mut self, id: i16, value: i64) {
+        self.field_header(id, THRIFT_I64);
+        write_varint(&mut self.bytes, zigzag(value));
+    }
+
+    fn bool(&mut self, id: i16, value: bool) {
+        self.field_header(id, if value { THRIFT_TRUE } else { THRIFT_FALSE });
+    }
+
+    fn binary(&mut self, id: i16, value: &[u8]) {
+        self.field_header(id, THRIFT_BINARY);
+        self.list_binary(value);
+    }
+
+    // A struct field, whose fields follow until `end_struct`.
+    fn begin_struct(&mut self, id: i16) {
+        self.field_header(id, THRIFT_STRUCT);
+        self.begin_list_struct();
+    }
+
+    fn end_struct(&mut self) {
+        self.bytes.push(0);
+        self.last_field_ids.pop();
+    }
+
+    // A list field, whose `len` elements follow.
+    fn list(&mut self, id: i16, kind: u8, len: usize) {
+        self.field_header(id, THRIFT_LIST);
+        if len < 15 {
+            self.bytes.push((len as u8) << 4 | kind);
+        } else {
+            self.bytes.push(0xF0 | kind);
+            write_varint(&mut self.bytes, len as u64);
+        }
+    }
+
+    // A struct in a list, whose fields follow until `end_struct`.
+    fn begin_list_struct(&mut self) {
+        self.last_field_ids.push(0);
+    }
+
+    fn list_i32(&mut self, value: i32) {
+        write_varint(&mut self.bytes, zigzag(value.into()));
+    }
+
+    fn list_binary(&mut self, value: &[u8]) {
+        write_varint(&mut self.bytes, value.len() as u64);
+        self.bytes.extend_from_slice(value);
+    }
+
+    // Ends the outermost struct.
+    fn finish(mut self) -> Vec<u8> {
+        self.bytes.push(0);
+        return self.bytes;
+    }
+}
+
+fn zigzag(value: i64) -> u64 {
+    return ((value << 1) ^ (value >> 63)) as u64;
+}
+
+// An unsigned LEB128 varint.
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::record::Field;
+
+    #[test]
+    fn zigzag_encodes_small_magnitudes_as_small_numbers() {
+        assert_eq!(zigzag(0), 0);
+        assert_eq!(zigzag(-1), 1);
+        assert_eq!(zigzag(1), 2);
+        assert_eq!(zigzag(-2), 3);
+        assert_eq!(zigzag(i64::MAX), u64::MAX - 1);
+        assert_eq!(zigzag(i64::MIN), u64::MAX);
+    }
+
+    #[test]
+    fn write_varint_uses_7_bits_a_byte() {
+        let varint = |value| {
+            let mut bytes = Vec::new();
+            write_varint(&mut bytes, value);
+            return bytes;
+        };
+        assert_eq!(varint(0), [0x00]);
+        assert_eq!(varint(127), [0x7F]);
+        assert_eq!(varint(128), [0x80, 0x01]);
+        assert_eq!(varint(300), [0xAC, 0x02]);
+        assert_eq!(varint(u64::MAX).len(), 10);
+    }
+
+    #[test]
+    fn thrift_writes_the_compact_protocol() {
+        let mut thrift = Thrift::new();
+        thrift.i32(1, 1);
+        thrift.i64(3, -1);
+        // Field IDs more than 15 apart, or going backwards, are written in full.
+        thrift.bool(20, true);
+        thrift.binary(2, b"ab");
+        thrift.begin_struct(3);
+        thrift.bool(1, false);
+        thrift.end_struct();
+        thrift.list(4, THRIFT_I32, 2);
+        thrift.list_i32(-1);
+        thrift.list_i32(1);
+        thrift.list(5, THRIFT_BINARY, 15);
+        assert_eq!(
+            thrift.finish(),
+            [
+                0x15, 0x02, // i32 1 = 1
+                0x26, 0x01, // i64 3 = -1
+                0x01, 0x28, // bool 20 = true
+                0x08, 0x04, 0x02, b'a', b'b', // binary 2 = "ab"
+                0x1C, 0x12, 0x00, // struct 3 { bool 1 = false }
+                0x19, 0x25, 0x01, 0x02, // list<i32> 4 = [-1, 1]
+                0x19, 0xF8, 0x0F, // list<binary> 5 with 15 elements
+                0x00, // the end of the struct
+            ]
+        );
+    }
+
+    #[test]
+    fn data_page_has_definition_levels_for_optional_columns() {
+        let column = Column {
+            name: "n",
+            required: false,
+            values: Values::Int32(vec![Some(1), None, None, Some(2)]),
+        };
+        let page = data_page(&column);
+        let body = [
+            6, 0, 0, 0, // the levels' length
+            0x02, 1, // 1 row with a value
+            0x04, 0, // 2 without
+            0x02, 1, // 1 with
+            1, 0, 0, 0, // 1
+            2, 0, 0, 0, // 2
+        ];
+        assert!(page.ends_with(&body), "{:?}", page);
+        // The header says how many rows there are, nulls included, and how big the page is.
+        let mut header = Thrift::new();
+        header.i32(1, PAGE_TYPE_DATA_PAGE);
+        header.i32(2, body.len() as i32);
+        header.i32(3, body.len() as i32);
+        header.begin_struct(5);
+        header.i32(1, 4);
+        header.i32(2, ENCODING_PLAIN);
+        header.i32(3, ENCODING_RLE);
+        header.i32(4, ENCODING_RLE);
+        header.end_struct();
+        assert_eq!(page[..page.len() - body.len()], header.finish());
+    }
+
+    #[test]
+    fn files_can_be_read_back() {
+        let file = write(&[
+            Column {
+                name: "time",
+                required: true,
+                values: Values::Timestamp(vec![Some(1), Some(2), Some(3)]),
+            },
+            Column {
+                name: "result",
+                required: true,
+                values: Values::String(vec![
+                    Some("success".to_string()),
+                    Some("timeout".to_string()),
+                    Some("success".to_string()),
+                ]),
+            },
+            Column {
+                name: "round_trip_ms",
+                required: false,
+                values: Values::Double(vec![Some(1.5), None, Some(2.5)]),
+            },
+            Column {
+                name: "ttl",
+                required: false,
+                values: Values::Int32(vec![None, None, Some(64)]),
+            },
+        ]);
+        let reader = SerializedFileReader::new(bytes::Bytes::from(file)).unwrap();
+        assert_eq!(reader.metadata().file_metadata().num_rows(), 3);
+        let rows: Vec<Vec<(String, Field)>> = reader
+            .get_row_iter(None)
+            .unwrap()
+            .map(|row| {
+                return row
+                    .unwrap()
+                    .get_column_iter()
+                    .map(|(name, field)| (name.clone(), field.clone()))
+                    .collect();
+            })
+            .collect();
+        let row = |time, result: &str, round_trip_ms, ttl| {
+            return vec![
+                ("time".to_string(), Field::TimestampMicros(time)),
+                ("result".to_string(), Field::Str(result.to_string())),
+                ("round_trip_ms".to_string(), round_trip_ms),
+                ("ttl".to_string(), ttl),
+            ];
+        };
+        assert_eq!(
+            rows,
+            [
+                row(1, "success", Field::Double(1.5), Field::Null),
+                row(2, "timeout", Field::Null, Field::Null),
+                row(3, "success", Field::Double(2.5), Field::Int(64)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,237 @@
+use super::{ProbeResult, Prober, Sample};
+use chrono::Duration as chrono_Duration;
+use chrono::Utc;
+use socket2::Socket;
+use std::ffi::CStr;
+use std::mem::MaybeUninit;
+use std::net::{IpAddr, Ipv4Addr};
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::time::Duration;
+
+// https://www.iana.org/assignments/arp-parameters/arp-parameters.xhtml
+const ARP_HARDWARE_TYPE_ETHERNET: u16 = 1;
+const ARP_OPERATION_REQUEST: u16 = 1;
+const ARP_OPERATION_REPLY: u16 = 2;
+// The size of an ARP message for IPv4 over Ethernet.
+const ARP_MESSAGE_SIZE: usize = 28;
+const BROADCAST_MAC: [u8; 6] = [0xFF; 6];
+
+// The local network interface a same-subnet host can be reached through.
+struct LocalInterface {
+    name: String,
+    index: i32,
+    ip_v4: Ipv4Addr,
+    mac: [u8; 6],
+}
+
+// Finds the interface whose IPv4 subnet contains `dest_ip_v4`.
+fn interface_for(dest_ip_v4: Ipv4Addr) -> Result<LocalInterface, String> {
+    let mut if_addrs: *mut libc::ifaddrs = std::ptr::null_mut();
+    if unsafe { libc::getifaddrs(&mut if_addrs) } != 0 {
+        return Err(format!(
+            "Failed to list network interfaces - {}",
+            std::io::Error::last_os_error()
+        ));
+    }
+    let mut interface: Option<(String, Ipv4Addr)> = None;
+    let mut macs: Vec<(String, [u8; 6])> = Vec::new();
+    let mut if_addr = if_addrs;
+    while !if_addr.is_null() {
+        let entry = unsafe { &*if_addr };
+        if_addr = entry.ifa_next;
+        if entry.ifa_addr.is_null() {
+            continue;
+        }
+        let name = unsafe { CStr::from_ptr(entry.ifa_name) }
+            .to_string_lossy()
+            .to_string();
+        match i32::from(unsafe { (*entry.ifa_addr).sa_family }) {
+            libc::AF_INET if !entry.ifa_netmask.is_null() => {
+                let addr = unsafe { &*(entry.ifa_addr as *const libc::sockaddr_in) };
+                let netmask = unsafe { &*(entry.ifa_netmask as *const libc::sockaddr_in) };
+                let ip_v4 = Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr));
+                let netmask = u32::from_be(netmask.sin_addr.s_addr);
+                if u32::from(ip_v4) & netmask == u32::from(dest_ip_v4) & netmask {
+                    interface = Some((name, ip_v4));
+                }
+            }
+            // Link-layer entries carry the interface's MAC address.
+            libc::AF_PACKET => {
+                let addr = unsafe { &*(entry.ifa_addr as *const libc::sockaddr_ll) };
+                if addr.sll_halen == 6 {
+                    let mut mac = [0; 6];
+                    mac.copy_from_slice(&addr.sll_addr[..6]);
+                    macs.push((name, mac));
+                }
+            }
+            _ => {}
+        }
+    }
+    unsafe { libc::freeifaddrs(if_addrs) };
+
+    let (name, ip_v4) = interface.ok_or(format!("{} isn't on a local subnet", dest_ip_v4))?;
+    let mac = macs
+        .iter()
+        .find(|(mac_if_name, _)| *mac_if_name == name)
+        .map(|(_, mac)| *mac)
+        .ok_or(format!("Interface {} has no MAC address", name))?;
+    let c_name = std::ffi::CString::new(name.clone()).unwrap();
+    let index = unsafe { libc::if_nametoindex(c_name.as_ptr()) } as i32;
+    return Ok(LocalInterface {
+        name,
+        index,
+        ip_v4,
+        mac,
+    });
+}
+
+// An Ethernet link-layer address for `interface_index`, suitable for `bind` and `sendto`.
+fn link_layer_addr(interface_index: i32, mac: [u8; 6]) -> libc::sockaddr_ll {
+    let mut sll_addr = [0; 8];
+    sll_addr[..6].copy_from_slice(&mac);
+    return libc::sockaddr_ll {
+        sll_family: libc::AF_PACKET as u16,
+        sll_protocol: (libc::ETH_P_ARP as u16).to_be(),
+        sll_ifindex: interface_index,
+        sll_hatype: 0,
+        sll_pkttype: 0,
+        sll_halen: 6,
+        sll_addr,
+    };
+}
+
+// Times ARP request/reply round trips, for devices on the local subnet that don't answer pings.
+pub struct ArpProber {
+    interface: LocalInterface,
+    dest_ip_v4: Ipv4Addr,
+    socket: Socket,
+    timeout: Duration,
+}
+impl ArpProber {
+    pub fn new(hostname: &str, dest_ip: IpAddr, timeout: Duration) -> ArpProber {
+        let IpAddr::V4(dest_ip_v4) = dest_ip else {
+            eprintln!(
+                "\nARP only supports IPv4, {} resolved to {}\n",
+                hostname, dest_ip
+            );
+            // We can't just panic, it'll just crash the thread. Exit the whole process.
+            std::process::exit(0x1);
+        };
+        let interface = match interface_for(dest_ip_v4) {
+            Ok(interface) => interface,
+            Err(err) => {
+                eprintln!("\nCan't ARP {} - {}\n", hostname, err);
+                std::process::exit(0x1);
+            }
+        };
+        // A link-layer socket that only sends and recvs ARP messages on `interface`.
+        // Being a datagram socket, the kernel adds and strips Ethernet headers for us.
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_PACKET,
+                libc::SOCK_DGRAM,
+                i32::from((libc::ETH_P_ARP as u16).to_be()),
+            )
+        };
+        if fd < 0 {
+            panic!(
+                "Failed to open an ARP socket - {}",
+                std::io::Error::last_os_error()
+            );
+        }
+        let socket = unsafe { Socket::from_raw_fd(fd) };
+        let bind_addr = link_layer_addr(interface.index, [0; 6]);
+        let res = unsafe {
+            libc::bind(
+                socket.as_raw_fd(),
+                &bind_addr as *const libc::sockaddr_ll as *const libc::sockaddr,
+                std::mem::size_of::<libc::sockaddr_ll>().try_into().unwrap(),
+            )
+        };
+        if res != 0 {
+            panic!(
+                "Failed to bind an ARP socket to {} - {}",
+                interface.name,
+                std::io::Error::last_os_error()
+            );
+        }
+        socket.set_read_timeout(Some(timeout)).unwrap();
+        // Log important details.
+        println!(
+            "Timing ARP requests to host {} (IP: {}) via {}",
+            hostname, dest_ip_v4, interface.name
+        );
+        return ArpProber {
+            interface,
+            dest_ip_v4,
+            socket,
+            timeout,
+        };
+    }
+
+    // Builds an ARP request asking who has `dest_ip_v4`.
+    // https://www.rfc-editor.org/rfc/rfc826
+    fn build_request(&self) -> [u8; ARP_MESSAGE_SIZE] {
+        let mut request = [0; ARP_MESSAGE_SIZE];
+        request[0..2].copy_from_slice(&ARP_HARDWARE_TYPE_ETHERNET.to_be_bytes());
+        request[2..4].copy_from_slice(&(libc::ETH_P_IP as u16).to_be_bytes());
+        request[4] = 6; // Hardware address length.
+        request[5] = 4; // Protocol address length.
+        request[6..8].copy_from_slice(&ARP_OPERATION_REQUEST.to_be_bytes());
+        request[8..14].copy_from_slice(&self.interface.mac);
+        request[14..18].copy_from_slice(&self.interface.ip_v4.octets());
+        // The target hardware address is what we're asking for, leave it zeroed.
+        request[24..28].copy_from_slice(&self.dest_ip_v4.octets());
+        return request;
+    }
+
+    // Whether `reply` is `dest_ip_v4` telling us its MAC address.
+    fn is_matching_reply(&self, reply: &[u8]) -> bool {
+        return reply.len() >= ARP_MESSAGE_SIZE
+            && reply[6..8] == ARP_OPERATION_REPLY.to_be_bytes()
+            && reply[14..18] == self.dest_ip_v4.octets()
+            && reply[24..28] == self.interface.ip_v4.octets();
+    }
+}
+impl Prober for ArpProber {
+    fn probe(&mut self) -> Sample {
+        let start_time = Utc::now();
+        let deadline = start_time + chrono_Duration::from_std(self.timeout).unwrap();
+        let request = self.build_request();
+        let dest_addr = link_layer_addr(self.interface.index, BROADCAST_MAC);
+        let res = unsafe {
+            libc::sendto(
+                self.socket.as_raw_fd(),
+                request.as_ptr() as *const libc::c_void,
+                request.len(),
+                0,
+                &dest_addr as *const libc::sockaddr_ll as *const libc::sockaddr,
+                std::mem::size_of::<libc::sockaddr_ll>().try_into().unwrap(),
+            )
+        };
+        if res < 0 {
+            let err = std::io::Error::last_os_error();
+            eprintln!(
+                "Error while sending ARP request for {} - {:?}",
+                self.dest_ip_v4, err
+            );
+            return Sample::new(ProbeResult::SendError(err.to_string()));
+        }
+        // Wait for the response.
+        // We'll see every ARP message on the interface, recv until the one we want shows up.
+        while Utc::now() < deadline {
+            let mut recv_buf = [MaybeUninit::new(0); 64];
+            match self.socket.recv(&mut recv_buf) {
+                Ok(size) => {
+                    // Every element of `recv_buf` was initialized when it was allocated.
+                    let reply = &unsafe { recv_buf.assume_init_ref() }[..size];
+                    if self.is_matching_reply(reply) {
+                        return Sample::success((Utc::now() - start_time).to_std().unwrap());
+                    }
+                }
+                Err(_err) => break, // Timed out.
+            }
+        }
+        return Sample::new(ProbeResult::Timeout);
+    }
+}
@@ -0,0 +1,76 @@
+use super::{http, ProbeResult, Prober, Sample};
+use crate::config;
+use chrono::Utc;
+use std::io::Read;
+use std::time::Duration;
+
+// The most we'll download in one test, in case the URL is bigger than expected.
+const MAX_DOWNLOAD_BYTES: u64 = 1024 * 1024 * 1024;
+
+// Measures download throughput by timing an HTTP(S) download of a (preferably large) file.
+// Tests use a lot of bandwidth, so they run much less often than other probes.
+pub struct BandwidthProber {
+    url: String,
+    agent: ureq::Agent,
+}
+impl BandwidthProber {
+    pub fn new(url: &str, timeout: Duration) -> BandwidthProber {
+        let agent = ureq::AgentBuilder::new()
+            .timeout(timeout)
+            // Open a fresh connection for every test, nobody should be relying on one staying open for an hour.
+            .max_idle_connections(0)
+            .build();
+        // Log important details.
+        println!(
+            "Testing download speed every {} minutes with {}",
+            config::get().bandwidth_test_interval_sec / 60,
+            url
+        );
+        return BandwidthProber {
+            url: url.to_string(),
+            agent,
+        };
+    }
+}
+impl Prober for BandwidthProber {
+    fn probe(&mut self) -> Sample {
+        let start_time = Utc::now();
+        let response = match self.agent.get(&self.url).call() {
+            Ok(response) => response,
+            Err(ureq::Error::Status(status_code, response)) => {
+                let failure = format!("HTTP {} {}", status_code, response.status_text());
+                eprintln!("Error while requesting {} - {}", self.url, failure);
+                return Sample::new(ProbeResult::Failed(failure));
+            }
+            Err(ureq::Error::Transport(err)) => {
+                eprintln!("Error while requesting {} - {}", self.url, err);
+                return Sample::new(http::transport_error_result(err));
+            }
+        };
+        // Only time the body, so connection setup doesn't count against the throughput.
+        let download_start_time = Utc::now();
+        let read_res = std::io::copy(
+            &mut response.into_reader().take(MAX_DOWNLOAD_BYTES),
+            &mut std::io::sink(),
+        );
+        let end_time = Utc::now();
+        let downloaded_bytes = match read_res {
+            Ok(size) => size,
+            Err(err) => {
+                eprintln!("Error while downloading {} - {}", self.url, err);
+                return Sample::new(err.into());
+            }
+        };
+        if downloaded_bytes == 0 {
+            return Sample::new(ProbeResult::Failed("the download was empty".to_string()));
+        }
+        let download_secs = (end_time - download_start_time)
+            .to_std()
+            .unwrap()
+            .as_secs_f64();
+        return Sample {
+            throughput_bps: Some(downloaded_bytes as f64 * 8.0 / download_secs),
+            ..Sample::success((end_time - start_time).to_std().unwrap())
+        };
+    }
+}
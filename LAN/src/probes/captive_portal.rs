@@ -0,0 +1,83 @@
+use super::http::transport_error_result;
+use super::{ProbeResult, Prober, Sample};
+use chrono::Utc;
+use std::io::Read;
+use std::time::Duration;
+
+// The most response body we'll read, a connectivity check's real response is tiny.
+const MAX_BODY_BYTES: u64 = 64 * 1024;
+
+// Fetches a connectivity check URL (the kind operating systems use to detect captive portals), to tell
+// whether we're really online or a network is intercepting our traffic, e.g. a hotel Wi-Fi login page.
+// The check passes on a `204 No Content`, or a `200 OK` saying "success" (the way Apple's and Firefox's
+// checks respond), anything else came from something other than the check's server.
+pub struct CaptivePortalProber {
+    url: String,
+    agent: ureq::Agent,
+}
+impl CaptivePortalProber {
+    pub fn new(url: &str, request_timeout: Duration) -> CaptivePortalProber {
+        let agent = ureq::AgentBuilder::new()
+            .timeout(request_timeout)
+            // Captive portals usually redirect to their login page, which is what we're looking for.
+            .redirects(0)
+            .build();
+        // Log important details.
+        println!("Checking for captive portals with {}", url);
+        return CaptivePortalProber {
+            url: url.to_string(),
+            agent,
+        };
+    }
+}
+impl Prober for CaptivePortalProber {
+    fn probe(&mut self) -> Sample {
+        let start_time = Utc::now();
+        let response = match self.agent.get(&self.url).call() {
+            Ok(response) => response,
+            // 4xx and 5xx statuses are errors to `ureq`. The check's server shouldn't send them, but
+            // a portal might.
+            Err(ureq::Error::Status(status_code, response)) => {
+                let failure = format!("HTTP {} {}", status_code, response.status_text());
+                return Sample {
+                    status: Some(status_code.to_string()),
+                    ..Sample::new(ProbeResult::Captive(failure))
+                };
+            }
+            Err(ureq::Error::Transport(err)) => {
+                eprintln!("Error while requesting {} - {}", self.url, err);
+                return Sample::new(transport_error_result(err));
+            }
+        };
+        let status_code = response.status();
+        let location = response
+            .header("Location")
+            .map(|location| location.to_string());
+        let mut body = String::new();
+        let read_res = response
+            .into_reader()
+            .take(MAX_BODY_BYTES)
+            .read_to_string(&mut body);
+        let round_trip = (Utc::now() - start_time).to_std().unwrap();
+        let result = match (read_res, status_code) {
+            (Err(err), _) => ProbeResult::from(err),
+            (Ok(_size), 204) => ProbeResult::Success(round_trip),
+            (Ok(_size), 200) if body.to_lowercase().contains("success") => {
+                ProbeResult::Success(round_trip)
+            }
+            (Ok(_size), 300..=399) => ProbeResult::Captive(format!(
+                "redirected to {}",
+                location.unwrap_or("nowhere".to_string())
+            )),
+            (Ok(_size), _) => ProbeResult::Captive(format!(
+                "unexpected HTTP {} response ({} bytes)",
+                status_code,
+                body.len()
+            )),
+        };
+        return Sample {
+            status: Some(status_code.to_string()),
+            ..Sample::new(result)
+        };
+    }
+}
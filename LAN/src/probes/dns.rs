@@ -0,0 +1,107 @@
+use super::{connect_udp, ProbeResult, Prober, Sample, SocketOptions};
+use crate::discovery;
+use chrono::Duration as chrono_Duration;
+use chrono::Utc;
+use rand::Rng;
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::time::Duration;
+
+pub const DNS_PORT: u16 = 53;
+const DNS_HEADER_SIZE: usize = 12;
+// Flags for a standard query asking the resolver to recurse for us.
+// https://www.rfc-editor.org/rfc/rfc1035#section-4.1.1
+const DNS_FLAGS_RECURSION_DESIRED: u16 = 0x0100;
+const DNS_FLAG_RESPONSE: u8 = 0x80;
+
+// The first nameserver in /etc/resolv.conf, usually the router or the ISP's resolver.
+pub fn system_resolver() -> Option<IpAddr> {
+    return std::fs::read_to_string("/etc/resolv.conf")
+        .ok()?
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("nameserver"))
+        // Link-local IPv6 resolvers may have a zone (e.g. `fe80::1%eth0`), which we can't use.
+        .and_then(|resolver| resolver.trim().parse().ok());
+}
+
+// Times how long a DNS resolver takes to answer a query for `query_name`'s A records.
+pub struct DnsProber {
+    dest_addr: SocketAddr,
+    query_name: String,
+    socket: UdpSocket,
+    timeout: Duration,
+}
+impl DnsProber {
+    pub fn new(
+        hostname: &str,
+        dest_ip: IpAddr,
+        query_name: &str,
+        timeout: Duration,
+        socket_options: &SocketOptions,
+    ) -> DnsProber {
+        let dest_addr = SocketAddr::new(dest_ip, DNS_PORT);
+        let socket = connect_udp(hostname, &dest_addr, socket_options);
+        socket.set_write_timeout(Some(timeout)).unwrap();
+        socket.set_read_timeout(Some(timeout)).unwrap();
+        // Log important details.
+        println!(
+            "Timing DNS queries for {} to resolver {} (address: {})",
+            query_name, hostname, dest_addr
+        );
+        return DnsProber {
+            dest_addr,
+            query_name: query_name.to_string(),
+            socket,
+            timeout,
+        };
+    }
+}
+impl Prober for DnsProber {
+    fn probe(&mut self) -> Sample {
+        let mut query = discovery::build_dns_query(&[(&self.query_name, discovery::DNS_TYPE_A)]);
+        // A random ID for each query, so late answers to earlier queries aren't mistaken for this one's.
+        let id: u16 = rand::thread_rng().gen();
+        query[0..2].copy_from_slice(&id.to_be_bytes());
+        query[2..4].copy_from_slice(&DNS_FLAGS_RECURSION_DESIRED.to_be_bytes());
+        let start_time = Utc::now();
+        if let Err(err) = self.socket.send(&query) {
+            eprintln!("Error while sending to {} - {:?}", self.dest_addr, err);
+            return Sample::new(ProbeResult::SendError(err.to_string()));
+        }
+        let deadline = start_time + chrono_Duration::from_std(self.timeout).unwrap();
+        while Utc::now() < deadline {
+            let mut response = [0; 512];
+            let size = match self.socket.recv(&mut response) {
+                Ok(size) => size,
+                Err(err) => {
+                    eprintln!("Error while recving from {} - {:?}", self.dest_addr, err);
+                    return Sample::new(err.into());
+                }
+            };
+            let round_trip = (Utc::now() - start_time).to_std().unwrap();
+            if size < DNS_HEADER_SIZE
+                || response[0..2] != id.to_be_bytes()
+                || response[2] & DNS_FLAG_RESPONSE == 0
+            {
+                continue;
+            }
+            let num_answers = u16::from_be_bytes([response[6], response[7]]);
+            // https://www.rfc-editor.org/rfc/rfc1035#section-4.1.1
+            let result = match response[3] & 0x0F {
+                0 => ProbeResult::Success(round_trip),
+                2 => ProbeResult::Failed("server failure (SERVFAIL)".to_string()),
+                3 => ProbeResult::Failed(format!("{} doesn't exist (NXDOMAIN)", self.query_name)),
+                5 => ProbeResult::Failed("query refused (REFUSED)".to_string()),
+                rcode => ProbeResult::Failed(format!("error code {}", rcode)),
+            };
+            return Sample {
+                status: Some(format!(
+                    "{} answer{}",
+                    num_answers,
+                    if num_answers == 1 { "" } else { "s" }
+                )),
+                ..Sample::new(result)
+            };
+        }
+        return Sample::new(ProbeResult::Timeout);
+    }
+}
@@ -0,0 +1,116 @@
+use super::{ProbeResult, Prober, Sample};
+use chrono::Utc;
+use std::time::Duration;
+use tonic::transport::{ClientTlsConfig, Endpoint};
+use tonic_health::pb::health_check_response::ServingStatus;
+use tonic_health::pb::health_client::HealthClient;
+use tonic_health::pb::HealthCheckRequest;
+
+// Times gRPC health checks (grpc.health.v1.Health/Check) and records whether the service is serving.
+// https://github.com/grpc/grpc/blob/master/doc/health-checking.md
+pub struct GrpcProber {
+    endpoint: Endpoint,
+    // The service to check, an empty string asks about the server as a whole.
+    service: String,
+    // gRPC is async, give the prober its own single-threaded runtime to block on.
+    runtime: tokio::runtime::Runtime,
+}
+impl GrpcProber {
+    pub fn new(hostname: &str, uri: &str, service: &str, timeout: Duration) -> GrpcProber {
+        let mut endpoint = Endpoint::from_shared(uri.to_string())
+            .unwrap()
+            .connect_timeout(timeout)
+            .timeout(timeout);
+        if uri.starts_with("https://") {
+            let tls_config = ClientTlsConfig::new()
+                .with_webpki_roots()
+                .domain_name(hostname);
+            endpoint = endpoint.tls_config(tls_config).unwrap();
+        }
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        // Log important details.
+        println!(
+            "Timing gRPC health checks of service \"{}\" at {}",
+            service, uri
+        );
+        return GrpcProber {
+            endpoint,
+            service: service.to_string(),
+            runtime,
+        };
+    }
+
+    // Connects and asks for the service's health.
+    async fn check(&self) -> Result<ServingStatus, ProbeResult> {
+        // Open a fresh connection for every check, so each sample includes TCP (and TLS) setup.
+        let channel = self
+            .endpoint
+            .connect()
+            .await
+            .map_err(|err| transport_error_result(&err))?;
+        let request = HealthCheckRequest {
+            service: self.service.clone(),
+        };
+        let response = HealthClient::new(channel)
+            .check(request)
+            .await
+            .map_err(|status| {
+                let reason = format!("{:?}: {}", status.code(), status.message());
+                match status.code() {
+                    tonic::Code::DeadlineExceeded => ProbeResult::Timeout,
+                    tonic::Code::Unavailable => ProbeResult::Unreachable(reason),
+                    _ => ProbeResult::Failed(reason),
+                }
+            })?;
+        return Ok(response.into_inner().status());
+    }
+}
+impl Prober for GrpcProber {
+    fn probe(&mut self) -> Sample {
+        let start_time = Utc::now();
+        let serving_status = match self.runtime.block_on(self.check()) {
+            Ok(serving_status) => serving_status,
+            Err(err) => {
+                eprintln!(
+                    "Error during gRPC health check of {} - {}",
+                    self.endpoint.uri(),
+                    err
+                );
+                return Sample::new(err);
+            }
+        };
+        let duration = (Utc::now() - start_time).to_std().unwrap();
+        let status = serving_status.as_str_name().to_string();
+        // The server answered, but anything other than SERVING means it isn't healthy.
+        let result = match serving_status {
+            ServingStatus::Serving => ProbeResult::Success(duration),
+            _ => ProbeResult::Failed("service isn't serving".to_string()),
+        };
+        return Sample {
+            status: Some(status),
+            ..Sample::new(result)
+        };
+    }
+}
+
+// Sorts tonic's errors for connections that couldn't be opened into the kind of failure they mean.
+fn transport_error_result(err: &tonic::transport::Error) -> ProbeResult {
+    // tonic's transport errors are vague on their own, the causes have the details.
+    let mut reason = err.to_string();
+    let mut source = std::error::Error::source(err);
+    while let Some(cause) = source {
+        reason += format!(" - {}", cause).as_str();
+        if let Some(io_err) = cause.downcast_ref::<std::io::Error>() {
+            return ProbeResult::from_io_error(io_err, reason);
+        }
+        // tower's timeout error isn't exposed by tonic, recognize it by its message.
+        if cause.to_string().contains("timed out") {
+            return ProbeResult::Timeout;
+        }
+        source = cause.source();
+    }
+    return ProbeResult::Failed(reason);
+}
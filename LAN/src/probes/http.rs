@@ -0,0 +1,77 @@
+use super::{ProbeResult, Prober, Sample};
+use chrono::Utc;
+use std::io::Read;
+use std::time::Duration;
+
+// The most response body we'll read, so a misbehaving endpoint can't make us download forever.
+const MAX_BODY_BYTES: u64 = 1024 * 1024;
+
+// Times HTTP(S) GET requests to a URL, for monitoring web services alongside raw ping latency.
+pub struct HttpProber {
+    url: String,
+    agent: ureq::Agent,
+}
+impl HttpProber {
+    pub fn new(url: &str, request_timeout: Duration) -> HttpProber {
+        let agent = ureq::AgentBuilder::new()
+            .timeout(request_timeout)
+            // Report the status of the URL itself, rather than wherever it redirects to.
+            .redirects(0)
+            // Open a fresh connection for every request, so each sample includes DNS, TCP and TLS setup.
+            .max_idle_connections(0)
+            .build();
+        // Log important details.
+        println!("Timing HTTP GET requests to {}", url);
+        return HttpProber {
+            url: url.to_string(),
+            agent,
+        };
+    }
+}
+impl Prober for HttpProber {
+    fn probe(&mut self) -> Sample {
+        let start_time = Utc::now();
+        let (response, failure) = match self.agent.get(&self.url).call() {
+            Ok(response) => (response, None),
+            // 4xx and 5xx statuses are errors to `ureq`, but we still got a response.
+            Err(ureq::Error::Status(status_code, response)) => {
+                let failure = format!("HTTP {} {}", status_code, response.status_text());
+                (response, Some(failure))
+            }
+            Err(ureq::Error::Transport(err)) => {
+                eprintln!("Error while requesting {} - {}", self.url, err);
+                return Sample::new(transport_error_result(err));
+            }
+        };
+        let status_code = response.status();
+        // The request isn't complete until the whole body has been received.
+        let read_res = std::io::copy(
+            &mut response.into_reader().take(MAX_BODY_BYTES),
+            &mut std::io::sink(),
+        );
+        let result = match (read_res, failure) {
+            (Err(err), _) => ProbeResult::from(err),
+            (Ok(_size), Some(failure)) => ProbeResult::Failed(failure),
+            (Ok(_size), None) => ProbeResult::Success((Utc::now() - start_time).to_std().unwrap()),
+        };
+        return Sample {
+            status: Some(status_code.to_string()),
+            ..Sample::new(result)
+        };
+    }
+}
+
+// Sorts `ureq`'s errors for requests that didn't get a response into the kind of failure they mean.
+pub fn transport_error_result(err: ureq::Transport) -> ProbeResult {
+    let reason = err.to_string();
+    if err.kind() == ureq::ErrorKind::Dns {
+        return ProbeResult::DnsError(reason);
+    }
+    // Other errors usually come from a socket, which says more about what happened.
+    let io_err =
+        std::error::Error::source(&err).and_then(|source| source.downcast_ref::<std::io::Error>());
+    return match io_err {
+        Some(io_err) => ProbeResult::from_io_error(io_err, reason),
+        None => ProbeResult::Failed(reason),
+    };
+}
@@ -0,0 +1,1057 @@
+use super::{ProbeResult, Prober, Sample, SocketOptions};
+use crate::workers;
+use byteorder::{BigEndian, ReadBytesExt};
+use chrono::Duration as chrono_Duration;
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::cmp;
+use std::collections::VecDeque;
+use std::io::{Cursor, ErrorKind, Read};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::os::unix::io::AsRawFd;
+use std::time::Duration;
+
+const IP_HEADER_SIZE: usize = 20;
+// IPv4 headers with options can be up to 60B.
+const MAX_IP_HEADER_SIZE: usize = 60;
+const IPV6_HEADER_SIZE: usize = 40;
+// How much of an ICMP error message we need to read the IP and ICMP headers it quotes, with room for
+// IPv4 options in both the error's IP header and the quoted one.
+const ICMP_ERROR_RECV_SIZE: usize =
+    MAX_IP_HEADER_SIZE + ICMP_HEADER_SIZE + MAX_IP_HEADER_SIZE + ICMP_HEADER_SIZE;
+const ICMP_HEADER_SIZE: usize = 8;
+// Bytes of data in Echo messages by default, to bring the message up to the standard 64B.
+// Like ping, the first 8 bytes of data are when the message was sent (if there's room for them).
+pub const DEFAULT_PAYLOAD_SIZE: usize = 56;
+// The most data that fits in an IPv4 packet, fragmented or not, after the IP and ICMP headers.
+pub const MAX_PAYLOAD_SIZE: usize = 65_535 - IP_HEADER_SIZE - ICMP_HEADER_SIZE;
+// Nanoseconds since the Unix epoch, as a big endian i64.
+const TIMESTAMP_SIZE: usize = 8;
+// How many of our latest Echos to remember, to recognize late and duplicate replies to them.
+const RECENT_ECHOES_TO_REMEMBER: usize = 64;
+// https://www.iana.org/assignments/icmp-parameters/icmp-parameters.xhtml
+pub const ICMP_ECHO_REQUEST: u8 = 8;
+pub const ICMP_ECHO_REPLY: u8 = 0;
+pub const ICMP_DEST_UNREACHABLE: u8 = 3;
+pub const ICMP_TIME_EXCEEDED: u8 = 11;
+// https://www.iana.org/assignments/icmpv6-parameters/icmpv6-parameters.xhtml
+pub const ICMPV6_ECHO_REQUEST: u8 = 128;
+pub const ICMPV6_ECHO_REPLY: u8 = 129;
+pub const ICMPV6_DEST_UNREACHABLE: u8 = 1;
+pub const ICMPV6_TIME_EXCEEDED: u8 = 3;
+
+// What an ICMP error message says happened to an Echo.
+struct IcmpError {
+    // Who the Echo was sent to.
+    dest_ip: IpAddr,
+    identifier: u16,
+    sequence_number: u16,
+    reason: String,
+}
+
+// Parses an ICMP Destination Unreachable or Time Exceeded message.
+// ICMP error messages quote the IP header and first 8B of the message that caused them, which is how
+// we tell which Echo an error is about.
+// https://www.rfc-editor.org/rfc/rfc792
+fn parse_icmp_error(buf: &[u8]) -> Option<IcmpError> {
+    let (msg_type, code) = (*buf.first()?, *buf.get(1)?);
+    let reason = match (msg_type, code) {
+        (ICMP_DEST_UNREACHABLE, 0) => "network unreachable",
+        (ICMP_DEST_UNREACHABLE, 1) => "host unreachable",
+        (ICMP_DEST_UNREACHABLE, 2) => "protocol unreachable",
+        (ICMP_DEST_UNREACHABLE, 3) => "port unreachable",
+        (ICMP_DEST_UNREACHABLE, 4) => "fragmentation needed",
+        (ICMP_DEST_UNREACHABLE, 9 | 10 | 13) => "administratively prohibited",
+        (ICMP_DEST_UNREACHABLE, _) => "destination unreachable",
+        (ICMP_TIME_EXCEEDED, 0) => "TTL exceeded in transit",
+        (ICMP_TIME_EXCEEDED, _) => "fragment reassembly time exceeded",
+        _ => return None,
+    };
+    let quoted_ip_header_start = ICMP_HEADER_SIZE;
+    let quoted_ip_header =
+        buf.get(quoted_ip_header_start..quoted_ip_header_start + IP_HEADER_SIZE)?;
+    let quoted_ip_header_size = usize::from(quoted_ip_header[0] & 0x0F) * 4;
+    let dest_ip_v4 = Ipv4Addr::new(
+        quoted_ip_header[16],
+        quoted_ip_header[17],
+        quoted_ip_header[18],
+        quoted_ip_header[19],
+    );
+    let quoted_echo_start = quoted_ip_header_start + quoted_ip_header_size;
+    let quoted_echo = buf.get(quoted_echo_start..quoted_echo_start + ICMP_HEADER_SIZE)?;
+    if quoted_echo[0] != ICMP_ECHO_REQUEST {
+        return None;
+    }
+    return Some(IcmpError {
+        dest_ip: IpAddr::V4(dest_ip_v4),
+        identifier: u16::from_be_bytes([quoted_echo[4], quoted_echo[5]]),
+        sequence_number: u16::from_be_bytes([quoted_echo[6], quoted_echo[7]]),
+        reason: reason.to_string(),
+    });
+}
+
+// The ICMPv6 equivalent of `parse_icmp_error`.
+// ICMPv6 error messages quote as much of the packet that caused them as will fit.
+// https://www.rfc-editor.org/rfc/rfc4443
+fn parse_icmpv6_error(buf: &[u8]) -> Option<IcmpError> {
+    let (msg_type, code) = (*buf.first()?, *buf.get(1)?);
+    let reason = match (msg_type, code) {
+        (ICMPV6_DEST_UNREACHABLE, 0) => "no route to destination",
+        (ICMPV6_DEST_UNREACHABLE, 1) => "administratively prohibited",
+        (ICMPV6_DEST_UNREACHABLE, 3) => "address unreachable",
+        (ICMPV6_DEST_UNREACHABLE, 4) => "port unreachable",
+        (ICMPV6_DEST_UNREACHABLE, 6) => "reject route to destination",
+        (ICMPV6_DEST_UNREACHABLE, _) => "destination unreachable",
+        (ICMPV6_TIME_EXCEEDED, 0) => "hop limit exceeded in transit",
+        (ICMPV6_TIME_EXCEEDED, _) => "fragment reassembly time exceeded",
+        _ => return None,
+    };
+    // Skip this message's 8B ICMPv6 header to get to the quoted IPv6 header.
+    // Our Echos don't use extension headers, so the quoted ICMPv6 header comes right after it.
+    let quoted_ip_header = buf.get(ICMP_HEADER_SIZE..ICMP_HEADER_SIZE + IPV6_HEADER_SIZE)?;
+    let dest_ip_v6 = Ipv6Addr::from(<[u8; 16]>::try_from(&quoted_ip_header[24..40]).unwrap());
+    let quoted_echo_start = ICMP_HEADER_SIZE + IPV6_HEADER_SIZE;
+    let quoted_echo = buf.get(quoted_echo_start..quoted_echo_start + ICMP_HEADER_SIZE)?;
+    if quoted_echo[0] != ICMPV6_ECHO_REQUEST {
+        return None;
+    }
+    return Some(IcmpError {
+        dest_ip: IpAddr::V6(dest_ip_v6),
+        identifier: u16::from_be_bytes([quoted_echo[4], quoted_echo[5]]),
+        sequence_number: u16::from_be_bytes([quoted_echo[6], quoted_echo[7]]),
+        reason: reason.to_string(),
+    });
+}
+
+#[derive(Debug)]
+pub struct IcmpEchoMessage {
+    msg_type: u8,
+    code: u8,
+    checksum: u16,
+    identifier: u16,
+    sequence_number: u16,
+    data: Vec<u8>,
+}
+impl IcmpEchoMessage {
+    // Allocates an ICMPv4 Echo message carrying `payload_size` bytes of data.
+    pub fn new(identifier: u16, sequence_number: u16, payload_size: usize) -> IcmpEchoMessage {
+        let mut message = IcmpEchoMessage::with_type(
+            ICMP_ECHO_REQUEST,
+            identifier,
+            sequence_number,
+            payload_size,
+        );
+        // Set the checksum.
+        message.populate_checksum();
+        return message;
+    }
+
+    // Allocates an ICMPv6 Echo message.
+    // ICMPv6 checksums cover a pseudo-header made up of parts of the IPv6 header, so the source and
+    // destination addresses are needed to compute it.
+    pub fn new_v6(
+        identifier: u16,
+        sequence_number: u16,
+        payload_size: usize,
+        src_ip_v6: Ipv6Addr,
+        dest_ip_v6: Ipv6Addr,
+    ) -> IcmpEchoMessage {
+        let mut message = IcmpEchoMessage::with_type(
+            ICMPV6_ECHO_REQUEST,
+            identifier,
+            sequence_number,
+            payload_size,
+        );
+        message.populate_checksum_v6(src_ip_v6, dest_ip_v6);
+        return message;
+    }
+
+    fn with_type(
+        msg_type: u8,
+        identifier: u16,
+        sequence_number: u16,
+        payload_size: usize,
+    ) -> IcmpEchoMessage {
+        // Allocate an ICMP message for an ECHO, use boring default values.
+        let mut message = IcmpEchoMessage {
+            msg_type,
+            code: 0,
+            checksum: 0,
+            identifier,
+            sequence_number,
+            data: vec![0; payload_size],
+        };
+        // Set some values in the data, just for fun.
+        // A nice plus: this exercises the checksum's carry-out.
+        for (i, byte) in message.data.iter_mut().enumerate() {
+            *byte = 0xFF - i as u8;
+        }
+        // Stamp the message with when it was sent, replies echo the data back so round trips can be
+        // timed without keeping track of when each message was sent.
+        if let Some(timestamp_bytes) = message.data.get_mut(..TIMESTAMP_SIZE) {
+            let now_nanos = Utc::now().timestamp_nanos_opt().unwrap();
+            timestamp_bytes.copy_from_slice(&now_nanos.to_be_bytes());
+        }
+        return message;
+    }
+
+    // When the Echo this message is (or is replying to) was sent, if it was sent by this program
+    // with enough data to include the time.
+    pub fn timestamp(&self) -> Option<DateTime<Utc>> {
+        let timestamp_bytes = self.data.get(..TIMESTAMP_SIZE)?;
+        let nanos = i64::from_be_bytes(timestamp_bytes.try_into().unwrap());
+        return Some(DateTime::from_timestamp_nanos(nanos));
+    }
+
+    // Takes the sum of this message as 16-bit words, adds back in any carry out,
+    // takes the 1's complement. Then sets the resulting value in the checksum field.
+    // http://www.faqs.org/rfcs/rfc1071.html is very helpful to understand the checksum's computation.
+    fn populate_checksum(&mut self) {
+        self.checksum = 0;
+        self.checksum = finish_checksum(ones_complement_sum(&self.serialize()));
+    }
+
+    // Same as `populate_checksum`, but also sums the IPv6 pseudo-header that precedes the message.
+    // https://www.rfc-editor.org/rfc/rfc8200#section-8.1
+    fn populate_checksum_v6(&mut self, src_ip_v6: Ipv6Addr, dest_ip_v6: Ipv6Addr) {
+        self.checksum = 0;
+        let serialized = self.serialize();
+        let mut pseudo_header = [0; 40];
+        pseudo_header[0..16].copy_from_slice(&src_ip_v6.octets());
+        pseudo_header[16..32].copy_from_slice(&dest_ip_v6.octets());
+        pseudo_header[32..36].copy_from_slice(&(serialized.len() as u32).to_be_bytes());
+        // 3 bytes of zeroes, then the Next Header value for ICMPv6.
+        pseudo_header[39] = libc::IPPROTO_ICMPV6 as u8;
+        let sum = ones_complement_sum(&pseudo_header) + ones_complement_sum(&serialized);
+        self.checksum = finish_checksum(sum);
+    }
+
+    // Marshall into a buffer using network byte order (big endian).
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buf_be = vec![0; ICMP_HEADER_SIZE + self.data.len()];
+        buf_be[0] = self.msg_type;
+        buf_be[1] = self.code;
+        buf_be[2] = self.checksum.to_be_bytes()[0];
+        buf_be[3] = self.checksum.to_be_bytes()[1];
+        buf_be[4] = self.identifier.to_be_bytes()[0];
+        buf_be[5] = self.identifier.to_be_bytes()[1];
+        buf_be[6] = self.sequence_number.to_be_bytes()[0];
+        buf_be[7] = self.sequence_number.to_be_bytes()[1];
+        buf_be[ICMP_HEADER_SIZE..].copy_from_slice(&self.data);
+        return buf_be;
+    }
+
+    // Marshall out of a network byte order (big endian) buffer.
+    fn from(buf_be: &[u8]) -> IcmpEchoMessage {
+        let mut buf_be_iter = Cursor::new(buf_be);
+        let mut message = IcmpEchoMessage {
+            msg_type: buf_be_iter.read_u8().unwrap(),
+            code: buf_be_iter.read_u8().unwrap(),
+            checksum: buf_be_iter.read_u16::<BigEndian>().unwrap(),
+            identifier: buf_be_iter.read_u16::<BigEndian>().unwrap(),
+            sequence_number: buf_be_iter.read_u16::<BigEndian>().unwrap(),
+            data: Vec::new(),
+        };
+        // The rest of the buffer is data.
+        buf_be_iter.read_to_end(&mut message.data).unwrap();
+        return message;
+    }
+}
+
+// Sums `buf` as big endian 16-bit words, an odd trailing byte is padded with zeroes.
+// Accumulate using a 32-bit variable so overflow is graceful.
+fn ones_complement_sum(buf: &[u8]) -> u32 {
+    let mut sum: u32 = 0;
+    for word in buf.chunks(2) {
+        sum += u32::from(u16::from_be_bytes([word[0], *word.get(1).unwrap_or(&0)]));
+    }
+    return sum;
+}
+
+// Adds any overflow back into the lower 16 bits, then takes the 1's complement.
+fn finish_checksum(mut sum: u32) -> u16 {
+    // So long as there is overflow, add it back into the lower 16 bits.
+    while (sum >> 16) > 0 {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    // Take the 1's complement of the sum and truncate to 16 bits.
+    return !sum as u16;
+}
+
+// Configures `socket` to only listen for ICMP Echo Reply messages, and the ICMP error messages
+// (Destination Unreachable and Time Exceeded) routers send back about our Echos.
+// Also applies a filter so `socket` will only listen for `icmp_msg_size` ICMP Echo Reply messages
+// annotated with ICMP ID == `echo_id` and ICMP Code == 0, and errors that quote an Echo to `dest_ip_v4`
+// annotated with `echo_id`.
+//
+// Replies aren't filtered by source address, some NATs and tunnels reply from a different address than
+// the one pinged. Callers can check the origin address returned by `recv_from` instead.
+fn filter_icmp_replies(socket: &Socket, dest_ip_v4: Ipv4Addr, icmp_msg_size: usize, echo_id: u16) {
+    // Filter so the socket will only recv Echo Reply, Destination Unreachable and Time Exceeded ICMP
+    // messages.
+    let icmp_types_to_listen_for_bitmask: libc::c_int =
+        !((1 << ICMP_ECHO_REPLY) | (1 << ICMP_DEST_UNREACHABLE) | (1 << ICMP_TIME_EXCEEDED));
+    unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_RAW,
+            1, /* ICMP_FILTER */
+            &icmp_types_to_listen_for_bitmask as *const libc::c_int as *const libc::c_void,
+            4, /* Size of the bitmask, it's 32 bits */
+        );
+    }
+    // Use libc::BPF to filter yet further. Only recv ICMP Echo Reply packets of the size we sent
+    // (20B IP header + `icmp_msg_size`, 84B by default) that are annotated with `echo_id`.
+    // ICMP error messages can come from any router along the way, so instead check the IP header and
+    // ICMP header they quote from the Echo that caused them (which are right after the error's own 8B
+    // ICMP header) to make sure the Echo was ours.
+    //
+    // About BPF and Packet memory layout:
+    // https://www.kernel.org/doc/Documentation/networking/filter.txt
+    // https://en.wikipedia.org/wiki/IPv4#/media/File:IPv4_Packet-en.svg
+    // Additional reading that can be helpful but doesn't apply to the messages in this program:
+    // https://en.wikipedia.org/wiki/Ethernet_frame
+    //
+    // The bytecode we use below was generated and tweaked starting with output from `tcpdump`:
+    // `sudo tcpdump icmp and src 192.168.1.1 and ip[3] == 84 and icmp[icmptype] == 0 and icmp[icmpcode] == 0 and icmp[4:2] == 0x00FF -dd`
+    // I used tcpdump's `-dd` output. You can use regex-replace to make that output into valid Rust:
+    // find: `\{ (.*), (.*), (.*), (.*) \},` -> replace: `libc::sock_filter { code: $1, jt: $2, jf: $3, k: $4 },`
+    //
+    // Notes on using tcpdump's generated bytecode:
+    // * tcpdump generates BPF bytecode targeting RAW AF_PACKET (the low-level packet interface) sockets.
+    //   This program uses Socket2's Domain::IPV4, which maps to AF_INET (a slightly hihger-level socket
+    //   type for IPv4 messaging). This socket type's higher level of abstraction means the kernel handles
+    //   a bit more and hides a bit more.
+    //   Practically speaking, this means BPF registered in this application will see fewer headers than TCP Dump's
+    //   BFP bytecode assumes are available. As a consequence, the BPF bytecode we get from TCP dump needs to be
+    //   modified before it can be used in this application. We need to remove any byte code interacting with the
+    //   Ethernet header (the first 14B) and all subsequent offsets need to be reduced by 14B.
+    // * We can simplify out some of the checks in the BPF the command above generates, too. For example, if you
+    //   look in ping.c, the BPF used doesn't check the contents of the flags and fragment offset field of the
+    //   IP header (the 2B at offset 6). We can do the same and save a couple instructions as a consequence.
+    // * We patch in variables like `dest_ip_v4` where appropriate.
+    // * Like the offsets for the ICMP header, the offsets for the quoted headers assume IP headers
+    //   without options. We never send options, and routers don't add them.
+    let mut bpf_bytecode = [
+        // Load 1B at offset 9 in the IP header (Protocol)
+        libc::sock_filter {
+            code: 0x30, /*ldb*/
+            jt: 0,
+            jf: 0,
+            k: 0x00000009,
+        },
+        // Continue if the protocol is ICMP, otherwise exit.
+        libc::sock_filter {
+            code: 0x15, /*jeq*/
+            jt: 0,
+            jf: 17,
+            k: 0x00000001, /*IPPROTO_ICMP*/
+        },
+        // Load byte at offset 0 in the ICMP header (20B IP header + 0), the ICMP Type.
+        libc::sock_filter {
+            code: 0x30, /*ldb*/
+            jt: 0,
+            jf: 0,
+            k: 0x00000014,
+        },
+        // Continue if the ICMP Type is 0 (Echo Reply), otherwise check whether it's an error.
+        libc::sock_filter {
+            code: 0x15, /*jeq*/
+            jt: 0,
+            jf: 6,
+            k: ICMP_ECHO_REPLY.into(),
+        },
+        // Load 2B at offset 2 in the IP header (Total Length).
+        libc::sock_filter {
+            code: 0x28, /*ldh*/
+            jt: 0,
+            jf: 0,
+            k: 0x00000002,
+        },
+        // Continue if the IP-layer message is the size we expect, otherwise exit.
+        libc::sock_filter {
+            code: 0x15, /*jeq*/
+            jt: 0,
+            jf: 13,
+            k: (IP_HEADER_SIZE + icmp_msg_size).try_into().unwrap(),
+        },
+        // Load byte at offset 1 in the ICMP header (20+1), the ICMP code.
+        libc::sock_filter {
+            code: 0x30, /*ldb*/
+            jt: 0,
+            jf: 0,
+            k: 0x00000015,
+        },
+        // Continue if the ICMP Code is 0, otherwise exit.
+        libc::sock_filter {
+            code: 0x15, /*jeq*/
+            jt: 0,
+            jf: 11,
+            k: 0x00000000,
+        },
+        // Load 2B at offset 4 in the ICMP header, the ICMP ID.
+        libc::sock_filter {
+            code: 0x28, /*ldh*/
+            jt: 0,
+            jf: 0,
+            k: 0x00000018,
+        },
+        // Accept if the loaded message ID matches the required ID, otherwise exit.
+        libc::sock_filter {
+            code: 0x15, /*jeq*/
+            jt: 8,
+            jf: 9,
+            k: echo_id.into(),
+        },
+        // Check the ICMP Type (still loaded) is 3 (Destination Unreachable) or 11 (Time Exceeded),
+        // otherwise exit.
+        libc::sock_filter {
+            code: 0x15, /*jeq*/
+            jt: 1,
+            jf: 0,
+            k: ICMP_DEST_UNREACHABLE.into(),
+        },
+        libc::sock_filter {
+            code: 0x15, /*jeq*/
+            jt: 0,
+            jf: 7,
+            k: ICMP_TIME_EXCEEDED.into(),
+        },
+        // Load 4B at offset 16 in the quoted IP header (20B IP header + 8B ICMP header + 16), the
+        // quoted Destination Address.
+        libc::sock_filter {
+            code: 0x20, /*ld*/
+            jt: 0,
+            jf: 0,
+            k: 0x0000002c,
+        },
+        // Continue if our Echo was to the IP we pinged, otherwise exit.
+        libc::sock_filter {
+            code: 0x15, /*jeq*/
+            jt: 0,
+            jf: 5,
+            k: u32::from_be_bytes(dest_ip_v4.octets()),
+        },
+        // Load byte at offset 0 in the quoted ICMP header (20+8+20+0), the quoted ICMP Type.
+        libc::sock_filter {
+            code: 0x30, /*ldb*/
+            jt: 0,
+            jf: 0,
+            k: 0x00000030,
+        },
+        // Continue if the quoted message is an Echo, otherwise exit.
+        libc::sock_filter {
+            code: 0x15, /*jeq*/
+            jt: 0,
+            jf: 3,
+            k: ICMP_ECHO_REQUEST.into(),
+        },
+        // Load 2B at offset 4 in the quoted ICMP header, the quoted ICMP ID.
+        libc::sock_filter {
+            code: 0x28, /*ldh*/
+            jt: 0,
+            jf: 0,
+            k: 0x00000034,
+        },
+        // Continue if the quoted message ID matches the required ID, otherwise exit.
+        libc::sock_filter {
+            code: 0x15, /*jeq*/
+            jt: 0,
+            jf: 1,
+            k: echo_id.into(),
+        },
+        // Indicate success, the criteria were fulfilled.
+        // The message's length will be truncated to the returned value, we return the largest an IP
+        // packet can be to keep the message intact.
+        libc::sock_filter {
+            code: 0x6, /*ret*/
+            jt: 0,
+            jf: 0,
+            k: 0x0000ffff,
+        },
+        // Indicate we didn't fulfill the criteria.
+        libc::sock_filter {
+            code: 0x6, /*ret*/
+            jt: 0,
+            jf: 0,
+            k: 0x00000000,
+        },
+    ];
+    let filter_program = libc::sock_fprog {
+        len: bpf_bytecode.len().try_into().unwrap(),
+        filter: bpf_bytecode.as_mut_ptr(),
+    };
+    let res: i32;
+    unsafe {
+        res = libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_ATTACH_FILTER,
+            &filter_program as *const libc::sock_fprog as *const libc::c_void,
+            std::mem::size_of::<libc::sock_fprog>().try_into().unwrap(),
+        );
+    }
+    if res != 0 {
+        eprintln!(
+            "\nFailed to apply BPF filter for IP {} and ID {} - ret {} errno {}\n",
+            dest_ip_v4,
+            echo_id,
+            res,
+            std::io::Error::last_os_error().raw_os_error().unwrap()
+        );
+        // We can't just panic, it'll just crash the thread. Exit the whole process.
+        std::process::exit(0x1);
+    }
+}
+
+// The ICMPv6 equivalent of `filter_icmp_replies`.
+// Configures `socket` to only listen for `icmp_msg_size` ICMPv6 Echo Reply messages annotated with
+// ICMP ID == `echo_id` and ICMP Code == 0, and ICMPv6 errors that quote an Echo annotated with `echo_id`.
+//
+// Raw ICMPv6 sockets don't see the IPv6 header, so unlike the IPv4 filter the BPF program can't check
+// the source address. Callers must check the origin address returned by `recv_from` (or the
+// destination address quoted in errors) instead.
+fn filter_icmpv6_replies(socket: &Socket, icmp_msg_size: usize, echo_id: u16) {
+    // Filter so the socket will only recv Echo Reply, Destination Unreachable and Time Exceeded ICMPv6
+    // messages.
+    // `struct icmp6_filter` is a 256-bit bitmask indexed by ICMPv6 type, a set bit blocks that type.
+    // https://www.rfc-editor.org/rfc/rfc3542#section-3.2
+    let mut icmp6_types_to_block_bitmask: [u32; 8] = [u32::MAX; 8];
+    for icmp6_type in [
+        ICMPV6_ECHO_REPLY,
+        ICMPV6_DEST_UNREACHABLE,
+        ICMPV6_TIME_EXCEEDED,
+    ] {
+        icmp6_types_to_block_bitmask[usize::from(icmp6_type >> 5)] &= !(1 << (icmp6_type & 31));
+    }
+    unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::IPPROTO_ICMPV6,
+            1, /* ICMP6_FILTER */
+            &icmp6_types_to_block_bitmask as *const [u32; 8] as *const libc::c_void,
+            std::mem::size_of::<[u32; 8]>().try_into().unwrap(),
+        );
+    }
+    // Use libc::BPF to filter yet further. Offsets are relative to the start of the ICMPv6 message.
+    // This mirrors the filter iputils' ping installs for IPv6, plus checks on the ICMPv6 header quoted
+    // by errors. It comes after the error's own 8B header and the quoted 40B IPv6 header, our Echos
+    // don't use extension headers.
+    let mut bpf_bytecode = [
+        // Load byte at offset 0 in the ICMPv6 header, the ICMP Type.
+        libc::sock_filter {
+            code: 0x30, /*ldb*/
+            jt: 0,
+            jf: 0,
+            k: 0x00000000,
+        },
+        // Continue if the ICMP Type is 129 (Echo Reply), otherwise check whether it's an error.
+        libc::sock_filter {
+            code: 0x15, /*jeq*/
+            jt: 0,
+            jf: 6,
+            k: ICMPV6_ECHO_REPLY.into(),
+        },
+        // Load the length of the message.
+        libc::sock_filter {
+            code: 0x80, /*ld len*/
+            jt: 0,
+            jf: 0,
+            k: 0x00000000,
+        },
+        // Continue if the ICMP-layer message is the size we expect, otherwise exit.
+        libc::sock_filter {
+            code: 0x15, /*jeq*/
+            jt: 0,
+            jf: 11,
+            k: icmp_msg_size.try_into().unwrap(),
+        },
+        // Load byte at offset 1 in the ICMPv6 header, the ICMP code.
+        libc::sock_filter {
+            code: 0x30, /*ldb*/
+            jt: 0,
+            jf: 0,
+            k: 0x00000001,
+        },
+        // Continue if the ICMP Code is 0, otherwise exit.
+        libc::sock_filter {
+            code: 0x15, /*jeq*/
+            jt: 0,
+            jf: 9,
+            k: 0x00000000,
+        },
+        // Load 2B at offset 4 in the ICMPv6 header, the ICMP ID.
+        libc::sock_filter {
+            code: 0x28, /*ldh*/
+            jt: 0,
+            jf: 0,
+            k: 0x00000004,
+        },
+        // Accept if the loaded message ID matches the required ID, otherwise exit.
+        libc::sock_filter {
+            code: 0x15, /*jeq*/
+            jt: 6,
+            jf: 7,
+            k: echo_id.into(),
+        },
+        // Check the ICMP Type (still loaded) is 1 (Destination Unreachable) or 3 (Time Exceeded),
+        // otherwise exit.
+        libc::sock_filter {
+            code: 0x15, /*jeq*/
+            jt: 1,
+            jf: 0,
+            k: ICMPV6_DEST_UNREACHABLE.into(),
+        },
+        libc::sock_filter {
+            code: 0x15, /*jeq*/
+            jt: 0,
+            jf: 5,
+            k: ICMPV6_TIME_EXCEEDED.into(),
+        },
+        // Load byte at offset 0 in the quoted ICMPv6 header (8+40+0), the quoted ICMP Type.
+        libc::sock_filter {
+            code: 0x30, /*ldb*/
+            jt: 0,
+            jf: 0,
+            k: 0x00000030,
+        },
+        // Continue if the quoted message is an Echo, otherwise exit.
+        libc::sock_filter {
+            code: 0x15, /*jeq*/
+            jt: 0,
+            jf: 3,
+            k: ICMPV6_ECHO_REQUEST.into(),
+        },
+        // Load 2B at offset 4 in the quoted ICMPv6 header, the quoted ICMP ID.
+        libc::sock_filter {
+            code: 0x28, /*ldh*/
+            jt: 0,
+            jf: 0,
+            k: 0x00000034,
+        },
+        // Continue if the quoted message ID matches the required ID, otherwise exit.
+        libc::sock_filter {
+            code: 0x15, /*jeq*/
+            jt: 0,
+            jf: 1,
+            k: echo_id.into(),
+        },
+        // Indicate success, keep the full message.
+        libc::sock_filter {
+            code: 0x6, /*ret*/
+            jt: 0,
+            jf: 0,
+            k: 0x0000ffff,
+        },
+        // Indicate we didn't fulfill the criteria.
+        libc::sock_filter {
+            code: 0x6, /*ret*/
+            jt: 0,
+            jf: 0,
+            k: 0x00000000,
+        },
+    ];
+    let filter_program = libc::sock_fprog {
+        len: bpf_bytecode.len().try_into().unwrap(),
+        filter: bpf_bytecode.as_mut_ptr(),
+    };
+    let res: i32;
+    unsafe {
+        res = libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_ATTACH_FILTER,
+            &filter_program as *const libc::sock_fprog as *const libc::c_void,
+            std::mem::size_of::<libc::sock_fprog>().try_into().unwrap(),
+        );
+    }
+    if res != 0 {
+        eprintln!(
+            "\nFailed to apply ICMPv6 BPF filter for ID {} - ret {} errno {}\n",
+            echo_id,
+            res,
+            std::io::Error::last_os_error().raw_os_error().unwrap()
+        );
+        // We can't just panic, it'll just crash the thread. Exit the whole process.
+        std::process::exit(0x1);
+    }
+}
+
+// Sets an integer socket option, returning whether it worked.
+fn set_int_option(
+    socket: &Socket,
+    level: libc::c_int,
+    option: libc::c_int,
+    value: libc::c_int,
+) -> bool {
+    let res = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            level,
+            option,
+            &value as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    return res == 0;
+}
+
+// Asks the kernel to report the TTL (or IPv6 hop limit) of each message `socket` receives, and when
+// it was received.
+fn enable_recv_metadata(socket: &Socket, dest_ip: IpAddr) {
+    let (level, option) = match dest_ip {
+        IpAddr::V4(_) => (libc::IPPROTO_IP, libc::IP_RECVTTL),
+        IpAddr::V6(_) => (libc::IPPROTO_IPV6, libc::IPV6_RECVHOPLIMIT),
+    };
+    // Neither is worth giving up over, samples just won't have a reply TTL or will be timed less
+    // precisely.
+    if !set_int_option(socket, level, option, 1) {
+        eprintln!(
+            "Failed to enable reply TTLs for {} - {}",
+            dest_ip,
+            std::io::Error::last_os_error()
+        );
+    }
+    if !set_int_option(socket, libc::SOL_SOCKET, libc::SO_TIMESTAMPNS, 1) {
+        eprintln!(
+            "Failed to enable receive timestamps for {} - {}",
+            dest_ip,
+            std::io::Error::last_os_error()
+        );
+    }
+}
+
+// A message received by `recv_with_metadata`.
+struct Received {
+    size: usize,
+    origin_ip: Option<IpAddr>,
+    // The TTL (or IPv6 hop limit) the message arrived with.
+    ttl: Option<u8>,
+    // When the kernel received the message, before it waited for us to read it.
+    timestamp: Option<DateTime<Utc>>,
+}
+
+// Receives a message like `recv_from`, along with the metadata `enable_recv_metadata` asked for.
+fn recv_with_metadata(socket: &Socket, buf: &mut [u8]) -> std::io::Result<Received> {
+    let mut origin: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+        iov_len: buf.len(),
+    };
+    // Control messages must be aligned like `cmsghdr`, a u64 array is aligned enough.
+    let mut control = [0u64; 16];
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_name = &mut origin as *mut libc::sockaddr_storage as *mut libc::c_void;
+    msg.msg_namelen = std::mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = std::mem::size_of_val(&control);
+    let size = unsafe { libc::recvmsg(socket.as_raw_fd(), &mut msg, 0) };
+    if size < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let origin_ip = match libc::c_int::from(origin.ss_family) {
+        libc::AF_INET => {
+            let origin_v4 =
+                unsafe { &*(&origin as *const libc::sockaddr_storage as *const libc::sockaddr_in) };
+            Some(IpAddr::V4(Ipv4Addr::from(u32::from_be(
+                origin_v4.sin_addr.s_addr,
+            ))))
+        }
+        libc::AF_INET6 => {
+            let origin_v6 = unsafe {
+                &*(&origin as *const libc::sockaddr_storage as *const libc::sockaddr_in6)
+            };
+            Some(IpAddr::V6(Ipv6Addr::from(origin_v6.sin6_addr.s6_addr)))
+        }
+        _ => None,
+    };
+    let mut received = Received {
+        size: size as usize,
+        origin_ip,
+        ttl: None,
+        timestamp: None,
+    };
+    let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(&msg) };
+    while !cmsg.is_null() {
+        let cmsg_ref = unsafe { &*cmsg };
+        let cmsg_data = unsafe { libc::CMSG_DATA(cmsg) };
+        match (cmsg_ref.cmsg_level, cmsg_ref.cmsg_type) {
+            (libc::IPPROTO_IP, libc::IP_TTL) | (libc::IPPROTO_IPV6, libc::IPV6_HOPLIMIT) => {
+                let value = unsafe { std::ptr::read_unaligned(cmsg_data as *const libc::c_int) };
+                received.ttl = u8::try_from(value).ok();
+            }
+            (libc::SOL_SOCKET, libc::SCM_TIMESTAMPNS) => {
+                let value = unsafe { std::ptr::read_unaligned(cmsg_data as *const libc::timespec) };
+                received.timestamp = DateTime::from_timestamp(value.tv_sec, value.tv_nsec as u32);
+            }
+            _ => {}
+        }
+        cmsg = unsafe { libc::CMSG_NXTHDR(&msg, cmsg) };
+    }
+    return Ok(received);
+}
+
+// Determines which local address the kernel will send from to reach `dest_ip_v6`.
+// Connecting a UDP socket doesn't send any packets, it only performs route selection.
+pub fn source_address_for(dest_ip_v6: Ipv6Addr) -> Ipv6Addr {
+    let route_probe = UdpSocket::bind((Ipv6Addr::UNSPECIFIED, 0)).unwrap();
+    route_probe.connect((dest_ip_v6, 9 /* discard */)).unwrap();
+    return match route_probe.local_addr().unwrap().ip() {
+        IpAddr::V6(src_ip_v6) => src_ip_v6,
+        IpAddr::V4(_) => unreachable!(),
+    };
+}
+
+// Pings a destination with ICMP (or ICMPv6) Echo messages.
+pub struct IcmpProber {
+    dest_ip: IpAddr,
+    dest_addr: socket2::SockAddr,
+    socket: Socket,
+    // The address the socket is bound to, if any. ICMPv6 checksums need to know the source address.
+    source_ip: Option<IpAddr>,
+    unique_threadlocal_id: u16,
+    sequence_number: u16,
+    // The sequence numbers of our latest Echos, and whether each has been answered.
+    recent_echoes: VecDeque<(u16, bool)>,
+    // Bytes of data in each Echo, replies carry the same data back.
+    payload_size: usize,
+    // Raw IPv4 sockets include the IP header in what they recv, raw IPv6 sockets don't.
+    recv_header_size: usize,
+    expected_reply_type: u8,
+    ping_timeout: Duration,
+}
+impl IcmpProber {
+    pub fn new(
+        hostname: &str,
+        dest_ip: IpAddr,
+        payload_size: usize,
+        ping_timeout: Duration,
+        socket_options: &SocketOptions,
+    ) -> IcmpProber {
+        // Set up this thread's ping metadata.
+        let unique_threadlocal_id: u16 = rand::thread_rng().gen::<u16>();
+        let dest_addr_v1 = SocketAddr::new(dest_ip, 0);
+        let dest_addr_v2: socket2::SockAddr = dest_addr_v1.into();
+        // Set up a socket.
+        // This is a raw ICMP socket, it will recv all ICMP traffic to this host.
+        // We will apply filters to make it behave more reasonably.
+        let socket = match dest_ip {
+            IpAddr::V4(dest_ip_v4) => {
+                let socket = Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::ICMPV4)).unwrap();
+                // Apply filters so we only recv and process relevant packets.
+                filter_icmp_replies(
+                    &socket,
+                    dest_ip_v4,
+                    ICMP_HEADER_SIZE + payload_size,
+                    unique_threadlocal_id,
+                );
+                socket
+            }
+            IpAddr::V6(_) => {
+                let socket = Socket::new(Domain::IPV6, Type::RAW, Some(Protocol::ICMPV6)).unwrap();
+                filter_icmpv6_replies(
+                    &socket,
+                    ICMP_HEADER_SIZE + payload_size,
+                    unique_threadlocal_id,
+                );
+                socket
+            }
+        };
+        socket_options.apply_or_exit(&socket, hostname);
+        enable_recv_metadata(&socket, dest_ip);
+        let (recv_header_size, expected_reply_type) = match dest_ip {
+            IpAddr::V4(_) => (IP_HEADER_SIZE, ICMP_ECHO_REPLY),
+            IpAddr::V6(_) => (0, ICMPV6_ECHO_REPLY),
+        };
+        // Set the ping timeout.
+        socket.set_write_timeout(Some(ping_timeout)).unwrap();
+        socket.set_read_timeout(Some(ping_timeout)).unwrap();
+        // Log important details.
+        println!(
+            "Pinging host {} (IP: {}) using ID {} and {}B of data",
+            hostname, dest_ip, unique_threadlocal_id, payload_size
+        );
+        return IcmpProber {
+            dest_ip,
+            dest_addr: dest_addr_v2,
+            socket,
+            source_ip: socket_options.source,
+            unique_threadlocal_id,
+            sequence_number: 0,
+            recent_echoes: VecDeque::new(),
+            payload_size,
+            recv_header_size,
+            expected_reply_type,
+            ping_timeout,
+        };
+    }
+}
+impl Prober for IcmpProber {
+    // Asks the kernel how long the filter attached by `filter_icmp_replies` or
+    // `filter_icmpv6_replies` is, rather than trusting that it's still there.
+    fn bpf_filter(&self) -> Option<Result<usize, String>> {
+        // Without a buffer, SO_GET_FILTER (the same option number as SO_ATTACH_FILTER) gives the
+        // number of instructions instead of copying them.
+        let mut num_instructions: libc::socklen_t = 0;
+        let res = unsafe {
+            libc::getsockopt(
+                self.socket.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_ATTACH_FILTER,
+                std::ptr::null_mut(),
+                &mut num_instructions,
+            )
+        };
+        if res != 0 {
+            return Some(Err(std::io::Error::last_os_error().to_string()));
+        }
+        return Some(Ok(num_instructions as usize));
+    }
+
+    fn probe(&mut self) -> Sample {
+        self.sequence_number = self.sequence_number.wrapping_add(1);
+        let start_time = Utc::now();
+        let deadline = start_time + chrono_Duration::from_std(self.ping_timeout).unwrap();
+        // Construct an ICMP Ping message.
+        let request = match self.dest_ip {
+            IpAddr::V4(_) => IcmpEchoMessage::new(
+                self.unique_threadlocal_id,
+                self.sequence_number,
+                self.payload_size,
+            ),
+            IpAddr::V6(dest_ip_v6) => IcmpEchoMessage::new_v6(
+                self.unique_threadlocal_id,
+                self.sequence_number,
+                self.payload_size,
+                match self.source_ip {
+                    Some(IpAddr::V6(source_ip_v6)) => source_ip_v6,
+                    _ => source_address_for(dest_ip_v6),
+                },
+                dest_ip_v6,
+            ),
+        };
+        // Send the ping.
+        let send_res = self.socket.send_to(&request.serialize(), &self.dest_addr);
+        if let Err(err) = send_res {
+            eprintln!("Error while sending to {} - {:?}", self.dest_ip, err);
+            return Sample::new(ProbeResult::SendError(err.to_string()));
+        }
+        self.recent_echoes.push_back((self.sequence_number, false));
+        if self.recent_echoes.len() > RECENT_ECHOES_TO_REMEMBER {
+            self.recent_echoes.pop_front();
+        }
+        // Wait for the response.
+        // We are using a raw ICMP socket. Even with filters may see ICMP Echo Replies meant for other
+        // threads or processes. Thus, we recv in a loop until our remote's response is the one we recv.
+        // Routers may also tell us our Echo isn't going to make it, with an ICMP error message.
+        let mut sample = None;
+        let mut late_replies = 0;
+        let mut duplicate_replies = 0;
+        while Utc::now() < deadline && sample.is_none() {
+            // Leave room for the headers errors quote, even when Echos are smaller than them.
+            let recv_size = cmp::max(
+                self.recv_header_size + ICMP_HEADER_SIZE + self.payload_size,
+                ICMP_ERROR_RECV_SIZE,
+            );
+            let mut recv_buf = vec![0; recv_size];
+            let received = match recv_with_metadata(&self.socket, &mut recv_buf) {
+                Ok(received) => received,
+                Err(err) => {
+                    eprintln!("Error while recving from {} - {:?}", self.dest_ip, err);
+                    // Running out of time waiting for a reply is just a timeout.
+                    if !matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) {
+                        workers::socket_error(format!("recv - {}", err));
+                    }
+                    continue;
+                }
+            };
+            let response_buf = &recv_buf[self.recv_header_size..received.size];
+            if response_buf.first() != Some(&self.expected_reply_type) {
+                let icmp_error = match self.dest_ip {
+                    IpAddr::V4(_) => parse_icmp_error(response_buf),
+                    IpAddr::V6(_) => parse_icmpv6_error(response_buf),
+                };
+                match icmp_error {
+                    Some(icmp_error)
+                        if icmp_error.dest_ip == self.dest_ip
+                            && icmp_error.identifier == self.unique_threadlocal_id
+                            && icmp_error.sequence_number == self.sequence_number =>
+                    {
+                        let reason = match received.origin_ip {
+                            Some(origin_ip) => {
+                                format!("{} (from {})", icmp_error.reason, origin_ip)
+                            }
+                            None => icmp_error.reason,
+                        };
+                        eprintln!("Error while pinging {} - {}", self.dest_ip, reason);
+                        sample = Some(Sample::new(ProbeResult::Unreachable(reason)));
+                    }
+                    // An error about an earlier Echo, or one to another host that shares our ID.
+                    _ => {}
+                }
+                continue;
+            }
+            let response = IcmpEchoMessage::from(response_buf);
+            let echo = self
+                .recent_echoes
+                .iter_mut()
+                .find(|(sequence_number, _)| *sequence_number == response.sequence_number);
+            let matching_response_found: bool = response.msg_type == self.expected_reply_type
+                && response.code == 0
+                && response.identifier == self.unique_threadlocal_id
+                && echo.is_some();
+            if !matching_response_found {
+                eprintln!(
+                    "An unexpected message got through the BPF filter: {:?} from {:?}. Expected code={} id={} seq={}.",
+                    response,
+                    received.origin_ip,
+                    0,
+                    self.unique_threadlocal_id,
+                    self.sequence_number
+                );
+                continue;
+            }
+            // A reply to an Echo that was already answered was duplicated along the way, a reply to
+            // an earlier Echo arrived after we gave up on it.
+            let (sequence_number, answered) = echo.unwrap();
+            if *answered {
+                duplicate_replies += 1;
+                continue;
+            }
+            *answered = true;
+            if *sequence_number != self.sequence_number {
+                late_replies += 1;
+                continue;
+            }
+            // Time the round trip from the send time the reply echoed back to when the kernel got
+            // the reply, so neither waiting to be scheduled nor other messages count against it.
+            // Fall back to our own bookends for payloads too small to hold a timestamp, or that
+            // were mangled on the way back.
+            let sent_at = response
+                .timestamp()
+                .filter(|sent_at| *sent_at >= start_time)
+                .unwrap_or(start_time);
+            let received_at = received.timestamp.unwrap_or_else(Utc::now);
+            let round_trip = (received_at - sent_at)
+                .to_std()
+                // The clock stepped backwards mid-ping.
+                .unwrap_or_else(|_| (Utc::now() - start_time).to_std().unwrap());
+            sample = Some(Sample {
+                reply_ttl: received.ttl,
+                // Some NATs and tunnels reply from a different address than the one pinged.
+                reply_source: received
+                    .origin_ip
+                    .filter(|origin_ip| *origin_ip != self.dest_ip),
+                ..Sample::success(round_trip)
+            });
+        }
+        return Sample {
+            late_replies,
+            duplicate_replies,
+            ..sample.unwrap_or_else(|| Sample::new(ProbeResult::Timeout))
+        };
+    }
+}
@@ -0,0 +1,208 @@
+use super::{connect_tcp, ProbeResult, Prober, Sample, SocketOptions};
+use chrono::Utc;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpStream};
+use std::time::Duration;
+
+// The most we'll read of a single reply, so a misbehaving server can't make us read forever.
+const MAX_REPLY_BYTES: u64 = 64 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MailProtocol {
+    Smtp,
+    Imap,
+}
+impl MailProtocol {
+    fn name(&self) -> &'static str {
+        return match self {
+            MailProtocol::Smtp => "SMTP",
+            MailProtocol::Imap => "IMAP",
+        };
+    }
+}
+
+// A command to send after the greeting, to check the server is actually processing commands.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MailCommand {
+    // SMTP only.
+    Ehlo,
+    Noop,
+}
+impl MailCommand {
+    fn name(&self) -> &'static str {
+        return match self {
+            MailCommand::Ehlo => "EHLO",
+            MailCommand::Noop => "NOOP",
+        };
+    }
+}
+
+// A complete reply from the server.
+struct Reply {
+    // The SMTP reply code (e.g. `220`), or IMAP status (e.g. `OK`).
+    status: String,
+    // The reply's last line, minus the line ending.
+    text: String,
+    // Whether the status means the server is willing to talk to us.
+    positive: bool,
+}
+
+// Times how long a mail server takes to greet us (and optionally respond to a command), so mail
+// servers can be monitored alongside everything else.
+pub struct MailProber {
+    protocol: MailProtocol,
+    command: Option<MailCommand>,
+    dest_addr: SocketAddr,
+    timeout: Duration,
+    socket_options: SocketOptions,
+}
+impl MailProber {
+    pub fn new(
+        hostname: &str,
+        dest_ip: IpAddr,
+        protocol: MailProtocol,
+        port: u16,
+        command: Option<MailCommand>,
+        timeout: Duration,
+        socket_options: &SocketOptions,
+    ) -> MailProber {
+        let dest_addr = SocketAddr::new(dest_ip, port);
+        // Log important details.
+        println!(
+            "Timing {} greetings{} from host {} (address: {})",
+            protocol.name(),
+            match command {
+                Some(command) => format!(" and {} replies", command.name()),
+                None => "".to_string(),
+            },
+            hostname,
+            dest_addr
+        );
+        return MailProber {
+            protocol,
+            command,
+            dest_addr,
+            timeout,
+            socket_options: socket_options.clone(),
+        };
+    }
+
+    // Reads one reply, which may span multiple lines.
+    fn read_reply(&self, reader: &mut impl BufRead, tag: &str) -> Result<Reply, ProbeResult> {
+        loop {
+            let mut line = String::new();
+            let size = reader
+                .read_line(&mut line)
+                .map_err(|err| ProbeResult::from_io_error(&err, format!("no reply - {}", err)))?;
+            if size == 0 {
+                return Err(ProbeResult::Failed(
+                    "connection closed by the server".to_string(),
+                ));
+            }
+            let line = line.trim_end().to_string();
+            match self.protocol {
+                // SMTP replies are `code-text` lines, ending with a `code text` line.
+                // https://www.rfc-editor.org/rfc/rfc5321#section-4.2
+                MailProtocol::Smtp => {
+                    if line.len() < 3 || !line.as_bytes()[..3].iter().all(u8::is_ascii_digit) {
+                        return Err(ProbeResult::Failed(format!("malformed reply \"{}\"", line)));
+                    }
+                    if line.as_bytes().get(3) == Some(&b'-') {
+                        continue;
+                    }
+                    return Ok(Reply {
+                        status: line[..3].to_string(),
+                        positive: line.starts_with('2'),
+                        text: line,
+                    });
+                }
+                // IMAP responses to a command are tagged, with untagged (`*`) lines before them.
+                // The greeting is untagged.
+                // https://www.rfc-editor.org/rfc/rfc9051#section-7.1
+                MailProtocol::Imap => {
+                    let Some(rest) = line.strip_prefix(tag).and_then(|l| l.strip_prefix(' '))
+                    else {
+                        continue;
+                    };
+                    let status = rest.split(' ').next().unwrap_or_default().to_string();
+                    return Ok(Reply {
+                        positive: status == "OK" || status == "PREAUTH",
+                        status,
+                        text: line,
+                    });
+                }
+            }
+        }
+    }
+
+    // Connects, reads the greeting, and sends the command if there is one.
+    // Returns the last reply's status.
+    fn converse(&self) -> Result<String, ProbeResult> {
+        let tcp_stream =
+            connect_tcp(&self.dest_addr, self.timeout, &self.socket_options).map_err(|err| {
+                ProbeResult::from_io_error(&err, format!("failed to connect - {}", err))
+            })?;
+        tcp_stream.set_read_timeout(Some(self.timeout))?;
+        tcp_stream.set_write_timeout(Some(self.timeout))?;
+        let mut writer = tcp_stream.try_clone()?;
+        let mut reader = BufReader::new(tcp_stream.take(MAX_REPLY_BYTES));
+        let greeting = self.read_reply(&mut reader, "*")?;
+        if !greeting.positive {
+            return Err(ProbeResult::Failed(format!(
+                "server refused the connection \"{}\"",
+                greeting.text
+            )));
+        }
+        let Some(command) = self.command else {
+            self.quit(&mut writer);
+            return Ok(greeting.status);
+        };
+        let (command_line, tag) = match (self.protocol, command) {
+            (MailProtocol::Smtp, MailCommand::Ehlo) => ("EHLO network-monitor\r\n", ""),
+            (MailProtocol::Smtp, MailCommand::Noop) => ("NOOP\r\n", ""),
+            (MailProtocol::Imap, _) => ("a1 NOOP\r\n", "a1"),
+        };
+        writer.write_all(command_line.as_bytes()).map_err(|err| {
+            ProbeResult::from_io_error(&err, format!("failed to send {} - {}", command.name(), err))
+        })?;
+        let reply = self.read_reply(&mut reader, tag)?;
+        if !reply.positive {
+            return Err(ProbeResult::Failed(format!(
+                "server rejected {} \"{}\"",
+                command.name(),
+                reply.text
+            )));
+        }
+        self.quit(&mut writer);
+        return Ok(reply.status);
+    }
+
+    // Be polite and say goodbye, rather than just dropping the connection.
+    fn quit(&self, writer: &mut TcpStream) {
+        let quit = match self.protocol {
+            MailProtocol::Smtp => "QUIT\r\n",
+            MailProtocol::Imap => "a2 LOGOUT\r\n",
+        };
+        let _ = writer.write_all(quit.as_bytes());
+    }
+}
+impl Prober for MailProber {
+    fn probe(&mut self) -> Sample {
+        let start_time = Utc::now();
+        return match self.converse() {
+            Ok(status) => Sample {
+                status: Some(status),
+                ..Sample::success((Utc::now() - start_time).to_std().unwrap())
+            },
+            Err(err) => {
+                eprintln!(
+                    "Error during {} probe of {} - {}",
+                    self.protocol.name(),
+                    self.dest_addr,
+                    err
+                );
+                Sample::new(err)
+            }
+        };
+    }
+}
@@ -0,0 +1,1600 @@
+use crate::capture;
+use crate::config;
+use crate::discovery;
+use crate::events;
+use crate::notify;
+use crate::traceroute;
+use crate::workers;
+use crate::PingData;
+use chrono::Duration as chrono_Duration;
+use chrono::{DateTime, Local, NaiveTime, Utc};
+use dns_lookup::lookup_host;
+use schemars::JsonSchema;
+use serde::Serialize;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::collections::HashSet;
+use std::net::{IpAddr, SocketAddr, TcpStream, UdpSocket};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+mod arp;
+mod bandwidth;
+mod captive_portal;
+mod dns;
+mod grpc;
+mod http;
+pub mod icmp;
+pub mod mail;
+mod ntp;
+mod snmp;
+mod ssh;
+mod tcp;
+mod tls;
+pub mod udp;
+mod websocket;
+
+// Something that can repeatedly measure the latency to a target.
+pub trait Prober {
+    // Sends one probe and waits for the response.
+    fn probe(&mut self) -> Sample;
+
+    // How many instructions of BPF filter are attached to the prober's socket, for `/debug`. None for
+    // probers that don't filter what their socket receives.
+    fn bpf_filter(&self) -> Option<Result<usize, String>> {
+        return None;
+    }
+}
+
+// How a single probe turned out.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ProbeResult {
+    // The target responded, after this long.
+    Success(Duration),
+    // Nothing came back before the timeout.
+    Timeout,
+    // The target, or something on the way to it, said it can't be reached (e.g. a refused connection).
+    Unreachable(String),
+    // The probe couldn't be sent at all (e.g. the network is down).
+    SendError(String),
+    // The target's hostname couldn't be resolved.
+    DnsError(String),
+    // The target responded, but not the way it should have (e.g. an HTTP 500).
+    Failed(String),
+    // We're connected to a network, but something on it intercepted the request (e.g. a Wi-Fi login
+    // page), so we can't reach the Internet.
+    Captive(String),
+}
+impl ProbeResult {
+    // A short description of the kind of result, for display.
+    pub fn label(&self) -> &'static str {
+        return match self {
+            ProbeResult::Success(_) => "ok",
+            ProbeResult::Timeout => "timed out",
+            ProbeResult::Unreachable(_) => "unreachable",
+            ProbeResult::SendError(_) => "send error",
+            ProbeResult::DnsError(_) => "DNS error",
+            ProbeResult::Failed(_) => "failed",
+            ProbeResult::Captive(_) => "captive portal",
+        };
+    }
+
+    // How long the round trip took, if the probe succeeded.
+    pub fn round_trip(&self) -> Option<Duration> {
+        return match self {
+            ProbeResult::Success(round_trip) => Some(*round_trip),
+            _ => None,
+        };
+    }
+
+    // Why the probe failed, if it did.
+    pub fn failure(&self) -> Option<String> {
+        return match self {
+            ProbeResult::Success(_) => None,
+            ProbeResult::Timeout => Some("timed out".to_string()),
+            ProbeResult::Unreachable(reason)
+            | ProbeResult::SendError(reason)
+            | ProbeResult::DnsError(reason)
+            | ProbeResult::Failed(reason)
+            | ProbeResult::Captive(reason) => Some(reason.clone()),
+        };
+    }
+
+    pub fn is_failure(&self) -> bool {
+        return !matches!(self, ProbeResult::Success(_));
+    }
+
+    // Sorts a socket error into the kind of failure it means, described by `reason`.
+    pub fn from_io_error(err: &std::io::Error, reason: String) -> ProbeResult {
+        return match err.kind() {
+            // Timed out reads and writes report `WouldBlock`.
+            std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock => ProbeResult::Timeout,
+            std::io::ErrorKind::ConnectionRefused
+            | std::io::ErrorKind::HostUnreachable
+            | std::io::ErrorKind::NetworkUnreachable => ProbeResult::Unreachable(reason),
+            std::io::ErrorKind::NetworkDown | std::io::ErrorKind::AddrNotAvailable => {
+                ProbeResult::SendError(reason)
+            }
+            _ => ProbeResult::Failed(reason),
+        };
+    }
+}
+impl std::fmt::Display for ProbeResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        return match self {
+            ProbeResult::Success(round_trip) => {
+                write!(f, "{:.1} ms", round_trip.as_secs_f64() * 1000.0)
+            }
+            _ => write!(f, "{}", self.failure().unwrap()),
+        };
+    }
+}
+impl From<std::io::Error> for ProbeResult {
+    fn from(err: std::io::Error) -> ProbeResult {
+        return ProbeResult::from_io_error(&err, err.to_string());
+    }
+}
+
+// The outcome of a single probe.
+#[derive(Clone, Debug)]
+pub struct Sample {
+    pub result: ProbeResult,
+    // The status the target responded with, for protocols that have one (e.g. HTTP, gRPC).
+    pub status: Option<String>,
+    // When the target's TLS certificate expires, for probes that see one.
+    pub cert_not_after: Option<DateTime<Utc>>,
+    // How far ahead of our clock the target's clock is, for NTP probes.
+    pub clock_offset: Option<chrono_Duration>,
+    // How busy a network interface was since the previous sample, for SNMP probes.
+    pub interface_stats: Option<InterfaceStats>,
+    // Download throughput in bits per second, for bandwidth tests.
+    pub throughput_bps: Option<f64>,
+    // The TTL (or IPv6 hop limit) the reply arrived with, for ICMP probes. A change means the reply
+    // took a different path.
+    pub reply_ttl: Option<u8>,
+    // Where the reply came from, for ICMP probes, if it wasn't the address probed.
+    pub reply_source: Option<IpAddr>,
+    // Replies to earlier probes that arrived after we gave up on them, and extra copies of replies,
+    // for ICMP probes. Either hints at bufferbloat or something odd along the path.
+    pub late_replies: u32,
+    pub duplicate_replies: u32,
+    // How the probes went, for targets that send a burst of probes per sample.
+    pub burst: Option<BurstStats>,
+}
+impl Sample {
+    pub fn new(result: ProbeResult) -> Sample {
+        return Sample {
+            result,
+            status: None,
+            cert_not_after: None,
+            clock_offset: None,
+            interface_stats: None,
+            throughput_bps: None,
+            reply_ttl: None,
+            reply_source: None,
+            late_replies: 0,
+            duplicate_replies: 0,
+            burst: None,
+        };
+    }
+
+    pub fn success(round_trip: Duration) -> Sample {
+        return Sample::new(ProbeResult::Success(round_trip));
+    }
+}
+
+// How many of a burst of probes were lost, and the spread of the rest's round trips. The sample's
+// round trip is the median.
+#[derive(Clone, Debug)]
+pub struct BurstStats {
+    pub sent: u32,
+    pub lost: u32,
+    // None if every probe was lost.
+    pub fastest: Option<Duration>,
+    pub slowest: Option<Duration>,
+}
+
+// A network interface's traffic and error rates over a period of time.
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+pub struct InterfaceStats {
+    // Bits per second received.
+    pub in_bps: f64,
+    // Bits per second sent.
+    pub out_bps: f64,
+    // How many packets had errors (sending or receiving) over the period.
+    pub errors: u32,
+}
+
+// How a target is probed.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ProbeKind {
+    // ICMP (or ICMPv6) Echo.
+    Icmp,
+    // Time how long a TCP three-way handshake with `port` takes.
+    Tcp {
+        port: u16,
+    },
+    // Time HTTP(S) GET requests to `url`.
+    Http {
+        url: String,
+    },
+    // Time round trips to a UDP echo responder (see `udp::run_echo_responder`) listening on `port`.
+    Udp {
+        port: u16,
+    },
+    // Time TLS handshakes with `port`, and track when the certificate expires.
+    Tls {
+        port: u16,
+    },
+    // Time ARP requests, for hosts on the local subnet.
+    Arp,
+    // Query an NTP server, recording the round-trip delay and clock offset.
+    Ntp {
+        port: u16,
+    },
+    // Call the standard gRPC health check on the server at `uri`, asking about `service`.
+    Grpc {
+        uri: String,
+        service: String,
+    },
+    // Time how long an SSH server on `port` takes to send its version banner, and record the banner.
+    Ssh {
+        port: u16,
+    },
+    // Time how long a mail server on `port` takes to greet us, and to reply to `command` if there is one.
+    Mail {
+        protocol: mail::MailProtocol,
+        port: u16,
+        command: Option<mail::MailCommand>,
+    },
+    // Keep a WebSocket connection to `url` open, and time ping/pong round trips over it.
+    WebSocket {
+        url: String,
+    },
+    // Periodically download `url`, recording the throughput.
+    Bandwidth {
+        url: String,
+    },
+    // Fetch the connectivity check `url`, watching for captive portals intercepting it.
+    CaptivePortal {
+        url: String,
+    },
+    // Time how long a DNS resolver takes to look up `query_name`.
+    Dns {
+        query_name: String,
+    },
+    // Poll the traffic and error counters of interface `if_index` with SNMP v2c.
+    Snmp {
+        port: u16,
+        community: String,
+        if_index: u32,
+    },
+}
+
+impl ProbeKind {
+    // Whether the prober opens its own sockets, so `SocketOptions` can be applied to them.
+    fn applies_socket_options(&self) -> bool {
+        return !matches!(
+            self,
+            ProbeKind::Http { .. }
+                | ProbeKind::Grpc { .. }
+                | ProbeKind::Bandwidth { .. }
+                | ProbeKind::CaptivePortal { .. }
+                | ProbeKind::Arp
+        );
+    }
+
+    // How long to wait for a response, unless the target's options say otherwise.
+    fn default_timeout(&self) -> Duration {
+        let config = config::get();
+        return Duration::from_millis(match self {
+            ProbeKind::Http { .. } | ProbeKind::CaptivePortal { .. } => config.http_timeout_msec,
+            ProbeKind::Dns { .. } => config.dns_timeout_msec,
+            ProbeKind::Tls { .. } => config.tls_timeout_msec,
+            ProbeKind::Ssh { .. } => config.ssh_timeout_msec,
+            ProbeKind::Mail { .. } => config.mail_timeout_msec,
+            ProbeKind::WebSocket { .. } => config.websocket_timeout_msec,
+            ProbeKind::Bandwidth { .. } => config.bandwidth_timeout_msec,
+            ProbeKind::Grpc { .. } => config.grpc_timeout_msec,
+            _ => config.ping_timeout_msec,
+        });
+    }
+
+    // How long to wait between probes, unless the target's options say otherwise.
+    fn default_interval(&self) -> Duration {
+        return Duration::from_secs(match self {
+            // Bandwidth tests use a lot of data, so they're run much less often.
+            ProbeKind::Bandwidth { .. } => config::get().bandwidth_test_interval_sec,
+            _ => config::get().sec_between_pings,
+        });
+    }
+
+    // Whether the prober looks up the hostname itself for every probe, the way a browser would,
+    // rather than probing one address.
+    fn resolves_every_probe(&self) -> bool {
+        return matches!(
+            self,
+            ProbeKind::Http { .. }
+                | ProbeKind::Grpc { .. }
+                | ProbeKind::WebSocket { .. }
+                | ProbeKind::Bandwidth { .. }
+                | ProbeKind::CaptivePortal { .. }
+        );
+    }
+}
+
+// A target to monitor, as given on the command line.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Target {
+    // The target exactly as the user specified it, used to label its data.
+    pub name: String,
+    pub hostname: String,
+    // The address to probe, when it's pinned rather than resolved from `hostname`.
+    pub address: Option<IpAddr>,
+    pub kind: ProbeKind,
+    pub options: TargetOptions,
+}
+
+// Options that change how a target is probed.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TargetOptions {
+    // A friendlier name to show instead of the target spec, e.g. `Living room AP`.
+    pub label: Option<String>,
+    // Targets in the same group (e.g. `LAN`) are shown together, with a summary of how they're doing.
+    pub group: Option<String>,
+    // Probe every address the hostname resolves to, each as a separate target.
+    pub all_addresses: bool,
+    // Probe one IPv4 and one IPv6 address, as separate targets, so the two can be compared.
+    pub dual_stack: bool,
+    // Send this many probes in a row each interval, and summarize them as one sample.
+    pub burst: Option<u32>,
+    // Bytes of data to send in each ICMP Echo, instead of the usual 56.
+    pub payload_size: Option<usize>,
+    // Capture ICMP traffic into a pcap file in this directory when the target goes down.
+    pub capture_dir: Option<PathBuf>,
+    // How much of the target's history to keep, instead of the configured retention.
+    pub retention: Option<config::Retention>,
+    // Daily windows of time when the target isn't probed, e.g. while it sleeps overnight.
+    pub quiet_hours: Vec<QuietHours>,
+    // Where on the way to the Internet the target is, used to work out where outages happened.
+    pub tier: Option<Tier>,
+    // How long to wait between probes, instead of the usual interval for the kind of target.
+    pub interval: Option<Duration>,
+    // How long to wait for a response, instead of the usual timeout for the kind of target.
+    pub timeout: Option<Duration>,
+    pub socket: SocketOptions,
+}
+
+// A daily window of local time, e.g. from 23:00 until 07:00.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QuietHours {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+impl QuietHours {
+    // Parses a window like `23:00-07:00`.
+    fn parse(window: &str) -> Option<QuietHours> {
+        let (start, end) = window.split_once('-')?;
+        let start = NaiveTime::parse_from_str(start, "%H:%M").ok()?;
+        let end = NaiveTime::parse_from_str(end, "%H:%M").ok()?;
+        return (start != end).then_some(QuietHours { start, end });
+    }
+
+    // Whether `time` is in the window. Windows that end before they start span midnight.
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start < self.end {
+            return self.start <= time && time < self.end;
+        }
+        return self.start <= time || time < self.end;
+    }
+}
+
+// How far along the path to the Internet a target is, nearest first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Tier {
+    // A device on the local network, e.g. a switch or access point.
+    Lan,
+    // The router.
+    Gateway,
+    // A hop inside the ISP's network, e.g. the first hop past the router.
+    Isp,
+    // A host out on the Internet.
+    Internet,
+}
+impl Tier {
+    fn parse(tier: &str) -> Option<Tier> {
+        return match tier.to_lowercase().as_str() {
+            "lan" => Some(Tier::Lan),
+            "gateway" => Some(Tier::Gateway),
+            "isp" => Some(Tier::Isp),
+            "internet" => Some(Tier::Internet),
+            _ => None,
+        };
+    }
+}
+
+// Options applied to the sockets probes are sent with.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SocketOptions {
+    // Send probes out of this network interface (e.g. `wg0`), regardless of the routing table.
+    pub interface: Option<String>,
+    // Send probes from this local address, for hosts with more than one.
+    pub source: Option<IpAddr>,
+    // Mark probes with this DSCP value (0-63), to see how QoS treats them.
+    pub dscp: Option<u8>,
+    // Send probes with this TTL (or IPv6 hop limit) instead of the system's default.
+    pub ttl: Option<u32>,
+}
+impl SocketOptions {
+    // Applies the options to a socket, before it sends anything.
+    pub fn apply(&self, socket: &Socket) -> std::io::Result<()> {
+        if let Some(interface) = &self.interface {
+            socket.bind_device(Some(interface.as_bytes()))?;
+        }
+        if let Some(source) = self.source {
+            // Port 0 lets the kernel pick a port, like it would without binding.
+            socket.bind(&SocketAddr::new(source, 0).into())?;
+        }
+        if let Some(dscp) = self.dscp {
+            // DSCP is the top 6 bits of the IPv4 ToS or IPv6 Traffic Class byte, the rest is ECN.
+            let tos = libc::c_int::from(dscp) << 2;
+            if socket.domain()? == Domain::IPV6 {
+                // socket2 doesn't support IPV6_TCLASS.
+                let res = unsafe {
+                    libc::setsockopt(
+                        socket.as_raw_fd(),
+                        libc::IPPROTO_IPV6,
+                        libc::IPV6_TCLASS,
+                        &tos as *const libc::c_int as *const libc::c_void,
+                        std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+                    )
+                };
+                if res != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            } else {
+                socket.set_tos(tos as u32)?;
+            }
+        }
+        if let Some(ttl) = self.ttl {
+            if socket.domain()? == Domain::IPV6 {
+                socket.set_unicast_hops_v6(ttl)?;
+            } else {
+                socket.set_ttl(ttl)?;
+            }
+        }
+        return Ok(());
+    }
+
+    // Applies the options to a socket a prober can't work without.
+    pub fn apply_or_exit(&self, socket: &Socket, hostname: &str) {
+        if let Err(err) = self.apply(socket) {
+            eprintln!(
+                "\nFailed to set up a socket for {} with {:?} - {}\n",
+                hostname, self, err
+            );
+            // We can't just panic, it'll just crash the thread. Exit the whole process.
+            std::process::exit(0x1);
+        }
+    }
+}
+
+// Opens a TCP connection with `socket_options` applied.
+pub fn connect_tcp(
+    dest_addr: &SocketAddr,
+    timeout: Duration,
+    socket_options: &SocketOptions,
+) -> std::io::Result<TcpStream> {
+    let socket = Socket::new(
+        Domain::for_address(*dest_addr),
+        Type::STREAM,
+        Some(Protocol::TCP),
+    )?;
+    socket_options.apply(&socket)?;
+    socket.connect_timeout(&(*dest_addr).into(), timeout)?;
+    return Ok(socket.into());
+}
+
+// Opens a UDP socket with `socket_options` applied, connected to `dest_addr` so the kernel drops
+// datagrams from anyone else.
+pub fn connect_udp(
+    hostname: &str,
+    dest_addr: &SocketAddr,
+    socket_options: &SocketOptions,
+) -> UdpSocket {
+    let socket = Socket::new(
+        Domain::for_address(*dest_addr),
+        Type::DGRAM,
+        Some(Protocol::UDP),
+    )
+    .unwrap();
+    socket_options.apply_or_exit(&socket, hostname);
+    socket.connect(&(*dest_addr).into()).unwrap();
+    return socket.into();
+}
+
+// Returns `spec` with its option `key` set to `value`, or without it if `value` is empty, e.g. to
+// relabel a target.
+pub fn set_option(spec: &str, key: &str, value: &str) -> String {
+    let (target_spec, options) = spec.split_once('#').unwrap_or((spec, ""));
+    let mut options: Vec<&str> = options
+        .split(',')
+        .filter(|option| !option.is_empty() && option.split('=').next() != Some(key))
+        .collect();
+    let option = format!("{}={}", key, value);
+    if !value.is_empty() {
+        options.push(&option);
+    }
+    if options.is_empty() {
+        return target_spec.to_string();
+    }
+    return format!("{}#{}", target_spec, options.join(","));
+}
+
+impl Target {
+    // Parses a target spec, optionally followed by `#` and comma separated options (see
+    // `TargetOptions`), e.g. `example.com#all-addresses`. Options not given use `default_options`.
+    pub fn parse(spec: &str, default_options: &TargetOptions) -> Result<Target, String> {
+        let (target_spec, options) = match spec.split_once('#') {
+            Some((target_spec, options)) => (
+                target_spec,
+                TargetOptions::parse(options, default_options)
+                    .map_err(|err| format!("{} in {}", err, spec))?,
+            ),
+            None => (spec, default_options.clone()),
+        };
+        let (hostname, kind) = Target::parse_kind(target_spec)?;
+        if (options.all_addresses || options.dual_stack) && kind.resolves_every_probe() {
+            return Err(format!(
+                "{} looks up its hostname for every probe, it can't pick which addresses to probe",
+                spec
+            ));
+        }
+        if options.payload_size.is_some() && kind != ProbeKind::Icmp {
+            return Err(format!(
+                "{} isn't pinged with ICMP, it can't use the payload-size option",
+                spec
+            ));
+        }
+        if options.all_addresses && options.dual_stack {
+            return Err(format!(
+                "{} can't use both the all-addresses and dual-stack options",
+                spec
+            ));
+        }
+        return Ok(Target {
+            name: spec.to_string(),
+            hostname,
+            address: None,
+            kind,
+            options,
+        });
+    }
+
+    // Parses a target spec (without options) into the host to probe and how to probe it:
+    // * `hostname` pings `hostname` using ICMP.
+    // * `hostname:port` (or `[ipv6]:port`) times TCP connections to `port`.
+    // * `http://...` and `https://...` URLs time GET requests to the URL.
+    // * `udp://hostname[:port]` times round trips to a UDP echo responder.
+    // * `tls://hostname[:port]` times TLS handshakes (port 443 by default).
+    // * `arp://hostname` times ARP requests to a host on the local subnet.
+    // * `ntp://hostname[:port]` queries an NTP server with SNTP.
+    // * `grpc://hostname[:port][/service]` (or `grpcs://` for TLS) calls the gRPC health check.
+    // * `ssh://hostname[:port]` reads an SSH server's version banner (port 22 by default).
+    // * `smtp://hostname[:port][?ehlo|?noop]` and `imap://hostname[:port][?noop]` read a mail server's
+    //   greeting, then optionally send a command.
+    // * `ws://...` and `wss://...` URLs time WebSocket ping/pong round trips.
+    // * `bandwidth:` followed by an `http://...` or `https://...` URL tests download speed.
+    // * `captive-portal` checks for captive portals, optionally followed by `:` and the connectivity
+    //   check URL to use.
+    // * `snmp://[community@]hostname[:port][/ifIndex]` polls an interface's counters with SNMP v2c.
+    // * `dns://resolver[,resolver...]/name` times lookups of `name` by each resolver, where `system` is
+    //   the system's resolver.
+    fn parse_kind(spec: &str) -> Result<(String, ProbeKind), String> {
+        if spec.starts_with("http://") || spec.starts_with("https://") {
+            let url = parse_url(spec)?;
+            return Ok((
+                url_hostname(&url)?,
+                ProbeKind::Http {
+                    url: spec.to_string(),
+                },
+            ));
+        }
+        if let Some(download_url) = spec.strip_prefix("bandwidth:") {
+            let url = parse_url(download_url)?;
+            return Ok((
+                url_hostname(&url)?,
+                ProbeKind::Bandwidth {
+                    url: download_url.to_string(),
+                },
+            ));
+        }
+        if spec == "captive-portal" || spec.starts_with("captive-portal:") {
+            let check_url = spec
+                .strip_prefix("captive-portal:")
+                .unwrap_or(config::CAPTIVE_PORTAL_CHECK_URL);
+            let url = parse_url(check_url)?;
+            return Ok((
+                url_hostname(&url)?,
+                ProbeKind::CaptivePortal {
+                    url: check_url.to_string(),
+                },
+            ));
+        }
+        if spec.starts_with("ws://") || spec.starts_with("wss://") {
+            let url = parse_url(spec)?;
+            return Ok((
+                url_hostname(&url)?,
+                ProbeKind::WebSocket {
+                    url: spec.to_string(),
+                },
+            ));
+        }
+        if spec.starts_with("udp://") {
+            let url = parse_url(spec)?;
+            return Ok((
+                url_hostname(&url)?,
+                ProbeKind::Udp {
+                    port: url.port().unwrap_or(config::UDP_ECHO_PORT),
+                },
+            ));
+        }
+        if spec.starts_with("arp://") {
+            let url = parse_url(spec)?;
+            return Ok((url_hostname(&url)?, ProbeKind::Arp));
+        }
+        if spec.starts_with("ntp://") {
+            let url = parse_url(spec)?;
+            return Ok((
+                url_hostname(&url)?,
+                ProbeKind::Ntp {
+                    port: url.port().unwrap_or(123),
+                },
+            ));
+        }
+        if spec.starts_with("grpc://") || spec.starts_with("grpcs://") {
+            let url = parse_url(spec)?;
+            let (scheme, default_port) = match url.scheme() {
+                "grpcs" => ("https", 443),
+                // 50051 is the port gRPC's examples and most unencrypted services use.
+                _ => ("http", 50051),
+            };
+            let uri = format!(
+                "{}://{}:{}",
+                scheme,
+                url.host_str().unwrap(),
+                url.port().unwrap_or(default_port)
+            );
+            return Ok((
+                url_hostname(&url)?,
+                ProbeKind::Grpc {
+                    uri,
+                    service: url.path().trim_start_matches('/').to_string(),
+                },
+            ));
+        }
+        if let Some(dns_spec) = spec.strip_prefix("dns://") {
+            let Some((resolvers, query_name)) = dns_spec.split_once('/') else {
+                return Err(format!(
+                    "{} needs a name to look up, e.g. dns://1.1.1.1/example.com",
+                    spec
+                ));
+            };
+            let resolvers: Vec<String> =
+                resolvers
+                    .split(',')
+                    .map(|resolver| match resolver {
+                        "system" => match dns::system_resolver() {
+                            Some(system_resolver) => Ok(system_resolver.to_string()),
+                            None => Err("Couldn't find the system's resolver in /etc/resolv.conf"
+                                .to_string()),
+                        },
+                        resolver => match split_port(resolver)? {
+                            Some(_) => Err(format!(
+                                "{} can't have a port, DNS resolvers are queried on port {}",
+                                resolver,
+                                dns::DNS_PORT
+                            )),
+                            // Allow IPv6 resolvers to be bracketed, like they would be in a URL.
+                            None => Ok(resolver
+                                .trim_start_matches('[')
+                                .trim_end_matches(']')
+                                .to_string()),
+                        },
+                    })
+                    .collect::<Result<_, String>>()?;
+            // Several resolvers are split into a target each by `expand`.
+            return Ok((
+                resolvers.join(","),
+                ProbeKind::Dns {
+                    query_name: query_name.to_string(),
+                },
+            ));
+        }
+        if spec.starts_with("ssh://") {
+            let url = parse_url(spec)?;
+            return Ok((
+                url_hostname(&url)?,
+                ProbeKind::Ssh {
+                    port: url.port().unwrap_or(22),
+                },
+            ));
+        }
+        if spec.starts_with("smtp://") || spec.starts_with("imap://") {
+            let url = parse_url(spec)?;
+            let (protocol, default_port) = match url.scheme() {
+                "smtp" => (mail::MailProtocol::Smtp, 25),
+                _ => (mail::MailProtocol::Imap, 143),
+            };
+            let command = match (protocol, url.query()) {
+                (_, None) => None,
+                (mail::MailProtocol::Smtp, Some("ehlo")) => Some(mail::MailCommand::Ehlo),
+                (_, Some("noop")) => Some(mail::MailCommand::Noop),
+                (_, Some(query)) => {
+                    return Err(format!(
+                        "Unsupported mail command \"{}\" in {}",
+                        query, spec
+                    ))
+                }
+            };
+            return Ok((
+                url_hostname(&url)?,
+                ProbeKind::Mail {
+                    protocol,
+                    port: url.port().unwrap_or(default_port),
+                    command,
+                },
+            ));
+        }
+        if spec.starts_with("snmp://") {
+            let url = parse_url(spec)?;
+            let community = match url.username() {
+                "" => "public",
+                community => community,
+            };
+            let if_index = match url.path().trim_start_matches('/') {
+                "" => 1,
+                if_index => if_index.parse().map_err(|_err| {
+                    format!("Invalid interface index \"{}\" in {}", if_index, spec)
+                })?,
+            };
+            return Ok((
+                url_hostname(&url)?,
+                ProbeKind::Snmp {
+                    port: url.port().unwrap_or(161),
+                    community: community.to_string(),
+                    if_index,
+                },
+            ));
+        }
+        if spec.starts_with("tls://") {
+            let url = parse_url(spec)?;
+            return Ok((
+                url_hostname(&url)?,
+                ProbeKind::Tls {
+                    port: url.port().unwrap_or(443),
+                },
+            ));
+        }
+        return match split_port(spec)? {
+            Some((hostname, port)) => Ok((hostname.to_string(), ProbeKind::Tcp { port })),
+            None => Ok((spec.to_string(), ProbeKind::Icmp)),
+        };
+    }
+
+    // What to call the target when showing it, its label if it has one.
+    pub fn label(&self) -> &str {
+        return self.options.label.as_deref().unwrap_or(&self.name);
+    }
+
+    // A copy of the target with `suffix` added to its name and label, to tell apart the targets one
+    // is split into.
+    fn with_suffix(&self, suffix: &str) -> Target {
+        let mut target = self.clone();
+        target.name += suffix;
+        if let Some(label) = &mut target.options.label {
+            *label += suffix;
+        }
+        return target;
+    }
+
+    // How long to wait for a response, the target's own timeout if it has one.
+    pub fn timeout(&self) -> Duration {
+        return self
+            .options
+            .timeout
+            .unwrap_or_else(|| self.kind.default_timeout());
+    }
+
+    // How long to wait between probes, the target's own interval if it has one.
+    pub fn interval(&self) -> Duration {
+        return self
+            .options
+            .interval
+            .unwrap_or_else(|| self.kind.default_interval());
+    }
+
+    // Problems that would stop the target from being probed usefully, to report them all up front
+    // rather than having its thread fail later.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.address.is_none() && !self.kind.resolves_every_probe() {
+            if let Err(err) = resolve(&self.hostname) {
+                problems.push(format!("{} can't be probed - {}", self.name, err));
+            }
+        }
+        if self.timeout() > self.interval() {
+            problems.push(format!(
+                "{} has a {:?} timeout, longer than the {:?} between its probes",
+                self.name,
+                self.timeout(),
+                self.interval()
+            ));
+        }
+        return problems;
+    }
+
+    // The address to probe, resolving the hostname unless the address has been pinned.
+    fn dest_ip(&self) -> IpAddr {
+        return self
+            .address
+            .unwrap_or_else(|| resolve(&self.hostname).unwrap());
+    }
+
+    // Splits a target with the `all-addresses` option into a target for each address its hostname
+    // resolves to, and a target with the `dual-stack` option into an IPv4 and an IPv6 target.
+    // DNS targets with several resolvers are split into a target for each resolver.
+    // Other targets are returned as-is.
+    pub fn expand(self) -> Result<Vec<Target>, String> {
+        if matches!(self.kind, ProbeKind::Dns { .. }) && self.hostname.contains(',') {
+            let mut targets = Vec::new();
+            for resolver in self.hostname.split(',') {
+                targets.extend(
+                    Target {
+                        hostname: resolver.to_string(),
+                        ..self.with_suffix(&format!(" (via {})", resolver))
+                    }
+                    .expand()?,
+                );
+            }
+            return Ok(targets);
+        }
+        if self.options.dual_stack {
+            let resolved_ips = resolve_all(&self.hostname)?;
+            let ip_v4 = resolved_ips.iter().find(|ip| ip.is_ipv4());
+            let ip_v6 = resolved_ips.iter().find(|ip| ip.is_ipv6());
+            let (Some(ip_v4), Some(ip_v6)) = (ip_v4, ip_v6) else {
+                return Err(format!(
+                    "{} needs both IPv4 and IPv6 addresses to compare them, it resolved to {:?}",
+                    self.hostname, resolved_ips
+                ));
+            };
+            return Ok(vec![
+                Target {
+                    address: Some(*ip_v4),
+                    ..self.with_suffix(" (IPv4)")
+                },
+                Target {
+                    address: Some(*ip_v6),
+                    ..self.with_suffix(" (IPv6)")
+                },
+            ]);
+        }
+        if !self.options.all_addresses {
+            return Ok(vec![self]);
+        }
+        return Ok(resolve_all(&self.hostname)?
+            .into_iter()
+            .map(|ip| Target {
+                address: Some(ip),
+                ..self.with_suffix(&format!(" ({})", ip))
+            })
+            .collect());
+    }
+}
+
+impl TargetOptions {
+    // Parses comma separated options, e.g. `all-addresses,interface=wg0`, on top of `defaults`.
+    pub fn parse(options: &str, defaults: &TargetOptions) -> Result<TargetOptions, String> {
+        let mut target_options = defaults.clone();
+        for option in options.split(',').filter(|option| !option.is_empty()) {
+            match option.split_once('=') {
+                None if option == "all-addresses" => target_options.all_addresses = true,
+                None if option == "dual-stack" => target_options.dual_stack = true,
+                Some(("label", label)) => target_options.label = Some(label.to_string()),
+                Some(("group", group)) => target_options.group = Some(group.to_string()),
+                Some(("burst", burst)) => match burst.parse::<u32>() {
+                    Ok(burst) if burst > 0 => target_options.burst = Some(burst),
+                    _ => {
+                        return Err(format!(
+                            "Invalid burst \"{}\", it must be at least 1",
+                            burst
+                        ))
+                    }
+                },
+                Some(("payload-size", payload_size)) => match payload_size.parse::<usize>() {
+                    Ok(payload_size) if payload_size <= icmp::MAX_PAYLOAD_SIZE => {
+                        target_options.payload_size = Some(payload_size)
+                    }
+                    _ => {
+                        return Err(format!(
+                            "Invalid payload size \"{}\", it must be from 0 to {} bytes",
+                            payload_size,
+                            icmp::MAX_PAYLOAD_SIZE
+                        ))
+                    }
+                },
+                Some(("keep", retention)) => match retention.parse() {
+                    Ok(retention) => target_options.retention = Some(retention),
+                    Err(err) => return Err(format!("Invalid keep \"{}\", {}", retention, err)),
+                },
+                Some(("quiet", window)) => match QuietHours::parse(window) {
+                    Some(quiet_hours) => target_options.quiet_hours.push(quiet_hours),
+                    None => {
+                        return Err(format!(
+                            "Invalid quiet hours \"{}\", they must be like 23:00-07:00",
+                            window
+                        ))
+                    }
+                },
+                Some(("capture", capture_dir)) => {
+                    target_options.capture_dir = Some(PathBuf::from(capture_dir))
+                }
+                Some(("tier", tier)) => match Tier::parse(tier) {
+                    Some(tier) => target_options.tier = Some(tier),
+                    None => {
+                        return Err(format!(
+                            "Invalid tier \"{}\", it must be lan, gateway, isp or internet",
+                            tier
+                        ))
+                    }
+                },
+                Some(("interval", interval)) => match parse_duration::parse(interval) {
+                    Ok(interval) if interval >= Duration::from_secs(1) => {
+                        target_options.interval = Some(interval)
+                    }
+                    _ => {
+                        return Err(format!(
+                            "Invalid interval \"{}\", it must be at least 1s (e.g. 30s)",
+                            interval
+                        ))
+                    }
+                },
+                Some(("timeout", timeout)) => match parse_duration::parse(timeout) {
+                    Ok(timeout) if !timeout.is_zero() => target_options.timeout = Some(timeout),
+                    _ => {
+                        return Err(format!(
+                            "Invalid timeout \"{}\", it must be longer than 0 (e.g. 500ms)",
+                            timeout
+                        ))
+                    }
+                },
+                Some(("interface", interface)) => {
+                    target_options.socket.interface = Some(interface.to_string())
+                }
+                Some(("source", source)) => match source.parse::<IpAddr>() {
+                    Ok(source) => target_options.socket.source = Some(source),
+                    Err(err) => {
+                        return Err(format!("Invalid source address \"{}\" - {}", source, err))
+                    }
+                },
+                Some(("dscp", dscp)) => match parse_dscp(dscp) {
+                    Some(dscp) => target_options.socket.dscp = Some(dscp),
+                    None => return Err(format!("Invalid DSCP value \"{}\"", dscp)),
+                },
+                Some(("ttl", ttl)) => match ttl.parse::<u8>() {
+                    Ok(ttl) if ttl > 0 => target_options.socket.ttl = Some(ttl.into()),
+                    _ => return Err(format!("Invalid TTL \"{}\", it must be from 1 to 255", ttl)),
+                },
+                _ => return Err(format!("Unknown target option \"{}\"", option)),
+            }
+        }
+        return Ok(target_options);
+    }
+}
+
+// Parses a DSCP value, either a number from 0 to 63 or a class name like `ef`, `af41` or `cs1`.
+// https://www.rfc-editor.org/rfc/rfc4594#section-3
+fn parse_dscp(dscp: &str) -> Option<u8> {
+    let dscp = dscp.to_lowercase();
+    let value = if dscp == "ef" {
+        46
+    } else if let Some(class) = dscp.strip_prefix("cs") {
+        // Class selectors are the old IP precedence values, 0-7.
+        let class: u8 = class.parse().ok()?;
+        (class < 8).then_some(class << 3)?
+    } else if let Some(class_and_drop) = dscp.strip_prefix("af") {
+        // Assured forwarding classes are 1-4, each with drop precedences 1-3.
+        let &[class, drop] = class_and_drop.as_bytes() else {
+            return None;
+        };
+        let (class, drop) = (class.wrapping_sub(b'0'), drop.wrapping_sub(b'0'));
+        ((1..=4).contains(&class) && (1..=3).contains(&drop)).then_some(class << 3 | drop << 1)?
+    } else {
+        dscp.parse().ok()?
+    };
+    return (value < 64).then_some(value);
+}
+
+fn parse_url(spec: &str) -> Result<url::Url, String> {
+    let url = url::Url::parse(spec).map_err(|err| match err {
+        url::ParseError::InvalidPort => {
+            format!("Invalid port in {}, it must be from 1 to 65535", spec)
+        }
+        err => format!("Invalid URL \"{}\" - {}", spec, err),
+    })?;
+    if url.port() == Some(0) {
+        return Err(format!(
+            "Invalid port in {}, it must be from 1 to 65535",
+            spec
+        ));
+    }
+    return Ok(url);
+}
+
+// Splits `host:port` (or `[ipv6]:port`) into the host and port, checking it's a port that can be
+// probed. None if there's no port, e.g. for a hostname or a bare IPv6 address.
+fn split_port(spec: &str) -> Result<Option<(&str, u16)>, String> {
+    let Some((host, port)) = spec.rsplit_once(':') else {
+        return Ok(None);
+    };
+    let host = match host.strip_prefix('[').and_then(|h| h.strip_suffix(']')) {
+        Some(bracketed_ipv6) => bracketed_ipv6,
+        // A bare IPv6 address has colons of its own, treat it as a hostname.
+        None if host.contains(':') => return Ok(None),
+        None => host,
+    };
+    if port.is_empty() || !port.bytes().all(|byte| byte.is_ascii_digit()) {
+        return Ok(None);
+    }
+    return match port.parse::<u16>() {
+        Ok(port) if port > 0 => Ok(Some((host, port))),
+        _ => Err(format!(
+            "Invalid port \"{}\" in {}, it must be from 1 to 65535",
+            port, spec
+        )),
+    };
+}
+
+// The hostname (or IP address) in `url`, without the brackets URLs put around IPv6 addresses.
+fn url_hostname(url: &url::Url) -> Result<String, String> {
+    return match url.host() {
+        Some(url::Host::Domain(domain)) => Ok(domain.to_string()),
+        Some(url::Host::Ipv4(ip_v4)) => Ok(ip_v4.to_string()),
+        Some(url::Host::Ipv6(ip_v6)) => Ok(ip_v6.to_string()),
+        None => Err(format!("{} doesn't have a host", url)),
+    };
+}
+
+// Resolves `hostname` to an IP address.
+// Prefer IPv4, fall back to IPv6 for hosts that only resolve to AAAA records.
+pub fn resolve(hostname: &str) -> Result<IpAddr, String> {
+    return Ok(resolve_all(hostname)?[0]);
+}
+
+// Resolves `hostname` to all of its IP addresses, IPv4 addresses first.
+// Names DNS can't resolve are looked for amongst devices on the local network, so devices can be
+// monitored by their friendly name (e.g. "Living Room TV") or mDNS hostname.
+pub fn resolve_all(hostname: &str) -> Result<Vec<IpAddr>, String> {
+    let mut resolved_ips = match lookup_host(hostname) {
+        Ok(resolved_ips) => resolved_ips,
+        Err(err) => match discovery::lookup(hostname) {
+            Some(ip) => vec![ip],
+            None => return Err(format!("Failed to resolve {} - {}", hostname, err)),
+        },
+    };
+    // Each address is listed once per socket type, only keep the first of each.
+    let mut seen_ips = HashSet::new();
+    resolved_ips.retain(|ip| seen_ips.insert(*ip));
+    resolved_ips.sort_by_key(|ip| ip.is_ipv6());
+    if resolved_ips.is_empty() {
+        return Err(format!("{} has no IP addresses", hostname));
+    }
+    return Ok(resolved_ips);
+}
+
+// Repeatedly probes a target, until `stop` is set. The first probe is delayed by `phase` (from 0 to 1)
+// of the interval, so targets started together are probed at different times.
+pub fn repeatedly_probe(
+    target: Target,
+    phase: f64,
+    ping_data: Arc<Mutex<PingData>>,
+    stop: Arc<AtomicBool>,
+) {
+    let _worker = workers::start(&target.name);
+    let timeout = target.timeout();
+    // Determine destination and set up the prober.
+    // Not every prober can apply socket options, don't silently ignore them.
+    if target.options.socket != SocketOptions::default() && !target.kind.applies_socket_options() {
+        eprintln!(
+            "{} can't apply socket options, ignoring {:?}",
+            target.label(),
+            target.options.socket
+        );
+    }
+    // The address is resolved once, and probed until the target is restarted.
+    let resolved_ip = (!target.kind.resolves_every_probe()).then(|| target.dest_ip());
+    let mut prober: Box<dyn Prober> = match &target.kind {
+        ProbeKind::Icmp => Box::new(icmp::IcmpProber::new(
+            &target.hostname,
+            resolved_ip.unwrap(),
+            target
+                .options
+                .payload_size
+                .unwrap_or(icmp::DEFAULT_PAYLOAD_SIZE),
+            timeout,
+            &target.options.socket,
+        )),
+        ProbeKind::Tcp { port } => Box::new(tcp::TcpProber::new(
+            &target.hostname,
+            resolved_ip.unwrap(),
+            *port,
+            timeout,
+            &target.options.socket,
+        )),
+        // HTTP requests resolve the hostname each time, the same way a browser would.
+        ProbeKind::Http { url } => Box::new(http::HttpProber::new(url, timeout)),
+        ProbeKind::Udp { port } => Box::new(udp::UdpProber::new(
+            &target.hostname,
+            resolved_ip.unwrap(),
+            *port,
+            timeout,
+            &target.options.socket,
+        )),
+        ProbeKind::Arp => Box::new(arp::ArpProber::new(
+            &target.hostname,
+            resolved_ip.unwrap(),
+            timeout,
+        )),
+        ProbeKind::Ntp { port } => Box::new(ntp::NtpProber::new(
+            &target.hostname,
+            resolved_ip.unwrap(),
+            *port,
+            timeout,
+            &target.options.socket,
+        )),
+        ProbeKind::Dns { query_name } => Box::new(dns::DnsProber::new(
+            &target.hostname,
+            resolved_ip.unwrap(),
+            query_name,
+            timeout,
+            &target.options.socket,
+        )),
+        ProbeKind::Tls { port } => Box::new(tls::TlsProber::new(
+            &target.hostname,
+            resolved_ip.unwrap(),
+            *port,
+            timeout,
+            &target.options.socket,
+        )),
+        ProbeKind::Ssh { port } => Box::new(ssh::SshProber::new(
+            &target.hostname,
+            resolved_ip.unwrap(),
+            *port,
+            timeout,
+            &target.options.socket,
+        )),
+        ProbeKind::Mail {
+            protocol,
+            port,
+            command,
+        } => Box::new(mail::MailProber::new(
+            &target.hostname,
+            resolved_ip.unwrap(),
+            *protocol,
+            *port,
+            *command,
+            timeout,
+            &target.options.socket,
+        )),
+        ProbeKind::WebSocket { url } => Box::new(websocket::WebSocketProber::new(
+            &target.hostname,
+            url,
+            timeout,
+            &target.options.socket,
+        )),
+        ProbeKind::Snmp {
+            port,
+            community,
+            if_index,
+        } => Box::new(snmp::SnmpProber::new(
+            &target.hostname,
+            resolved_ip.unwrap(),
+            *port,
+            community,
+            *if_index,
+            timeout,
+            &target.options.socket,
+        )),
+        ProbeKind::Bandwidth { url } => Box::new(bandwidth::BandwidthProber::new(url, timeout)),
+        ProbeKind::CaptivePortal { url } => {
+            Box::new(captive_portal::CaptivePortalProber::new(url, timeout))
+        }
+        // Like HTTP, gRPC resolves the hostname each time it connects.
+        ProbeKind::Grpc { uri, service } => Box::new(grpc::GrpcProber::new(
+            &target.hostname,
+            uri,
+            service,
+            timeout,
+        )),
+    };
+    workers::set_prober(resolved_ip, prober.bpf_filter());
+    // When the current run of failed probes started, and how long it's been going.
+    let mut failing_since = None;
+    let mut consecutive_failures = 0;
+    thread::sleep(target.interval().mul_f64(phase));
+    // Why the target isn't being probed, if it's paused or in its quiet hours.
+    let mut quiet = None;
+    // Probe repeatedly.
+    while !stop.load(Ordering::SeqCst) {
+        let now = Utc::now();
+        let local_time = DateTime::<Local>::from(now).time();
+        let in_quiet_hours = target
+            .options
+            .quiet_hours
+            .iter()
+            .any(|quiet_hours| quiet_hours.contains(local_time));
+        let mut locked_ping_data = ping_data.lock().unwrap();
+        let reason = if locked_ping_data.is_paused(&target.name) {
+            Some("paused")
+        } else if in_quiet_hours {
+            Some("quiet hours")
+        } else {
+            None
+        };
+        if reason != quiet {
+            quiet = reason;
+            locked_ping_data.set_quiet(&target.name, now, quiet);
+        }
+        drop(locked_ping_data);
+        if let Some(reason) = quiet {
+            workers::set_state(reason);
+            thread::sleep(target.interval());
+            continue;
+        }
+        workers::set_state("waiting for a probe slot");
+        wait_for_probe_slot();
+        workers::set_state("probing");
+        let start_time = Utc::now();
+        let sample = match target.options.burst {
+            Some(count) => probe_burst(prober.as_mut(), count),
+            None => probe_once(prober.as_mut()),
+        };
+        let failed = sample.result.is_failure();
+        events::publish(&target.name, start_time, &sample);
+        // Store the probe's outcome.
+        ping_data
+            .lock()
+            .unwrap()
+            .add_entry(&target.name, start_time, sample);
+        // Enough failures in a row is an outage. Trace the path to the host as soon as one is
+        // detected, so there's a record of where the path broke.
+        if failed {
+            consecutive_failures += 1;
+            let outage_start = *failing_since.get_or_insert(start_time);
+            if consecutive_failures == config::OUTAGE_CONSECUTIVE_FAILURES {
+                eprintln!("{} is down, tracing the path to it", target.label());
+                let (connectivity_lost, num_targets) = {
+                    let mut locked_ping_data = ping_data.lock().unwrap();
+                    let connectivity_lost = locked_ping_data.add_outage(&target.name, outage_start);
+                    (connectivity_lost, locked_ping_data.outages.len())
+                };
+                if connectivity_lost.is_none() {
+                    hold_outage_alert(&target, outage_start, ping_data.clone());
+                }
+                if let Some(connectivity_lost) = connectivity_lost {
+                    eprintln!(
+                        "Every target is down, local connectivity was lost at {}",
+                        connectivity_lost
+                    );
+                    notify::send(notify::Alert {
+                        rule: "connectivity_loss",
+                        host: None,
+                        value: num_targets as f64,
+                        started: connectivity_lost,
+                        resolved: None,
+                    });
+                }
+                // Start capturing first, so the capture includes the traceroute.
+                if let Some(capture_dir) = &target.options.capture_dir {
+                    capture_outage(&target, capture_dir, outage_start, ping_data.clone());
+                }
+                trace_outage(&target, outage_start, ping_data.clone());
+            }
+        } else {
+            if let Some(outage_start) = failing_since.take() {
+                if consecutive_failures >= config::OUTAGE_CONSECUTIVE_FAILURES {
+                    let (connectivity_lost, num_targets, alerted) = {
+                        let mut locked_ping_data = ping_data.lock().unwrap();
+                        let connectivity_lost =
+                            locked_ping_data.end_outage(&target.name, outage_start, start_time);
+                        let alerted = locked_ping_data
+                            .outages
+                            .get(&target.name)
+                            .and_then(|outages| outages.get(&outage_start))
+                            .is_some_and(|outage| outage.alerted);
+                        (connectivity_lost, locked_ping_data.outages.len(), alerted)
+                    };
+                    // Outages still being held are resolved when their alert goes out.
+                    if alerted {
+                        notify::send(outage_alert(
+                            &target,
+                            consecutive_failures,
+                            outage_start,
+                            Some(start_time),
+                        ));
+                    }
+                    if let Some(connectivity_lost) = connectivity_lost {
+                        eprintln!(
+                            "Local connectivity is back, it was lost from {} until {}",
+                            connectivity_lost, start_time
+                        );
+                        notify::send(notify::Alert {
+                            rule: "connectivity_loss",
+                            host: None,
+                            value: num_targets as f64,
+                            started: connectivity_lost,
+                            resolved: Some(start_time),
+                        });
+                    }
+                }
+            }
+            consecutive_failures = 0;
+        }
+        workers::probed(consecutive_failures);
+        // Wait for the ping interval to elapse and repeat.
+        workers::set_state("sleeping");
+        let next_ping_time = start_time + chrono_Duration::from_std(target.interval()).unwrap();
+        let cur_time = Utc::now();
+        if next_ping_time > cur_time {
+            thread::sleep((next_ping_time - cur_time).to_std().unwrap());
+        }
+    }
+}
+
+// The alert for `target`'s outage since `started`, after `consecutive_failures` failed probes.
+fn outage_alert(
+    target: &Target,
+    consecutive_failures: usize,
+    started: DateTime<Utc>,
+    resolved: Option<DateTime<Utc>>,
+) -> notify::Alert {
+    return notify::Alert {
+        rule: "outage",
+        host: Some((target.name.clone(), target.label().to_string())),
+        value: consecutive_failures as f64,
+        started,
+        resolved,
+    };
+}
+
+// Alerts about a target's outage that started at `outage_start` in the background, once every other
+// target has had the time a loss of local connectivity allows to go down too, and to be noticed. If
+// they did, only the loss is alerted about. If the outage ended in the meantime, it's resolved right away.
+fn hold_outage_alert(
+    target: &Target,
+    outage_start: DateTime<Utc>,
+    ping_data: Arc<Mutex<PingData>>,
+) {
+    let target = target.clone();
+    thread::spawn(move || {
+        // Outages that start within the window take about as long as this one did to be detected.
+        let detected_after = (Utc::now() - outage_start).to_std().unwrap_or_default();
+        thread::sleep(
+            Duration::from_secs(config::CONNECTIVITY_LOSS_WINDOW_SEC as u64) + detected_after,
+        );
+        let mut locked_ping_data = ping_data.lock().unwrap();
+        let Some(ended) = locked_ping_data
+            .alert_outage(&target.name, outage_start)
+            .map(|outage| outage.ended)
+        else {
+            return;
+        };
+        // Every probe since it started failed, until it ended.
+        let failures = ended.map(|ended| {
+            locked_ping_data.data[&target.name]
+                .range(outage_start..ended)
+                .count()
+        });
+        drop(locked_ping_data);
+        notify::send(outage_alert(
+            &target,
+            config::OUTAGE_CONSECUTIVE_FAILURES,
+            outage_start,
+            None,
+        ));
+        if let (Some(ended), Some(failures)) = (ended, failures) {
+            notify::send(outage_alert(&target, failures, outage_start, Some(ended)));
+        }
+    });
+}
+
+// Traces the path to a target's host in the background, and stores it with the outage that started
+// at `outage_start`. Tracing takes up to a timeout per hop, which shouldn't hold up probing.
+fn trace_outage(target: &Target, outage_start: DateTime<Utc>, ping_data: Arc<Mutex<PingData>>) {
+    let name = target.name.clone();
+    let hostname = target.hostname.clone();
+    let address = target.address;
+    thread::spawn(move || {
+        let trace = match address {
+            Some(address) => Ok(address),
+            None => resolve(&hostname),
+        }
+        .and_then(traceroute::trace);
+        ping_data
+            .lock()
+            .unwrap()
+            .add_outage_trace(&name, outage_start, trace);
+    });
+}
+
+// Captures ICMP traffic into a pcap file in `capture_dir` in the background, and stores where it
+// was saved with the outage that started at `outage_start`.
+fn capture_outage(
+    target: &Target,
+    capture_dir: &Path,
+    outage_start: DateTime<Utc>,
+    ping_data: Arc<Mutex<PingData>>,
+) {
+    let name = target.name.clone();
+    let interface = target.options.socket.interface.clone();
+    // e.g. `example.com_443-20240101-123000.pcap`
+    let file_name = format!(
+        "{}-{}.pcap",
+        name.replace(
+            |c: char| !c.is_ascii_alphanumeric() && c != '.' && c != '-',
+            "_"
+        ),
+        outage_start.format("%Y%m%d-%H%M%S")
+    );
+    let path = capture_dir.join(file_name);
+    thread::spawn(move || {
+        let capture = capture::capture_icmp(
+            interface.as_deref(),
+            Duration::from_secs(config::OUTAGE_CAPTURE_SEC),
+            &path,
+        )
+        .map(|num_packets| (path.clone(), num_packets))
+        .map_err(|err| format!("Failed to capture packets to {} - {}", path.display(), err));
+        if let Err(err) = &capture {
+            eprintln!("{}", err);
+        }
+        ping_data
+            .lock()
+            .unwrap()
+            .add_outage_capture(&name, outage_start, capture);
+    });
+}
+
+// When the next probe can be sent without going over `max_probes_per_sec`, shared by every target.
+static NEXT_PROBE_SLOT: Mutex<Option<Instant>> = Mutex::new(None);
+
+// Waits for our turn to send a probe, so probes from every target together are spaced evenly at no
+// more than `max_probes_per_sec`.
+fn wait_for_probe_slot() {
+    let Some(max_probes_per_sec) = config::get().max_probes_per_sec else {
+        return;
+    };
+    // Claim the next slot, then wait for it without holding up other targets claiming theirs.
+    let slot = {
+        let mut next_probe_slot = NEXT_PROBE_SLOT.lock().unwrap();
+        let now = Instant::now();
+        let slot = next_probe_slot.map_or(now, |next| next.max(now));
+        *next_probe_slot = Some(slot + Duration::from_secs(1) / max_probes_per_sec);
+        slot
+    };
+    thread::sleep(slot.saturating_duration_since(Instant::now()));
+}
+
+// How long a probe would wait for a slot now. It keeps growing if targets want to send more probes
+// than `max_probes_per_sec` allows.
+pub fn probe_slot_backlog() -> Duration {
+    return NEXT_PROBE_SLOT
+        .lock()
+        .unwrap()
+        .map_or(Duration::ZERO, |next| {
+            next.saturating_duration_since(Instant::now())
+        });
+}
+
+// Sends a probe, noting when for `/debug`, and whether the prober's socket couldn't send it.
+fn probe_once(prober: &mut dyn Prober) -> Sample {
+    workers::sending();
+    let sample = prober.probe();
+    if let ProbeResult::SendError(err) = &sample.result {
+        workers::socket_error(format!("send - {}", err));
+    }
+    return sample;
+}
+
+// Sends `count` probes in a row, and summarizes them as one sample of the median round trip.
+// Shorter gaps between probes than between samples catch brief loss and jitter a single probe would
+// usually miss, like smokeping does.
+fn probe_burst(prober: &mut dyn Prober, count: u32) -> Sample {
+    let mut round_trips = Vec::new();
+    let mut last_success = None;
+    let mut last_failure = None;
+    let mut late_replies = 0;
+    let mut duplicate_replies = 0;
+    for i in 0..count {
+        if i > 0 {
+            thread::sleep(Duration::from_millis(
+                config::get().msec_between_burst_pings,
+            ));
+            wait_for_probe_slot();
+        }
+        let sample = probe_once(prober);
+        late_replies += sample.late_replies;
+        duplicate_replies += sample.duplicate_replies;
+        match sample.result.round_trip() {
+            Some(round_trip) => {
+                round_trips.push(round_trip);
+                last_success = Some(sample);
+            }
+            None => last_failure = Some(sample),
+        }
+    }
+    round_trips.sort();
+    let burst = BurstStats {
+        sent: count,
+        lost: count - round_trips.len() as u32,
+        fastest: round_trips.first().copied(),
+        slowest: round_trips.last().copied(),
+    };
+    // Other details (e.g. the reply TTL) come from the last successful probe. If every probe
+    // failed, the sample is the last failure.
+    let Some(last_success) = last_success else {
+        return Sample {
+            late_replies,
+            duplicate_replies,
+            burst: Some(burst),
+            ..last_failure.unwrap()
+        };
+    };
+    return Sample {
+        result: ProbeResult::Success(round_trips[round_trips.len() / 2]),
+        late_replies,
+        duplicate_replies,
+        burst: Some(burst),
+        ..last_success
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_tcp_ports() {
+        assert_eq!(
+            Target::parse_kind("127.0.0.1:8080").unwrap(),
+            ("127.0.0.1".to_string(), ProbeKind::Tcp { port: 8080 })
+        );
+        assert_eq!(
+            Target::parse_kind("[::1]:22").unwrap(),
+            ("::1".to_string(), ProbeKind::Tcp { port: 22 })
+        );
+        // A bare IPv6 address is pinged.
+        assert_eq!(
+            Target::parse_kind("::1").unwrap(),
+            ("::1".to_string(), ProbeKind::Icmp)
+        );
+    }
+
+    #[test]
+    fn reports_invalid_ports() {
+        for spec in [
+            "127.0.0.1:99999",
+            "127.0.0.1:0",
+            "[::1]:65536",
+            "udp://1.1.1.1:70000",
+            "dns://1.1.1.1:99999/example.com",
+        ] {
+            let err = Target::parse_kind(spec).unwrap_err();
+            assert!(err.starts_with("Invalid port"), "{}: {}", spec, err);
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use super::{connect_udp, ProbeResult, Prober, Sample, SocketOptions};
+use chrono::Duration as chrono_Duration;
+use chrono::{DateTime, Utc};
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::time::Duration;
+
+const NTP_MESSAGE_SIZE: usize = 48;
+// NTP timestamps count seconds from 1900, Unix timestamps count from 1970.
+const NTP_UNIX_EPOCH_DELTA_SECS: i64 = 2_208_988_800;
+// Leap Indicator 0 (no warning), Version 4, Mode 3 (client).
+const SNTP_CLIENT_FLAGS: u8 = (4 << 3) | 3;
+const NTP_MODE_SERVER: u8 = 4;
+
+// Converts to the 64-bit NTP timestamp format: 32 bits of seconds, 32 bits of fractional seconds.
+// https://www.rfc-editor.org/rfc/rfc4330#section-3
+fn to_ntp_timestamp(when: DateTime<Utc>) -> u64 {
+    let secs = (when.timestamp() + NTP_UNIX_EPOCH_DELTA_SECS) as u64;
+    let fraction = (u64::from(when.timestamp_subsec_nanos()) << 32) / 1_000_000_000;
+    return (secs << 32) | fraction;
+}
+
+fn from_ntp_timestamp(ntp_timestamp: u64) -> DateTime<Utc> {
+    let secs = (ntp_timestamp >> 32) as i64 - NTP_UNIX_EPOCH_DELTA_SECS;
+    let nanos = ((ntp_timestamp & 0xFFFF_FFFF) * 1_000_000_000) >> 32;
+    return DateTime::<Utc>::from_timestamp(secs, nanos as u32).unwrap();
+}
+
+fn read_ntp_timestamp(buf: &[u8], offset: usize) -> u64 {
+    return u64::from_be_bytes(buf[offset..offset + 8].try_into().unwrap());
+}
+
+// Queries an NTP server with SNTP, recording the round-trip delay and how far our clock is from the
+// server's.
+pub struct NtpProber {
+    dest_addr: SocketAddr,
+    socket: UdpSocket,
+    timeout: Duration,
+}
+impl NtpProber {
+    pub fn new(
+        hostname: &str,
+        dest_ip: IpAddr,
+        port: u16,
+        timeout: Duration,
+        socket_options: &SocketOptions,
+    ) -> NtpProber {
+        let dest_addr = SocketAddr::new(dest_ip, port);
+        let socket = connect_udp(hostname, &dest_addr, socket_options);
+        socket.set_write_timeout(Some(timeout)).unwrap();
+        socket.set_read_timeout(Some(timeout)).unwrap();
+        // Log important details.
+        println!("Querying NTP server {} (address: {})", hostname, dest_addr);
+        return NtpProber {
+            dest_addr,
+            socket,
+            timeout,
+        };
+    }
+}
+impl Prober for NtpProber {
+    fn probe(&mut self) -> Sample {
+        let mut request = [0; NTP_MESSAGE_SIZE];
+        request[0] = SNTP_CLIENT_FLAGS;
+        let originate_time = Utc::now();
+        let originate_timestamp = to_ntp_timestamp(originate_time);
+        // The server copies our transmit timestamp into its reply's originate timestamp.
+        request[40..48].copy_from_slice(&originate_timestamp.to_be_bytes());
+        if let Err(err) = self.socket.send(&request) {
+            eprintln!("Error while sending to {} - {:?}", self.dest_addr, err);
+            return Sample::new(ProbeResult::SendError(err.to_string()));
+        }
+        // Wait for the response, skipping any late replies to earlier requests.
+        let deadline = originate_time + chrono_Duration::from_std(self.timeout).unwrap();
+        while Utc::now() < deadline {
+            let mut reply = [0; NTP_MESSAGE_SIZE];
+            let size = match self.socket.recv(&mut reply) {
+                Ok(size) => size,
+                Err(err) => {
+                    eprintln!("Error while recving from {} - {:?}", self.dest_addr, err);
+                    return Sample::new(err.into());
+                }
+            };
+            let destination_time = Utc::now();
+            if size < NTP_MESSAGE_SIZE
+                || reply[0] & 0x7 != NTP_MODE_SERVER
+                || read_ntp_timestamp(&reply, 24) != originate_timestamp
+            {
+                continue;
+            }
+            // A stratum of 0 is a "kiss-o'-death", the server is telling us to go away.
+            if reply[1] == 0 {
+                let kiss_code = String::from_utf8_lossy(&reply[12..16]).to_string();
+                return Sample::new(ProbeResult::Failed(format!(
+                    "server refused the request ({})",
+                    kiss_code
+                )));
+            }
+            let receive_time = from_ntp_timestamp(read_ntp_timestamp(&reply, 32));
+            let transmit_time = from_ntp_timestamp(read_ntp_timestamp(&reply, 40));
+            // https://www.rfc-editor.org/rfc/rfc4330#section-5
+            let round_trip_delay =
+                (destination_time - originate_time) - (transmit_time - receive_time);
+            let clock_offset =
+                ((receive_time - originate_time) + (transmit_time - destination_time)) / 2;
+            return Sample {
+                clock_offset: Some(clock_offset),
+                ..Sample::success(round_trip_delay.to_std().unwrap_or(Duration::ZERO))
+            };
+        }
+        return Sample::new(ProbeResult::Timeout);
+    }
+}
@@ -0,0 +1,301 @@
+use super::{connect_udp, InterfaceStats, ProbeResult, Prober, Sample, SocketOptions};
+use chrono::Duration as chrono_Duration;
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::time::Duration;
+
+// BER tags used by SNMP.
+// https://www.rfc-editor.org/rfc/rfc3416#section-3
+const BER_INTEGER: u8 = 0x02;
+const BER_OCTET_STRING: u8 = 0x04;
+const BER_NULL: u8 = 0x05;
+const BER_OBJECT_IDENTIFIER: u8 = 0x06;
+const BER_SEQUENCE: u8 = 0x30;
+const SNMP_COUNTER32: u8 = 0x41;
+const SNMP_NO_SUCH_OBJECT: u8 = 0x80;
+const SNMP_NO_SUCH_INSTANCE: u8 = 0x81;
+const SNMP_GET_REQUEST: u8 = 0xA0;
+const SNMP_RESPONSE: u8 = 0xA2;
+// SNMP versions are numbered from 0, v2c is 1.
+const SNMP_VERSION_2C: i64 = 1;
+
+// Columns of IF-MIB's ifTable, the interface's index is appended to each.
+// https://www.rfc-editor.org/rfc/rfc2863#section-6
+const IF_IN_OCTETS: [u32; 10] = [1, 3, 6, 1, 2, 1, 2, 2, 1, 10];
+const IF_IN_ERRORS: [u32; 10] = [1, 3, 6, 1, 2, 1, 2, 2, 1, 14];
+const IF_OUT_OCTETS: [u32; 10] = [1, 3, 6, 1, 2, 1, 2, 2, 1, 16];
+const IF_OUT_ERRORS: [u32; 10] = [1, 3, 6, 1, 2, 1, 2, 2, 1, 20];
+
+// Wraps `content` in a BER tag and length.
+fn encode_tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut tlv = vec![tag];
+    if content.len() < 0x80 {
+        tlv.push(content.len() as u8);
+    } else {
+        // The long form: the number of length bytes, then the length.
+        let len_bytes = (content.len() as u32).to_be_bytes();
+        let first_nonzero = len_bytes.iter().position(|b| *b != 0).unwrap();
+        tlv.push(0x80 | (len_bytes.len() - first_nonzero) as u8);
+        tlv.extend_from_slice(&len_bytes[first_nonzero..]);
+    }
+    tlv.extend_from_slice(content);
+    return tlv;
+}
+
+fn encode_integer(value: i64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    // Use the fewest bytes that keep the sign bit correct.
+    let mut start = 0;
+    while start < bytes.len() - 1
+        && ((bytes[start] == 0x00 && bytes[start + 1] & 0x80 == 0)
+            || (bytes[start] == 0xFF && bytes[start + 1] & 0x80 != 0))
+    {
+        start += 1;
+    }
+    return encode_tlv(BER_INTEGER, &bytes[start..]);
+}
+
+fn encode_oid(oid: &[u32]) -> Vec<u8> {
+    // The first two components share a byte, the rest are base 128 with a continuation bit.
+    let mut content = vec![(oid[0] * 40 + oid[1]) as u8];
+    for component in &oid[2..] {
+        let mut base_128 = vec![(component & 0x7F) as u8];
+        let mut remaining = component >> 7;
+        while remaining > 0 {
+            base_128.push(0x80 | (remaining & 0x7F) as u8);
+            remaining >>= 7;
+        }
+        content.extend(base_128.iter().rev());
+    }
+    return encode_tlv(BER_OBJECT_IDENTIFIER, &content);
+}
+
+// Splits the first BER value off of `buf`, returning its tag, content and whatever follows it.
+fn read_tlv(buf: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let tag = *buf.first()?;
+    let first_len_byte = *buf.get(1)?;
+    let (len, header_size) = if first_len_byte < 0x80 {
+        (usize::from(first_len_byte), 2)
+    } else {
+        let num_len_bytes = usize::from(first_len_byte & 0x7F);
+        let len_bytes = buf.get(2..2 + num_len_bytes)?;
+        if num_len_bytes > 4 {
+            return None;
+        }
+        let len = len_bytes
+            .iter()
+            .fold(0, |len, byte| (len << 8) | usize::from(*byte));
+        (len, 2 + num_len_bytes)
+    };
+    let content = buf.get(header_size..header_size + len)?;
+    return Some((tag, content, &buf[header_size + len..]));
+}
+
+// Reads an INTEGER or Counter32's content, which are big-endian two's complement.
+fn read_integer(content: &[u8]) -> i64 {
+    let initial: i64 = if content.first().is_some_and(|b| b & 0x80 != 0) {
+        -1
+    } else {
+        0
+    };
+    return content
+        .iter()
+        .fold(initial, |value, byte| (value << 8) | i64::from(*byte));
+}
+
+// The counters we poll, as of one poll.
+struct Counters {
+    when: DateTime<Utc>,
+    in_octets: u32,
+    out_octets: u32,
+    errors: u32,
+}
+
+// Polls an interface's traffic and error counters over SNMP v2c, so link saturation can be seen
+// alongside latency. The duration of each sample is how long the agent took to respond.
+pub struct SnmpProber {
+    dest_addr: SocketAddr,
+    community: String,
+    if_index: u32,
+    socket: UdpSocket,
+    timeout: Duration,
+    // The previous poll's counters, rates are the difference between polls.
+    last_counters: Option<Counters>,
+}
+impl SnmpProber {
+    pub fn new(
+        hostname: &str,
+        dest_ip: IpAddr,
+        port: u16,
+        community: &str,
+        if_index: u32,
+        timeout: Duration,
+        socket_options: &SocketOptions,
+    ) -> SnmpProber {
+        let dest_addr = SocketAddr::new(dest_ip, port);
+        let socket = connect_udp(hostname, &dest_addr, socket_options);
+        socket.set_write_timeout(Some(timeout)).unwrap();
+        socket.set_read_timeout(Some(timeout)).unwrap();
+        // Log important details.
+        println!(
+            "Polling SNMP counters of interface {} on host {} (address: {})",
+            if_index, hostname, dest_addr
+        );
+        return SnmpProber {
+            dest_addr,
+            community: community.to_string(),
+            if_index,
+            socket,
+            timeout,
+            last_counters: None,
+        };
+    }
+
+    // The OIDs we poll, in the order their values are returned.
+    fn oids(&self) -> [Vec<u32>; 4] {
+        return [IF_IN_OCTETS, IF_OUT_OCTETS, IF_IN_ERRORS, IF_OUT_ERRORS].map(|column| {
+            let mut oid = column.to_vec();
+            oid.push(self.if_index);
+            oid
+        });
+    }
+
+    // Builds a GetRequest for all of the counters we poll.
+    fn build_request(&self, request_id: i32) -> Vec<u8> {
+        let mut var_binds = Vec::new();
+        for oid in self.oids() {
+            let mut var_bind = encode_oid(&oid);
+            var_bind.extend(encode_tlv(BER_NULL, &[]));
+            var_binds.extend(encode_tlv(BER_SEQUENCE, &var_bind));
+        }
+        let mut pdu = encode_integer(request_id.into());
+        pdu.extend(encode_integer(0)); // Error status.
+        pdu.extend(encode_integer(0)); // Error index.
+        pdu.extend(encode_tlv(BER_SEQUENCE, &var_binds));
+        let mut message = encode_integer(SNMP_VERSION_2C);
+        message.extend(encode_tlv(BER_OCTET_STRING, self.community.as_bytes()));
+        message.extend(encode_tlv(SNMP_GET_REQUEST, &pdu));
+        return encode_tlv(BER_SEQUENCE, &message);
+    }
+
+    // Parses a Response to the request with `request_id`.
+    // Returns None for anything else, or the counters' values (in the order they were requested).
+    fn parse_response(&self, buf: &[u8], request_id: i32) -> Option<Result<Vec<u32>, String>> {
+        let (BER_SEQUENCE, message, _) = read_tlv(buf)? else {
+            return None;
+        };
+        let (BER_INTEGER, _version, rest) = read_tlv(message)? else {
+            return None;
+        };
+        let (BER_OCTET_STRING, _community, rest) = read_tlv(rest)? else {
+            return None;
+        };
+        let (SNMP_RESPONSE, pdu, _) = read_tlv(rest)? else {
+            return None;
+        };
+        let (BER_INTEGER, response_id, rest) = read_tlv(pdu)? else {
+            return None;
+        };
+        if read_integer(response_id) != i64::from(request_id) {
+            return None;
+        }
+        let (BER_INTEGER, error_status, rest) = read_tlv(rest)? else {
+            return None;
+        };
+        let (BER_INTEGER, _error_index, rest) = read_tlv(rest)? else {
+            return None;
+        };
+        // https://www.rfc-editor.org/rfc/rfc3416#section-3
+        if read_integer(error_status) != 0 {
+            return Some(Err(format!(
+                "agent returned error status {}",
+                read_integer(error_status)
+            )));
+        }
+        let (BER_SEQUENCE, mut var_binds, _) = read_tlv(rest)? else {
+            return None;
+        };
+        let mut values = Vec::new();
+        while !var_binds.is_empty() {
+            let (BER_SEQUENCE, var_bind, rest) = read_tlv(var_binds)? else {
+                return None;
+            };
+            var_binds = rest;
+            let (BER_OBJECT_IDENTIFIER, _oid, rest) = read_tlv(var_bind)? else {
+                return None;
+            };
+            let value = match read_tlv(rest)? {
+                (SNMP_COUNTER32, value, _) => read_integer(value) as u32,
+                (SNMP_NO_SUCH_OBJECT | SNMP_NO_SUCH_INSTANCE, _, _) => {
+                    return Some(Err(format!("no interface with index {}", self.if_index)));
+                }
+                (tag, _, _) => {
+                    return Some(Err(format!("unexpected value type 0x{:02X}", tag)));
+                }
+            };
+            values.push(value);
+        }
+        if values.len() != self.oids().len() {
+            return None;
+        }
+        return Some(Ok(values));
+    }
+
+    // The traffic and error rates between the last poll and this one.
+    fn rates_since_last_poll(&self, counters: &Counters) -> Option<InterfaceStats> {
+        let last_counters = self.last_counters.as_ref()?;
+        let secs = (counters.when - last_counters.when).num_milliseconds() as f64 / 1000.0;
+        if secs <= 0.0 {
+            return None;
+        }
+        // Counters wrap around, `wrapping_sub` still gives the right difference when they do.
+        let bits_per_sec = |cur: u32, last: u32| f64::from(cur.wrapping_sub(last)) * 8.0 / secs;
+        return Some(InterfaceStats {
+            in_bps: bits_per_sec(counters.in_octets, last_counters.in_octets),
+            out_bps: bits_per_sec(counters.out_octets, last_counters.out_octets),
+            errors: counters.errors.wrapping_sub(last_counters.errors),
+        });
+    }
+}
+impl Prober for SnmpProber {
+    fn probe(&mut self) -> Sample {
+        let request_id = rand::thread_rng().gen_range(0..i32::MAX);
+        let start_time = Utc::now();
+        let deadline = start_time + chrono_Duration::from_std(self.timeout).unwrap();
+        if let Err(err) = self.socket.send(&self.build_request(request_id)) {
+            eprintln!("Error while sending to {} - {:?}", self.dest_addr, err);
+            return Sample::new(ProbeResult::SendError(err.to_string()));
+        }
+        // Wait for the response, skipping any late replies to earlier requests.
+        while Utc::now() < deadline {
+            let mut reply = [0; 1500];
+            let size = match self.socket.recv(&mut reply) {
+                Ok(size) => size,
+                Err(err) => {
+                    eprintln!("Error while recving from {} - {:?}", self.dest_addr, err);
+                    return Sample::new(err.into());
+                }
+            };
+            let values = match self.parse_response(&reply[..size], request_id) {
+                Some(Ok(values)) => values,
+                Some(Err(err)) => return Sample::new(ProbeResult::Failed(err)),
+                None => continue,
+            };
+            let now = Utc::now();
+            let counters = Counters {
+                when: now,
+                in_octets: values[0],
+                out_octets: values[1],
+                errors: values[2].wrapping_add(values[3]),
+            };
+            let interface_stats = self.rates_since_last_poll(&counters);
+            self.last_counters = Some(counters);
+            return Sample {
+                interface_stats,
+                ..Sample::success((now - start_time).to_std().unwrap())
+            };
+        }
+        return Sample::new(ProbeResult::Timeout);
+    }
+}
@@ -0,0 +1,90 @@
+use super::{connect_tcp, ProbeResult, Prober, Sample, SocketOptions};
+use chrono::Utc;
+use std::io::{BufRead, BufReader, Read};
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+// The most we'll read looking for the banner. Servers may send other lines before it, but the
+// banner itself is at most 255 characters.
+// https://www.rfc-editor.org/rfc/rfc4253#section-4.2
+const MAX_PRE_BANNER_BYTES: u64 = 8 * 1024;
+
+// Times how long an SSH server takes to send its version banner, and keeps track of the banner so
+// upgrades (or a different server answering) stand out.
+pub struct SshProber {
+    dest_addr: SocketAddr,
+    timeout: Duration,
+    socket_options: SocketOptions,
+    last_banner: Option<String>,
+}
+impl SshProber {
+    pub fn new(
+        hostname: &str,
+        dest_ip: IpAddr,
+        port: u16,
+        timeout: Duration,
+        socket_options: &SocketOptions,
+    ) -> SshProber {
+        let dest_addr = SocketAddr::new(dest_ip, port);
+        // Log important details.
+        println!(
+            "Timing SSH banners from host {} (address: {})",
+            hostname, dest_addr
+        );
+        return SshProber {
+            dest_addr,
+            timeout,
+            socket_options: socket_options.clone(),
+            last_banner: None,
+        };
+    }
+
+    // Connects and reads lines until the server identifies itself.
+    fn read_banner(&self) -> Result<String, ProbeResult> {
+        let tcp_stream = connect_tcp(&self.dest_addr, self.timeout, &self.socket_options)?;
+        tcp_stream.set_read_timeout(Some(self.timeout))?;
+        let mut reader = BufReader::new(tcp_stream.take(MAX_PRE_BANNER_BYTES));
+        loop {
+            let mut line = String::new();
+            let size = reader.read_line(&mut line)?;
+            if size == 0 {
+                return Err(ProbeResult::Failed(
+                    "connection closed before the SSH banner was sent".to_string(),
+                ));
+            }
+            if line.starts_with("SSH-") {
+                // We close the connection without sending a banner of our own.
+                return Ok(line.trim_end().to_string());
+            }
+        }
+    }
+}
+impl Prober for SshProber {
+    fn probe(&mut self) -> Sample {
+        let start_time = Utc::now();
+        let banner = match self.read_banner() {
+            Ok(banner) => banner,
+            Err(err) => {
+                eprintln!(
+                    "Error while reading the SSH banner from {} - {}",
+                    self.dest_addr, err
+                );
+                return Sample::new(err);
+            }
+        };
+        let duration = (Utc::now() - start_time).to_std().unwrap();
+        if let Some(last_banner) = &self.last_banner {
+            if *last_banner != banner {
+                println!(
+                    "SSH banner from {} changed from \"{}\" to \"{}\"",
+                    self.dest_addr, last_banner, banner
+                );
+            }
+        }
+        self.last_banner = Some(banner.clone());
+        return Sample {
+            status: Some(banner),
+            ..Sample::success(duration)
+        };
+    }
+}
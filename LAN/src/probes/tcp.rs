@@ -0,0 +1,46 @@
+use super::{connect_tcp, Prober, Sample, SocketOptions};
+use chrono::Utc;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+// Times TCP three-way handshakes, for hosts that drop ICMP but expose TCP services.
+pub struct TcpProber {
+    dest_addr: SocketAddr,
+    connect_timeout: Duration,
+    socket_options: SocketOptions,
+}
+impl TcpProber {
+    pub fn new(
+        hostname: &str,
+        dest_ip: IpAddr,
+        port: u16,
+        connect_timeout: Duration,
+        socket_options: &SocketOptions,
+    ) -> TcpProber {
+        let dest_addr = SocketAddr::new(dest_ip, port);
+        // Log important details.
+        println!(
+            "Timing TCP connections to host {} (address: {})",
+            hostname, dest_addr
+        );
+        return TcpProber {
+            dest_addr,
+            connect_timeout,
+            socket_options: socket_options.clone(),
+        };
+    }
+}
+impl Prober for TcpProber {
+    fn probe(&mut self) -> Sample {
+        let start_time = Utc::now();
+        // `connect_timeout` returns once the handshake completes, we close the connection right away.
+        match connect_tcp(&self.dest_addr, self.connect_timeout, &self.socket_options) {
+            Ok(_stream) => {}
+            Err(err) => {
+                eprintln!("Error while connecting to {} - {:?}", self.dest_addr, err);
+                return Sample::new(err.into());
+            }
+        }
+        return Sample::success((Utc::now() - start_time).to_std().unwrap());
+    }
+}
@@ -0,0 +1,99 @@
+use super::{connect_tcp, ProbeResult, Prober, Sample, SocketOptions};
+use chrono::{DateTime, Utc};
+use rustls::pki_types::ServerName;
+use rustls::{ClientConfig, ClientConnection, RootCertStore};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+// A client config that verifies certificates the same way a browser would, using Mozilla's root
+// certificates.
+pub fn client_config() -> Arc<ClientConfig> {
+    let root_store = RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    let tls_config =
+        ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(root_store)
+            .with_no_client_auth();
+    return Arc::new(tls_config);
+}
+
+// Times TLS handshakes and keeps track of when the server's certificate expires.
+pub struct TlsProber {
+    dest_addr: SocketAddr,
+    server_name: ServerName<'static>,
+    tls_config: Arc<ClientConfig>,
+    timeout: Duration,
+    socket_options: SocketOptions,
+}
+impl TlsProber {
+    pub fn new(
+        hostname: &str,
+        dest_ip: IpAddr,
+        port: u16,
+        timeout: Duration,
+        socket_options: &SocketOptions,
+    ) -> TlsProber {
+        let dest_addr = SocketAddr::new(dest_ip, port);
+        // Log important details.
+        println!(
+            "Timing TLS handshakes with host {} (address: {})",
+            hostname, dest_addr
+        );
+        return TlsProber {
+            dest_addr,
+            server_name: ServerName::try_from(hostname.to_string()).unwrap(),
+            tls_config: client_config(),
+            timeout,
+            socket_options: socket_options.clone(),
+        };
+    }
+
+    // Connects and completes a TLS handshake.
+    // Returns how long the handshake took, and when the server's certificate expires.
+    fn handshake(&self) -> Result<(Duration, Option<DateTime<Utc>>), ProbeResult> {
+        let mut tcp_stream = connect_tcp(&self.dest_addr, self.timeout, &self.socket_options)?;
+        tcp_stream.set_read_timeout(Some(self.timeout))?;
+        tcp_stream.set_write_timeout(Some(self.timeout))?;
+        let mut tls_connection =
+            ClientConnection::new(self.tls_config.clone(), self.server_name.clone())
+                .map_err(|err| ProbeResult::Failed(err.to_string()))?;
+        // Only time the TLS handshake, the TCP handshake is already done.
+        let start_time = Utc::now();
+        // Certificate problems are reported as `InvalidData` errors, and are failures.
+        tls_connection.complete_io(&mut tcp_stream)?;
+        let handshake_duration = (Utc::now() - start_time).to_std().unwrap();
+        // The first certificate is the server's own, the rest are intermediates.
+        let cert_not_after = tls_connection
+            .peer_certificates()
+            .and_then(|certs| certs.first())
+            .and_then(|cert| x509_parser::parse_x509_certificate(cert.as_ref()).ok())
+            .and_then(|(_, cert)| {
+                DateTime::<Utc>::from_timestamp(cert.validity().not_after.timestamp(), 0)
+            });
+        // Be polite and close the connection cleanly.
+        tls_connection.send_close_notify();
+        let _ = tls_connection.complete_io(&mut tcp_stream);
+        return Ok((handshake_duration, cert_not_after));
+    }
+}
+impl Prober for TlsProber {
+    fn probe(&mut self) -> Sample {
+        return match self.handshake() {
+            Ok((handshake_duration, cert_not_after)) => Sample {
+                cert_not_after,
+                ..Sample::success(handshake_duration)
+            },
+            Err(err) => {
+                eprintln!(
+                    "Error during TLS handshake with {} - {}",
+                    self.dest_addr, err
+                );
+                Sample::new(err)
+            }
+        };
+    }
+}
@@ -0,0 +1,120 @@
+use super::{connect_udp, ProbeResult, Prober, Sample, SocketOptions};
+use chrono::Duration as chrono_Duration;
+use chrono::Utc;
+use rand::Rng;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::{IpAddr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::time::Duration;
+
+// Marks datagrams as ours, so the responder doesn't reflect arbitrary traffic.
+const ECHO_MAGIC: &[u8; 4] = b"NMUE";
+// 4B magic + 2B ID + 4B sequence number, padded to the same 64B as our ICMP messages.
+const ECHO_MESSAGE_SIZE: usize = 64;
+
+fn build_echo_message(identifier: u16, sequence_number: u32) -> [u8; ECHO_MESSAGE_SIZE] {
+    let mut message = [0; ECHO_MESSAGE_SIZE];
+    message[0..4].copy_from_slice(ECHO_MAGIC);
+    message[4..6].copy_from_slice(&identifier.to_be_bytes());
+    message[6..10].copy_from_slice(&sequence_number.to_be_bytes());
+    return message;
+}
+
+// Times UDP round trips to a host running `run_echo_responder`, to measure UDP latency and loss
+// separately from ICMP (which some ISPs deprioritize).
+pub struct UdpProber {
+    dest_addr: SocketAddr,
+    socket: UdpSocket,
+    unique_threadlocal_id: u16,
+    sequence_number: u32,
+    timeout: Duration,
+}
+impl UdpProber {
+    pub fn new(
+        hostname: &str,
+        dest_ip: IpAddr,
+        port: u16,
+        timeout: Duration,
+        socket_options: &SocketOptions,
+    ) -> UdpProber {
+        let dest_addr = SocketAddr::new(dest_ip, port);
+        let socket = connect_udp(hostname, &dest_addr, socket_options);
+        socket.set_write_timeout(Some(timeout)).unwrap();
+        let unique_threadlocal_id: u16 = rand::thread_rng().gen::<u16>();
+        // Log important details.
+        println!(
+            "Timing UDP echoes to host {} (address: {}) using ID {}",
+            hostname, dest_addr, unique_threadlocal_id
+        );
+        return UdpProber {
+            dest_addr,
+            socket,
+            unique_threadlocal_id,
+            sequence_number: 0,
+            timeout,
+        };
+    }
+}
+impl Prober for UdpProber {
+    fn probe(&mut self) -> Sample {
+        self.sequence_number = self.sequence_number.wrapping_add(1);
+        let start_time = Utc::now();
+        let deadline = start_time + chrono_Duration::from_std(self.timeout).unwrap();
+        let request = build_echo_message(self.unique_threadlocal_id, self.sequence_number);
+        if let Err(err) = self.socket.send(&request) {
+            eprintln!("Error while sending to {} - {:?}", self.dest_addr, err);
+            return Sample::new(ProbeResult::SendError(err.to_string()));
+        }
+        // Wait for the response.
+        // Late replies to earlier probes may still arrive, so recv in a loop until ours shows up.
+        loop {
+            let remaining = deadline - Utc::now();
+            if remaining <= chrono_Duration::zero() {
+                return Sample::new(ProbeResult::Timeout);
+            }
+            self.socket
+                .set_read_timeout(Some(remaining.to_std().unwrap()))
+                .unwrap();
+            let mut recv_buf = [0; ECHO_MESSAGE_SIZE];
+            match self.socket.recv(&mut recv_buf) {
+                Ok(size) => {
+                    if size == ECHO_MESSAGE_SIZE && recv_buf == request {
+                        return Sample::success((Utc::now() - start_time).to_std().unwrap());
+                    }
+                }
+                Err(err) => {
+                    eprintln!("Error while recving from {} - {:?}", self.dest_addr, err);
+                    // E.g. an ICMP Port Unreachable when no responder is running is a refused
+                    // connection.
+                    return Sample::new(err.into());
+                }
+            }
+        }
+    }
+}
+
+// Echoes UDP probes back to whoever sent them, run this on a remote host to act as a `udp://` target.
+// Only well-formed probes are echoed, and replies are never larger than requests, so the responder
+// can't be used to amplify traffic.
+pub fn run_echo_responder(port: u16) -> ! {
+    // Listen on both IPv4 and IPv6.
+    let socket = Socket::new(Domain::IPV6, Type::DGRAM, Some(Protocol::UDP)).unwrap();
+    socket.set_only_v6(false).unwrap();
+    let bind_addr: SocketAddr = (Ipv6Addr::UNSPECIFIED, port).into();
+    socket.bind(&bind_addr.into()).unwrap();
+    let socket: UdpSocket = socket.into();
+    println!("Echoing UDP probes on port {}", port);
+    loop {
+        let mut recv_buf = [0; ECHO_MESSAGE_SIZE];
+        match socket.recv_from(&mut recv_buf) {
+            Ok((size, origin_addr)) => {
+                if size != ECHO_MESSAGE_SIZE || &recv_buf[0..4] != ECHO_MAGIC {
+                    continue;
+                }
+                if let Err(err) = socket.send_to(&recv_buf, origin_addr) {
+                    eprintln!("Error while echoing to {} - {:?}", origin_addr, err);
+                }
+            }
+            Err(err) => eprintln!("Error while recving UDP probes - {:?}", err),
+        }
+    }
+}
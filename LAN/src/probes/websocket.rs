@@ -0,0 +1,111 @@
+use super::{connect_tcp, tls, ProbeResult, Prober, Sample, SocketOptions};
+use chrono::Duration as chrono_Duration;
+use chrono::Utc;
+use std::net::{SocketAddr, TcpStream};
+use std::time::Duration;
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{Connector, Message, WebSocket};
+
+// Keeps a WebSocket connection open and times ping/pong round trips over it, for realtime services
+// where what matters is how responsive an established connection is.
+pub struct WebSocketProber {
+    url: url::Url,
+    hostname: String,
+    connection: Option<WebSocket<MaybeTlsStream<TcpStream>>>,
+    // Each ping carries a unique payload, so a late pong can't be mistaken for the current one.
+    next_ping_id: u64,
+    timeout: Duration,
+    socket_options: SocketOptions,
+}
+impl WebSocketProber {
+    pub fn new(
+        hostname: &str,
+        url: &str,
+        timeout: Duration,
+        socket_options: &SocketOptions,
+    ) -> WebSocketProber {
+        // Log important details.
+        println!("Timing WebSocket ping/pong round trips with {}", url);
+        return WebSocketProber {
+            url: url::Url::parse(url).unwrap(),
+            hostname: hostname.to_string(),
+            connection: None,
+            next_ping_id: 0,
+            timeout,
+            socket_options: socket_options.clone(),
+        };
+    }
+
+    // Opens the WebSocket connection, resolving the hostname each time the same way a browser would.
+    fn connect(&self) -> Result<WebSocket<MaybeTlsStream<TcpStream>>, ProbeResult> {
+        let dest_addr = SocketAddr::new(
+            super::resolve(&self.hostname).map_err(ProbeResult::DnsError)?,
+            self.url.port_or_known_default().unwrap(),
+        );
+        let tcp_stream = connect_tcp(&dest_addr, self.timeout, &self.socket_options)?;
+        tcp_stream.set_read_timeout(Some(self.timeout))?;
+        tcp_stream.set_write_timeout(Some(self.timeout))?;
+        let (connection, _response) = tungstenite::client_tls_with_config(
+            self.url.as_str(),
+            tcp_stream,
+            None,
+            Some(Connector::Rustls(tls::client_config())),
+        )
+        .map_err(|err| match err {
+            tungstenite::HandshakeError::Failure(err) => error_result(err),
+            // The stream blocks, so the handshake can't be interrupted.
+            tungstenite::HandshakeError::Interrupted(_) => unreachable!(),
+        })?;
+        println!("Opened a WebSocket connection to {}", self.url);
+        return Ok(connection);
+    }
+
+    // Sends a ping and waits for the matching pong.
+    fn ping(&mut self) -> Result<Duration, ProbeResult> {
+        if self.connection.is_none() {
+            self.connection = Some(self.connect()?);
+        }
+        let connection = self.connection.as_mut().unwrap();
+        let payload = self.next_ping_id.to_be_bytes().to_vec();
+        self.next_ping_id += 1;
+        let start_time = Utc::now();
+        let deadline = start_time + chrono_Duration::from_std(self.timeout).unwrap();
+        connection
+            .send(Message::Ping(payload.clone()))
+            .map_err(error_result)?;
+        while Utc::now() < deadline {
+            // Reading also answers the server's pings and close requests for us.
+            match connection.read().map_err(error_result)? {
+                Message::Pong(pong_payload) if pong_payload == payload => {
+                    return Ok((Utc::now() - start_time).to_std().unwrap());
+                }
+                // Anything else is the service's own traffic, or a late pong.
+                _ => continue,
+            }
+        }
+        return Err(ProbeResult::Timeout);
+    }
+}
+
+// Sorts `tungstenite`'s errors into the kind of failure they mean, by their underlying socket error
+// if there is one.
+fn error_result(err: tungstenite::Error) -> ProbeResult {
+    return match err {
+        tungstenite::Error::Io(io_err) => io_err.into(),
+        err => ProbeResult::Failed(err.to_string()),
+    };
+}
+
+impl Prober for WebSocketProber {
+    fn probe(&mut self) -> Sample {
+        return match self.ping() {
+            Ok(round_trip) => Sample::success(round_trip),
+            Err(err) => {
+                eprintln!("Error while pinging {} - {}", self.url, err);
+                // Start over with a fresh connection next time, the old one may be in any state.
+                self.connection = None;
+                Sample::new(err)
+            }
+        };
+    }
+}
@@ -0,0 +1,159 @@
+use crate::config;
+use crate::PingData;
+use chrono::Utc;
+use rand::Rng;
+use std::io::Read;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// https://www.rfc-editor.org/rfc/rfc8489#section-5
+const STUN_HEADER_SIZE: usize = 20;
+const STUN_MAGIC_COOKIE: u32 = 0x2112A442;
+const STUN_BINDING_REQUEST: u16 = 0x0001;
+const STUN_BINDING_SUCCESS: u16 = 0x0101;
+// https://www.rfc-editor.org/rfc/rfc8489#section-18.3
+const STUN_ATTR_MAPPED_ADDRESS: u16 = 0x0001;
+const STUN_ATTR_XOR_MAPPED_ADDRESS: u16 = 0x0020;
+const STUN_DEFAULT_PORT: u16 = 3478;
+
+// Periodically looks up our public IP address with `source`, recording whenever it changes.
+// `source` is either an `http://...` or `https://...` URL that responds with the caller's address
+// as plain text, or `stun:host[:port]` for a STUN server.
+pub fn repeatedly_discover(source: String, ping_data: Arc<Mutex<PingData>>) {
+    // Log important details.
+    println!("Watching for public IP changes with {}", source);
+    loop {
+        match discover(&source) {
+            Ok(public_ip) => ping_data
+                .lock()
+                .unwrap()
+                .add_public_ip(Utc::now(), public_ip),
+            Err(err) => eprintln!("Failed to look up our public IP with {} - {}", source, err),
+        }
+        thread::sleep(Duration::from_secs(
+            config::get().public_ip_check_interval_sec,
+        ));
+    }
+}
+
+// Looks up our public IP address with `source`, see `repeatedly_discover`.
+fn discover(source: &str) -> Result<IpAddr, String> {
+    return match source.strip_prefix("stun:") {
+        Some(server) => discover_via_stun(server),
+        None => discover_via_http(source),
+    };
+}
+
+// Asks an HTTP echo service (e.g. https://api.ipify.org) what address our request came from.
+fn discover_via_http(url: &str) -> Result<IpAddr, String> {
+    let response = ureq::get(url)
+        .timeout(Duration::from_millis(config::get().public_ip_timeout_msec))
+        .call()
+        .map_err(|err| err.to_string())?;
+    let mut body = String::new();
+    response
+        .into_reader()
+        .take(1024)
+        .read_to_string(&mut body)
+        .map_err(|err| err.to_string())?;
+    return body
+        .trim()
+        .parse()
+        .map_err(|_err| format!("Unexpected response \"{}\"", body.trim()));
+}
+
+// Sends a STUN Binding request to `server` (`host[:port]`), which replies with the address and
+// port our request came from after any NAT.
+// https://www.rfc-editor.org/rfc/rfc8489
+fn discover_via_stun(server: &str) -> Result<IpAddr, String> {
+    let server_addr = match server.to_socket_addrs() {
+        Ok(mut addrs) => addrs.next(),
+        // No port, use the default.
+        Err(_err) => (server, STUN_DEFAULT_PORT)
+            .to_socket_addrs()
+            .map_err(|err| format!("Failed to resolve {} - {}", server, err))?
+            .next(),
+    }
+    .ok_or(format!("Failed to resolve {}", server))?;
+    let bind_addr: SocketAddr = match server_addr {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let socket = UdpSocket::bind(bind_addr).map_err(|err| err.to_string())?;
+    socket
+        .set_read_timeout(Some(Duration::from_millis(
+            config::get().public_ip_timeout_msec,
+        )))
+        .map_err(|err| err.to_string())?;
+    socket.connect(server_addr).map_err(|err| err.to_string())?;
+
+    let transaction_id: [u8; 12] = rand::thread_rng().gen();
+    let mut request = [0; STUN_HEADER_SIZE];
+    request[0..2].copy_from_slice(&STUN_BINDING_REQUEST.to_be_bytes());
+    // The message length (bytes 2..4) is 0, the request has no attributes.
+    request[4..8].copy_from_slice(&STUN_MAGIC_COOKIE.to_be_bytes());
+    request[8..20].copy_from_slice(&transaction_id);
+    socket.send(&request).map_err(|err| err.to_string())?;
+
+    // Replies to earlier requests that arrived late don't say anything new, skip them.
+    loop {
+        let mut response = [0; 512];
+        let size = socket.recv(&mut response).map_err(|err| err.to_string())?;
+        let response = &response[..size];
+        if response.len() < STUN_HEADER_SIZE || response[8..20] != transaction_id {
+            continue;
+        }
+        if response[0..2] != STUN_BINDING_SUCCESS.to_be_bytes() {
+            return Err(format!(
+                "Unexpected STUN response type {:#06x}",
+                u16::from_be_bytes([response[0], response[1]])
+            ));
+        }
+        return parse_mapped_address(&response[STUN_HEADER_SIZE..], &transaction_id)
+            .ok_or("The STUN response didn't include our address".to_string());
+    }
+}
+
+// Reads our address from a STUN Binding response's attributes, preferring XOR-MAPPED-ADDRESS,
+// which NATs that rewrite addresses in packets can't mangle.
+fn parse_mapped_address(mut attributes: &[u8], transaction_id: &[u8; 12]) -> Option<IpAddr> {
+    let mut mapped_address = None;
+    while attributes.len() >= 4 {
+        let attr_type = u16::from_be_bytes([attributes[0], attributes[1]]);
+        let attr_len = u16::from_be_bytes([attributes[2], attributes[3]]) as usize;
+        let value = attributes.get(4..4 + attr_len)?;
+        // The value is followed by a family byte, port and address.
+        // https://www.rfc-editor.org/rfc/rfc8489#section-14.1
+        let address = match (value.get(1), value.get(4..)) {
+            (Some(1), Some(address)) if address.len() == 4 => Some(address.to_vec()),
+            (Some(2), Some(address)) if address.len() == 16 => Some(address.to_vec()),
+            _ => None,
+        };
+        match (attr_type, address) {
+            (STUN_ATTR_XOR_MAPPED_ADDRESS, Some(mut address)) => {
+                // The address is XORed with the magic cookie, then the transaction ID for IPv6.
+                let mask = [STUN_MAGIC_COOKIE.to_be_bytes().as_slice(), transaction_id].concat();
+                for (byte, mask_byte) in address.iter_mut().zip(mask) {
+                    *byte ^= mask_byte;
+                }
+                return Some(to_ip(&address));
+            }
+            (STUN_ATTR_MAPPED_ADDRESS, Some(address)) => mapped_address = Some(to_ip(&address)),
+            _ => {}
+        }
+        // Attributes are padded to a multiple of 4 bytes.
+        let padded_len = attr_len.next_multiple_of(4);
+        attributes = attributes.get(4 + padded_len..).unwrap_or_default();
+    }
+    return mapped_address;
+}
+
+// An IPv4 or IPv6 address from its 4 or 16 bytes.
+fn to_ip(octets: &[u8]) -> IpAddr {
+    return match octets.len() {
+        4 => IpAddr::from(<[u8; 4]>::try_from(octets).unwrap()),
+        _ => IpAddr::from(<[u8; 16]>::try_from(octets).unwrap()),
+    };
+}
@@ -0,0 +1,211 @@
+use crate::config;
+use crate::json;
+use crate::probes;
+use chrono::Duration as chrono_Duration;
+use chrono::{DateTime, DurationRound, Utc};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+// Long-term history, RRD-style: samples that age out of a target's raw history are summarized by the
+// minute, and those summaries by the hour as they age out in turn. Memory stays bounded however long
+// the monitor runs, e.g. a year of hourly summaries is under 9,000 per target.
+#[derive(Default)]
+pub struct Rollups {
+    // By the start of the minute or hour they summarize.
+    pub minutes: BTreeMap<DateTime<Utc>, Aggregate>,
+    pub hours: BTreeMap<DateTime<Utc>, Aggregate>,
+}
+impl Rollups {
+    // Summarizes a sample that aged out of raw history, and ages out older summaries.
+    pub fn add(&mut self, when: DateTime<Utc>, sample: &probes::Sample) {
+        let minute = when.duration_trunc(chrono_Duration::minutes(1)).unwrap();
+        self.minutes.entry(minute).or_default().add(sample);
+        self.age_out(when);
+    }
+
+    // Moves minutes older than they're kept into hours, and drops hours older than they're kept, as
+    // of `now`.
+    pub fn age_out(&mut self, now: DateTime<Utc>) {
+        let config = config::get();
+        let oldest_minute = now - chrono_Duration::from_std(config.minute_rollup_max_age).unwrap();
+        while let Some(entry) = self.minutes.first_entry() {
+            if *entry.key() >= oldest_minute {
+                break;
+            }
+            let (minute, aggregate) = entry.remove_entry();
+            let hour = minute.duration_trunc(chrono_Duration::hours(1)).unwrap();
+            self.hours.entry(hour).or_default().merge(&aggregate);
+        }
+        let oldest_hour = now - chrono_Duration::from_std(config.hour_rollup_max_age).unwrap();
+        while self
+            .hours
+            .first_key_value()
+            .is_some_and(|(hour, _)| *hour < oldest_hour)
+        {
+            self.hours.pop_first();
+        }
+    }
+}
+
+// A summary of the samples taken over a minute or an hour.
+#[derive(Clone, Debug, Default)]
+pub struct Aggregate {
+    pub samples: u32,
+    pub failures: u32,
+    // The round trips of the successful samples.
+    pub fastest: Option<Duration>,
+    pub slowest: Option<Duration>,
+    pub total_round_trip: Duration,
+    // The throughput of bandwidth tests, in bits per second.
+    pub total_throughput_bps: f64,
+}
+impl Aggregate {
+    fn add(&mut self, sample: &probes::Sample) {
+        self.samples += 1;
+        if sample.result.is_failure() {
+            self.failures += 1;
+        }
+        if let Some(round_trip) = sample.result.round_trip() {
+            self.fastest = self.fastest.into_iter().chain(Some(round_trip)).min();
+            self.slowest = self.slowest.into_iter().chain(Some(round_trip)).max();
+            self.total_round_trip += round_trip;
+        }
+        self.total_throughput_bps += sample.throughput_bps.unwrap_or(0.0);
+    }
+
+    fn merge(&mut self, other: &Aggregate) {
+        self.samples += other.samples;
+        self.failures += other.failures;
+        self.fastest = self.fastest.into_iter().chain(other.fastest).min();
+        self.slowest = self.slowest.into_iter().chain(other.slowest).max();
+        self.total_round_trip += other.total_round_trip;
+        self.total_throughput_bps += other.total_throughput_bps;
+    }
+
+    // The average round trip of the successful samples, None if none succeeded.
+    pub fn mean_round_trip(&self) -> Option<Duration> {
+        let successes = self.samples - self.failures;
+        if successes == 0 {
+            return None;
+        }
+        return Some(self.total_round_trip / successes);
+    }
+
+    pub fn mean_throughput_bps(&self) -> f64 {
+        return self.total_throughput_bps / self.samples.max(1) as f64;
+    }
+
+    // The fraction of samples that failed, from 0 to 1.
+    pub fn loss(&self) -> f64 {
+        return self.failures as f64 / self.samples.max(1) as f64;
+    }
+}
+
+// Probes sent and lost to a target, by the minute and by the hour, counted as each sample is taken.
+// Unlike `Rollups`, which only summarizes samples once they age out, these cover recent samples too,
+// so loss over any stretch can be charted without going through every sample, and without timeouts
+// having to be picked out of the latencies.
+#[derive(Default)]
+pub struct LossCounters {
+    // By the start of the minute or hour they count.
+    pub minutes: BTreeMap<DateTime<Utc>, LossCount>,
+    pub hours: BTreeMap<DateTime<Utc>, LossCount>,
+    // Every probe counted since the monitor started (including history loaded at startup), which
+    // never ages out.
+    pub total: LossCount,
+}
+impl LossCounters {
+    // Counts a sample as it's taken, and drops counts older than the rollups are kept.
+    pub fn add(&mut self, when: DateTime<Utc>, sample: &probes::Sample) {
+        // Samples from a burst count each of its probes.
+        let (sent, lost) = match &sample.burst {
+            Some(burst) => (burst.sent, burst.lost),
+            None => (1, sample.result.is_failure() as u32),
+        };
+        for (counts, unit) in [
+            (&mut self.minutes, chrono_Duration::minutes(1)),
+            (&mut self.hours, chrono_Duration::hours(1)),
+        ] {
+            let count = counts
+                .entry(when.duration_trunc(unit).unwrap())
+                .or_default();
+            count.sent += sent as u64;
+            count.lost += lost as u64;
+        }
+        self.total.sent += sent as u64;
+        self.total.lost += lost as u64;
+        self.age_out(when);
+    }
+
+    // The counts in buckets of `width` (a minute, or a whole number of minutes or hours), by the start
+    // of each bucket.
+    pub fn by(&self, width: chrono_Duration) -> BTreeMap<DateTime<Utc>, LossCount> {
+        let counts = if width < chrono_Duration::hours(1) {
+            &self.minutes
+        } else {
+            &self.hours
+        };
+        let mut buckets: BTreeMap<DateTime<Utc>, LossCount> = BTreeMap::new();
+        for (start, count) in counts {
+            let bucket = buckets
+                .entry(start.duration_trunc(width).unwrap())
+                .or_default();
+            bucket.sent += count.sent;
+            bucket.lost += count.lost;
+        }
+        return buckets;
+    }
+
+    // Drops counts older than the rollups are kept, as of `now`.
+    pub fn age_out(&mut self, now: DateTime<Utc>) {
+        let config = config::get();
+        let oldest_minute = now - chrono_Duration::from_std(config.minute_rollup_max_age).unwrap();
+        let oldest_hour = now - chrono_Duration::from_std(config.hour_rollup_max_age).unwrap();
+        for (counts, oldest) in [
+            (&mut self.minutes, oldest_minute),
+            (&mut self.hours, oldest_hour),
+        ] {
+            while counts
+                .first_key_value()
+                .is_some_and(|(start, _)| *start < oldest)
+            {
+                counts.pop_first();
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, JsonSchema)]
+pub struct LossCount {
+    pub sent: u64,
+    pub lost: u64,
+}
+impl LossCount {
+    // The fraction of probes that were lost, from 0 to 1.
+    pub fn loss(&self) -> f64 {
+        return self.lost as f64 / self.sent.max(1) as f64;
+    }
+}
+
+// A bucket of `LossCounters::by`, as `/api/loss` returns it.
+#[derive(Serialize, JsonSchema)]
+#[schemars(rename = "LossCount")]
+pub struct LossBucket {
+    #[serde(serialize_with = "json::seconds")]
+    start: DateTime<Utc>,
+    #[serde(flatten)]
+    count: LossCount,
+    #[schemars(description = "The fraction lost, from 0 to 1.")]
+    loss: f64,
+}
+impl LossBucket {
+    pub fn new(start: DateTime<Utc>, count: LossCount) -> LossBucket {
+        return LossBucket {
+            start,
+            count,
+            loss: count.loss(),
+        };
+    }
+}
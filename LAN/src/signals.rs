@@ -0,0 +1,35 @@
+use std::io::Read;
+use std::os::unix::io::IntoRawFd;
+use std::os::unix::net::UnixStream;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::thread;
+
+// Where the SIGHUP handler writes to wake up the thread waiting for it.
+static SIGHUP_WRITE_FD: AtomicI32 = AtomicI32::new(-1);
+
+extern "C" fn handle_sighup(_signal: libc::c_int) {
+    // Only async-signal-safe functions can be called here, so just wake up the waiting thread.
+    let fd = SIGHUP_WRITE_FD.load(Ordering::SeqCst);
+    unsafe {
+        libc::write(fd, [0_u8].as_ptr() as *const libc::c_void, 1);
+    }
+}
+
+// Calls `on_sighup` from a thread of its own every time the process gets SIGHUP, the usual signal
+// for a daemon to reload its config (e.g. `kill -HUP` or `systemctl reload`).
+pub fn on_sighup(mut on_sighup: impl FnMut() + Send + 'static) {
+    let (mut reader, writer) = UnixStream::pair().unwrap();
+    SIGHUP_WRITE_FD.store(writer.into_raw_fd(), Ordering::SeqCst);
+    unsafe {
+        libc::signal(
+            libc::SIGHUP,
+            handle_sighup as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+    }
+    thread::spawn(move || {
+        let mut signal = [0_u8; 1];
+        while let Ok(1) = reader.read(&mut signal) {
+            on_sighup();
+        }
+    });
+}
@@ -0,0 +1,822 @@
+use crate::json::{self, Json};
+use crate::parquet;
+use crate::probes;
+use crate::rollups;
+use crate::storage::{
+    get_duration, get_ip, get_number, get_string, get_time, get_whole_number, nanos,
+    sample_from_json, TargetSample, TimedSample,
+};
+use crate::traceroute;
+use crate::{ConnectivityLoss, Outage, PingData, QuietPeriod};
+use actix_web::body::{BodySize, MessageBody};
+use actix_web::web::Bytes;
+use chrono::{DateTime, NaiveDate, Utc};
+use schemars::JsonSchema;
+use serde::{Serialize, Serializer};
+use std::collections::{btree_map, BTreeMap};
+use std::convert::Infallible;
+use std::io::{BufRead, BufReader, Read};
+use std::net::IpAddr;
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+// A snapshot is everything the monitor remembers as one JSON document, to carry history over to
+// another host or a reinstall. Format versions change when fields change meaning, not when they're
+// added.
+const SNAPSHOT_VERSION: u64 = 1;
+
+// How many samples to format each time the lock is taken while streaming.
+const STREAM_BATCH_SIZE: usize = 1000;
+
+// What importing a snapshot added.
+#[derive(Default)]
+pub struct ImportSummary {
+    pub num_samples: usize,
+    pub num_outages: usize,
+    // Targets in the snapshot that aren't monitored here, whose history was left out.
+    pub skipped_targets: Vec<String>,
+}
+
+// Everything `ping_data` has recorded.
+pub fn export(ping_data: &PingData) -> Export {
+    let mut targets = Vec::new();
+    for hostname in &ping_data.hostnames_in_order {
+        let samples = ping_data.data.get(hostname).into_iter().flatten();
+        let rollups = ping_data.rollups.get(hostname);
+        let outages = ping_data.outages.get(hostname).into_iter().flatten();
+        let quiet_periods = ping_data.quiet_periods.get(hostname).into_iter().flatten();
+        let history = TargetHistory {
+            samples: samples
+                .map(|(when, sample)| TimedSample::new(*when, sample))
+                .collect(),
+            minutes: rollups.map(|rollups| summaries(&rollups.minutes)),
+            hours: rollups.map(|rollups| summaries(&rollups.hours)),
+            outages: outages
+                .map(|(started, outage)| ExportedOutage::new(*started, outage))
+                .collect(),
+            quiet_periods: quiet_periods
+                .map(|(started, quiet_period)| ExportedQuietPeriod {
+                    period: Period {
+                        started: *started,
+                        ended: quiet_period.ended,
+                    },
+                    reason: quiet_period.reason,
+                })
+                .collect(),
+            paused: ping_data.is_paused(hostname),
+        };
+        targets.push((hostname.clone(), history));
+    }
+    return Export {
+        version: SNAPSHOT_VERSION,
+        exported_at: Utc::now(),
+        targets,
+        connectivity_losses: ping_data
+            .connectivity_losses
+            .iter()
+            .map(|(started, loss)| Period {
+                started: *started,
+                ended: loss.ended,
+            })
+            .collect(),
+        public_ips: ping_data
+            .public_ips
+            .iter()
+            .map(|(when, ip)| PublicIp {
+                time: *when,
+                ip: *ip,
+            })
+            .collect(),
+    };
+}
+
+// A snapshot as `export` writes it, for `GET /api/snapshot` and backups.
+#[derive(Serialize, JsonSchema)]
+#[schemars(
+    rename = "Snapshot",
+    description = "Everything a monitor has recorded. Only exchanged between monitors, its \
+                   details may change between versions."
+)]
+pub struct Export {
+    version: u64,
+    exported_at: DateTime<Utc>,
+    // By name, in the order the targets are shown.
+    #[serde(serialize_with = "in_order")]
+    #[schemars(
+        with = "BTreeMap<String, TargetHistory>",
+        description = "Each target's samples, summaries, outages and quiet periods, by name."
+    )]
+    targets: Vec<(String, TargetHistory)>,
+    connectivity_losses: Vec<Period>,
+    public_ips: Vec<PublicIp>,
+}
+
+fn in_order<S: Serializer>(
+    targets: &[(String, TargetHistory)],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    return serializer.collect_map(targets.iter().map(|(name, history)| (name, history)));
+}
+
+// One target's history. Targets that were just added have no summaries yet.
+#[derive(Serialize, JsonSchema)]
+#[schemars(inline)]
+struct TargetHistory {
+    samples: Vec<TimedSample>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    minutes: Option<Vec<Summary>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hours: Option<Vec<Summary>>,
+    outages: Vec<ExportedOutage>,
+    quiet_periods: Vec<ExportedQuietPeriod>,
+    paused: bool,
+}
+
+// When something started, and ended if it has.
+#[derive(Serialize, JsonSchema)]
+#[schemars(inline)]
+struct Period {
+    started: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ended: Option<DateTime<Utc>>,
+}
+
+// An outage, with the path to the target as it went down and the packets captured, or why they
+// couldn't be.
+#[derive(Serialize, JsonSchema)]
+#[schemars(inline)]
+struct ExportedOutage {
+    #[serde(flatten)]
+    period: Period,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trace: Option<ExportedTrace>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trace_error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    capture: Option<Capture>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    capture_error: Option<String>,
+}
+impl ExportedOutage {
+    fn new(started: DateTime<Utc>, outage: &Outage) -> ExportedOutage {
+        let (trace, trace_error) = match &outage.trace {
+            Some(Ok(trace)) => (Some(ExportedTrace::new(trace)), None),
+            Some(Err(err)) => (None, Some(err.clone())),
+            None => (None, None),
+        };
+        let (capture, capture_error) = match &outage.capture {
+            Some(Ok((path, packets))) => (
+                Some(Capture {
+                    path: path.display().to_string(),
+                    packets: *packets,
+                }),
+                None,
+            ),
+            Some(Err(err)) => (None, Some(err.clone())),
+            None => (None, None),
+        };
+        return ExportedOutage {
+            period: Period {
+                started,
+                ended: outage.ended,
+            },
+            trace,
+            trace_error,
+            capture,
+            capture_error,
+        };
+    }
+}
+
+#[derive(Serialize, JsonSchema)]
+#[schemars(inline)]
+struct Capture {
+    path: String,
+    packets: usize,
+}
+
+#[derive(Serialize, JsonSchema)]
+#[schemars(inline)]
+struct ExportedTrace {
+    dest_ip: IpAddr,
+    hops: Vec<ExportedHop>,
+    reached_dest: bool,
+}
+impl ExportedTrace {
+    fn new(trace: &traceroute::Trace) -> ExportedTrace {
+        let hops = trace
+            .hops
+            .iter()
+            .map(|hop| ExportedHop {
+                ttl: hop.ttl,
+                responder: hop.responder,
+                responder_hostname: hop.responder_hostname.clone(),
+                rtt_ns: hop.rtt.map(nanos),
+            })
+            .collect();
+        return ExportedTrace {
+            dest_ip: trace.dest_ip,
+            hops,
+            reached_dest: trace.reached_dest,
+        };
+    }
+}
+
+#[derive(Serialize, JsonSchema)]
+#[schemars(inline)]
+struct ExportedHop {
+    ttl: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    responder: Option<IpAddr>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    responder_hostname: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rtt_ns: Option<u64>,
+}
+
+#[derive(Serialize, JsonSchema)]
+#[schemars(inline)]
+struct ExportedQuietPeriod {
+    #[serde(flatten)]
+    period: Period,
+    reason: &'static str,
+}
+
+// A minute or hour summary, see `rollups::Aggregate`.
+#[derive(Serialize, JsonSchema)]
+#[schemars(inline)]
+struct Summary {
+    start: DateTime<Utc>,
+    samples: u32,
+    failures: u32,
+    total_round_trip_ns: u64,
+    total_throughput_bps: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fastest_ns: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    slowest_ns: Option<u64>,
+}
+
+fn summaries(aggregates: &BTreeMap<DateTime<Utc>, rollups::Aggregate>) -> Vec<Summary> {
+    return aggregates
+        .iter()
+        .map(|(start, aggregate)| Summary {
+            start: *start,
+            samples: aggregate.samples,
+            failures: aggregate.failures,
+            total_round_trip_ns: nanos(aggregate.total_round_trip),
+            total_throughput_bps: aggregate.total_throughput_bps,
+            fastest_ns: aggregate.fastest.map(nanos),
+            slowest_ns: aggregate.slowest.map(nanos),
+        })
+        .collect();
+}
+
+#[derive(Serialize, JsonSchema)]
+#[schemars(inline)]
+struct PublicIp {
+    time: DateTime<Utc>,
+    ip: IpAddr,
+}
+
+// A snapshot written by `export` that's been read, ready to `apply`.
+pub struct Snapshot {
+    targets: Vec<(String, ImportedTarget)>,
+    connectivity_losses: Vec<(DateTime<Utc>, Option<DateTime<Utc>>)>,
+    public_ips: Vec<(DateTime<Utc>, IpAddr)>,
+}
+
+// Adds the history in a snapshot written by `export` to `ping_data`, see `apply`.
+// Nothing is imported if the snapshot can't be read.
+pub fn import(ping_data: &mut PingData, json: &Json) -> Result<ImportSummary, String> {
+    let snapshot = read(json)?;
+    return Ok(apply(ping_data, snapshot));
+}
+
+// Reads everything in a snapshot written by `export`, or says what's wrong with it. Anything still
+// ongoing when the snapshot was taken is ended then, since the monitor that was tracking it stopped.
+pub fn read(json: &Json) -> Result<Snapshot, String> {
+    match get_whole_number(json, "version")? {
+        Some(SNAPSHOT_VERSION) => {}
+        Some(version) => return Err(format!("Unsupported snapshot version {}", version)),
+        None => return Err("version is missing".to_string()),
+    }
+    let exported_at = get_time(json, "exported_at")?.ok_or("exported_at is missing")?;
+    let Some(Json::Object(targets)) = json.get("targets") else {
+        return Err("targets must be an object".to_string());
+    };
+
+    let mut imported_targets = Vec::new();
+    for (hostname, target) in targets {
+        let target = import_target(target, exported_at)
+            .map_err(|err| format!("In the history of {} - {}", hostname, err))?;
+        imported_targets.push((hostname.clone(), target));
+    }
+    let connectivity_losses = array(json, "connectivity_losses")?
+        .iter()
+        .map(|loss| import_period(loss, exported_at))
+        .collect::<Result<Vec<_>, String>>()
+        .map_err(|err| format!("In connectivity_losses - {}", err))?;
+    let public_ips = array(json, "public_ips")?
+        .iter()
+        .map(|public_ip| {
+            let when = get_time(public_ip, "time")?.ok_or("time is missing")?;
+            let ip = get_ip(public_ip, "ip")?.ok_or("ip is missing")?;
+            return Ok((when, ip));
+        })
+        .collect::<Result<Vec<_>, String>>()
+        .map_err(|err| format!("In public_ips - {}", err))?;
+    return Ok(Snapshot {
+        targets: imported_targets,
+        connectivity_losses,
+        public_ips,
+    });
+}
+
+// Adds a snapshot's history to `ping_data`, alongside what's already there. Only targets that are
+// monitored are imported, and their retention applies as samples are added.
+pub fn apply(ping_data: &mut PingData, snapshot: Snapshot) -> ImportSummary {
+    let mut summary = ImportSummary::default();
+    let Snapshot {
+        targets,
+        connectivity_losses,
+        public_ips,
+    } = snapshot;
+    for (hostname, target) in targets {
+        if !ping_data.hostnames_in_order.contains(&hostname) {
+            summary.skipped_targets.push(hostname);
+            continue;
+        }
+        // Importing the same snapshot twice doesn't count anything twice.
+        for (when, sample) in target.samples {
+            if !ping_data.data[&hostname].contains_key(&when) {
+                ping_data.add_entry(&hostname, when, sample);
+                summary.num_samples += 1;
+            }
+        }
+        let rollups = ping_data.rollups.entry(hostname.clone()).or_default();
+        for (minute, aggregate) in target.minutes {
+            rollups.minutes.entry(minute).or_insert(aggregate);
+        }
+        for (hour, aggregate) in target.hours {
+            rollups.hours.entry(hour).or_insert(aggregate);
+        }
+        let outages = ping_data.outages.entry(hostname.clone()).or_default();
+        for (started, outage) in target.outages {
+            if let btree_map::Entry::Vacant(entry) = outages.entry(started) {
+                entry.insert(outage);
+                summary.num_outages += 1;
+            }
+        }
+        let quiet_periods = ping_data.quiet_periods.entry(hostname.clone()).or_default();
+        for (started, quiet_period) in target.quiet_periods {
+            quiet_periods.entry(started).or_insert(quiet_period);
+        }
+        if target.paused {
+            ping_data.paused.insert(hostname.clone());
+        }
+    }
+    for (started, ended) in connectivity_losses {
+        ping_data
+            .connectivity_losses
+            .entry(started)
+            .or_insert(ConnectivityLoss { ended });
+    }
+    for (when, ip) in public_ips {
+        ping_data.public_ips.entry(when).or_insert(ip);
+    }
+    return summary;
+}
+
+// One target's history from a snapshot.
+struct ImportedTarget {
+    samples: Vec<(DateTime<Utc>, probes::Sample)>,
+    minutes: Vec<(DateTime<Utc>, rollups::Aggregate)>,
+    hours: Vec<(DateTime<Utc>, rollups::Aggregate)>,
+    outages: Vec<(DateTime<Utc>, Outage)>,
+    quiet_periods: Vec<(DateTime<Utc>, QuietPeriod)>,
+    paused: bool,
+}
+
+fn import_target(json: &Json, exported_at: DateTime<Utc>) -> Result<ImportedTarget, String> {
+    let samples = array(json, "samples")?
+        .iter()
+        .map(sample_from_json)
+        .collect::<Result<_, String>>()?;
+    let minutes = aggregates_from_json(json, "minutes")?;
+    let hours = aggregates_from_json(json, "hours")?;
+    let outages = array(json, "outages")?
+        .iter()
+        .map(|outage| {
+            let (started, ended) = import_period(outage, exported_at)?;
+            let trace = match (
+                json_field(outage, "trace"),
+                get_string(outage, "trace_error")?,
+            ) {
+                (Some(trace), _) => Some(Ok(trace_from_json(trace)?)),
+                (None, Some(err)) => Some(Err(err)),
+                (None, None) => None,
+            };
+            let capture = match (
+                json_field(outage, "capture"),
+                get_string(outage, "capture_error")?,
+            ) {
+                (Some(capture), _) => Some(Ok((
+                    PathBuf::from(get_string(capture, "path")?.ok_or("path is missing")?),
+                    get_whole_number(capture, "packets")?.unwrap_or_default() as usize,
+                ))),
+                (None, Some(err)) => Some(Err(err)),
+                (None, None) => None,
+            };
+            return Ok((
+                started,
+                Outage {
+                    ended,
+                    trace,
+                    capture,
+                    // Another monitor alerted about it, if anything did.
+                    alerted: false,
+                },
+            ));
+        })
+        .collect::<Result<_, String>>()?;
+    let quiet_periods = array(json, "quiet_periods")?
+        .iter()
+        .map(|quiet_period| {
+            let (started, ended) = import_period(quiet_period, exported_at)?;
+            // Reasons are kept as static strings, anything that isn't a pause was quiet hours.
+            let reason = match get_string(quiet_period, "reason")?.as_deref() {
+                Some("paused") => "paused",
+                _ => "quiet hours",
+            };
+            return Ok((started, QuietPeriod { ended, reason }));
+        })
+        .collect::<Result<_, String>>()?;
+    let paused = match json.get("paused") {
+        Some(Json::Bool(paused)) => *paused,
+        Some(_) => return Err("paused must be true or false".to_string()),
+        None => false,
+    };
+    return Ok(ImportedTarget {
+        samples,
+        minutes,
+        hours,
+        outages,
+        quiet_periods,
+        paused,
+    });
+}
+
+// When something started and ended, ending it at `exported_at` if it was ongoing.
+fn import_period(
+    json: &Json,
+    exported_at: DateTime<Utc>,
+) -> Result<(DateTime<Utc>, Option<DateTime<Utc>>), String> {
+    let started = get_time(json, "started")?.ok_or("started is missing")?;
+    let ended = get_time(json, "ended")?.unwrap_or(exported_at);
+    return Ok((started, Some(ended)));
+}
+
+fn trace_from_json(json: &Json) -> Result<traceroute::Trace, String> {
+    let hops = array(json, "hops")?
+        .iter()
+        .map(|hop| {
+            return Ok(traceroute::Hop {
+                ttl: get_whole_number(hop, "ttl")?.ok_or("ttl is missing")? as u8,
+                responder: get_ip(hop, "responder")?,
+                responder_hostname: get_string(hop, "responder_hostname")?,
+                rtt: get_duration(hop, "rtt_ns")?,
+            });
+        })
+        .collect::<Result<_, String>>()?;
+    return Ok(traceroute::Trace {
+        dest_ip: get_ip(json, "dest_ip")?.ok_or("dest_ip is missing")?,
+        hops,
+        reached_dest: json.get("reached_dest") == Some(&Json::Bool(true)),
+    });
+}
+
+fn aggregates_from_json(
+    json: &Json,
+    key: &str,
+) -> Result<Vec<(DateTime<Utc>, rollups::Aggregate)>, String> {
+    return array(json, key)?
+        .iter()
+        .map(|aggregate| {
+            let start = get_time(aggregate, "start")?.ok_or("start is missing")?;
+            return Ok((
+                start,
+                rollups::Aggregate {
+                    samples: get_whole_number(aggregate, "samples")?.unwrap_or_default() as u32,
+                    failures: get_whole_number(aggregate, "failures")?.unwrap_or_default() as u32,
+                    fastest: get_duration(aggregate, "fastest_ns")?,
+                    slowest: get_duration(aggregate, "slowest_ns")?,
+                    total_round_trip: get_duration(aggregate, "total_round_trip_ns")?
+                        .unwrap_or_default(),
+                    total_throughput_bps: get_number(aggregate, "total_throughput_bps")?
+                        .unwrap_or_default(),
+                },
+            ));
+        })
+        .collect();
+}
+
+// The array at `key`, empty if it's missing.
+fn array<'a>(json: &'a Json, key: &str) -> Result<&'a [Json], String> {
+    return match json.get(key) {
+        Some(Json::Array(values)) => Ok(values),
+        Some(_) => Err(format!("{} must be an array", key)),
+        None => Ok(&[]),
+    };
+}
+
+// The value at `key`, None if it's missing or null.
+fn json_field<'a>(json: &'a Json, key: &str) -> Option<&'a Json> {
+    return json.get(key).filter(|value| **value != Json::Null);
+}
+
+// Fetches `api_path` (e.g. `/api/snapshot`) from the monitor whose web UI is at `url`.
+pub fn download(url: &str, api_path: &str) -> Result<Vec<u8>, String> {
+    let response = ureq::get(&format!("{}{}", url.trim_end_matches('/'), api_path)).call();
+    let mut body = Vec::new();
+    response_or_error(response)?
+        .into_reader()
+        .read_to_end(&mut body)
+        .map_err(|err| err.to_string())?;
+    return Ok(body);
+}
+
+// Sends `body` to `api_path` of the monitor whose web UI is at `url`, returning what it made of it.
+pub fn upload(
+    url: &str,
+    api_path: &str,
+    content_type: &str,
+    body: &[u8],
+) -> Result<String, String> {
+    let response = ureq::post(&format!("{}{}", url.trim_end_matches('/'), api_path))
+        .set("Content-Type", content_type)
+        .send_bytes(body);
+    return response_or_error(response)?
+        .into_string()
+        .map_err(|err| err.to_string());
+}
+
+fn response_or_error(
+    response: Result<ureq::Response, ureq::Error>,
+) -> Result<ureq::Response, String> {
+    return match response {
+        Ok(response) => Ok(response),
+        // The admin API explains what went wrong.
+        Err(ureq::Error::Status(status_code, response)) => match response.into_string() {
+            Ok(message) if !message.trim().is_empty() => Err(message.trim_end().to_string()),
+            _ => Err(format!("HTTP status {}", status_code)),
+        },
+        Err(ureq::Error::Transport(err)) => Err(err.to_string()),
+    };
+}
+
+// Every sample, streamed as NDJSON (one sample per line, like the sample log, with the target it's
+// for as `target`), a batch at a time. Unlike a snapshot, the whole history is never in memory twice
+// over, and the lock is only held long enough to format each batch.
+pub struct SampleStream {
+    ping_data: Arc<Mutex<PingData>>,
+    // The targets still to stream, in reverse.
+    hostnames: Vec<String>,
+    // When the last sample streamed for the current target was taken.
+    after: Option<DateTime<Utc>>,
+}
+impl SampleStream {
+    // Streams the samples of `hostname`, or of every target if it's None.
+    pub fn new(ping_data: Arc<Mutex<PingData>>, hostname: Option<String>) -> SampleStream {
+        let mut hostnames = match hostname {
+            Some(hostname) => vec![hostname],
+            None => ping_data.lock().unwrap().hostnames_in_order.clone(),
+        };
+        hostnames.reverse();
+        return SampleStream {
+            ping_data,
+            hostnames,
+            after: None,
+        };
+    }
+
+    // The next batch of lines, None once every target is done.
+    fn next_batch(&mut self) -> Option<String> {
+        let ping_data = self.ping_data.lock().unwrap();
+        loop {
+            let hostname = self.hostnames.last()?;
+            let after = match self.after {
+                Some(after) => Bound::Excluded(after),
+                None => Bound::Unbounded,
+            };
+            // The target may have been removed since streaming started.
+            let samples = ping_data
+                .data
+                .get(hostname)
+                .into_iter()
+                .flat_map(|samples| samples.range((after, Bound::Unbounded)))
+                .take(STREAM_BATCH_SIZE);
+            let mut lines = String::new();
+            for (when, sample) in samples {
+                let sample = TargetSample {
+                    target: hostname,
+                    sample: TimedSample::new(*when, sample),
+                };
+                lines += &format!("{}\n", serde_json::to_string(&sample).unwrap());
+                self.after = Some(*when);
+            }
+            if !lines.is_empty() {
+                return Some(lines);
+            }
+            self.hostnames.pop();
+            self.after = None;
+        }
+    }
+}
+impl MessageBody for SampleStream {
+    type Error = Infallible;
+
+    fn size(&self) -> BodySize {
+        return BodySize::Stream;
+    }
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        _cx: &mut Context,
+    ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        return Poll::Ready(
+            self.get_mut()
+                .next_batch()
+                .map(|lines| Ok(Bytes::from(lines))),
+        );
+    }
+}
+
+// Streams every sample from the monitor whose web UI is at `url` into Parquet files in `dir`, one
+// per target per day (in UTC), at `dir/TARGET/YYYY-MM-DD.parquet`. Files from an earlier export are
+// replaced, since the day's samples are all exported again. Returns how many files were written.
+pub fn export_parquet(url: &str, dir: &Path) -> Result<usize, String> {
+    let response =
+        response_or_error(ureq::get(&format!("{}/api/samples", url.trim_end_matches('/'))).call())?;
+    // Samples arrive a target at a time, in the order they were taken, so each day's file can be
+    // written as soon as the next day's samples start.
+    let mut num_files = 0;
+    let mut day_samples: Vec<Json> = Vec::new();
+    let mut current_day: Option<(String, NaiveDate)> = None;
+    for line in BufReader::new(response.into_reader()).lines() {
+        let sample = json::parse(&line.map_err(|err| err.to_string())?)?;
+        let target = get_string(&sample, "target")?.ok_or("target is missing")?;
+        let day = get_time(&sample, "time")?
+            .ok_or("time is missing")?
+            .date_naive();
+        if current_day.as_ref() != Some(&(target.clone(), day)) {
+            if let Some((target, day)) = current_day.take() {
+                write_parquet_day(dir, &target, day, &day_samples)?;
+                num_files += 1;
+            }
+            day_samples.clear();
+            current_day = Some((target, day));
+        }
+        day_samples.push(sample);
+    }
+    if let Some((target, day)) = current_day {
+        write_parquet_day(dir, &target, day, &day_samples)?;
+        num_files += 1;
+    }
+    return Ok(num_files);
+}
+
+// Writes a day of a target's samples, as they're streamed from `/api/samples`, to a Parquet file.
+fn write_parquet_day(
+    dir: &Path,
+    target: &str,
+    day: NaiveDate,
+    samples: &[Json],
+) -> Result<(), String> {
+    let strings = |key: &str| {
+        return samples
+            .iter()
+            .map(|sample| get_string(sample, key))
+            .collect::<Result<Vec<_>, String>>();
+    };
+    let numbers = |json_key: &str| {
+        return samples
+            .iter()
+            .map(|sample| {
+                let json = match json_key.split_once('.') {
+                    Some((object, _)) => sample.get(object),
+                    None => Some(sample),
+                };
+                let key = json_key.rsplit('.').next().unwrap();
+                return json.map_or(Ok(None), |json| get_number(json, key));
+            })
+            .collect::<Result<Vec<_>, String>>();
+    };
+    let micros = |key: &str| {
+        return samples
+            .iter()
+            .map(|sample| Ok(get_time(sample, key)?.map(|time| time.timestamp_micros())))
+            .collect::<Result<Vec<_>, String>>();
+    };
+    // Durations are in milliseconds, which is what the web UI shows.
+    let millis = |key: &str| {
+        return Ok::<_, String>(
+            numbers(key)?
+                .into_iter()
+                .map(|nanos| nanos.map(|nanos| nanos / 1e6))
+                .collect(),
+        );
+    };
+    let int32s = |key: &str| {
+        return Ok::<_, String>(
+            numbers(key)?
+                .into_iter()
+                .map(|number| number.map(|number| number as i32))
+                .collect::<Vec<_>>(),
+        );
+    };
+    let counts = |key: &str| {
+        return Ok::<_, String>(
+            int32s(key)?
+                .into_iter()
+                .map(|count| Some(count.unwrap_or(0)))
+                .collect(),
+        );
+    };
+    let column = |name, required, values| parquet::Column {
+        name,
+        required,
+        values,
+    };
+    let columns = [
+        column(
+            "target",
+            true,
+            parquet::Values::String(vec![Some(target.to_string()); samples.len()]),
+        ),
+        column("time", true, parquet::Values::Timestamp(micros("time")?)),
+        column("result", true, parquet::Values::String(strings("result")?)),
+        column(
+            "round_trip_ms",
+            false,
+            parquet::Values::Double(millis("round_trip_ns")?),
+        ),
+        column("error", false, parquet::Values::String(strings("error")?)),
+        column("status", false, parquet::Values::String(strings("status")?)),
+        column(
+            "cert_not_after",
+            false,
+            parquet::Values::Timestamp(micros("cert_not_after")?),
+        ),
+        column(
+            "clock_offset_ms",
+            false,
+            parquet::Values::Double(millis("clock_offset_ns")?),
+        ),
+        column(
+            "throughput_bps",
+            false,
+            parquet::Values::Double(numbers("throughput_bps")?),
+        ),
+        column(
+            "reply_ttl",
+            false,
+            parquet::Values::Int32(int32s("reply_ttl")?),
+        ),
+        column(
+            "reply_source",
+            false,
+            parquet::Values::String(strings("reply_source")?),
+        ),
+        column(
+            "late_replies",
+            true,
+            parquet::Values::Int32(counts("late_replies")?),
+        ),
+        column(
+            "duplicate_replies",
+            true,
+            parquet::Values::Int32(counts("duplicate_replies")?),
+        ),
+        column(
+            "burst_sent",
+            false,
+            parquet::Values::Int32(int32s("burst.sent")?),
+        ),
+        column(
+            "burst_lost",
+            false,
+            parquet::Values::Int32(int32s("burst.lost")?),
+        ),
+    ];
+
+    let target_dir: String = url::form_urlencoded::byte_serialize(target.as_bytes()).collect();
+    let target_dir = dir.join(target_dir);
+    let path = target_dir.join(format!("{}.parquet", day.format("%Y-%m-%d")));
+    return std::fs::create_dir_all(&target_dir)
+        .and_then(|()| std::fs::write(&path, parquet::write(&columns)))
+        .map_err(|err| format!("Failed to write {} - {}", path.display(), err));
+}
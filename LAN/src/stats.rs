@@ -0,0 +1,199 @@
+use crate::config;
+use crate::json;
+use crate::probes;
+use crate::rollups;
+use crate::storage;
+use crate::PingData;
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::mem::size_of;
+use std::time::Duration;
+
+// What the monitor itself is using, for `/api/stats/self`: how much each target has recorded, about
+// how much memory that takes, how much is stored on disk, and whether targets are being probed on
+// time. Handy for tuning retention before a Raspberry Pi runs out of memory.
+#[derive(Serialize, JsonSchema)]
+pub struct SelfStats {
+    pub resident_memory_bytes: Option<usize>,
+    pub estimated_memory_bytes: usize,
+    pub scheduler: Scheduler,
+    // None when samples are only kept in memory.
+    pub storage: Option<StorageStats>,
+    pub targets: Vec<TargetStats>,
+}
+
+// Whether targets are being probed on time.
+#[derive(Serialize, JsonSchema)]
+pub struct Scheduler {
+    pub targets: usize,
+    pub paused: usize,
+    pub overdue: usize,
+    pub max_probes_per_sec: Option<u32>,
+    pub probe_slot_backlog_ms: f64,
+}
+
+// Where samples are kept beyond memory, and how that's going.
+#[derive(Serialize, JsonSchema)]
+pub struct StorageStats {
+    #[schemars(extend("enum" = ["log_dir", "postgres"]))]
+    pub backend: &'static str,
+    #[serde(serialize_with = "json::maybe_seconds")]
+    pub last_flush: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    pub stored_bytes: Option<u64>,
+}
+
+// How much a target has recorded, and whether it's being probed on time.
+#[derive(Serialize, JsonSchema)]
+pub struct TargetStats {
+    pub name: String,
+    pub samples: usize,
+    pub minute_summaries: usize,
+    pub hour_summaries: usize,
+    pub outages: usize,
+    pub estimated_memory_bytes: usize,
+    pub stored_bytes: Option<u64>,
+    #[serde(serialize_with = "json::maybe_seconds")]
+    pub last_sample: Option<DateTime<Utc>>,
+    pub interval_sec: f64,
+    // Why it isn't being probed right now, e.g. "quiet hours".
+    pub quiet: Option<&'static str>,
+    pub overdue: bool,
+}
+
+// What the monitor is using right now.
+pub fn collect(ping_data: &PingData) -> SelfStats {
+    let now = Utc::now();
+    let config = config::get();
+    let storage_status = storage::status().unwrap_or_default();
+    let mut targets = Vec::new();
+    let mut total_memory_bytes = 0;
+    let mut num_overdue = 0;
+    for target in &ping_data.targets {
+        let name = &target.name;
+        // A target being renamed has its history moved a moment before it's replaced.
+        let Some(samples) = ping_data.data.get(name) else {
+            continue;
+        };
+        let rollups = &ping_data.rollups[name];
+        let memory_bytes = estimated_memory_bytes(ping_data, name);
+        total_memory_bytes += memory_bytes;
+        let last_sample = samples.keys().next_back().copied();
+        let quiet = ping_data.quiet_periods[name]
+            .values()
+            .next_back()
+            .filter(|quiet_period| quiet_period.ended.is_none())
+            .map(|quiet_period| quiet_period.reason);
+        // A probe (or burst of them) can take up to its timeout on top of the interval, anything
+        // much later than that means its thread is stuck or starved.
+        let num_probes = target.options.burst.unwrap_or(1);
+        let burst_gap = Duration::from_millis(config.msec_between_burst_pings);
+        let allowance = 2 * target.interval() + num_probes * (target.timeout() + burst_gap);
+        let overdue = quiet.is_none()
+            && last_sample.is_some_and(|last_sample| {
+                (now - last_sample).to_std().unwrap_or_default() > allowance
+            });
+        if overdue {
+            num_overdue += 1;
+        }
+        targets.push(TargetStats {
+            name: name.clone(),
+            samples: samples.len(),
+            minute_summaries: rollups.minutes.len(),
+            hour_summaries: rollups.hours.len(),
+            outages: ping_data.outages[name].len(),
+            estimated_memory_bytes: memory_bytes,
+            stored_bytes: storage_status.stored_bytes.get(name).copied(),
+            last_sample,
+            interval_sec: target.interval().as_secs_f64(),
+            quiet,
+            overdue,
+        });
+    }
+    total_memory_bytes += ping_data.connectivity_losses.len()
+        * entry_bytes::<DateTime<Utc>, crate::ConnectivityLoss>()
+        + ping_data.public_ips.len() * entry_bytes::<DateTime<Utc>, std::net::IpAddr>();
+
+    let storage = if config.log_dir.is_some() || config.postgres_url.is_some() {
+        Some(StorageStats {
+            backend: match config.postgres_url {
+                Some(_) => "postgres",
+                None => "log_dir",
+            },
+            last_flush: storage_status.last_flush,
+            stored_bytes: if storage_status.stored_bytes.is_empty() {
+                None
+            } else {
+                Some(storage_status.stored_bytes.values().sum())
+            },
+            last_error: storage_status.last_error,
+        })
+    } else {
+        None
+    };
+
+    return SelfStats {
+        resident_memory_bytes: resident_memory_bytes(),
+        estimated_memory_bytes: total_memory_bytes,
+        scheduler: Scheduler {
+            targets: ping_data.targets.len(),
+            paused: ping_data.paused.len(),
+            overdue: num_overdue,
+            max_probes_per_sec: config.max_probes_per_sec,
+            probe_slot_backlog_ms: probes::probe_slot_backlog().as_secs_f64() * 1000.0,
+        },
+        storage,
+        targets,
+    };
+}
+
+// Roughly how much memory a target's history takes up: the entries of its maps, plus any text in
+// its samples. B-tree nodes aren't always full, so the entries get half again for the spare room.
+fn estimated_memory_bytes(ping_data: &PingData, name: &str) -> usize {
+    let samples = &ping_data.data[name];
+    let text_bytes: usize = samples
+        .values()
+        .map(|sample| {
+            let error_len = match &sample.result {
+                probes::ProbeResult::Unreachable(error)
+                | probes::ProbeResult::SendError(error)
+                | probes::ProbeResult::DnsError(error)
+                | probes::ProbeResult::Failed(error)
+                | probes::ProbeResult::Captive(error) => error.capacity(),
+                probes::ProbeResult::Success(_) | probes::ProbeResult::Timeout => 0,
+            };
+            return error_len + sample.status.as_ref().map_or(0, String::capacity);
+        })
+        .sum();
+    let rollups = &ping_data.rollups[name];
+    let loss_counters = &ping_data.loss_counters[name];
+    let entries_bytes = samples.len() * entry_bytes::<DateTime<Utc>, probes::Sample>()
+        + (rollups.minutes.len() + rollups.hours.len())
+            * entry_bytes::<DateTime<Utc>, rollups::Aggregate>()
+        + (loss_counters.minutes.len() + loss_counters.hours.len())
+            * entry_bytes::<DateTime<Utc>, rollups::LossCount>()
+        + ping_data.outages[name].len() * entry_bytes::<DateTime<Utc>, crate::Outage>()
+        + ping_data.quiet_periods[name].len() * entry_bytes::<DateTime<Utc>, crate::QuietPeriod>();
+    return entries_bytes + text_bytes;
+}
+
+// The memory an entry of a `BTreeMap<K, V>` takes up, allowing for spare room in its node.
+fn entry_bytes<K, V>() -> usize {
+    return (size_of::<K>() + size_of::<V>()) * 3 / 2;
+}
+
+// How much of the process is in RAM, from Linux's /proc. None elsewhere.
+fn resident_memory_bytes() -> Option<usize> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    // e.g. `VmRSS:	    5432 kB`
+    let kilobytes = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse::<usize>()
+        .ok()?;
+    return Some(kilobytes * 1024);
+}
@@ -0,0 +1,724 @@
+use crate::config;
+use crate::json::{self, Json};
+use crate::postgres;
+use crate::probes;
+use crate::PingData;
+use chrono::Duration as chrono_Duration;
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Read, Write};
+use std::net::IpAddr;
+use std::ops::Bound;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// Somewhere samples are kept beyond memory, so history survives a restart.
+pub trait Backend: Send {
+    // Every sample kept for the target named `name`, in the order they were taken.
+    fn load(&mut self, name: &str) -> Result<Vec<(DateTime<Utc>, probes::Sample)>, String>;
+    // Keeps new samples for the target named `name`, in the order they were taken.
+    fn append(
+        &mut self,
+        name: &str,
+        samples: &[(DateTime<Utc>, probes::Sample)],
+    ) -> Result<(), String>;
+    // Deletes the samples kept for the target named `name` that were taken before `before`.
+    // Returns how many were deleted.
+    fn delete_before(&mut self, name: &str, before: DateTime<Utc>) -> Result<usize, String>;
+    // How much space the samples kept for the target named `name` take up, if it's quick to tell.
+    fn stored_bytes(&mut self, _name: &str) -> Option<u64> {
+        return None;
+    }
+}
+
+// How storing samples is going, for `/api/stats/self`.
+#[derive(Clone, Default)]
+pub struct Status {
+    pub last_flush: Option<DateTime<Utc>>,
+    // Why storing samples last failed, None if the last flush succeeded.
+    pub last_error: Option<String>,
+    // How much space each target's samples take up, by name, if the backend can tell.
+    pub stored_bytes: HashMap<String, u64>,
+}
+// None until samples start being stored, or if they're only kept in memory.
+static STATUS: Mutex<Option<Status>> = Mutex::new(None);
+
+pub fn status() -> Option<Status> {
+    return STATUS.lock().unwrap().clone();
+}
+
+// The backend the config asks for, if any.
+pub fn open(config: &config::Config) -> Result<Option<Box<dyn Backend>>, String> {
+    if let Some(postgres_url) = &config.postgres_url {
+        let instance = config
+            .storage_instance
+            .clone()
+            .unwrap_or_else(postgres::default_instance);
+        return Ok(Some(Box::new(postgres::Postgres::connect(
+            postgres_url,
+            instance,
+        )?)));
+    }
+    if let Some(log_dir) = &config.log_dir {
+        std::fs::create_dir_all(log_dir)
+            .map_err(|err| format!("Failed to create {} - {}", log_dir.display(), err))?;
+        return Ok(Some(Box::new(LogFiles {
+            dir: log_dir.clone(),
+        })));
+    }
+    return Ok(None);
+}
+
+// Each target's samples are kept in an append-only log in a directory, so history survives a
+// restart without needing a database. Each line of a log is a sample as a JSON object (NDJSON), in
+// the order they were taken, ending with a CRC-32 of the rest of the line, e.g.
+// `{"time":"2024-01-01T00:00:00Z","result":"success","round_trip_ns":1500000,"crc32":"1a2b3c4d"}`
+// The checksum catches lines an SD card mangled, and a line cut short by a power cut is cut off the
+// log when it's loaded, so the samples appended after it aren't lost too.
+const LOG_EXTENSION: &str = "ndjson";
+pub struct LogFiles {
+    dir: PathBuf,
+}
+impl LogFiles {
+    // Where the log for the target named `name` is. Names are percent-encoded, since they can have
+    // slashes in them (e.g. URLs).
+    fn path(&self, name: &str) -> PathBuf {
+        let file_name: String = url::form_urlencoded::byte_serialize(name.as_bytes()).collect();
+        return self.dir.join(format!("{}.{}", file_name, LOG_EXTENSION));
+    }
+}
+impl Backend for LogFiles {
+    fn load(&mut self, name: &str) -> Result<Vec<(DateTime<Utc>, probes::Sample)>, String> {
+        let path = self.path(name);
+        let mut log = match OpenOptions::new().read(true).write(true).open(&path) {
+            Ok(log) => log,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(format!("Failed to open {} - {}", path.display(), err)),
+        };
+        let mut contents = Vec::new();
+        log.read_to_end(&mut contents)
+            .map_err(|err| format!("Failed to read {} - {}", path.display(), err))?;
+        // A crash part way through an append leaves the last line without its newline. Cut it off, or
+        // the next append would run on from it and spoil the first new line too.
+        let complete_len = contents
+            .iter()
+            .rposition(|b| *b == b'\n')
+            .map_or(0, |newline| newline + 1);
+        if complete_len < contents.len() {
+            eprintln!(
+                "Dropping an unfinished line at the end of {}",
+                path.display()
+            );
+            log.set_len(complete_len as u64)
+                .and_then(|_| log.sync_all())
+                .map_err(|err| format!("Failed to truncate {} - {}", path.display(), err))?;
+            contents.truncate(complete_len);
+        }
+
+        let mut samples = Vec::new();
+        for (line_number, line) in contents.split(|b| *b == b'\n').enumerate() {
+            if line.is_empty() {
+                continue;
+            }
+            // Skip anything unreadable, e.g. a line garbled on disk.
+            match parse_line(line) {
+                Ok(sample) => samples.push(sample),
+                Err(err) => eprintln!(
+                    "Skipping line {} of {} - {}",
+                    line_number + 1,
+                    path.display(),
+                    err
+                ),
+            }
+        }
+        return Ok(samples);
+    }
+
+    fn append(
+        &mut self,
+        name: &str,
+        samples: &[(DateTime<Utc>, probes::Sample)],
+    ) -> Result<(), String> {
+        let mut lines = String::new();
+        for (when, sample) in samples {
+            lines += &format!(
+                "{}\n",
+                with_checksum(&sample_to_json(*when, sample).to_string())
+            );
+        }
+        let path = self.path(name);
+        let fsync = config::get().log_fsync;
+        let is_new = !path.exists();
+        return OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|log| {
+                let mut log = BufWriter::new(log);
+                log.write_all(lines.as_bytes())?;
+                log.flush()?;
+                if fsync {
+                    log.get_ref().sync_data()?;
+                    // A new file's entry in the directory has to be on disk too, or the file can
+                    // disappear in a power cut.
+                    if is_new {
+                        File::open(&self.dir)?.sync_all()?;
+                    }
+                }
+                return Ok(());
+            })
+            .map_err(|err| format!("Failed to append to {} - {}", path.display(), err));
+    }
+
+    // Rewrites the log without the old samples, and swaps it in for the old log in one go so a
+    // crash can't leave it half written. Unreadable lines among the old samples go too.
+    fn delete_before(&mut self, name: &str, before: DateTime<Utc>) -> Result<usize, String> {
+        let path = self.path(name);
+        let contents = match std::fs::read(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(err) => return Err(format!("Failed to read {} - {}", path.display(), err)),
+        };
+        // Samples are in the order they were taken, so everything from the first one new enough on
+        // is kept.
+        let mut keep_from = 0;
+        let mut num_deleted = 0;
+        for line in contents.split_inclusive(|b| *b == b'\n') {
+            if parse_line(line.strip_suffix(b"\n").unwrap_or(line))
+                .is_ok_and(|(when, _)| when >= before)
+            {
+                break;
+            }
+            keep_from += line.len();
+            num_deleted += 1;
+        }
+        if keep_from == 0 {
+            return Ok(0);
+        }
+        let new_path = path.with_extension(format!("{}.new", LOG_EXTENSION));
+        return File::create(&new_path)
+            .and_then(|mut log| {
+                log.write_all(&contents[keep_from..])?;
+                log.sync_data()?;
+                std::fs::rename(&new_path, &path)?;
+                return File::open(&self.dir)?.sync_all();
+            })
+            .map(|_| num_deleted)
+            .map_err(|err| format!("Failed to rewrite {} - {}", path.display(), err));
+    }
+
+    fn stored_bytes(&mut self, name: &str) -> Option<u64> {
+        return std::fs::metadata(self.path(name))
+            .ok()
+            .map(|metadata| metadata.len());
+    }
+}
+
+// A line of a log, without its newline.
+fn parse_line(line: &[u8]) -> Result<(DateTime<Utc>, probes::Sample), String> {
+    return std::str::from_utf8(line)
+        .map_err(|err| err.to_string())
+        .and_then(verify_checksum)
+        .and_then(json::parse)
+        .and_then(|json| sample_from_json(&json));
+}
+
+// How a log line ends: the checksum of the line before it, as 8 hex digits, and the end of the object.
+const CHECKSUM_PREFIX: &str = ",\"crc32\":\"";
+const CHECKSUM_SUFFIX: &str = "\"}";
+const CHECKSUM_LEN: usize = CHECKSUM_PREFIX.len() + 8 + CHECKSUM_SUFFIX.len();
+
+// Adds a `crc32` field to the end of a JSON object, with the checksum of the object without it.
+fn with_checksum(object: &str) -> String {
+    let without_end = &object[..object.len() - 1];
+    return format!(
+        "{}{}{:08x}{}",
+        without_end,
+        CHECKSUM_PREFIX,
+        crc32(object.as_bytes()),
+        CHECKSUM_SUFFIX
+    );
+}
+
+// Checks a log line written by `with_checksum`, and returns it if it's intact. Lines without a
+// checksum (from before they had one) are taken as they are.
+fn verify_checksum(line: &str) -> Result<&str, String> {
+    let checksum_start = match line.len().checked_sub(CHECKSUM_LEN) {
+        Some(start)
+            if line
+                .get(start..)
+                .is_some_and(|end| end.starts_with(CHECKSUM_PREFIX)) =>
+        {
+            start
+        }
+        _ => return Ok(line),
+    };
+    let expected =
+        &line[checksum_start + CHECKSUM_PREFIX.len()..line.len() - CHECKSUM_SUFFIX.len()];
+    let expected = u32::from_str_radix(expected, 16)
+        .map_err(|_err| format!("Invalid checksum \"{}\"", expected))?;
+    let object = format!("{}}}", &line[..checksum_start]);
+    if crc32(object.as_bytes()) != expected {
+        return Err("The checksum doesn't match, the line is corrupt".to_string());
+    }
+    return Ok(line);
+}
+
+// The CRC-32 (as used by zlib and Ethernet) of `bytes`.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    return !crc;
+}
+
+// Loads the samples kept for every target back into memory, e.g. at startup. Samples older than the
+// target's retention are dropped as they're added, like any other sample.
+// Returns when the latest sample kept for each target was taken, so they aren't kept again.
+pub fn replay(
+    backend: &mut dyn Backend,
+    ping_data: &Mutex<PingData>,
+) -> HashMap<String, DateTime<Utc>> {
+    let mut flushed_until = HashMap::new();
+    let hostnames = ping_data.lock().unwrap().hostnames_in_order.clone();
+    for hostname in hostnames {
+        let samples = match backend.load(&hostname) {
+            Ok(samples) => samples,
+            Err(err) => {
+                eprintln!("Failed to load the history of {} - {}", hostname, err);
+                continue;
+            }
+        };
+        let num_replayed = samples.len();
+        for (when, sample) in samples {
+            ping_data.lock().unwrap().add_entry(&hostname, when, sample);
+            flushed_until.insert(hostname.clone(), when);
+        }
+        println!("Replayed {} samples for {}", num_replayed, hostname);
+    }
+    return flushed_until;
+}
+
+// Periodically passes new samples to the backend, and deletes samples from it that are older than
+// they're kept for (every `STORED_RETENTION_CHECK_SEC`). `flushed_until` is when the latest sample
+// the backend already has for each target was taken.
+pub fn repeatedly_flush(
+    mut backend: Box<dyn Backend>,
+    ping_data: Arc<Mutex<PingData>>,
+    mut flushed_until: HashMap<String, DateTime<Utc>>,
+) {
+    let mut last_expired = None;
+    loop {
+        thread::sleep(Duration::from_secs(config::get().log_flush_interval_sec));
+        // Copy the new samples while holding the lock, and store them after releasing it.
+        let mut new_samples = Vec::new();
+        {
+            let locked_ping_data = ping_data.lock().unwrap();
+            for hostname in &locked_ping_data.hostnames_in_order {
+                let Some(samples) = locked_ping_data.data.get(hostname) else {
+                    continue;
+                };
+                let after = match flushed_until.get(hostname) {
+                    Some(flushed_until) => Bound::Excluded(*flushed_until),
+                    None => Bound::Unbounded,
+                };
+                let samples: Vec<(DateTime<Utc>, probes::Sample)> = samples
+                    .range((after, Bound::Unbounded))
+                    .map(|(when, sample)| (*when, sample.clone()))
+                    .collect();
+                if !samples.is_empty() {
+                    new_samples.push((hostname.clone(), samples));
+                }
+            }
+        }
+        let mut last_error = None;
+        for (hostname, samples) in new_samples {
+            match backend.append(&hostname, &samples) {
+                Ok(()) => {
+                    flushed_until.insert(hostname, samples.last().unwrap().0);
+                }
+                // Try again next time.
+                Err(err) => {
+                    let err = format!("Failed to store samples of {} - {}", hostname, err);
+                    eprintln!("{}", err);
+                    last_error = Some(err);
+                }
+            }
+        }
+        let now = Utc::now();
+        if last_expired.is_none_or(|last_expired| {
+            now - last_expired >= chrono_Duration::seconds(config::STORED_RETENTION_CHECK_SEC)
+        }) {
+            last_expired = Some(now);
+            if let Err(err) = expire(backend.as_mut(), &ping_data, now) {
+                eprintln!("{}", err);
+                last_error = Some(err);
+            }
+        }
+        let hostnames = ping_data.lock().unwrap().hostnames_in_order.clone();
+        let stored_bytes = hostnames
+            .into_iter()
+            .filter_map(|hostname| {
+                let stored_bytes = backend.stored_bytes(&hostname)?;
+                return Some((hostname, stored_bytes));
+            })
+            .collect();
+        *STATUS.lock().unwrap() = Some(Status {
+            last_flush: Some(Utc::now()),
+            last_error,
+            stored_bytes,
+        });
+    }
+}
+
+// Deletes every target's samples that are older than `stored_max_age` as of `now`, or than the
+// target's retention if that's longer, so samples that are still in memory are loaded again after a
+// restart.
+fn expire(
+    backend: &mut dyn Backend,
+    ping_data: &Mutex<PingData>,
+    now: DateTime<Utc>,
+) -> Result<(), String> {
+    let stored_max_age = config::get().stored_max_age();
+    let max_ages: Vec<(String, Duration)> = {
+        let locked_ping_data = ping_data.lock().unwrap();
+        locked_ping_data
+            .hostnames_in_order
+            .iter()
+            .map(|hostname| {
+                let max_age = match locked_ping_data.retention(hostname) {
+                    config::Retention::Age(max_age) => max_age.max(stored_max_age),
+                    config::Retention::Entries(_) => stored_max_age,
+                };
+                return (hostname.clone(), max_age);
+            })
+            .collect()
+    };
+    let mut errors = Vec::new();
+    for (hostname, max_age) in max_ages {
+        let before = now - chrono_Duration::from_std(max_age).unwrap();
+        match backend.delete_before(&hostname, before) {
+            Ok(0) => {}
+            Ok(num_deleted) => eprintln!(
+                "Deleted {} stored samples of {} from before {}",
+                num_deleted, hostname, before
+            ),
+            Err(err) => errors.push(format!(
+                "Failed to delete old samples of {} - {}",
+                hostname, err
+            )),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors.join(", "));
+    }
+    return Ok(());
+}
+
+// A sample as a JSON object, see `TimedSample`.
+pub fn sample_to_json(when: DateTime<Utc>, sample: &probes::Sample) -> Json {
+    let value = serde_json::to_value(TimedSample::new(when, sample)).unwrap();
+    return Json::from(value);
+}
+
+// A sample and when it was taken, as the API returns it, e.g. from `/api/hosts/{host}/pings`, and as
+// the sample log keeps it. Fields that don't apply to the sample are left out.
+#[derive(Serialize, JsonSchema)]
+#[schemars(rename = "Sample")]
+pub struct TimedSample {
+    pub time: DateTime<Utc>,
+    pub result: Outcome,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(description = "Only when the result is success.")]
+    pub round_trip_ns: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(description = "Why the probe failed.")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(description = "The status the target responded with, e.g. HTTP's.")]
+    pub status: Option<String>,
+    #[serde(
+        serialize_with = "json::maybe_seconds",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub cert_not_after: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clock_offset_ns: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interface_stats: Option<probes::InterfaceStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub throughput_bps: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reply_ttl: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reply_source: Option<IpAddr>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub late_replies: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duplicate_replies: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub burst: Option<Burst>,
+}
+impl TimedSample {
+    pub fn new(when: DateTime<Utc>, sample: &probes::Sample) -> TimedSample {
+        let (result, error) = match &sample.result {
+            probes::ProbeResult::Success(_) => (Outcome::Success, None),
+            probes::ProbeResult::Timeout => (Outcome::Timeout, None),
+            probes::ProbeResult::Unreachable(error) => (Outcome::Unreachable, Some(error)),
+            probes::ProbeResult::SendError(error) => (Outcome::SendError, Some(error)),
+            probes::ProbeResult::DnsError(error) => (Outcome::DnsError, Some(error)),
+            probes::ProbeResult::Failed(error) => (Outcome::Failed, Some(error)),
+            probes::ProbeResult::Captive(error) => (Outcome::Captive, Some(error)),
+        };
+        return TimedSample {
+            time: when,
+            result,
+            round_trip_ns: sample.result.round_trip().map(nanos),
+            error: error.cloned(),
+            status: sample.status.clone(),
+            cert_not_after: sample.cert_not_after,
+            clock_offset_ns: sample
+                .clock_offset
+                .and_then(|offset| offset.num_nanoseconds()),
+            interface_stats: sample.interface_stats.clone(),
+            throughput_bps: sample.throughput_bps,
+            reply_ttl: sample.reply_ttl,
+            reply_source: sample.reply_source,
+            late_replies: Some(sample.late_replies).filter(|late| *late > 0),
+            duplicate_replies: Some(sample.duplicate_replies).filter(|duplicates| *duplicates > 0),
+            burst: sample.burst.as_ref().map(|burst| Burst {
+                sent: burst.sent,
+                lost: burst.lost,
+                fastest_ns: burst.fastest.map(nanos),
+                slowest_ns: burst.slowest.map(nanos),
+            }),
+        };
+    }
+}
+
+// How a probe went, see `probes::ProbeResult`.
+#[derive(Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Success,
+    Timeout,
+    Unreachable,
+    SendError,
+    DnsError,
+    Failed,
+    Captive,
+}
+
+// A burst of probes sent as one sample, see `probes::BurstStats`.
+#[derive(Serialize, JsonSchema)]
+pub struct Burst {
+    pub sent: u32,
+    pub lost: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fastest_ns: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub slowest_ns: Option<u64>,
+}
+
+// A sample with the target it's for, as `/api/samples` and `/events` send them.
+#[derive(Serialize, JsonSchema)]
+pub struct TargetSample<'a> {
+    #[schemars(description = "The name of the target the sample is for.")]
+    pub target: &'a str,
+    #[serde(flatten)]
+    pub sample: TimedSample,
+}
+
+pub fn nanos(duration: Duration) -> u64 {
+    return duration.as_nanos() as u64;
+}
+
+// Parses a sample written by `sample_to_json`, returning when it was taken and the sample.
+pub fn sample_from_json(json: &Json) -> Result<(DateTime<Utc>, probes::Sample), String> {
+    let when = get_time(json, "time")?.ok_or("time is missing")?;
+    let error = || get_string(json, "error").map(Option::unwrap_or_default);
+    let result = match get_string(json, "result")?.as_deref() {
+        Some("success") => probes::ProbeResult::Success(
+            get_duration(json, "round_trip_ns")?.ok_or("round_trip_ns is missing")?,
+        ),
+        Some("timeout") => probes::ProbeResult::Timeout,
+        Some("unreachable") => probes::ProbeResult::Unreachable(error()?),
+        Some("send_error") => probes::ProbeResult::SendError(error()?),
+        Some("dns_error") => probes::ProbeResult::DnsError(error()?),
+        Some("failed") => probes::ProbeResult::Failed(error()?),
+        Some("captive") => probes::ProbeResult::Captive(error()?),
+        Some(result) => return Err(format!("Unknown result \"{}\"", result)),
+        None => return Err("result is missing".to_string()),
+    };
+    let mut sample = probes::Sample::new(result);
+    sample.status = get_string(json, "status")?;
+    sample.cert_not_after = get_time(json, "cert_not_after")?;
+    sample.clock_offset = get_number(json, "clock_offset_ns")?
+        .map(|offset| chrono_Duration::nanoseconds(offset as i64));
+    if let Some(interface_stats) = json.get("interface_stats") {
+        sample.interface_stats = Some(probes::InterfaceStats {
+            in_bps: get_number(interface_stats, "in_bps")?.unwrap_or_default(),
+            out_bps: get_number(interface_stats, "out_bps")?.unwrap_or_default(),
+            errors: get_whole_number(interface_stats, "errors")?.unwrap_or_default() as u32,
+        });
+    }
+    sample.throughput_bps = get_number(json, "throughput_bps")?;
+    sample.reply_ttl = get_whole_number(json, "reply_ttl")?.map(|ttl| ttl as u8);
+    sample.reply_source = get_ip(json, "reply_source")?;
+    sample.late_replies = get_whole_number(json, "late_replies")?.unwrap_or_default() as u32;
+    sample.duplicate_replies =
+        get_whole_number(json, "duplicate_replies")?.unwrap_or_default() as u32;
+    if let Some(burst) = json.get("burst") {
+        sample.burst = Some(probes::BurstStats {
+            sent: get_whole_number(burst, "sent")?.unwrap_or_default() as u32,
+            lost: get_whole_number(burst, "lost")?.unwrap_or_default() as u32,
+            fastest: get_duration(burst, "fastest_ns")?,
+            slowest: get_duration(burst, "slowest_ns")?,
+        });
+    }
+    return Ok((when, sample));
+}
+
+// Reading fields of JSON objects, None if they're missing.
+
+pub fn get_string(json: &Json, key: &str) -> Result<Option<String>, String> {
+    return json
+        .get(key)
+        .map(|string| {
+            string
+                .as_str()
+                .map(str::to_string)
+                .ok_or(format!("{} must be a string", key))
+        })
+        .transpose();
+}
+
+pub fn get_number(json: &Json, key: &str) -> Result<Option<f64>, String> {
+    return json
+        .get(key)
+        .map(|number| number.as_f64().ok_or(format!("{} must be a number", key)))
+        .transpose();
+}
+
+pub fn get_whole_number(json: &Json, key: &str) -> Result<Option<u64>, String> {
+    return json
+        .get(key)
+        .map(|number| {
+            number
+                .as_u64()
+                .ok_or(format!("{} must be a whole number", key))
+        })
+        .transpose();
+}
+
+// A duration in nanoseconds.
+pub fn get_duration(json: &Json, key: &str) -> Result<Option<Duration>, String> {
+    return Ok(get_whole_number(json, key)?.map(Duration::from_nanos));
+}
+
+pub fn get_time(json: &Json, key: &str) -> Result<Option<DateTime<Utc>>, String> {
+    return get_string(json, key)?
+        .map(|time| {
+            DateTime::parse_from_rfc3339(&time)
+                .map(|time| time.with_timezone(&Utc))
+                .map_err(|err| format!("Invalid {} \"{}\" - {}", key, time, err))
+        })
+        .transpose();
+}
+
+pub fn get_ip(json: &Json, key: &str) -> Result<Option<IpAddr>, String> {
+    return get_string(json, key)?
+        .map(|ip| {
+            ip.parse()
+                .map_err(|_err| format!("Invalid {} \"{}\"", key, ip))
+        })
+        .transpose();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_matches_zlib() {
+        // The check value from the CRC catalogue.
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"{}"), 0xA3A6_BF43);
+    }
+
+    #[test]
+    fn checksummed_lines_verify() {
+        let line = with_checksum("{\"time\":\"2024-01-01T00:00:00Z\",\"result\":\"success\"}");
+        assert!(line
+            .starts_with("{\"time\":\"2024-01-01T00:00:00Z\",\"result\":\"success\",\"crc32\":\""));
+        assert!(line.ends_with("\"}"));
+        assert_eq!(verify_checksum(&line), Ok(line.as_str()));
+    }
+
+    #[test]
+    fn corrupt_lines_do_not_verify() {
+        let line = with_checksum("{\"time\":\"2024-01-01T00:00:00Z\",\"result\":\"success\"}");
+        let flipped = line.replacen("success", "suckess", 1);
+        assert_eq!(
+            verify_checksum(&flipped),
+            Err("The checksum doesn't match, the line is corrupt".to_string())
+        );
+        let bad_hex = format!("{}zzzzzzzz\"}}", &line[..line.len() - 10]);
+        assert_eq!(
+            verify_checksum(&bad_hex),
+            Err("Invalid checksum \"zzzzzzzz\"".to_string())
+        );
+    }
+
+    #[test]
+    fn samples_read_back_as_they_were_written() {
+        let when = "2024-01-01T08:00:00.5Z".parse().unwrap();
+        let mut sample =
+            probes::Sample::new(probes::ProbeResult::Success(Duration::from_millis(12)));
+        sample.status = Some("200".to_string());
+        sample.cert_not_after = Some("2025-01-01T00:00:00Z".parse().unwrap());
+        sample.reply_ttl = Some(57);
+        sample.reply_source = Some("192.0.2.1".parse().unwrap());
+        sample.late_replies = 1;
+        sample.interface_stats = Some(probes::InterfaceStats {
+            in_bps: 1e6,
+            out_bps: 2.5e5,
+            errors: 0,
+        });
+        sample.burst = Some(probes::BurstStats {
+            sent: 5,
+            lost: 1,
+            fastest: Some(Duration::from_millis(10)),
+            slowest: Some(Duration::from_millis(15)),
+        });
+        let line = sample_to_json(when, &sample).to_string();
+        assert_eq!(
+            line,
+            "{\"time\":\"2024-01-01T08:00:00.500Z\",\"result\":\"success\",\
+             \"round_trip_ns\":12000000,\"status\":\"200\",\
+             \"cert_not_after\":\"2025-01-01T00:00:00Z\",\
+             \"interface_stats\":{\"in_bps\":1000000,\"out_bps\":250000,\"errors\":0},\
+             \"reply_ttl\":57,\"reply_source\":\"192.0.2.1\",\"late_replies\":1,\
+             \"burst\":{\"sent\":5,\"lost\":1,\"fastest_ns\":10000000,\"slowest_ns\":15000000}}"
+        );
+        let (read_when, read_sample) = sample_from_json(&json::parse(&line).unwrap()).unwrap();
+        assert_eq!(read_when, when);
+        assert_eq!(sample_to_json(read_when, &read_sample).to_string(), line);
+    }
+
+    #[test]
+    fn lines_without_a_checksum_are_taken_as_they_are() {
+        let line = "{\"time\":\"2024-01-01T00:00:00Z\",\"result\":\"timeout\"}";
+        assert_eq!(verify_checksum(line), Ok(line));
+    }
+}
@@ -0,0 +1,118 @@
+use crate::i18n;
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+// The time zone pages show times in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Zone {
+    // The monitor's.
+    Local,
+    // A zone from the IANA database, e.g. `America/New_York`.
+    Named(Tz),
+    // UTC, written as ISO 8601, e.g. `2024-01-01T19:30:00Z`.
+    Iso,
+}
+impl std::str::FromStr for Zone {
+    type Err = String;
+
+    // Parses `local`, `utc` or an IANA zone's name.
+    fn from_str(name: &str) -> Result<Zone, String> {
+        if name.eq_ignore_ascii_case("local") {
+            return Ok(Zone::Local);
+        }
+        if name.eq_ignore_ascii_case("utc") {
+            return Ok(Zone::Iso);
+        }
+        return match name.parse() {
+            Ok(tz) => Ok(Zone::Named(tz)),
+            Err(_) => Err(format!(
+                "Unknown time zone \"{}\", it can be local, utc or a zone like America/New_York",
+                name
+            )),
+        };
+    }
+}
+impl Zone {
+    // `time` in this zone.
+    pub fn at(&self, time: DateTime<Utc>) -> DateTime<FixedOffset> {
+        return match self {
+            Zone::Local => DateTime::<Local>::from(time).fixed_offset(),
+            Zone::Named(tz) => time.with_timezone(tz).fixed_offset(),
+            Zone::Iso => time.fixed_offset(),
+        };
+    }
+
+    // When a time without a time zone happened in this zone, None if it's skipped by a change to
+    // daylight saving time. Times that happen twice are the first.
+    pub fn resolve(&self, time: NaiveDateTime) -> Option<DateTime<Utc>> {
+        return match self {
+            Zone::Local => Local
+                .from_local_datetime(&time)
+                .earliest()
+                .map(|time| time.with_timezone(&Utc)),
+            Zone::Named(tz) => tz
+                .from_local_datetime(&time)
+                .earliest()
+                .map(|time| time.with_timezone(&Utc)),
+            Zone::Iso => Some(time.and_utc()),
+        };
+    }
+
+    // e.g. `America/New_York`.
+    pub fn describe(&self) -> String {
+        return match self {
+            Zone::Local => i18n::text("the monitor's time zone").to_string(),
+            Zone::Named(tz) => tz.name().to_string(),
+            Zone::Iso => "UTC".to_string(),
+        };
+    }
+
+    pub fn today(&self) -> NaiveDate {
+        return self.at(Utc::now()).date_naive();
+    }
+
+    // e.g. `2024-01-01 19:30:00`, or `01.01.2024 19:30:00` in German.
+    pub fn date_time(&self, time: DateTime<Utc>) -> String {
+        let format = format!("{} %H:%M:%S", i18n::locale().date_format());
+        return self.format(time, &format, "%Y-%m-%dT%H:%M:%SZ");
+    }
+
+    // e.g. `2024-01-01 19:30`.
+    pub fn date_time_minutes(&self, time: DateTime<Utc>) -> String {
+        let format = format!("{} %H:%M", i18n::locale().date_format());
+        return self.format(time, &format, "%Y-%m-%dT%H:%MZ");
+    }
+
+    // e.g. `07:30:00 PM`, or `19:30:00` where the 24-hour clock is usual.
+    pub fn time_of_day(&self, time: DateTime<Utc>) -> String {
+        let format = match i18n::locale().twelve_hour_clock() {
+            true => "%I:%M:%S %p",
+            false => "%H:%M:%S",
+        };
+        return self.format(time, format, "%H:%M:%SZ");
+    }
+
+    // A sample's time on the timeline, e.g. `01-01 07:30:00 PM`, or a summary's without the seconds.
+    pub fn timeline_time(&self, time: DateTime<Utc>, with_seconds: bool) -> String {
+        let locale = i18n::locale();
+        let time_of_day = match (locale.twelve_hour_clock(), with_seconds) {
+            (true, true) => "%I:%M:%S %p",
+            (true, false) => "%I:%M %p",
+            (false, true) => "%H:%M:%S",
+            (false, false) => "%H:%M",
+        };
+        let format = format!("{} {}", locale.month_day_format(), time_of_day);
+        return match with_seconds {
+            true => self.format(time, &format, "%Y-%m-%dT%H:%M:%SZ"),
+            false => self.format(time, &format, "%Y-%m-%dT%H:%MZ"),
+        };
+    }
+
+    // Formats `time` in this zone with `format`, or with `iso_format` in UTC for ISO 8601.
+    pub fn format(&self, time: DateTime<Utc>, format: &str, iso_format: &str) -> String {
+        return match self {
+            Zone::Iso => time.format(iso_format).to_string(),
+            _ => self.at(time).format(format).to_string(),
+        };
+    }
+}
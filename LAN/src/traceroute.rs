@@ -0,0 +1,223 @@
+use crate::config;
+use crate::probes::icmp::{
+    source_address_for, IcmpEchoMessage, DEFAULT_PAYLOAD_SIZE, ICMPV6_DEST_UNREACHABLE,
+    ICMPV6_ECHO_REPLY, ICMPV6_TIME_EXCEEDED, ICMP_DEST_UNREACHABLE, ICMP_ECHO_REPLY,
+    ICMP_TIME_EXCEEDED,
+};
+use chrono::Duration as chrono_Duration;
+use chrono::Utc;
+use dns_lookup::lookup_addr;
+use rand::Rng;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::mem::MaybeUninit;
+use std::net::{IpAddr, SocketAddr};
+use std::os::unix::io::AsRawFd;
+use std::time::Duration;
+
+// One step along the path to a destination.
+pub struct Hop {
+    pub ttl: u8,
+    // Who responded to the probe with this TTL, None if nobody did before the timeout.
+    pub responder: Option<IpAddr>,
+    pub responder_hostname: Option<String>,
+    pub rtt: Option<Duration>,
+}
+
+pub struct Trace {
+    pub dest_ip: IpAddr,
+    pub hops: Vec<Hop>,
+    // Whether the destination itself responded.
+    pub reached_dest: bool,
+}
+
+// What a received ICMP message says about one of our probes.
+enum ProbeResponse {
+    // A router along the way dropped the probe because its TTL ran out.
+    TimeExceeded,
+    // The destination (or a router) reports the destination is unreachable.
+    Unreachable,
+    // The destination responded.
+    EchoReply,
+}
+
+// Traces the path to `dest_ip` by sending ICMP Echo messages with increasing TTLs (hop limits for
+// IPv6), and listening for the ICMP Time Exceeded messages routers send back when the TTL runs out.
+pub fn trace(dest_ip: IpAddr) -> Result<Trace, String> {
+    let unique_id: u16 = rand::thread_rng().gen::<u16>();
+    let timeout = Duration::from_millis(config::get().ping_timeout_msec);
+    let dest_addr: socket2::SockAddr = SocketAddr::new(dest_ip, 0).into();
+    let socket = match dest_ip {
+        IpAddr::V4(_) => Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::ICMPV4)),
+        IpAddr::V6(_) => Socket::new(Domain::IPV6, Type::RAW, Some(Protocol::ICMPV6)),
+    }
+    .map_err(|err| format!("Failed to open a raw socket - {}", err))?;
+    filter_traceroute_responses(&socket, dest_ip);
+    socket.set_read_timeout(Some(timeout)).unwrap();
+    socket.set_write_timeout(Some(timeout)).unwrap();
+
+    let mut hops = Vec::new();
+    for ttl in 1..=config::TRACEROUTE_MAX_HOPS {
+        match dest_ip {
+            IpAddr::V4(_) => socket.set_ttl(ttl.into()),
+            IpAddr::V6(_) => socket.set_unicast_hops_v6(ttl.into()),
+        }
+        .map_err(|err| format!("Failed to set TTL {} - {}", ttl, err))?;
+        // Use the TTL as the sequence number, so responses can be matched to the hop they came from.
+        let request = match dest_ip {
+            IpAddr::V4(_) => IcmpEchoMessage::new(unique_id, ttl.into(), DEFAULT_PAYLOAD_SIZE),
+            IpAddr::V6(dest_ip_v6) => IcmpEchoMessage::new_v6(
+                unique_id,
+                ttl.into(),
+                DEFAULT_PAYLOAD_SIZE,
+                source_address_for(dest_ip_v6),
+                dest_ip_v6,
+            ),
+        };
+        let start_time = Utc::now();
+        let deadline = start_time + chrono_Duration::from_std(timeout).unwrap();
+        socket
+            .send_to(&request.serialize(), &dest_addr)
+            .map_err(|err| format!("Error while sending to {} - {}", dest_ip, err))?;
+        let mut hop = Hop {
+            ttl,
+            responder: None,
+            responder_hostname: None,
+            rtt: None,
+        };
+        // Echo Replies and Destination Unreachable messages mean there's no point going further.
+        let mut end_of_path = false;
+        while Utc::now() < deadline && hop.responder.is_none() {
+            let mut recv_buf = [MaybeUninit::new(0); 1500];
+            let (size, origin_addr) = match socket.recv_from(&mut recv_buf) {
+                Ok(recv_res) => recv_res,
+                Err(_err) => break, // Timed out, nobody at this hop responded.
+            };
+            // Every element of `recv_buf` was initialized when it was allocated.
+            let response_buf = &unsafe { recv_buf.assume_init_ref() }[..size];
+            let response = match dest_ip {
+                IpAddr::V4(_) => parse_response_v4(response_buf, unique_id, ttl.into()),
+                IpAddr::V6(_) => parse_response_v6(response_buf, unique_id, ttl.into()),
+            };
+            let Some(response) = response else {
+                continue; // A response to someone else's probe, or an older hop's.
+            };
+            let responder = origin_addr.as_socket().unwrap().ip();
+            hop.responder = Some(responder);
+            hop.rtt = Some((Utc::now() - start_time).to_std().unwrap());
+            end_of_path = match response {
+                ProbeResponse::EchoReply | ProbeResponse::Unreachable => true,
+                ProbeResponse::TimeExceeded => responder == dest_ip,
+            };
+        }
+        hop.responder_hostname = hop
+            .responder
+            .and_then(|responder| lookup_addr(&responder).ok())
+            .filter(|hostname| hop.responder.unwrap().to_string() != *hostname);
+        hops.push(hop);
+        if end_of_path {
+            break;
+        }
+    }
+    let reached_dest = hops.last().and_then(|hop| hop.responder) == Some(dest_ip);
+    return Ok(Trace {
+        dest_ip,
+        hops,
+        reached_dest,
+    });
+}
+
+// Only recv the ICMP types traceroute cares about.
+fn filter_traceroute_responses(socket: &Socket, dest_ip: IpAddr) {
+    match dest_ip {
+        IpAddr::V4(_) => {
+            let icmp_types_to_listen_for_bitmask: libc::c_int = !((1 << ICMP_ECHO_REPLY)
+                | (1 << ICMP_DEST_UNREACHABLE)
+                | (1 << ICMP_TIME_EXCEEDED));
+            unsafe {
+                libc::setsockopt(
+                    socket.as_raw_fd(),
+                    libc::SOL_RAW,
+                    1, /* ICMP_FILTER */
+                    &icmp_types_to_listen_for_bitmask as *const libc::c_int as *const libc::c_void,
+                    4, /* Size of the bitmask, it's 32 bits */
+                );
+            }
+        }
+        IpAddr::V6(_) => {
+            // A set bit blocks that type, see `filter_icmpv6_replies`.
+            let mut icmp6_types_to_block_bitmask: [u32; 8] = [u32::MAX; 8];
+            for icmp6_type in [
+                ICMPV6_ECHO_REPLY,
+                ICMPV6_DEST_UNREACHABLE,
+                ICMPV6_TIME_EXCEEDED,
+            ] {
+                icmp6_types_to_block_bitmask[usize::from(icmp6_type >> 5)] &=
+                    !(1 << (icmp6_type & 31));
+            }
+            unsafe {
+                libc::setsockopt(
+                    socket.as_raw_fd(),
+                    libc::IPPROTO_ICMPV6,
+                    1, /* ICMP6_FILTER */
+                    &icmp6_types_to_block_bitmask as *const [u32; 8] as *const libc::c_void,
+                    std::mem::size_of::<[u32; 8]>().try_into().unwrap(),
+                );
+            }
+        }
+    }
+}
+
+// Reads the 2B ID and 2B sequence number at `offset` (the start of an ICMP Echo header) in `buf`.
+fn echo_id_and_seq(buf: &[u8], offset: usize) -> Option<(u16, u16)> {
+    let header = buf.get(offset..offset + 8)?;
+    return Some((
+        u16::from_be_bytes([header[4], header[5]]),
+        u16::from_be_bytes([header[6], header[7]]),
+    ));
+}
+
+// Parses a packet recv'd on a raw IPv4 socket, which starts with the IP header.
+// ICMP error messages quote the IP header and first 8B of the message that caused them, which is how
+// we tell whether an error is about one of our probes.
+// https://www.rfc-editor.org/rfc/rfc792
+fn parse_response_v4(buf: &[u8], echo_id: u16, sequence_number: u16) -> Option<ProbeResponse> {
+    let ip_header_size = usize::from(buf.first()? & 0x0F) * 4;
+    let icmp_type = *buf.get(ip_header_size)?;
+    let (response, echo_offset) = match icmp_type {
+        ICMP_ECHO_REPLY => (ProbeResponse::EchoReply, ip_header_size),
+        ICMP_TIME_EXCEEDED | ICMP_DEST_UNREACHABLE => {
+            // Skip this message's 8B ICMP header to get to the quoted IP header.
+            let quoted_ip_header_start = ip_header_size + 8;
+            let quoted_ip_header_size = usize::from(buf.get(quoted_ip_header_start)? & 0x0F) * 4;
+            let response = if icmp_type == ICMP_TIME_EXCEEDED {
+                ProbeResponse::TimeExceeded
+            } else {
+                ProbeResponse::Unreachable
+            };
+            (response, quoted_ip_header_start + quoted_ip_header_size)
+        }
+        _ => return None,
+    };
+    if echo_id_and_seq(buf, echo_offset)? != (echo_id, sequence_number) {
+        return None;
+    }
+    return Some(response);
+}
+
+// Parses a packet recv'd on a raw IPv6 socket, which starts with the ICMPv6 header.
+// ICMPv6 error messages quote as much of the packet that caused them as will fit.
+// https://www.rfc-editor.org/rfc/rfc4443
+fn parse_response_v6(buf: &[u8], echo_id: u16, sequence_number: u16) -> Option<ProbeResponse> {
+    let (response, echo_offset) = match *buf.first()? {
+        ICMPV6_ECHO_REPLY => (ProbeResponse::EchoReply, 0),
+        // Skip this message's 8B ICMPv6 header and the quoted 40B IPv6 header.
+        // Our probes don't use extension headers, so the quoted ICMPv6 header comes right after.
+        ICMPV6_TIME_EXCEEDED => (ProbeResponse::TimeExceeded, 8 + 40),
+        ICMPV6_DEST_UNREACHABLE => (ProbeResponse::Unreachable, 8 + 40),
+        _ => return None,
+    };
+    if echo_id_and_seq(buf, echo_offset)? != (echo_id, sequence_number) {
+        return None;
+    }
+    return Some(response);
+}
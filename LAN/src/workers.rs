@@ -0,0 +1,215 @@
+use crate::json;
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+
+// What each target's probing thread is up to, for `/debug`, to see why a target stopped getting
+// samples without digging through the log.
+#[derive(Clone, Serialize, JsonSchema)]
+struct Worker {
+    // Tells apart a target's thread from the one replacing it when it's edited, which starts before
+    // the old one stops.
+    #[serde(skip)]
+    id: u64,
+    // e.g. "probing", "waiting for a probe slot" or "crashed", see `set_state`. `Report` shows it.
+    #[serde(skip)]
+    state: &'static str,
+    #[serde(serialize_with = "json::seconds")]
+    state_since: DateTime<Utc>,
+    #[serde(serialize_with = "json::seconds")]
+    started: DateTime<Utc>,
+    #[serde(serialize_with = "json::maybe_seconds")]
+    last_send: Option<DateTime<Utc>>,
+    #[serde(serialize_with = "json::maybe_seconds")]
+    last_reply: Option<DateTime<Utc>>,
+    consecutive_failures: usize,
+    // The address being probed. None for probers that resolve the hostname for every probe, or
+    // until it's been resolved.
+    #[schemars(description = "Null for probers that resolve the hostname for every probe.")]
+    resolved_ip: Option<IpAddr>,
+    // Sends and receives that failed, e.g. with "network is unreachable", and the latest one.
+    socket_errors: u64,
+    last_socket_error: Option<String>,
+    #[schemars(description = "Null for probers that don't filter what they receive.")]
+    bpf_filter: Option<BpfFilter>,
+}
+
+// Whether the BPF filter is attached to a prober's socket.
+#[derive(Clone, Serialize, JsonSchema)]
+struct BpfFilter {
+    attached: bool,
+    // How many instructions it has.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    instructions: Option<usize>,
+    // Why that couldn't be found out.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+static WORKERS: OnceLock<Mutex<HashMap<String, Worker>>> = OnceLock::new();
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    // The target this thread probes, and its worker's id.
+    static CURRENT: RefCell<Option<(String, u64)>> = const { RefCell::new(None) };
+}
+
+fn workers() -> &'static Mutex<HashMap<String, Worker>> {
+    return WORKERS.get_or_init(|| Mutex::new(HashMap::new()));
+}
+
+// Registers this thread as the one probing `name`. It's forgotten when the returned guard is
+// dropped, unless the thread panicked, in which case it's kept as "crashed".
+pub fn start(name: &str) -> Guard {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let now = Utc::now();
+    workers().lock().unwrap().insert(
+        name.to_string(),
+        Worker {
+            id,
+            started: now,
+            state: "starting",
+            state_since: now,
+            resolved_ip: None,
+            bpf_filter: None,
+            last_send: None,
+            last_reply: None,
+            consecutive_failures: 0,
+            socket_errors: 0,
+            last_socket_error: None,
+        },
+    );
+    CURRENT.with(|current| *current.borrow_mut() = Some((name.to_string(), id)));
+    return Guard;
+}
+
+pub struct Guard;
+impl Drop for Guard {
+    fn drop(&mut self) {
+        let Some((name, id)) = CURRENT.with(|current| current.borrow_mut().take()) else {
+            return;
+        };
+        let mut workers = workers().lock().unwrap_or_else(|err| err.into_inner());
+        if workers.get(&name).is_none_or(|worker| worker.id != id) {
+            return;
+        }
+        if thread::panicking() {
+            let worker = workers.get_mut(&name).unwrap();
+            worker.state = "crashed";
+            worker.state_since = Utc::now();
+        } else {
+            workers.remove(&name);
+        }
+    }
+}
+
+// Updates this thread's worker, if it's probing a target.
+fn update(change: impl FnOnce(&mut Worker)) {
+    CURRENT.with(|current| {
+        let Some((name, id)) = &*current.borrow() else {
+            return;
+        };
+        let mut workers = workers().lock().unwrap();
+        if let Some(worker) = workers.get_mut(name).filter(|worker| worker.id == *id) {
+            change(worker);
+        }
+    });
+}
+
+pub fn set_state(state: &'static str) {
+    update(|worker| {
+        if worker.state != state {
+            worker.state = state;
+            worker.state_since = Utc::now();
+        }
+    });
+}
+
+// Notes the address being probed, and how many instructions of BPF filter are attached to the
+// prober's socket or why that couldn't be found out.
+pub fn set_prober(resolved_ip: Option<IpAddr>, bpf_filter: Option<Result<usize, String>>) {
+    let bpf_filter = bpf_filter.map(|instructions| match instructions {
+        Ok(instructions) => BpfFilter {
+            attached: instructions > 0,
+            instructions: Some(instructions),
+            error: None,
+        },
+        Err(err) => BpfFilter {
+            attached: false,
+            instructions: None,
+            error: Some(err),
+        },
+    });
+    update(|worker| {
+        worker.resolved_ip = resolved_ip;
+        worker.bpf_filter = bpf_filter;
+    });
+}
+
+// Notes that a probe is about to be sent.
+pub fn sending() {
+    update(|worker| worker.last_send = Some(Utc::now()));
+}
+
+// Notes a send or receive on the prober's socket that failed.
+pub fn socket_error(err: String) {
+    update(|worker| {
+        worker.socket_errors += 1;
+        worker.last_socket_error = Some(err);
+    });
+}
+
+// Notes that a probe (or burst of them) was answered if there are no `consecutive_failures`, which
+// count it.
+pub fn probed(consecutive_failures: usize) {
+    update(|worker| {
+        if consecutive_failures == 0 {
+            worker.last_reply = Some(Utc::now());
+        }
+        worker.consecutive_failures = consecutive_failures;
+    });
+}
+
+// The workers of the targets `names`, in that order, as `/debug` shows them.
+pub fn report<'a>(names: impl Iterator<Item = &'a str>) -> Report {
+    let workers = workers().lock().unwrap();
+    return Report {
+        targets: names
+            .map(|name| {
+                let worker = workers.get(name).cloned();
+                return TargetWorker {
+                    name: name.to_string(),
+                    state: worker.as_ref().map_or("not running", |worker| worker.state),
+                    worker,
+                };
+            })
+            .collect(),
+    };
+}
+
+// Each target's worker, as `/debug` shows them.
+#[derive(Serialize, JsonSchema)]
+#[schemars(rename = "Debug")]
+pub struct Report {
+    targets: Vec<TargetWorker>,
+}
+
+// A target's name and its worker, None if its thread hasn't started yet or the target is being
+// replaced.
+#[derive(Serialize, JsonSchema)]
+struct TargetWorker {
+    name: String,
+    #[schemars(
+        description = "e.g. probing, sleeping, waiting for a probe slot, paused, or \
+                              crashed if the thread panicked."
+    )]
+    state: &'static str,
+    #[serde(flatten)]
+    worker: Option<Worker>,
+}
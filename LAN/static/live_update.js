@@ -0,0 +1,16 @@
+// How long to wait after a sample is taken before fetching the page, the viewer's refresh interval.
+const refreshMsec = Number(document.currentScript.dataset.refreshMsec);
+let refresh = null;
+new EventSource('/events').addEventListener('sample', () => {
+    refresh ??= setTimeout(async () => {
+        try {
+            const response = await fetch(location.href);
+            if (response.ok) {
+                const page = new DOMParser().parseFromString(await response.text(), 'text/html');
+                document.body.replaceWith(page.body);
+            }
+        } finally {
+            refresh = null;
+        }
+    }, refreshMsec);
+});
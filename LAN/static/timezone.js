@@ -0,0 +1,3 @@
+// Tells the monitor which time zone this browser is in, for showing times in the viewer's zone.
+document.cookie = 'browser_tz=' + Intl.DateTimeFormat().resolvedOptions().timeZone +
+    '; path=/; max-age=31536000; samesite=lax';